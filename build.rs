@@ -1,43 +1,112 @@
 use std::fs;
 use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use reqwest::blocking::Client;
 
+// The docs pages are copies of the wiki, vendored in src/embedded_docs so the
+// build never needs the network. TIDAL_FETCH_DOCS=1 downloads them again to
+// refresh the copies; docs.rs links to the same wiki.
+const WIKI_REPO: &str = "Tidal-Lang/Tidal";
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
-    
+    println!("cargo:rerun-if-changed=src/embedded_docs");
+    println!("cargo:rerun-if-env-changed=TIDAL_FETCH_DOCS");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Some(git_ref) = fs::read_to_string(".git/HEAD").ok().and_then(|h| h.strip_prefix("ref: ").map(|r| r.trim().to_string())) {
+        println!("cargo:rerun-if-changed=.git/{}", git_ref);
+    }
+
+    // shown by td --version
+    println!("cargo:rustc-env=TIDAL_GIT_COMMIT={}", git_commit());
+    println!("cargo:rustc-env=TIDAL_BUILD_DATE={}", build_date());
+
+    // td docs links to the wiki the pages come from
+    println!("cargo:rustc-env=TIDAL_WIKI_URL=https://github.com/{}/wiki", WIKI_REPO);
+
+    // rust-embed needs the folder to exist even when no pages are vendored
     let docs_dir = Path::new("src").join("embedded_docs");
     fs::create_dir_all(&docs_dir).expect("Failed to create embedded_docs directory");
 
+    if std::env::var("TIDAL_FETCH_DOCS").as_deref() != Ok("1") {
+        return;
+    }
+
     let wiki_pages = [
-        // vec of all docs on wiki
-        ("Home", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Home.md"),
-        ("Brain Rot Mode", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Brain-Rot-Mode-☠%EF%B8%8F.md"),
-        ("Control Flow", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Control-Flow.md"),
-        ("Data Types", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Data-Types.md"),
-        ("File Extension", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/File-Extension.md"),
-        ("Libraries", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Libraries.md"),
-        ("Loops", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Loops.md"),
-        ("Operators", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Operators.md"),
-        ("Syntax", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Syntax.md"),
-        ("Variables", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Variables.md"),
-        ("Functions", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Functions.md"),
-        ("Verbose Mode", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/Verbose-Mode.md"),
-        ("For Developers", "https://raw.githubusercontent.com/wiki/Tidal-Lang/Tidal/For-Developers.md"),
+        // page title, file name on the wiki
+        ("Home", "Home"),
+        ("Brain Rot Mode", "Brain-Rot-Mode-☠%EF%B8%8F"),
+        ("Control Flow", "Control-Flow"),
+        ("Data Types", "Data-Types"),
+        ("File Extension", "File-Extension"),
+        ("Libraries", "Libraries"),
+        ("Loops", "Loops"),
+        ("Operators", "Operators"),
+        ("Syntax", "Syntax"),
+        ("Variables", "Variables"),
+        ("Functions", "Functions"),
+        ("Verbose Mode", "Verbose-Mode"),
+        ("For Developers", "For-Developers"),
     ];
 
-    let client = Client::new();
-    for (name, url) in wiki_pages.iter() {
-        println!("Fetching documentation: {}", name);
-        let content = client.get(*url)
+    // a build without network waits at most this long for each page
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to set up the docs download");
+    let mut fetched = 0;
+    for (name, page) in wiki_pages.iter() {
+        let url = format!("https://raw.githubusercontent.com/wiki/{}/{}.md", WIKI_REPO, page);
+        let content = client.get(&url)
             .send()
-            .expect(&format!("Failed to fetch {}", name))
-            .text()
-            .expect(&format!("Failed to read content for {}", name));
-        
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text());
+        let content = match content {
+            Ok(content) => content,
+            Err(e) => {
+                println!("cargo:warning=Could not download the docs page {}: {}", name, e);
+                continue;
+            }
+        };
+
         let file_path = docs_dir.join(format!("{}.md", name.replace(" ", "_")));
         fs::write(&file_path, content)
-            .expect(&format!("Failed to write {}", file_path.display()));
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", file_path.display(), e));
+        fetched += 1;
     }
 
-    println!("Successfully fetched all documentation files");
-}
\ No newline at end of file
+    println!("cargo:warning=Refreshed {} of {} documentation pages", fetched, wiki_pages.len());
+}
+
+// short hash of the checked-out commit, or "unknown" outside a git checkout
+fn git_commit() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// YYYY-MM-DD in UTC; SOURCE_DATE_EPOCH pins it for reproducible builds
+fn build_date() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+
+    // civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,64 @@
+use crate::interpreter::{Session, Settings};
+use crate::libs::bench::{format_seconds, measure};
+use crate::parser::ASTNode;
+use serde_json::{json, Value as Json};
+use std::error::Error;
+use std::fs;
+
+// td bench file.td: the script runs once for its setup, then every top-level
+// function named bench_* that takes no arguments is timed, in the order the
+// script declares them. --save writes the results as JSON, e.g.
+//   {"bench_sort":{"min":0.0012,"mean":0.0013,"p95":0.0015,"iterations":100}}
+// and --baseline compares against such a file, failing the run when a mean
+// got slower by more than --threshold percent.
+pub struct Options<'a> {
+    // the script, which external libraries are looked up next to
+    pub script: &'a str,
+    pub iterations: usize,
+    pub baseline: Option<&'a str>,
+    pub save: Option<&'a str>,
+    pub threshold: f64,
+}
+
+// Ok(false) when a benchmark regressed against the baseline
+pub fn run(ast: Vec<ASTNode>, options: &Options) -> Result<bool, Box<dyn Error>> {
+    let baseline: Json = match options.baseline {
+        Some(path) => serde_json::from_str(&fs::read_to_string(path).map_err(|e| format!("cannot read baseline '{}': {}", path, e))?)?,
+        None => Json::Null,
+    };
+
+    let settings = Settings { script: Some(options.script.to_string()), ..Settings::default() };
+    let mut session = Session::new(false).with_settings(settings);
+    session.eval(ast)?;
+    let benchmarks: Vec<String> = session.functions().into_iter()
+        .filter(|(name, params, _)| name.starts_with("bench_") && params.is_empty())
+        .map(|(name, _, _)| name)
+        .collect();
+    if benchmarks.is_empty() {
+        return Err("no bench_* functions without parameters to run".into());
+    }
+
+    let mut results = serde_json::Map::new();
+    let mut passed = true;
+    for name in benchmarks {
+        let call = vec![ASTNode::FunctionCall(name.clone(), vec![])];
+        let stats = measure(options.iterations, || session.eval(call.clone()).map(|_| ()))?;
+        let mut line = stats.report(&name);
+        if let Some(before) = baseline[&name]["mean"].as_f64().filter(|mean| *mean > 0.0) {
+            let change = (stats.mean - before) / before * 100.0;
+            line.push_str(&format!("  {:+.1}% vs {}", change, format_seconds(before)));
+            if change > options.threshold {
+                line.push_str("  REGRESSION");
+                passed = false;
+            }
+        }
+        outln!("{}", line);
+        results.insert(name, json!({ "min": stats.min, "mean": stats.mean, "p95": stats.p95, "iterations": stats.iterations }));
+    }
+
+    if let Some(path) = options.save {
+        fs::write(path, Json::Object(results).to_string()).map_err(|e| format!("cannot write '{}': {}", path, e))?;
+        outln!("Saved results to {}", path);
+    }
+    Ok(passed)
+}
@@ -0,0 +1,361 @@
+// The one description of td's command line. main dispatches on what parse()
+// returns and shell completions are generated from the same tables, so a new
+// subcommand or flag only has to be added here.
+use indexmap::IndexMap;
+
+pub struct Flag {
+    pub long: &'static str,
+    pub short: Option<char>,
+    pub value: Option<&'static str>, // placeholder when the flag takes a value
+    pub help: &'static str,
+    pub conflicts: &'static [&'static str], // long names that can't be combined with this one
+}
+
+impl Flag {
+    pub fn matches(&self, arg: &str) -> bool {
+        arg.strip_prefix("--") == Some(self.long)
+            || self.short.is_some_and(|c| arg == format!("-{}", c))
+    }
+}
+
+pub enum Operand {
+    None,
+    // a script to run; everything after it belongs to the script
+    Script,
+    Choice(&'static [&'static str]),
+    // like Choice, but may be left out
+    OptionalChoice(&'static [&'static str]),
+    // exactly one file of any kind, described by the placeholder
+    File(&'static str),
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub operand: Operand,
+    pub flags: &'static [Flag],
+}
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+
+const VERBOSE: Flag = Flag { long: "verbose", short: Some('v'), value: None, help: "Enable verbose output (to stderr)", conflicts: &["quiet"] };
+const QUIET: Flag = Flag { long: "quiet", short: Some('q'), value: None, help: "Print nothing but the script's own output and errors", conflicts: &["verbose"] };
+
+// flags for running a script; they go before the script's own arguments
+pub const RUN_FLAGS: &[Flag] = &[
+    VERBOSE,
+    QUIET,
+    Flag { long: "warn", short: Some('W'), value: None, help: "Warn about float equality and implicit int/float conversions", conflicts: &[] },
+    Flag { long: "strict", short: None, value: None, help: "Treat int/float == comparisons as errors (implies --warn)", conflicts: &[] },
+    Flag { long: "trace-out", short: None, value: Some("file"), help: "Write a JSON line per executed statement to the file", conflicts: &[] },
+    Flag { long: "seed", short: None, value: Some("n"), help: "Start the random library from this seed", conflicts: &[] },
+    Flag { long: "loop-guard", short: None, value: Some("n"), help: "Stop a loop after n iterations with its condition unchanged", conflicts: &[] },
+    Flag { long: "fake-time", short: None, value: Some("secs"), help: "Start the time library's clock at this unix time; sleep() returns at once", conflicts: &[] },
+    Flag { long: "unbuffered", short: Some('u'), value: None, help: "Write output as soon as it is printed, even to a pipe", conflicts: &[] },
+    Flag { long: "max-alloc", short: None, value: Some("mb"), help: "Refuse to build a string or array repetition bigger than this (default 256)", conflicts: &[] },
+    Flag { long: "debug-dump", short: None, value: Some("file"), help: "If a runtime error ends the script, write the interpreter's state to the file", conflicts: &[] },
+    Flag { long: "summary", short: None, value: Some("json"), help: "When the script ends, write what it did (time, memory, statements, calls, files) to stderr as JSON", conflicts: &[] },
+    Flag { long: "dialect", short: None, value: Some("name"), help: "Read the script in this dialect (brainrot, french), or none to read a .br file as plain Tidal", conflicts: &[] },
+];
+
+// accepted anywhere, in place of a subcommand
+pub const GLOBAL_FLAGS: &[Flag] = &[
+    Flag { long: "help", short: Some('h'), value: None, help: "Display the help message", conflicts: &[] },
+    Flag { long: "version", short: Some('V'), value: None, help: "Display version, build and library information", conflicts: &[] },
+];
+
+pub const COMMANDS: &[Command] = &[
+    Command { name: "run", help: "Run a script (td file.td is short for td run file.td)", operand: Operand::Script, flags: RUN_FLAGS },
+    Command {
+        name: "docs",
+        help: "Display built-in docs",
+        operand: Operand::None,
+        flags: &[Flag { long: "pg", short: None, value: Some("number"), help: "Page number to display", conflicts: &[] }],
+    },
+    Command { name: "explain", help: "Explain an error code, like E0007", operand: Operand::Choice(crate::error::CODES), flags: &[] },
+    Command { name: "grammar", help: "Print the language grammar as EBNF", operand: Operand::None, flags: &[] },
+    Command {
+        name: "trace-view",
+        help: "Summarize a trace written by --trace-out",
+        operand: Operand::File("trace"),
+        flags: &[
+            Flag { long: "top", short: None, value: Some("n"), help: "Rows per table (default 10)", conflicts: &["replay"] },
+            Flag { long: "replay", short: None, value: None, help: "Print every statement in the order it ran", conflicts: &["top"] },
+        ],
+    },
+    Command {
+        name: "bench",
+        help: "Time the bench_* functions in a script",
+        operand: Operand::Script,
+        flags: &[
+            Flag { long: "iterations", short: Some('n'), value: Some("n"), help: "Timed runs per benchmark (default 100)", conflicts: &[] },
+            Flag { long: "baseline", short: None, value: Some("file"), help: "Compare against results saved with --save", conflicts: &[] },
+            Flag { long: "save", short: None, value: Some("file"), help: "Write the results as JSON", conflicts: &[] },
+            Flag { long: "threshold", short: None, value: Some("percent"), help: "Slowdown that counts as a regression (default 10)", conflicts: &[] },
+        ],
+    },
+    Command {
+        name: "test",
+        help: "Run the examples in a file's /** doc comments */",
+        operand: Operand::File("source"),
+        flags: &[Flag { long: "doc", short: None, value: None, help: "Run doc comment examples, the only kind of test so far", conflicts: &[] }],
+    },
+    Command {
+        name: "doc",
+        help: "Write API docs for a .tdx library from its /** doc comments */",
+        operand: Operand::File("library"),
+        flags: &[
+            Flag { long: "out", short: Some('o'), value: Some("dir"), help: "Directory to write to (default docs)", conflicts: &[] },
+            Flag { long: "format", short: None, value: Some("md|html"), help: "Markdown or HTML (default md)", conflicts: &[] },
+        ],
+    },
+    Command {
+        name: "graph",
+        help: "Print the script's import graph",
+        operand: Operand::Script,
+        flags: &[
+            Flag { long: "dot", short: None, value: None, help: "As Graphviz DOT (the default)", conflicts: &["mermaid"] },
+            Flag { long: "mermaid", short: None, value: None, help: "As a Mermaid flowchart", conflicts: &["dot"] },
+        ],
+    },
+    Command {
+        name: "minify",
+        help: "Write a script without comments or spacing, with short local names",
+        operand: Operand::Script,
+        flags: &[Flag { long: "out", short: Some('o'), value: Some("file"), help: "Write to the file instead of printing", conflicts: &[] }],
+    },
+    Command {
+        name: "transpile",
+        help: "Write a script as Python or JavaScript",
+        operand: Operand::Script,
+        flags: &[
+            Flag { long: "to", short: None, value: Some("py|js"), help: "The language to write", conflicts: &[] },
+            Flag { long: "out", short: Some('o'), value: Some("file"), help: "Write to the file instead of printing", conflicts: &[] },
+        ],
+    },
+    Command { name: "repl", help: "Start an interactive session", operand: Operand::None, flags: &[VERBOSE, QUIET] },
+    Command { name: "notebook", help: "Run notebook cells sent as JSON-RPC lines on stdin in one session", operand: Operand::None, flags: &[] },
+    Command {
+        name: "upgrade",
+        help: "Install the latest release",
+        operand: Operand::None,
+        flags: &[Flag { long: "check", short: None, value: None, help: "Only report whether a newer release exists", conflicts: &[] }],
+    },
+    Command { name: "completions", help: "Print a shell completion script", operand: Operand::Choice(SHELLS), flags: &[] },
+    Command { name: "version", help: "Display version, build and library information", operand: Operand::None, flags: &[] },
+    Command { name: "help", help: "Display this help, or a command's in full", operand: Operand::OptionalChoice(COMMAND_NAMES), flags: &[] },
+];
+
+// the names in COMMANDS, in the same order, for td help <command>
+pub const COMMAND_NAMES: &[&str] = &[
+    "run", "docs", "explain", "grammar", "trace-view", "bench", "test", "doc", "graph", "minify",
+    "transpile", "repl", "notebook", "upgrade", "completions", "version", "help",
+];
+
+// .td and the extension of each dialect in dialect::DIALECTS
+pub const SCRIPT_EXTENSIONS: &[&str] = &["td", "br", "fr"];
+
+pub fn is_script(path: &str) -> bool {
+    SCRIPT_EXTENSIONS.iter().any(|ext| path.ends_with(&format!(".{}", ext)))
+}
+
+// "a .td, .br or .fr script", for errors
+pub fn script_kinds() -> String {
+    let extensions: Vec<String> = SCRIPT_EXTENSIONS.iter().map(|ext| format!(".{}", ext)).collect();
+    let (last, rest) = extensions.split_last().unwrap();
+    format!("a {} or {} script", rest.join(", "), last)
+}
+
+fn command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|c| c.name == name)
+}
+
+pub struct Invocation {
+    pub command: &'static Command,
+    flags: IndexMap<&'static str, Option<String>>,
+    pub operands: Vec<String>,
+    // for run: the arguments after the script, passed on to it
+    pub script_args: Vec<String>,
+}
+
+impl Invocation {
+    pub fn has(&self, long: &str) -> bool {
+        self.flags.contains_key(long)
+    }
+
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.flags.get(long).and_then(|v| v.as_deref())
+    }
+}
+
+// Parses the arguments after the program name. Flags may come before or
+// after the subcommand and its operands; for run, only the arguments after
+// the script that aren't run flags, or anything after `--`, go to the script.
+pub fn parse(args: &[String]) -> Result<Invocation, String> {
+    // flags seen before we know which command they belong to
+    let mut pending: Vec<&str> = Vec::new();
+    let mut rest = args.iter().map(String::as_str);
+    let mut selected = None;
+
+    while let Some(arg) = rest.next() {
+        if let Some(global) = GLOBAL_FLAGS.iter().find(|f| f.matches(arg)) {
+            return Ok(bare(command(global.long).unwrap()));
+        }
+        if arg.starts_with('-') && arg.len() > 1 && arg != "--" {
+            pending.push(arg);
+            // a value may follow as its own argument; it's claimed once the command is known
+            if !arg.contains('=') && COMMANDS.iter().flat_map(|c| c.flags).any(|f| f.value.is_some() && f.matches(arg)) {
+                if let Some(value) = rest.next() {
+                    pending.push(value);
+                }
+            }
+            continue;
+        }
+        selected = Some(match command(arg) {
+            Some(command) => (command, None),
+            None if is_script(arg) => (command("run").unwrap(), Some(arg)),
+            None => return Err(format!("'{}' is not a td command or {}", arg, script_kinds())),
+        });
+        break;
+    }
+
+    let (command, script) = match selected {
+        Some(selected) => selected,
+        None if pending.is_empty() => return Ok(bare(command("help").unwrap())),
+        None => return Err(format!("'{}' must follow a command or a script", pending[0])),
+    };
+
+    let mut invocation = bare(command);
+    let mut words = pending.into_iter().chain(script).chain(rest);
+    let mut only_operands = false;
+
+    while let Some(arg) = words.next() {
+        let is_flag = !only_operands && arg.starts_with('-') && arg.len() > 1;
+        let script_started = matches!(command.operand, Operand::Script) && !invocation.operands.is_empty();
+
+        if arg == "--" && !only_operands && invocation.script_args.is_empty() {
+            only_operands = true;
+            continue;
+        }
+        if script_started && (!is_flag || !invocation.script_args.is_empty()) {
+            invocation.script_args.push(arg.to_string());
+            continue;
+        }
+        if is_flag && !script_started {
+            if let Some(global) = GLOBAL_FLAGS.iter().find(|f| f.matches(arg)) {
+                let mut invocation = bare(self::command(global.long).unwrap());
+                // td bench --help is td help bench
+                if global.long == "help" {
+                    invocation.operands.push(command.name.to_string());
+                }
+                return Ok(invocation);
+            }
+        }
+        if is_flag {
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg, None),
+            };
+            let flag = match command.flags.iter().find(|f| f.matches(name)) {
+                Some(flag) => flag,
+                // after the script, unknown flags are the script's own
+                None if script_started => {
+                    invocation.script_args.push(arg.to_string());
+                    continue;
+                }
+                None => return Err(format!("unknown flag '{}' for 'td {}'", name, command.name)),
+            };
+            let value = match (flag.value, inline_value) {
+                (None, None) => None,
+                (None, Some(_)) => return Err(format!("--{} does not take a value", flag.long)),
+                (Some(_), Some(value)) => Some(value),
+                (Some(placeholder), None) => match words.next() {
+                    Some(value) => Some(value.to_string()),
+                    None => return Err(format!("--{} requires a <{}>", flag.long, placeholder)),
+                },
+            };
+            invocation.flags.insert(flag.long, value);
+            continue;
+        }
+
+        match &command.operand {
+            Operand::None => return Err(format!("'td {}' takes no arguments, got '{}'", command.name, arg)),
+            Operand::Choice(choices) | Operand::OptionalChoice(choices) if !invocation.operands.is_empty() || !choices.contains(&arg) => {
+                return Err(format!("'td {}' expects one of: {}", command.name, choices.join(", ")));
+            }
+            Operand::Script if !is_script(arg) => return Err(format!("'{}' is not {}", arg, script_kinds())),
+            Operand::File(_) if !invocation.operands.is_empty() => {
+                return Err(format!("'td {}' takes only {}, got '{}' as well", command.name, operand_name(command), arg));
+            }
+            _ => invocation.operands.push(arg.to_string()),
+        }
+    }
+
+    for (name, _) in &invocation.flags {
+        let flag = command.flags.iter().find(|f| f.long == *name).unwrap();
+        if let Some(other) = flag.conflicts.iter().find(|other| invocation.flags.contains_key(*other)) {
+            return Err(format!("--{} cannot be used with --{}", flag.long, other));
+        }
+    }
+    match command.operand {
+        Operand::Script | Operand::Choice(_) | Operand::File(_) if invocation.operands.is_empty() => {
+            Err(format!("'td {}' requires {}", command.name, operand_name(command)))
+        }
+        _ => Ok(invocation),
+    }
+}
+
+fn bare(command: &'static Command) -> Invocation {
+    Invocation { command, flags: IndexMap::new(), operands: Vec::new(), script_args: Vec::new() }
+}
+
+fn operand_name(command: &Command) -> String {
+    match command.operand {
+        Operand::Choice(choices) | Operand::OptionalChoice(choices) => format!("one of: {}", choices.join(", ")),
+        Operand::File(placeholder) => format!("a {} file", placeholder),
+        _ => script_kinds(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(args: &[&str]) -> Result<Invocation, String> {
+        parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn flags_may_come_anywhere_before_the_scripts_own_arguments() {
+        let run = parsed(&["--seed", "3", "game.td", "-v", "level1", "-q", "--", "x"]).unwrap();
+        assert_eq!(run.command.name, "run");
+        assert_eq!(run.value("seed"), Some("3"));
+        assert!(run.has("verbose") && !run.has("quiet"));
+        assert_eq!(run.operands, ["game.td"]);
+        assert_eq!(run.script_args, ["level1", "-q", "--", "x"]);
+
+        let bench = parsed(&["bench", "-n=5", "b.td", "--save", "out.json"]).unwrap();
+        assert_eq!((bench.command.name, bench.value("iterations"), bench.value("save")), ("bench", Some("5"), Some("out.json")));
+        assert_eq!(parsed(&["game.td", "--", "-v"]).unwrap().script_args, ["-v"]);
+    }
+
+    #[test]
+    fn help_stands_in_for_any_command() {
+        let help = parsed(&["bench", "--help"]).unwrap();
+        assert_eq!((help.command.name, help.operands.as_slice()), ("help", ["bench".to_string()].as_slice()));
+        assert_eq!(parsed(&[]).unwrap().command.name, "help");
+        assert_eq!(parsed(&["-V"]).unwrap().command.name, "version");
+    }
+
+    #[test]
+    fn mistakes_are_explained() {
+        let error = |args: &[&str]| parsed(args).err().unwrap();
+        assert_eq!(error(&["-v", "-q", "a.td"]), "--verbose cannot be used with --quiet");
+        assert_eq!(error(&["a.td", "--seed"]), "--seed requires a <n>");
+        assert_eq!(error(&["--warn=yes", "a.td"]), "--warn does not take a value");
+        assert_eq!(error(&["repl", "--seed", "1"]), "unknown flag '--seed' for 'td repl'");
+        assert_eq!(error(&["notes.txt"]), "'notes.txt' is not a td command or a .td, .br or .fr script");
+        assert_eq!(error(&["completions", "tcsh"]), "'td completions' expects one of: bash, zsh, fish, powershell");
+        assert_eq!(error(&["trace-view"]), "'td trace-view' requires a trace file");
+    }
+}
@@ -0,0 +1,312 @@
+use crate::cli::{Command, Flag, Operand, COMMANDS, GLOBAL_FLAGS, RUN_FLAGS, SCRIPT_EXTENSIONS};
+
+// td completions <shell>: the completion script for the shell, or None if
+// the shell isn't one of cli::SHELLS
+pub fn generate(shell: &str) -> Option<String> {
+    match shell {
+        "bash" => Some(bash()),
+        "zsh" => Some(zsh()),
+        "fish" => Some(fish()),
+        "powershell" => Some(powershell()),
+        _ => None,
+    }
+}
+
+fn spellings(flag: &Flag) -> Vec<String> {
+    let mut names = vec![format!("--{}", flag.long)];
+    if let Some(c) = flag.short {
+        names.push(format!("-{}", c));
+    }
+    names
+}
+
+fn all_spellings(flags: &[Flag]) -> Vec<String> {
+    flags.iter().flat_map(spellings).collect()
+}
+
+fn choices(command: &Command) -> &'static [&'static str] {
+    match command.operand {
+        Operand::Choice(choices) | Operand::OptionalChoice(choices) => choices,
+        Operand::None | Operand::Script | Operand::File(_) => &[],
+    }
+}
+
+// whether the command's operand is a script
+fn takes_files(command: &Command) -> bool {
+    matches!(command.operand, Operand::Script)
+}
+
+// escapes for text inside single quotes
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+}
+
+fn fish_quote(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn powershell_quote(text: &str) -> String {
+    text.replace('\'', "''")
+}
+
+fn bash() -> String {
+    let top_level: Vec<String> = COMMANDS.iter().map(|c| c.name.to_string())
+        .chain(all_spellings(GLOBAL_FLAGS))
+        .collect();
+    let value_flags = |file: bool| -> Vec<String> {
+        COMMANDS.iter().flat_map(|c| c.flags)
+            .filter(|f| f.value.is_some() && (f.value == Some("file")) == file)
+            .flat_map(spellings)
+            .collect()
+    };
+    let (file_flags, other_flags) = (value_flags(true), value_flags(false));
+
+    let mut script = String::from("# bash completion for td\n_td() {\n");
+    script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    script.push_str("    COMPREPLY=()\n");
+    if !file_flags.is_empty() || !other_flags.is_empty() {
+        script.push_str("    case \"$prev\" in\n");
+        if !file_flags.is_empty() {
+            script.push_str(&format!("        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;\n", file_flags.join("|")));
+        }
+        if !other_flags.is_empty() {
+            script.push_str(&format!("        {}) return ;;\n", other_flags.join("|")));
+        }
+        script.push_str("    esac\n");
+    }
+    script.push_str("    if [ \"$COMP_CWORD\" -eq 1 ]; then\n");
+    script.push_str(&format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", top_level.join(" ")));
+    for ext in SCRIPT_EXTENSIONS {
+        script.push_str(&format!("        COMPREPLY+=($(compgen -f -X '!*.{}' -- \"$cur\"))\n", ext));
+    }
+    script.push_str("        COMPREPLY+=($(compgen -d -- \"$cur\"))\n        return\n    fi\n");
+
+    script.push_str("    case \"${COMP_WORDS[1]}\" in\n");
+    for command in COMMANDS {
+        script.push_str(&format!("        {})\n", command.name));
+        let choices = choices(command);
+        if !choices.is_empty() {
+            script.push_str(&format!(
+                "            [ \"$COMP_CWORD\" -eq 2 ] && COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+                choices.join(" ")
+            ));
+        }
+        if !command.flags.is_empty() {
+            script.push_str(&format!("            COMPREPLY+=($(compgen -W \"{}\" -- \"$cur\"))\n", all_spellings(command.flags).join(" ")));
+        }
+        if takes_files(command) {
+            for ext in SCRIPT_EXTENSIONS {
+                script.push_str(&format!("            COMPREPLY+=($(compgen -f -X '!*.{}' -- \"$cur\"))\n", ext));
+            }
+            script.push_str("            COMPREPLY+=($(compgen -d -- \"$cur\"))\n");
+        }
+        if let Operand::File(_) = command.operand {
+            script.push_str("            COMPREPLY+=($(compgen -f -- \"$cur\"))\n");
+        }
+        script.push_str("            ;;\n");
+    }
+    let patterns: Vec<String> = SCRIPT_EXTENSIONS.iter().map(|ext| format!("*.{}", ext)).collect();
+    script.push_str(&format!("        {})\n", patterns.join("|")));
+    script.push_str(&format!("            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", all_spellings(RUN_FLAGS).join(" ")));
+    script.push_str("            ;;\n    esac\n}\ncomplete -o filenames -F _td td\n");
+    script
+}
+
+fn zsh_flag(flag: &Flag) -> String {
+    let help = zsh_quote(&flag.help.replace('[', "(").replace(']', ")"));
+    let value = match flag.value {
+        Some("file") => ":file:_files".to_string(),
+        Some(v) => format!(":{}:", v),
+        None => String::new(),
+    };
+    match flag.short {
+        Some(c) => format!("'(--{long} -{c})'{{--{long},-{c}}}'[{help}]{value}'", long = flag.long, c = c, help = help, value = value),
+        None => format!("'--{}[{}]{}'", flag.long, help, value),
+    }
+}
+
+fn zsh() -> String {
+    let describe = |name: &str, help: &str| format!("'{}:{}'", name.replace(':', "\\:"), zsh_quote(help));
+
+    let mut script = String::from("#compdef td\n\n_td() {\n    local -a commands options\n    commands=(\n");
+    for command in COMMANDS {
+        script.push_str(&format!("        {}\n", describe(command.name, command.help)));
+    }
+    script.push_str("    )\n    options=(\n");
+    for flag in GLOBAL_FLAGS {
+        for name in spellings(flag) {
+            script.push_str(&format!("        {}\n", describe(&name, flag.help)));
+        }
+    }
+    script.push_str("    )\n\n    if (( CURRENT == 2 )); then\n");
+    script.push_str("        _describe -t commands 'td command' commands\n");
+    script.push_str("        _describe -t options 'option' options\n");
+    script.push_str(&format!("        _files -g '*.({})'\n        return\n    fi\n\n", SCRIPT_EXTENSIONS.join("|")));
+
+    script.push_str("    case $words[2] in\n");
+    for command in COMMANDS {
+        let mut specs: Vec<String> = command.flags.iter().map(zsh_flag).collect();
+        let choices = choices(command);
+        if !choices.is_empty() {
+            specs.push(format!("'1:argument:({})'", choices.join(" ")));
+        }
+        if takes_files(command) {
+            specs.push(format!("'*:script:_files -g \"*.({})\"'", SCRIPT_EXTENSIONS.join("|")));
+        }
+        if let Operand::File(placeholder) = command.operand {
+            specs.push(format!("'1:{}:_files'", placeholder));
+        }
+        if specs.is_empty() {
+            continue;
+        }
+        script.push_str(&format!("        {})\n            _arguments -s {}\n            ;;\n", command.name, specs.join(" ")));
+    }
+    let patterns: Vec<String> = SCRIPT_EXTENSIONS.iter().map(|ext| format!("*.{}", ext)).collect();
+    let run_specs: Vec<String> = RUN_FLAGS.iter().map(zsh_flag).collect();
+    script.push_str(&format!("        {})\n            _arguments -s {} '*::argument:_files'\n            ;;\n", patterns.join("|"), run_specs.join(" ")));
+    script.push_str("    esac\n}\n\n_td \"$@\"\n");
+    script
+}
+
+fn fish_flag(condition: &str, flag: &Flag) -> String {
+    let mut line = format!("complete -c td -n '{}' -l {}", condition, flag.long);
+    if let Some(c) = flag.short {
+        line.push_str(&format!(" -s {}", c));
+    }
+    match flag.value {
+        Some("file") => line.push_str(" -r -F"),
+        Some(_) => line.push_str(" -x"),
+        None => {}
+    }
+    line.push_str(&format!(" -d '{}'\n", fish_quote(flag.help)));
+    line
+}
+
+fn fish() -> String {
+    let mut script = String::from("# fish completion for td\ncomplete -c td -f\n");
+    for command in COMMANDS {
+        script.push_str(&format!("complete -c td -n '__fish_use_subcommand' -a {} -d '{}'\n", command.name, fish_quote(command.help)));
+    }
+    for flag in GLOBAL_FLAGS {
+        script.push_str(&fish_flag("__fish_use_subcommand", flag));
+    }
+    let suffixes: Vec<String> = SCRIPT_EXTENSIONS.iter().map(|ext| format!("__fish_complete_suffix .{}", ext)).collect();
+    script.push_str(&format!("complete -c td -n '__fish_use_subcommand' -k -a '({})'\n", suffixes.join("; ")));
+
+    for command in COMMANDS {
+        let condition = format!("__fish_seen_subcommand_from {}", command.name);
+        let choices = choices(command);
+        if !choices.is_empty() {
+            script.push_str(&format!("complete -c td -n '{}' -a '{}'\n", condition, choices.join(" ")));
+        }
+        if takes_files(command) {
+            script.push_str(&format!("complete -c td -n '{}' -k -a '({})'\n", condition, suffixes.join("; ")));
+        }
+        if let Operand::File(_) = command.operand {
+            script.push_str(&format!("complete -c td -n '{}' -F\n", condition));
+        }
+        for flag in command.flags {
+            script.push_str(&fish_flag(&condition, flag));
+        }
+    }
+
+    let script_given = fish_quote(&format!(
+        "not __fish_use_subcommand; and string match -qr '\\.({})$' -- (commandline -opc)[2]",
+        SCRIPT_EXTENSIONS.join("|")
+    ));
+    for flag in RUN_FLAGS {
+        script.push_str(&fish_flag(&script_given, flag));
+    }
+    script
+}
+
+fn powershell_list(items: &[String]) -> String {
+    let quoted: Vec<String> = items.iter().map(|item| format!("'{}'", powershell_quote(item))).collect();
+    format!("@({})", quoted.join(", "))
+}
+
+// the scripts and folders in the current folder
+fn powershell_scripts(extensions: &[String]) -> String {
+    format!(
+        "@(Get-ChildItem | Where-Object {{ $_.PSIsContainer -or $_.Extension -in {} }} | ForEach-Object {{ $_.Name }})",
+        powershell_list(extensions)
+    )
+}
+
+fn powershell() -> String {
+    let top_level: Vec<String> = COMMANDS.iter().map(|c| c.name.to_string())
+        .chain(all_spellings(GLOBAL_FLAGS))
+        .collect();
+    let extensions: Vec<String> = SCRIPT_EXTENSIONS.iter().map(|ext| format!(".{}", ext)).collect();
+
+    let mut script = String::from("# PowerShell completion for td\n");
+    script.push_str("Register-ArgumentCompleter -Native -CommandName td -ScriptBlock {\n");
+    script.push_str("    param($wordToComplete, $commandAst, $cursorPosition)\n");
+    script.push_str("    $words = @($commandAst.CommandElements | ForEach-Object { $_.ToString() })\n");
+    script.push_str("    $position = if ($wordToComplete) { $words.Count - 1 } else { $words.Count }\n");
+    script.push_str("    $candidates = @()\n");
+    script.push_str("    if ($position -le 1) {\n");
+    script.push_str(&format!("        $candidates = {}\n", powershell_list(&top_level)));
+    script.push_str(&format!("        $candidates += {}\n", powershell_scripts(&extensions)));
+    script.push_str("    } else {\n        switch -Wildcard ($words[1]) {\n");
+    for command in COMMANDS {
+        let choices: Vec<String> = choices(command).iter().map(|c| c.to_string()).collect();
+        let any_file = matches!(command.operand, Operand::File(_));
+        if choices.is_empty() && command.flags.is_empty() && !takes_files(command) && !any_file {
+            continue;
+        }
+        script.push_str(&format!("            '{}' {{\n", command.name));
+        if !choices.is_empty() {
+            script.push_str(&format!("                if ($position -eq 2) {{ $candidates += {} }}\n", powershell_list(&choices)));
+        }
+        if !command.flags.is_empty() {
+            script.push_str(&format!("                $candidates += {}\n", powershell_list(&all_spellings(command.flags))));
+        }
+        if takes_files(command) {
+            script.push_str(&format!("                $candidates += {}\n", powershell_scripts(&extensions)));
+        }
+        if any_file {
+            script.push_str("                $candidates += @(Get-ChildItem | ForEach-Object { $_.Name })\n");
+        }
+        script.push_str("            }\n");
+    }
+    for ext in SCRIPT_EXTENSIONS {
+        script.push_str(&format!("            '*.{}' {{ $candidates = {} }}\n", ext, powershell_list(&all_spellings(RUN_FLAGS))));
+    }
+    script.push_str("        }\n    }\n");
+    script.push_str("    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n");
+    script.push_str("        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n");
+    script.push_str("    }\n}\n");
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{COMMAND_NAMES, SHELLS};
+
+    #[test]
+    fn every_shell_completes_every_command_and_run_flag() {
+        for shell in SHELLS {
+            let script = generate(shell).unwrap();
+            for name in COMMAND_NAMES {
+                assert!(script.contains(name), "{} completion lacks '{}'", shell, name);
+            }
+            for flag in RUN_FLAGS {
+                assert!(script.contains(flag.long), "{} completion lacks --{}", shell, flag.long);
+            }
+        }
+        assert!(generate("tcsh").is_none());
+    }
+
+    #[test]
+    fn bash_completes_files_only_where_a_file_goes() {
+        let script = bash();
+        // --trace-out <file> completes files, --seed <n> completes nothing
+        let file_case = script.lines().find(|line| line.contains("compgen -f -- \"$cur\")); return ;;")).unwrap();
+        assert!(file_case.contains("--trace-out") && !file_case.contains("--seed"), "{}", file_case);
+        let value_case = script.lines().find(|line| line.trim_end().ends_with(") return ;;")).unwrap();
+        assert!(value_case.contains("--seed") && !value_case.contains("--trace-out"), "{}", value_case);
+        assert!(script.ends_with("complete -o filenames -F _td td\n"));
+    }
+}
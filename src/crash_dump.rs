@@ -0,0 +1,186 @@
+use crate::error::Error;
+use crate::parser::{ASTNode, Value};
+use crate::trace;
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+
+// --debug-dump <file>: when a runtime error ends the script, the file gets
+// what a bug report needs and the error message alone doesn't say: the node
+// that failed and its line, the variables it could see, the functions that
+// were being called, and the statements that ran just before.
+
+// how many finished statements the dump lists, and how long a value may get
+const RECENT_STATEMENTS: usize = 20;
+const MAX_VALUE_LENGTH: usize = 80;
+const MAX_NODE_LENGTH: usize = 400;
+
+// the variables in one scope: name, value as shown, and whether it's a var
+pub type Scope = (String, Vec<(String, String, bool)>);
+
+struct Crash {
+    // the error as it was first raised, to tell it from a later one
+    message: String,
+    line: usize,
+    node: String,
+    scopes: Vec<Scope>,
+    calls: Vec<(String, usize)>,
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    // user functions being called, outermost first, with the line of each call
+    static CALLS: RefCell<Vec<(String, usize)>> = const { RefCell::new(Vec::new()) };
+    static CRASH: RefCell<Option<Crash>> = const { RefCell::new(None) };
+}
+
+pub fn enable() {
+    ENABLED.with(|enabled| enabled.set(true));
+    trace::keep_recent(RECENT_STATEMENTS);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+// on the call stack until dropped
+pub struct Call(bool);
+
+pub fn enter_call(name: &str, line: usize) -> Call {
+    if is_enabled() {
+        CALLS.with(|calls| calls.borrow_mut().push((name.to_string(), line)));
+    }
+    Call(is_enabled())
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if self.0 {
+            CALLS.with(|calls| calls.borrow_mut().pop());
+        }
+    }
+}
+
+// Called for every node an error passes through on its way out. The first
+// node it is seen at raised it, so that one is kept; a different error
+// replaces it, since the first one was handled somewhere.
+pub fn capture(error: &Error, node: &ASTNode, line: usize, scopes: impl FnOnce() -> Vec<Scope>) {
+    let message = error.to_string();
+    if CRASH.with(|crash| crash.borrow().as_ref().is_some_and(|crash| crash.message == message)) {
+        return;
+    }
+    let calls = CALLS.with(|calls| calls.borrow().clone());
+    let mut scopes = scopes();
+    // the environment doesn't know which function it belongs to; the stack does
+    if let Some((function, _)) = calls.last() {
+        for (name, _) in scopes.iter_mut().filter(|(name, _)| name == "function") {
+            *name = format!("function {}", function);
+        }
+    }
+    let crash = Crash { message, line, node: truncate(&format!("{:?}", node), MAX_NODE_LENGTH), scopes, calls };
+    CRASH.with(|current| *current.borrow_mut() = Some(crash));
+}
+
+// a value as the dump shows it: strings quoted, anything long cut short
+pub fn show(value: &Value) -> String {
+    let text = match value {
+        Value::String(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    };
+    truncate(&text, MAX_VALUE_LENGTH)
+}
+
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    format!("{}... ({} chars)", text.chars().take(max).collect::<String>(), text.chars().count())
+}
+
+fn render(script: &str, error: &Error) -> String {
+    let mut out = format!("Tidal {} debug dump\n", env!("CARGO_PKG_VERSION"));
+    out.push_str(&format!("script: {}\n", script));
+    out.push_str(&format!("error: [{}] {}\n", error.code(), error));
+    let crash = CRASH.with(|crash| crash.borrow_mut().take()).filter(|crash| crash.message == error.to_string());
+    let Some(crash) = crash else {
+        out.push_str("\nThe error was raised outside any statement; no interpreter state was captured.\n");
+        return out;
+    };
+
+    out.push_str(&format!("\nfailed at line {}:\n", crash.line));
+    let source_line = fs::read_to_string(script).ok()
+        .and_then(|source| source.lines().nth(crash.line.wrapping_sub(1)).map(|line| line.trim().to_string()));
+    if let Some(text) = source_line {
+        out.push_str(&format!("  {}\n", text));
+    }
+    out.push_str(&format!("  {}\n", crash.node));
+
+    out.push_str("\ncall stack (innermost last):\n");
+    out.push_str(&format!("  {}\n", script));
+    for (name, line) in &crash.calls {
+        out.push_str(&format!("  {}(), called at line {}\n", name, line));
+    }
+
+    out.push_str("\nscopes (innermost last):\n");
+    for (name, variables) in &crash.scopes {
+        out.push_str(&format!("  {}:\n", name));
+        if variables.is_empty() {
+            out.push_str("    (empty)\n");
+        }
+        for (variable, value, is_mutable) in variables {
+            out.push_str(&format!("    {} {} = {}\n", if *is_mutable { "var" } else { "novar" }, variable, value));
+        }
+    }
+
+    out.push_str("\nlast statements run, as --trace-out records them (oldest first):\n");
+    let recent = trace::recent();
+    if recent.is_empty() {
+        out.push_str("  (none)\n");
+    }
+    for record in recent {
+        out.push_str(&format!("  {}\n", record));
+    }
+    out
+}
+
+pub fn write(path: &str, script: &str, error: &Error) -> io::Result<()> {
+    fs::write(path, render(script, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter;
+    use crate::parser::Parser;
+
+    #[test]
+    fn a_failing_script_dumps_its_state() {
+        let source = "var total = 0;\nnovar label = \"sum\";\nfunc add(amount) {\n    global total;\n    var doubled = amount * 2;\n    total = total + len(doubled);\n}\nadd(3);\n";
+        enable();
+        let ast = Parser::new(source).with_lines().parse().unwrap();
+        let Err(error) = interpreter::run(ast, false, interpreter::Settings::default()) else { panic!("the script should fail") };
+        let dump = render("main.td", &error);
+        ENABLED.with(|enabled| enabled.set(false));
+
+        assert!(dump.contains("error: [E0015] Cannot get length of int value: 6\n"), "{}", dump);
+        assert!(dump.contains("\nfailed at line 6:\n  LenCall(Identifier(\"doubled\"))\n"), "{}", dump);
+        assert!(dump.contains("\ncall stack (innermost last):\n  main.td\n  add(), called at line 8\n"), "{}", dump);
+        assert!(dump.contains("  function add:\n    var amount = 3\n    var doubled = 6\n"), "{}", dump);
+        assert!(dump.contains("  script:\n    novar label = \"sum\"\n    var total = 0\n"), "{}", dump);
+        assert!(dump.contains("{\"seq\":2,\"line\":2,"), "{}", dump);
+        assert!(dump.contains("\"line\":6,\"depth\":1,\"kind\":\"Assign\","), "{}", dump);
+    }
+
+    #[test]
+    fn an_error_inside_a_function_reports_the_function_line() {
+        let source = "func pick(i) {\n    var items = [1, 2];\n    var at = i + 1;\n    return items[at + 5];\n}\nvar i = 1;\npick(i);\n";
+        enable();
+        let ast = Parser::new(source).with_lines().parse().unwrap();
+        let Err(error) = interpreter::run(ast, false, interpreter::Settings::default()) else { panic!("the script should fail") };
+        let dump = render("main.td", &error);
+        ENABLED.with(|enabled| enabled.set(false));
+
+        assert!(dump.contains("\nfailed at line 4:\n  Return(Some(Index("), "{}", dump);
+        assert!(dump.contains("  pick(), called at line 7\n"), "{}", dump);
+    }
+}
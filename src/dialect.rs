@@ -0,0 +1,421 @@
+use crate::error::Error;
+
+// Dialects are other spellings of Tidal that are translated to plain Tidal
+// before parsing. Translation keeps every newline where it was, so line N of
+// the output is line N of the original; a SourceMap records which original
+// column each output character came from, so errors point into the file the
+// user wrote rather than the translated text.
+
+// a table of words and phrases and the Tidal each one stands for; scripts
+// with the dialect's extension are translated with it
+pub struct Dialect {
+    pub name: &'static str,
+    pub extension: &'static str,
+    words: &'static [(&'static str, &'static str)],
+}
+
+// every extension here is also in cli::SCRIPT_EXTENSIONS
+pub const DIALECTS: &[Dialect] = &[
+    Dialect { name: "brainrot", extension: "br", words: BRAIN_ROT },
+    Dialect { name: "french", extension: "fr", words: FRENCH },
+];
+
+// how a script is read, from --dialect: a dialect's name translates any
+// script with it, "none" reads even a .br file as plain Tidal, and no flag
+// goes by the script's extension
+#[derive(Clone, Copy)]
+pub enum Choice {
+    ByExtension,
+    Plain,
+    Dialect(&'static Dialect),
+}
+
+pub fn choose(name: Option<&str>) -> Result<Choice, String> {
+    Ok(match name {
+        None => Choice::ByExtension,
+        Some("none") => Choice::Plain,
+        Some(name) => match DIALECTS.iter().find(|dialect| dialect.name == name) {
+            Some(dialect) => Choice::Dialect(dialect),
+            None => {
+                let names: Vec<&str> = DIALECTS.iter().map(|dialect| dialect.name).collect();
+                return Err(format!("--dialect expects none or one of: {}", names.join(", ")));
+            }
+        },
+    })
+}
+
+// the dialect a script is written in, or None for plain Tidal
+pub fn for_script(path: &str, choice: Choice) -> Option<&'static Dialect> {
+    match choice {
+        Choice::ByExtension => DIALECTS.iter().find(|dialect| path.ends_with(&format!(".{}", dialect.extension))),
+        Choice::Plain => None,
+        Choice::Dialect(dialect) => Some(dialect),
+    }
+}
+
+pub struct SourceMap {
+    // columns[line - 1][column - 1] is the original column of that output
+    // character; the last entry is the column just past the line's end
+    columns: Vec<Vec<usize>>,
+}
+
+impl SourceMap {
+    // the original position of an output line and column, both 1-based
+    pub fn original(&self, line: usize, column: usize) -> (usize, usize) {
+        let Some(columns) = line.checked_sub(1).and_then(|index| self.columns.get(index)) else {
+            return (line, column);
+        };
+        let end = columns.len() - 1;
+        match column.checked_sub(1) {
+            Some(index) if index < end => (line, columns[index]),
+            // past the end of the line, e.g. a statement missing its ';'
+            Some(index) => (line, columns[end] + (index - end)),
+            None => (line, column),
+        }
+    }
+
+    // rewrites every "line L, column C" in a message to the original position
+    pub fn remap(&self, message: &str) -> String {
+        let mut result = String::new();
+        let mut rest = message;
+        while let Some(start) = rest.find("line ") {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match self.position_at(rest) {
+                Some((line, column, length)) => {
+                    let (line, column) = self.original(line, column);
+                    result.push_str(&format!("line {}, column {}", line, column));
+                    rest = &rest[length..];
+                }
+                None => {
+                    result.push_str("line ");
+                    rest = &rest["line ".len()..];
+                }
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+
+    // the error with the positions in its message moved to the original;
+    // only the lexer and parser report columns
+    pub fn remap_error(&self, error: Error) -> Error {
+        match error {
+            Error::SyntaxError(msg) => Error::SyntaxError(self.remap(&msg)),
+            Error::LexerError(msg) => Error::LexerError(self.remap(&msg)),
+            Error::ParserError(msg) => Error::ParserError(self.remap(&msg)),
+            other => other,
+        }
+    }
+
+    // parses "line L, column C" at the start of text into (L, C, length)
+    fn position_at(&self, text: &str) -> Option<(usize, usize, usize)> {
+        let after_line = &text["line ".len()..];
+        let line_digits = after_line.find(|c: char| !c.is_ascii_digit()).unwrap_or(after_line.len());
+        let line = after_line[..line_digits].parse().ok()?;
+        let after_comma = after_line[line_digits..].strip_prefix(", column ")?;
+        let column_digits = after_comma.find(|c: char| !c.is_ascii_digit()).unwrap_or(after_comma.len());
+        let column = after_comma[..column_digits].parse().ok()?;
+        Some((line, column, text.len() - after_comma.len() + column_digits))
+    }
+}
+
+// builds the translated text and its map together
+struct Translation {
+    text: String,
+    columns: Vec<Vec<usize>>,
+}
+
+impl Translation {
+    // appends text that stands for the original at `column`
+    fn push(&mut self, text: &str, column: usize) {
+        for c in text.chars() {
+            self.text.push(c);
+            self.columns.last_mut().unwrap().push(column);
+        }
+    }
+
+    // a newline at `column` of the original ends the current line there
+    fn newline(&mut self, column: usize) {
+        self.text.push('\n');
+        self.columns.last_mut().unwrap().push(column);
+        self.columns.push(Vec::new());
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Piece<'a> {
+    Word(&'a str, usize),
+    Separator(char, usize),
+    Space(char, usize),
+    // a string or /* comment */, copied as it is
+    Literal(&'a str, usize),
+}
+
+// characters that end a word without being part of one; not + or -, which
+// Brain Rot's "aura +69420" needs
+const SEPARATORS: &str = "=(),[]{};.:!<>*/%&|";
+
+//okay, here is where the brainrot starts ☠️☠️
+const BRAIN_ROT: &[(&str, &str)] = &[
+    ("rizzler", "var"),
+    ("sigma", "novar"),
+    ("be", "="),
+    ("no cap", ";"),
+    ("skibidi", "print"),
+    ("fanum tax", "type"),
+    ("bussin", "for"),
+    ("yeet", "return"),
+    ("sussy", "/*"),
+    ("baka", "*/"),
+    ("aura +69420", "break"),
+    ("aura -69420", "continue"),
+    ("drip", "if"),
+    ("mid", "elif"),
+    ("nah", "else"),
+    ("gyatt", "true"),
+    ("diddy", "false"),
+    ("big yikes", "func"),
+    ("spill", "while"),
+    ("goat", "input"),
+    ("boogey", "import"),
+];
+
+// mostly one word for each keyword, so a script reads like the English one
+const FRENCH: &[(&str, &str)] = &[
+    ("soit", "var"),
+    ("constante", "novar"),
+    ("afficher", "print"),
+    ("sinon si", "elif"),
+    ("si", "if"),
+    ("sinon", "else"),
+    ("pour", "for"),
+    ("dans", "in"),
+    ("tant que", "while"),
+    ("arrêter", "break"),
+    ("continuer", "continue"),
+    ("fonction", "func"),
+    ("retourner", "return"),
+    ("vrai", "true"),
+    ("faux", "false"),
+    ("nul", "null"),
+    ("saisir", "input"),
+    ("longueur", "len"),
+    ("supprimer", "del"),
+    ("importer", "import"),
+    ("globale", "global"),
+    ("entier", "int"),
+    ("chaîne", "str"),
+    ("flottant", "float"),
+    ("booléen", "bool"),
+];
+
+impl Dialect {
+    // Translates a script to Tidal. Words are replaced one by one; a phrase
+    // like "no cap" matches its words separated by any whitespace, and
+    // newlines inside it are kept after the replacement so later lines do
+    // not move. Strings and comments are left as they are.
+    pub fn translate(&self, input: &str) -> (String, SourceMap) {
+        translate(self.words, input)
+    }
+}
+
+fn translate(words: &[(&str, &'static str)], input: &str) -> (String, SourceMap) {
+    let pieces = split(input);
+    let mut out = Translation { text: String::new(), columns: vec![Vec::new()] };
+    let mut end_column = 1;
+
+    let mut i = 0;
+    while i < pieces.len() {
+        match pieces[i] {
+            Piece::Word(word, column) => {
+                let (replacement, consumed) = match_phrase(words, &pieces[i..])
+                    .unwrap_or_else(|| (words.iter().find(|(slang, _)| *slang == word).map_or(word, |(_, tidal)| tidal), 1));
+                out.push(replacement, column);
+                for piece in &pieces[i + 1..i + consumed] {
+                    if let Piece::Space('\n', column) = piece {
+                        out.newline(*column);
+                    }
+                }
+                if let Piece::Word(last, column) = pieces[i + consumed - 1] {
+                    end_column = column + last.chars().count();
+                }
+                i += consumed;
+                continue;
+            }
+            Piece::Separator(c, column) => {
+                out.push(c.encode_utf8(&mut [0; 4]), column);
+                end_column = column + 1;
+            }
+            Piece::Space('\n', column) => {
+                out.newline(column);
+                end_column = 1;
+            }
+            Piece::Space(c, column) => {
+                out.push(c.encode_utf8(&mut [0; 4]), column);
+                end_column = column + 1;
+            }
+            Piece::Literal(text, mut column) => {
+                for c in text.chars() {
+                    if c == '\n' {
+                        out.newline(column);
+                        column = 1;
+                    } else {
+                        out.push(c.encode_utf8(&mut [0; 4]), column);
+                        column += 1;
+                    }
+                }
+                end_column = column;
+            }
+        }
+        i += 1;
+    }
+    out.columns.last_mut().unwrap().push(end_column);
+    (out.text, SourceMap { columns: out.columns })
+}
+
+// the replacement for a multi-word phrase starting at pieces[0], and how many
+// pieces it spans
+fn match_phrase(words: &[(&str, &'static str)], pieces: &[Piece]) -> Option<(&'static str, usize)> {
+    'phrases: for (slang, tidal) in words.iter().filter(|(slang, _)| slang.contains(' ')) {
+        let mut at = 0;
+        for (n, part) in slang.split(' ').enumerate() {
+            if n > 0 {
+                let spaces = pieces[at..].iter().take_while(|piece| matches!(piece, Piece::Space(..))).count();
+                if spaces == 0 {
+                    continue 'phrases;
+                }
+                at += spaces;
+            }
+            match pieces.get(at) {
+                Some(Piece::Word(word, _)) if word == &part => at += 1,
+                _ => continue 'phrases,
+            }
+        }
+        return Some((tidal, at));
+    }
+    None
+}
+
+// splits the input into words, separators, whitespace and literals, with
+// the column each starts at
+fn split(input: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut word_start: Option<(usize, usize)> = None;
+    let mut column = 1;
+    let mut chars = input.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let literal_end = literal_length(&input[index..]).map(|length| index + length);
+        let ends_word = literal_end.is_some() || c.is_whitespace() || SEPARATORS.contains(c);
+        if ends_word {
+            if let Some((start, start_column)) = word_start.take() {
+                pieces.push(Piece::Word(&input[start..index], start_column));
+            }
+        }
+        if let Some(end) = literal_end {
+            pieces.push(Piece::Literal(&input[index..end], column));
+            for c in input[index..end].chars() {
+                column = if c == '\n' { 1 } else { column + 1 };
+            }
+            while chars.next_if(|(i, _)| *i < end).is_some() {}
+            continue;
+        }
+        if ends_word {
+            pieces.push(if c.is_whitespace() { Piece::Space(c, column) } else { Piece::Separator(c, column) });
+        } else if word_start.is_none() {
+            word_start = Some((index, column));
+        }
+        column = if c == '\n' { 1 } else { column + 1 };
+    }
+    if let Some((start, start_column)) = word_start {
+        pieces.push(Piece::Word(&input[start..], start_column));
+    }
+    pieces
+}
+
+// the length of the string or comment text starts with, as the lexer reads
+// it: no escapes, and comments nest; an unclosed one runs to the end
+fn literal_length(text: &str) -> Option<usize> {
+    if let Some(rest) = text.strip_prefix("\"\"\"") {
+        return Some(rest.find("\"\"\"").map_or(text.len(), |end| 3 + end + 3));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        return Some(rest.find('"').map_or(text.len(), |end| 1 + end + 1));
+    }
+    if !text.starts_with("/*") {
+        return None;
+    }
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return Some(i);
+            }
+        } else {
+            i += text[i..].chars().next().unwrap().len_utf8();
+        }
+    }
+    Some(text.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn dialect(name: &str) -> &'static Dialect {
+        DIALECTS.iter().find(|dialect| dialect.name == name).unwrap()
+    }
+
+    fn translate_brain_rot(input: &str) -> (String, SourceMap) {
+        dialect("brainrot").translate(input)
+    }
+
+    #[test]
+    fn brain_rot_errors_point_into_the_original_file() {
+        let source = "rizzler greeting be \"hi\" no cap\nrizzler   count be 1 +\n  no cap";
+        let (text, map) = translate_brain_rot(source);
+        assert_eq!(text, "var greeting = \"hi\" ;\nvar   count = 1 +\n  ;");
+
+        // "be" on line 2 is column 17 of the original but 13 of the translation
+        assert_eq!(map.original(2, 13), (2, 17));
+        let (text, map) = translate_brain_rot("skibidi(1) no cap\nrizzler x be 1 & 2 no cap");
+        let error = map.remap_error(Parser::new(&text).parse().unwrap_err());
+        assert_eq!(error.to_string(), "LexerError: Unexpected character: & at line 2, column 16");
+
+        // a phrase split over lines keeps the line after it in place
+        let (text, map) = translate_brain_rot("skibidi(1) no\ncap sigma x be 2 no cap");
+        assert_eq!(text, "print(1) ;\n novar x = 2 ;");
+        assert_eq!(map.original(2, 2), (2, 5));
+        assert_eq!(map.remap("at line 2, column 15 and line 7"), "at line 2, column 24 and line 7");
+    }
+
+    #[test]
+    fn strings_and_comments_are_not_translated() {
+        let french = dialect("french");
+        let (text, _) = french.translate("si (vrai) { afficher(\"si vrai\"); } sinon si (faux) {}");
+        assert_eq!(text, "if (true) { print(\"si vrai\"); } elif (false) {}");
+        let (text, map) = french.translate("/* soit\n/* nul */ */ soit x = \"\"\"a\nsi\"\"\";tant que");
+        assert_eq!(text, "/* soit\n/* nul */ */ var x = \"\"\"a\nsi\"\"\";while");
+        // the string's second line is kept, and "while" starts at column 6
+        assert_eq!(map.original(3, 6), (3, 6));
+    }
+
+    #[test]
+    fn every_dialect_extension_is_a_script_extension() {
+        for dialect in DIALECTS {
+            assert!(crate::cli::SCRIPT_EXTENSIONS.contains(&dialect.extension), "{}", dialect.extension);
+        }
+        assert!(choose(Some("klingon")).is_err());
+        assert!(for_script("main.br", choose(Some("none")).unwrap()).is_none());
+        assert_eq!(for_script("main.td", choose(Some("french")).unwrap()).map(|dialect| dialect.name), Some("french"));
+        assert_eq!(for_script("main.br", choose(None).unwrap()).map(|dialect| dialect.name), Some("brainrot"));
+        assert!(for_script("main.td", Choice::ByExtension).is_none());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::interpreter::type_str_of_value;
+use crate::parser::{read_array, Value};
+
+// Where two values differ, for assert_eq. Arrays are compared element by
+// element and [key, value] pair lists key by key, so a failure names the
+// path of each difference the way get_path() spells it ("users[0].name")
+// instead of printing both values whole. Strings that differ get a caret
+// under the first character that does.
+
+// differences listed before the rest are only counted
+const MAX_DIFFERENCES: usize = 10;
+const MAX_VALUE_LENGTH: usize = 60;
+
+// the differences between actual and expected, one or more lines each;
+// empty when they are equal
+pub fn diff(actual: &Value, expected: &Value) -> Vec<String> {
+    let mut differences = Vec::new();
+    compare("", actual, expected, &mut differences);
+    if differences.len() > MAX_DIFFERENCES {
+        let more = differences.len() - MAX_DIFFERENCES;
+        differences.truncate(MAX_DIFFERENCES);
+        differences.push(format!("... and {} more difference{}", more, if more == 1 { "" } else { "s" }));
+    }
+    differences
+}
+
+fn compare(path: &str, actual: &Value, expected: &Value, differences: &mut Vec<String>) {
+    if actual == expected {
+        return;
+    }
+    let at = if path.is_empty() { "value".to_string() } else { path.to_string() };
+    match (actual, expected) {
+        (Value::Array(a), Value::Array(e)) => {
+            let (a, e) = (read_array(a).clone(), read_array(e).clone());
+            if let (Some(a), Some(e)) = (pairs(&a), pairs(&e)) {
+                compare_pairs(path, &a, &e, differences);
+                return;
+            }
+            for (i, (a, e)) in a.iter().zip(&e).enumerate() {
+                compare(&format!("{}[{}]", path, i), a, e, differences);
+            }
+            if a.len() != e.len() {
+                differences.push(format!("{}: expected {} elements, got {}", at, e.len(), a.len()));
+            }
+            for (i, extra) in a.iter().enumerate().skip(e.len()) {
+                differences.push(format!("{}[{}]: unexpected {}", path, i, literal(extra)));
+            }
+            for (i, missing) in e.iter().enumerate().skip(a.len()) {
+                differences.push(format!("{}[{}]: missing {}", path, i, literal(missing)));
+            }
+        }
+        (Value::String(a), Value::String(e)) => differences.push(string_difference(&at, a, e)),
+        _ if type_str_of_value(actual) != type_str_of_value(expected) => differences.push(format!(
+            "{}: expected {} {}, got {} {}",
+            at, type_str_of_value(expected), literal(expected), type_str_of_value(actual), literal(actual),
+        )),
+        _ => differences.push(format!("{}: expected {}, got {}", at, literal(expected), literal(actual))),
+    }
+}
+
+// [[key, value], ...] with string keys, as the libraries pass key/value data
+fn pairs(values: &[Value]) -> Option<Vec<(String, Value)>> {
+    if values.is_empty() {
+        return None;
+    }
+    values.iter().map(|pair| match pair {
+        Value::Array(pair) => match read_array(pair).as_slice() {
+            [Value::String(key), value] => Some((key.to_string(), value.clone())),
+            _ => None,
+        },
+        _ => None,
+    }).collect()
+}
+
+fn compare_pairs(path: &str, actual: &[(String, Value)], expected: &[(String, Value)], differences: &mut Vec<String>) {
+    let key_path = |key: &str| if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) };
+    for (key, e) in expected {
+        match actual.iter().find(|(k, _)| k == key) {
+            Some((_, a)) => compare(&key_path(key), a, e, differences),
+            None => differences.push(format!("{}: missing, expected {}", key_path(key), literal(e))),
+        }
+    }
+    for (key, a) in actual {
+        if !expected.iter().any(|(k, _)| k == key) {
+            differences.push(format!("{}: unexpected key, got {}", key_path(key), literal(a)));
+        }
+    }
+}
+
+// both strings, cut to a window around the first difference, with a caret under it
+fn string_difference(at: &str, actual: &str, expected: &str) -> String {
+    let (a, e): (Vec<char>, Vec<char>) = (actual.chars().collect(), expected.chars().collect());
+    let first = a.iter().zip(&e).take_while(|(a, e)| a == e).count();
+    let start = first.saturating_sub(MAX_VALUE_LENGTH / 2);
+    let window = |chars: &[char]| {
+        let end = chars.len().min(start + MAX_VALUE_LENGTH);
+        let text: String = chars[start.min(chars.len())..end].iter().collect();
+        format!("{}\"{}\"{}", if start > 0 { "..." } else { "" }, text, if end < chars.len() { "..." } else { "" })
+    };
+    let offset = if start > 0 { 3 } else { 0 } + 1 + (first - start);
+    format!(
+        "{}: strings differ at index {}\n    expected: {}\n    actual:   {}\n              {}^",
+        at, first, window(&e), window(&a), " ".repeat(offset),
+    )
+}
+
+// a value as it would be written in a script, cut short when long
+fn literal(value: &Value) -> String {
+    let text = match value {
+        Value::String(s) => format!("\"{}\"", s),
+        // 1.0 rather than 1, so it isn't mistaken for the int
+        Value::Float(f) if f.is_finite() && f.fract() == 0.0 => format!("{:.1}", f),
+        Value::Array(values) => format!("[{}]", read_array(values).iter().map(literal).collect::<Vec<_>>().join(", ")),
+        other => other.to_string(),
+    };
+    if text.chars().count() > MAX_VALUE_LENGTH {
+        format!("{}...", text.chars().take(MAX_VALUE_LENGTH - 3).collect::<String>())
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    fn pair(key: &str, value: Value) -> Value {
+        array(vec![string(key), value])
+    }
+
+    #[test]
+    fn differences_are_listed_by_path() {
+        let n = Value::Number;
+        assert!(diff(&array(vec![n(1)]), &array(vec![n(1)])).is_empty());
+        assert_eq!(diff(&n(1), &Value::Float(1.0)), vec!["value: expected float 1.0, got int 1"]);
+
+        let actual = array(vec![n(1), array(vec![n(2), n(9)]), n(4), n(5)]);
+        let expected = array(vec![n(1), array(vec![n(2), n(3)]), n(4)]);
+        assert_eq!(diff(&actual, &expected), vec![
+            "[1][1]: expected 3, got 9",
+            "value: expected 3 elements, got 4",
+            "[3]: unexpected 5",
+        ]);
+
+        let user = |name: &str, age: i32| array(vec![pair("name", string(name)), pair("age", n(age))]);
+        let actual = array(vec![pair("users", array(vec![user("Ada", 36)])), pair("extra", Value::Null)]);
+        let expected = array(vec![pair("users", array(vec![user("Ada", 37), user("Alan", 41)])), pair("count", n(2))]);
+        assert_eq!(diff(&actual, &expected), vec![
+            "users[0].age: expected 37, got 36",
+            "users: expected 2 elements, got 1",
+            "users[1]: missing [[\"name\", \"Alan\"], [\"age\", 41]]",
+            "count: missing, expected 2",
+            "extra: unexpected key, got null",
+        ]);
+
+        let many: Vec<Value> = (0..15).map(n).collect();
+        let shifted: Vec<Value> = (1..16).map(n).collect();
+        let differences = diff(&array(many), &array(shifted));
+        assert_eq!(differences.len(), 11);
+        assert_eq!(differences[10], "... and 5 more differences");
+    }
+
+    #[test]
+    fn strings_show_where_they_first_differ() {
+        assert_eq!(diff(&string("hello world"), &string("hello_world")), vec![concat!(
+            "value: strings differ at index 5\n",
+            "    expected: \"hello_world\"\n",
+            "    actual:   \"hello world\"\n",
+            "                    ^",
+        )]);
+        let long = "x".repeat(100);
+        let difference = &diff(&string(&format!("{}a", long)), &string(&format!("{}b", long)))[0];
+        assert!(difference.contains("    expected: ...\"xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxb\"\n"), "{}", difference);
+        assert!(difference.ends_with(&format!("{}^", " ".repeat(14 + 3 + 1 + 30))), "{}", difference);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::doctest::doc_comments;
+use crate::error::Error;
+use crate::parser::{ASTNode, Parser};
+use crate::snapshot;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// td doc: API documentation for a .tdx library, as Markdown or HTML. Each
+// function and variable is documented by the /** doc comment */ right above
+// it, or for a function without one, by the string its body starts with.
+// A doc comment that documents no declaration, such as one at the top of the
+// file, describes the library itself. __init__ and __teardown__ are left out.
+
+struct Page {
+    name: String,
+    about: Vec<String>,
+    functions: Vec<Item>,
+    variables: Vec<Item>,
+}
+
+struct Item {
+    signature: String,
+    doc: Vec<String>,
+}
+
+fn page(name: &str, source: &str) -> Result<Page, Error> {
+    let ast = Parser::new(source).with_lines().parse()?;
+    let mut comments = doc_comments(source);
+    let mut page = Page { name: name.to_string(), about: Vec::new(), functions: Vec::new(), variables: Vec::new() };
+
+    for node in &ast {
+        let ASTNode::Located(line, node) = node else { continue };
+        let doc = comments.iter().position(|comment| comment.next_line == *line)
+            .map(|index| trim_blank(comments.remove(index).lines));
+        match node.as_ref() {
+            ASTNode::FunctionDecl(name, _, _) if name.starts_with("__") && name.ends_with("__") => {}
+            ASTNode::FunctionDecl(name, params, body) => {
+                let first = body.first().map(|node| match node {
+                    ASTNode::Located(_, node) => node.as_ref(),
+                    node => node,
+                });
+                let doc = doc.unwrap_or_else(|| match first {
+                    Some(ASTNode::String(doc)) => doc.lines().map(str::to_string).collect(),
+                    _ => Vec::new(),
+                });
+                page.functions.push(Item { signature: format!("{}({})", name, params.join(", ")), doc });
+            }
+            ASTNode::Var(name, initializer, is_mutable) => {
+                let keyword = if *is_mutable { "var" } else { "novar" };
+                let signature = match initializer {
+                    Some(value) => format!("{} {} = {}", keyword, name, snapshot::expr(value)?),
+                    None => format!("{} {}", keyword, name),
+                };
+                page.variables.push(Item { signature, doc: doc.unwrap_or_default() });
+            }
+            _ => {}
+        }
+    }
+    // what is left documents no declaration
+    if let Some(comment) = comments.into_iter().next() {
+        page.about = trim_blank(comment.lines);
+    }
+    Ok(page)
+}
+
+fn trim_blank(mut lines: Vec<String>) -> Vec<String> {
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let leading = lines.iter().take_while(|line| line.is_empty()).count();
+    lines.split_off(leading)
+}
+
+fn markdown(page: &Page) -> String {
+    let mut out = format!("# {}\n", page.name);
+    if !page.about.is_empty() {
+        out.push_str(&format!("\n{}\n", page.about.join("\n")));
+    }
+    for (title, items) in [("Functions", &page.functions), ("Variables", &page.variables)] {
+        if items.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\n## {}\n", title));
+        for item in items {
+            out.push_str(&format!("\n### `{}`\n", item.signature));
+            if !item.doc.is_empty() {
+                out.push_str(&format!("\n{}\n", item.doc.join("\n")));
+            }
+        }
+    }
+    out
+}
+
+fn html(page: &Page) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        escape(&page.name),
+    );
+    out.push_str(&html_text(&page.about));
+    for (title, items) in [("Functions", &page.functions), ("Variables", &page.variables)] {
+        if items.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("<h2>{}</h2>\n", title));
+        for item in items {
+            out.push_str(&format!("<h3><code>{}</code></h3>\n", escape(&item.signature)));
+            out.push_str(&html_text(&item.doc));
+        }
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+// doc text as HTML: paragraphs split by blank lines, and ``` fences as code
+fn html_text(lines: &[String]) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    let end_paragraph = |paragraph: &mut Vec<&str>, out: &mut String| {
+        if !paragraph.is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", escape(&paragraph.join("\n"))));
+            paragraph.clear();
+        }
+    };
+    for line in lines {
+        match code.as_mut() {
+            Some(block) if line.trim() == "```" => {
+                out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&block.join("\n"))));
+                code = None;
+            }
+            Some(block) => block.push(line),
+            None if line.trim().starts_with("```") => {
+                end_paragraph(&mut paragraph, &mut out);
+                code = Some(Vec::new());
+            }
+            None if line.trim().is_empty() => end_paragraph(&mut paragraph, &mut out),
+            None => paragraph.push(line),
+        }
+    }
+    end_paragraph(&mut paragraph, &mut out);
+    // an unclosed fence still shows its code
+    if let Some(block) = code {
+        out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&block.join("\n"))));
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// td doc <lib.tdx> [--out dir] [--format md|html]: writes dir/<lib>.md or .html
+pub fn run(path: &str, out: Option<&str>, format: Option<&str>) -> i32 {
+    match write(path, out.unwrap_or("docs"), format.unwrap_or("md")) {
+        Ok(target) => {
+            outln!("Wrote {}", target.display());
+            0
+        }
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            1
+        }
+    }
+}
+
+fn write(path: &str, out: &str, format: &str) -> Result<PathBuf, String> {
+    let format = match format {
+        "md" | "markdown" => "md",
+        "html" => "html",
+        other => return Err(format!("--format expects md or html, not '{}'", other)),
+    };
+    let name = path.strip_suffix(".tdx")
+        .and_then(|stem| Path::new(stem).file_name())
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("'{}' must be a .tdx library", path))?;
+    let source = fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path, e))?;
+    let page = page(name, &source).map_err(|e| e.to_string())?;
+
+    let dir = Path::new(out);
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create '{}': {}", dir.display(), e))?;
+    let target = dir.join(format!("{}.{}", name, format));
+    let text = if format == "html" { html(&page) } else { markdown(&page) };
+    fs::write(&target, text).map_err(|e| format!("cannot write '{}': {}", target.display(), e))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn libraries_are_documented_from_their_doc_comments() {
+        let source = r#"/** Shapes & sizes. */
+
+/**
+ * The area of a circle.
+ * ```td
+ * print(shapes.area(1) > 3);
+ * # => true
+ * ```
+ */
+func area(r) { return r * r * 3.14159; }
+
+func square(x) { "Squares x."; return x * x; }
+
+/** How many sides a square has. */
+novar SIDES = 2 + 2;
+func __init__() { }
+"#;
+        let page = page("shapes", source).unwrap();
+        assert_eq!(markdown(&page), "# shapes\n\nShapes & sizes.\n\n## Functions\n\n### `area(r)`\n\nThe area of a circle.\n```td\nprint(shapes.area(1) > 3);\n# => true\n```\n\n### `square(x)`\n\nSquares x.\n\n## Variables\n\n### `novar SIDES = 2 + 2`\n\nHow many sides a square has.\n");
+        let html = html(&page);
+        assert!(html.contains("<p>Shapes &amp; sizes.</p>"), "{}", html);
+        assert!(html.contains("<h3><code>area(r)</code></h3>\n<p>The area of a circle.</p>\n<pre><code>print(shapes.area(1) &gt; 3);\n# =&gt; true</code></pre>\n"), "{}", html);
+    }
+}
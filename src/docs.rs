@@ -7,6 +7,19 @@ use colored::*;
 #[folder = "src/embedded_docs/"]
 struct DocAssets;
 
+// one page per error code, written for td explain
+#[derive(RustEmbed)]
+#[folder = "src/error_docs/"]
+struct ErrorDocs;
+
+// the longer text td help <command> shows after the options
+#[derive(RustEmbed)]
+#[folder = "src/command_docs/"]
+struct CommandDocs;
+
+// set by build.rs, which fetches the pages from the same wiki
+const WIKI_URL: &str = env!("TIDAL_WIKI_URL");
+
 #[derive(Debug, Clone)]
 pub struct RuntimeWikiPage {
     pub title: String,
@@ -27,16 +40,25 @@ pub fn fetch_docs() -> Result<Vec<RuntimeWikiPage>, Box<dyn Error>> {
         }
     }
 
+    // embed order is not guaranteed; keep page numbers stable between builds
+    pages.sort_by(|a, b| a.title.cmp(&b.title));
     Ok(pages)
 }
 
 pub fn display_docs(pages: &[RuntimeWikiPage], page_num: usize) {
+    if pages.is_empty() {
+        print_missing();
+        return;
+    }
     if page_num == 0 || page_num > pages.len() {
         println!("{}", "Invalid page number!".red());
         return;
     }
 
-    let page = &pages[page_num - 1];
+    print_page(&pages[page_num - 1]);
+}
+
+pub fn print_page(page: &RuntimeWikiPage) {
     println!("\n{}", format!("# {}", page.title).bright_green());
     println!("{}", "-".repeat(40));
 
@@ -44,7 +66,30 @@ pub fn display_docs(pages: &[RuntimeWikiPage], page_num: usize) {
     skin.print_text(&page.content);
 }
 
+// look a page up by number or title; "control flow", "Control_Flow" and
+// "control-flow" all name the same page, and a unique prefix is enough
+pub fn find_page<'a>(pages: &'a [RuntimeWikiPage], topic: &str) -> Option<&'a RuntimeWikiPage> {
+    if let Ok(num) = topic.parse::<usize>() {
+        return num.checked_sub(1).and_then(|i| pages.get(i));
+    }
+
+    let normalize = |s: &str| s.to_lowercase().replace(['_', '-'], " ");
+    let topic = normalize(topic.trim());
+    if let Some(page) = pages.iter().find(|p| normalize(&p.title) == topic) {
+        return Some(page);
+    }
+    let mut matches = pages.iter().filter(|p| normalize(&p.title).starts_with(&topic));
+    match (matches.next(), matches.next()) {
+        (Some(page), None) => Some(page),
+        _ => None,
+    }
+}
+
 pub fn list_pages(pages: &[RuntimeWikiPage]) {
+    if pages.is_empty() {
+        print_missing();
+        return;
+    }
     println!("\n{}", "Available Documentation Pages:".bright_green());
     println!("{}", "-".repeat(40));
     
@@ -53,3 +98,45 @@ pub fn list_pages(pages: &[RuntimeWikiPage]) {
     }
     println!("\nUse 'td docs --pg <number>' to view a specific page");
 }
+
+// nothing was vendored in src/embedded_docs when this was built
+fn print_missing() {
+    println!("{}", "No documentation pages are bundled with this build.".yellow());
+    println!("Read them online at {}", WIKI_URL);
+}
+
+// the extended explanation of an error code, like E0007
+pub fn explanation(code: &str) -> Option<String> {
+    ErrorDocs::get(&format!("{}.md", code)).map(|file| String::from_utf8_lossy(file.data.as_ref()).into_owned())
+}
+
+// the page for a subcommand, like bench
+pub fn command_doc(name: &str) -> Option<String> {
+    CommandDocs::get(&format!("{}.md", name)).map(|file| String::from_utf8_lossy(file.data.as_ref()).into_owned())
+}
+
+pub fn print_explanation(text: &str) {
+    MadSkin::default().print_text(text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_error_code_is_explained() {
+        for code in crate::error::CODES {
+            let text = explanation(code).unwrap_or_else(|| panic!("{} has no page in src/error_docs", code));
+            assert!(text.starts_with(&format!("# {}: ", code)), "{} starts with {:?}", code, text.lines().next());
+        }
+        assert_eq!(ErrorDocs::iter().count(), crate::error::CODES.len());
+    }
+
+    #[test]
+    fn wiki_pages_are_vendored() {
+        let pages = fetch_docs().unwrap();
+        assert_eq!(pages.len(), 13);
+        assert_eq!(find_page(&pages, "control-flow").unwrap().title, "Control Flow");
+        assert!(find_page(&pages, "brain rot").unwrap().content.contains("rizzler"));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::input::CannedInput;
+use crate::interpreter::{Session, Settings};
+use crate::output::CaptureOutput;
+use crate::parser::Parser;
+use std::fs;
+
+// td test --doc: runs the examples in a file's doc comments. A doc comment
+// starts with /** and may hold fenced ```td blocks; each block runs in a
+// fresh session, and a `# => text` line after some code asserts what that
+// code printed, one annotation per printed line:
+//   /**
+//    * Squares a number.
+//    * ```td
+//    * import(shapes);
+//    * print(shapes.square(3));
+//    * # => 9
+//    * ```
+//    */
+// Imports in an example resolve next to the file, as they would in a script
+// there.
+
+pub struct DocComment {
+    // line of the /** that opens it
+    pub line: usize,
+    // the comment's lines, without the leading * that continues each one
+    pub lines: Vec<String>,
+    // line of whatever follows the comment, which is what it documents
+    pub next_line: usize,
+}
+
+// the /** doc comments */ in a source file, skipping strings and plain comments
+pub fn doc_comments(source: &str) -> Vec<DocComment> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut comments = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // strings have no escapes; a triple-quoted one ends only at """
+            '"' => {
+                let quotes = if chars[i..].starts_with(&['"'; 3]) { 3 } else { 1 };
+                i += quotes;
+                while i < chars.len() && !chars[i..].starts_with(&['"'; 3][..quotes]) {
+                    if chars[i] == '\n' {
+                        line += 1;
+                    }
+                    i += 1;
+                }
+                i += quotes;
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let start_line = line;
+                let is_doc = chars.get(i + 2) == Some(&'*') && chars.get(i + 3) != Some(&'/');
+                let body_start = i + if is_doc { 3 } else { 2 };
+                // comments nest, as they do in the lexer
+                let mut depth = 1;
+                i = body_start;
+                while i < chars.len() && depth > 0 {
+                    match (chars[i], chars.get(i + 1)) {
+                        ('*', Some('/')) => {
+                            depth -= 1;
+                            i += 1;
+                        }
+                        ('/', Some('*')) => {
+                            depth += 1;
+                            i += 1;
+                        }
+                        ('\n', _) => line += 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                if is_doc {
+                    let body: String = chars[body_start..i.saturating_sub(2).max(body_start)].iter().collect();
+                    let mut next_line = line;
+                    for c in chars[i..].iter().take_while(|c| c.is_whitespace()) {
+                        if *c == '\n' {
+                            next_line += 1;
+                        }
+                    }
+                    comments.push(DocComment { line: start_line, lines: body.lines().map(strip_margin).collect(), next_line });
+                }
+                continue;
+            }
+            '\n' => line += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    comments
+}
+
+// " * text" -> "text"
+fn strip_margin(line: &str) -> String {
+    let line = line.trim_start();
+    let line = line.strip_prefix('*').unwrap_or(line);
+    line.strip_prefix(' ').unwrap_or(line).trim_end().to_string()
+}
+
+pub struct DocTest {
+    // line of the ```td fence
+    pub line: usize,
+    steps: Vec<Step>,
+}
+
+// some code, and what it should print if it is followed by `# =>` lines
+struct Step {
+    line: usize,
+    code: String,
+    expected: Option<Vec<String>>,
+}
+
+pub fn doc_tests(source: &str) -> Vec<DocTest> {
+    let mut tests = Vec::new();
+    for comment in doc_comments(source) {
+        let mut current: Option<DocTest> = None;
+        for (offset, text) in comment.lines.iter().enumerate() {
+            let line = comment.line + offset;
+            let trimmed = text.trim();
+            let Some(test) = current.as_mut() else {
+                if trimmed == "```td" {
+                    current = Some(DocTest { line, steps: Vec::new() });
+                }
+                continue;
+            };
+            if trimmed == "```" {
+                tests.extend(current.take());
+            } else if let Some(expected) = trimmed.strip_prefix("# =>") {
+                let expected = expected.strip_prefix(' ').unwrap_or(expected).to_string();
+                match test.steps.last_mut() {
+                    Some(Step { expected: Some(lines), .. }) => lines.push(expected),
+                    Some(step) => step.expected = Some(vec![expected]),
+                    None => test.steps.push(Step { line, code: String::new(), expected: Some(vec![expected]) }),
+                }
+            } else {
+                match test.steps.last_mut() {
+                    Some(step) if step.expected.is_none() => {
+                        step.code.push('\n');
+                        step.code.push_str(text);
+                    }
+                    _ => test.steps.push(Step { line, code: text.clone(), expected: None }),
+                }
+            }
+        }
+    }
+    tests
+}
+
+// Runs one example from the file at `path`, which external libraries are
+// looked up next to; the error says which line failed and how
+pub fn run(test: &DocTest, path: &str) -> Result<(), String> {
+    let capture = CaptureOutput::new();
+    let settings = Settings { script: Some(path.to_string()), ..Settings::default() };
+    let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default())).with_settings(settings);
+    for step in &test.steps {
+        // blank lines in front make parse errors report the file's own lines
+        let code = format!("{}{}", "\n".repeat(step.line - 1), step.code);
+        Parser::new(&code).parse()
+            .and_then(|ast| session.eval(ast))
+            .map_err(|e| format!("line {}: {}", step.line, e))?;
+        let (printed, _) = capture.take();
+        if let Some(expected) = &step.expected {
+            let printed: Vec<&str> = printed.lines().collect();
+            if printed != *expected {
+                return Err(format!(
+                    "line {}: expected\n{}\n  but it printed\n{}",
+                    step.line, indent(expected), indent(&printed),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn indent<S: AsRef<str>>(lines: &[S]) -> String {
+    if lines.is_empty() {
+        return "    (nothing)".to_string();
+    }
+    lines.iter().map(|line| format!("    {}", line.as_ref())).collect::<Vec<_>>().join("\n")
+}
+
+// td test --doc <file>: runs every example and returns the exit code
+pub fn run_file(path: &str) -> i32 {
+    if !path.ends_with(".td") && !path.ends_with(".tdx") {
+        errln!("Error: '{}' must be a .td script or a .tdx library", path);
+        return 1;
+    }
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            errln!("Error: cannot read '{}': {}", path, e);
+            return 1;
+        }
+    };
+
+    let tests = doc_tests(&source);
+    let mut failed = 0;
+    for test in &tests {
+        match run(test, path) {
+            Ok(()) => outln!("doc test {}:{} ... ok", path, test.line),
+            Err(message) => {
+                failed += 1;
+                outln!("doc test {}:{} ... FAILED", path, test.line);
+                outln!("  {}", message);
+            }
+        }
+    }
+    outln!("");
+    outln!("{} passed, {} failed", tests.len() - failed, failed);
+    if failed > 0 { 1 } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doc_examples_are_checked_against_their_annotations() {
+        let source = r#"
+var banner = "/** not a comment */" + """ "/** nor this */" """ + "\";
+/* a plain comment /** with a nested one */ */
+/**
+ * Adds one.
+ * ```td
+ * print(1 + 1);
+ * # => 2
+ * var xs = [1, 2];
+ * for (var i = 0; i < 2; i = i + 1) { print(xs[i]); }
+ * # => 1
+ * # => 2
+ * ```
+ */
+func inc(x) { return x + 1; }
+
+/** ```td
+print("three");
+# => 3
+``` */
+"#;
+        let comments = doc_comments(source);
+        assert_eq!(comments.len(), 2);
+        assert_eq!((comments[0].line, comments[0].next_line), (4, 15));
+        assert_eq!(comments[0].lines[1], "Adds one.");
+
+        let tests = doc_tests(source);
+        assert_eq!(tests.iter().map(|test| test.line).collect::<Vec<_>>(), vec![6, 17]);
+        assert_eq!(run(&tests[0], "math.tdx"), Ok(()));
+        assert_eq!(run(&tests[1], "math.tdx"), Err("line 18: expected\n    3\n  but it printed\n    three".to_string()));
+    }
+}
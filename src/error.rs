@@ -25,9 +25,66 @@ pub enum Error {
     InvalidFunctionArguments(String, usize, usize),
     InvalidIndex,
     LibraryError(String),
+    // lib.name = value on a library constant that is not settable
+    ImmutableConstant(String),
     ReturnOutsideFunction,
     UnexpectedValue(String),
     UnsupportedUnaryOperation,
+    AssertionFailed(String),
+    // a host stopped the run through its CancellationToken
+    Cancelled,
+    // not a failure: unwinds the interpreter so main can flush and exit with the code
+    Exit(i32),
+    // a value too big to make, like "a" * 2000000000, refused before allocating it
+    ResourceLimit(String),
+    // td transpile: a target it can't write, or a construct with no equivalent in the target
+    CannotTranspile(String),
+}
+
+// Every variant's code, in the order of the enum. Codes are shown with
+// errors and explained by td explain, so they are never renumbered or
+// reused: a new variant takes the next one.
+pub const CODES: &[&str] = &[
+    "E0001", "E0002", "E0003", "E0004", "E0005", "E0006", "E0007", "E0008", "E0009", "E0010",
+    "E0011", "E0012", "E0013", "E0014", "E0015", "E0016", "E0017", "E0018", "E0019", "E0020",
+    "E0021", "E0022", "E0023", "E0024", "E0025", "E0026", "E0027", "E0028", "E0029", "E0030",
+];
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::SyntaxError(..) => "E0001",
+            Error::IndexOutOfBounds(..) => "E0002",
+            Error::VariableNotDeclared(..) => "E0003",
+            Error::VariableAlreadyDeclared(..) => "E0004",
+            Error::TypeError(..) => "E0005",
+            Error::UnsupportedOperation(..) => "E0006",
+            Error::BreakOutsideLoop => "E0007",
+            Error::ContinueOutsideLoop => "E0008",
+            Error::FileNotFound(..) => "E0009",
+            Error::InvalidFileExtension(..) => "E0010",
+            Error::LexerError(..) => "E0011",
+            Error::ParserError(..) => "E0012",
+            Error::InterpreterError(..) => "E0013",
+            Error::UnknownError(..) => "E0014",
+            Error::CannotGetLength(..) => "E0015",
+            Error::DelRequiresVariableName => "E0016",
+            Error::FunctionCallError(..) => "E0017",
+            Error::InvalidArrayIdentifier => "E0018",
+            Error::InvalidFunctionArguments(..) => "E0019",
+            Error::InvalidIndex => "E0020",
+            Error::LibraryError(..) => "E0021",
+            Error::ImmutableConstant(..) => "E0022",
+            Error::ReturnOutsideFunction => "E0023",
+            Error::UnexpectedValue(..) => "E0024",
+            Error::UnsupportedUnaryOperation => "E0025",
+            Error::AssertionFailed(..) => "E0026",
+            Error::Cancelled => "E0027",
+            Error::Exit(..) => "E0028",
+            Error::ResourceLimit(..) => "E0029",
+            Error::CannotTranspile(..) => "E0030",
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -55,9 +112,15 @@ impl fmt::Display for Error {
                 write!(f, "Function '{}' expects {} arguments but got {}", name, expected, got),
             Error::InvalidIndex => write!(f, "Expected integer index in array assignment"),
             Error::LibraryError(msg) => write!(f, "Library error: {}", msg),
+            Error::ImmutableConstant(msg) => write!(f, "ImmutableConstant: {}", msg),
             Error::ReturnOutsideFunction => write!(f, "'return' outside function"),
             Error::UnexpectedValue(msg) => write!(f, "Unexpected value: {}", msg),
             Error::UnsupportedUnaryOperation => write!(f, "Unsupported unary operation"),
+            Error::AssertionFailed(msg) => write!(f, "AssertionFailed: {}", msg),
+            Error::Cancelled => write!(f, "Cancelled: the script was stopped by its host"),
+            Error::Exit(code) => write!(f, "exit({})", code),
+            Error::ResourceLimit(msg) => write!(f, "ResourceLimit: {}", msg),
+            Error::CannotTranspile(msg) => write!(f, "CannotTranspile: {}", msg),
         }
     }
 }
@@ -0,0 +1,223 @@
+use crate::lexer::{Token, KEYWORDS};
+use crate::parser::{Parser, UNARY_MINUS_PRECEDENCE};
+use crate::snapshot::op_source;
+
+// td grammar: Tidal's syntax as ISO EBNF. Statements and factors mirror the
+// parse_* functions they are named after; the binary expression levels and
+// the keyword list are built from the parser's own precedence table and the
+// lexer's KEYWORDS, so a new operator or keyword shows up here by itself.
+
+// every token that is written as an operator, in the order the levels list them
+const OPERATORS: &[Token] = &[
+    Token::Or, Token::And,
+    Token::Equal, Token::NotEqual, Token::Less, Token::LessEqual, Token::Greater, Token::GreaterEqual,
+    Token::Plus, Token::Minus,
+    Token::Multiply, Token::Divide, Token::FloorDivide, Token::Modulus,
+    Token::Power,
+    Token::Not,
+];
+
+// what the rule for each precedence level is called, loosest first
+const LEVEL_NAMES: &[(u8, &str)] = &[
+    (1, "or_expression"),
+    (2, "and_expression"),
+    (3, "comparison"),
+    (4, "sum"),
+    (5, "term"),
+    (UNARY_MINUS_PRECEDENCE, "power"),
+];
+
+const STATEMENTS: &str = r#"program = { statement } ;
+
+(* parse_bare_statement: the first token decides the kind of statement *)
+statement = var_declaration
+          | "print" , arguments , ";"
+          | if_statement
+          | for_loop
+          | while_loop
+          | "break" , ";"
+          | "continue" , ";"
+          | "type" , "(" , expression , ")" , ";"
+          | function_declaration
+          | "return" , [ expression ] , ";"
+          | del_call , ";"
+          | import
+          | "global" , identifier , { "," , identifier } , ";"
+          | literal_statement
+          | name_statement ;
+
+var_declaration = ( "var" | "novar" ) , identifier , [ "=" , expression ] , ";" ;
+
+if_statement = "if" , "(" , expression , ")" , block ,
+               { "elif" , "(" , expression , ")" , block } ,
+               [ "else" , block ] ;
+
+while_loop = "while" , "(" , expression , ")" , block ;
+
+(* "in" is a word only in a for-in loop; elsewhere it is a name like any other *)
+for_loop = "for" , "(" , ( var_declaration | assignment ) , expression , ";" , assignment , ")" , block
+         | "for" , "(" , identifier , "in" , expression , ")" , block ;
+
+(* parse_assign_stmt, as the for loop uses it *)
+assignment = identifier , [ index_target ] , "=" , expression , [ ";" ] ;
+
+(* a comma between parameters may be left out *)
+function_declaration = "func" , identifier , "(" , { parameter , [ "," ] } , ")" , block ;
+
+(* a pattern's names become variables at the top of the body *)
+parameter = identifier
+          | "[" , [ identifier , { "," , identifier } ] , [ [ "," ] , "..." , identifier ] , "]"
+          | "{" , [ identifier , { "," , identifier } ] , "}" ;
+
+import = "import" , "(" , identifier , [ version_requirement ] , [ "," , ( "embedded" | "external" ) ] , ")" , ";" ;
+
+version_requirement = ( "==" | "<" | "<=" | ">" | ">=" ) , string ;
+
+(* only an expression that starts with a number, a string, true, false or "["
+   makes a statement on its own *)
+literal_statement = expression , [ ";" ] ;
+
+name_statement = identifier , ( arguments , ";"
+                              | "=" , expression , ";"
+                              | index_target , "=" , expression , ";"
+                              | "." , member , ( arguments , ";" | "=" , expression , ";" | ";" ) ) ;
+
+(* a[i][j] = x writes into a[i] at j *)
+index_target = "[" , expression , "]" , { "[" , expression , "]" } ;
+
+block = "{" , { statement } , "}" ;
+"#;
+
+const FACTORS: &str = r#"(* parse_factor; an operand of "-" runs up to the next operator looser than "**" *)
+factor = "input" , "(" , expression , ")"
+       | "len" , "(" , expression , ")"
+       | del_call
+       | "-" , {minus_operand}
+       | "!" , factor
+       | integer
+       | float
+       | "(" , expression , ")"
+       | array
+       | primary ;
+
+(* parse_primary *)
+primary = ( string
+          | boolean
+          | "null"
+          | type_name
+          | type_name , "(" , expression , ")"
+          | "type" , "(" , expression , ")"
+          | "include_str" , "(" , string , ")"
+          | identifier , [ arguments | "." , member , [ arguments ] ] ) ,
+          { index } ;
+
+del_call = "del" , "(" , expression , ")" ;
+
+(* s[i], or a slice s[start:stop:step] with any part left out *)
+index = "[" , ( expression | [ expression ] , ":" , [ expression ] , [ ":" , [ expression ] ] ) , "]" ;
+
+(* positional arguments come before the ones passed by name *)
+arguments = "(" , [ argument , { "," , argument } ] , ")" ;
+argument = "..." , expression | identifier , "=" , expression | expression ;
+
+array = "[" , [ [ "..." ] , expression , { "," , [ "..." ] , expression } ] , "]" ;
+
+(* after "lib.", keywords are names too: std.print, std.len *)
+member = identifier | keyword ;
+"#;
+
+const TOKENS: &str = r#"(* lexer *)
+identifier = ( "_" | ? Unicode XID_Start ? ) , { ? Unicode XID_Continue ? } - keyword ;
+type_name = "int" | "str" | "float" | "bool" ;
+boolean = "true" | "false" ;
+integer = digit , { digit } ;
+float = digit , { digit } , "." , { digit } , [ exponent ]
+      | "." , digit , { digit } , [ exponent ]
+      | digit , { digit } , exponent ;
+exponent = ( "e" | "E" ) , [ "+" | "-" ] , digit , { digit } ;
+digit = "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" ;
+(* backslashes are never escapes, so the r prefix changes nothing *)
+string = [ "r" ] , '"' , { ? any character but '"' ? } , '"'
+       | '"""' , { ? any character ? } , '"""' ;
+(* comments nest, and are skipped like whitespace *)
+comment = "/*" , { comment | ? any character ? } , "*/" ;
+"#;
+
+// the binary operators the parser folds in at this precedence
+fn operators_at(precedence: u8) -> Vec<&'static str> {
+    OPERATORS.iter()
+        .filter(|op| Parser::infix_precedence(op).is_some_and(|(p, _)| p == precedence))
+        .map(op_source)
+        .collect()
+}
+
+fn level_name(precedence: u8) -> &'static str {
+    LEVEL_NAMES.iter().find(|(p, _)| *p == precedence).map(|(_, name)| *name)
+        .unwrap_or_else(|| panic!("no rule name for precedence {} in grammar::LEVEL_NAMES", precedence))
+}
+
+fn quoted(words: &[&str]) -> String {
+    words.iter().map(|word| format!("\"{}\"", word)).collect::<Vec<_>>().join(" | ")
+}
+
+// the expression rules, one per precedence level, from parse_binary's table
+fn expressions() -> String {
+    let mut levels: Vec<u8> = OPERATORS.iter().filter_map(Parser::infix_precedence).map(|(p, _)| p).collect();
+    levels.sort();
+    levels.dedup();
+
+    let mut out = format!("expression = {} ;\n", level_name(levels[0]));
+    for (i, &precedence) in levels.iter().enumerate() {
+        let name = level_name(precedence);
+        let operand = levels.get(i + 1).map_or("factor", |&next| level_name(next));
+        let ops = operators_at(precedence);
+        let op = if ops.len() == 1 { quoted(&ops) } else { format!("( {} )", quoted(&ops)) };
+        let right_assoc = OPERATORS.iter().any(|token| Parser::infix_precedence(token) == Some((precedence, true)));
+        if right_assoc {
+            out.push_str(&format!("(* right associative: a {0} b {0} c is a {0} (b {0} c) *)\n", ops[0]));
+            out.push_str(&format!("{} = {} , [ {} , {} ] ;\n", name, operand, op, name));
+        } else {
+            if ops.iter().all(|op| ["==", "!=", "<", "<=", ">", ">="].contains(op)) {
+                out.push_str("(* a chain like 1 < x < 10 compares each neighbouring pair, as in Python *)\n");
+            }
+            out.push_str(&format!("{} = {} , {{ {} , {} }} ;\n", name, operand, op, operand));
+        }
+    }
+    out
+}
+
+pub fn ebnf() -> String {
+    let mut out = format!("(* Tidal {} grammar, ISO/IEC 14977 EBNF, from `td grammar` *)\n\n", env!("CARGO_PKG_VERSION"));
+    out.push_str(STATEMENTS);
+    out.push('\n');
+    out.push_str(&expressions());
+    out.push('\n');
+    out.push_str(&FACTORS.replace("{minus_operand}", level_name(UNARY_MINUS_PRECEDENCE)));
+    out.push('\n');
+    out.push_str(TOKENS);
+    out.push_str(&format!("keyword = {} ;\n", quoted(KEYWORDS)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_grammar_covers_every_keyword_and_operator() {
+        let grammar = ebnf();
+        for word in KEYWORDS {
+            let mentions = grammar.matches(&format!("\"{}\"", word)).count();
+            // once in the keyword rule, and at least once where it is used
+            assert!(mentions >= 2, "{} is never used", word);
+        }
+        for op in OPERATORS {
+            assert!(grammar.contains(&format!("\"{}\"", op_source(op))), "{:?}", op);
+        }
+        assert!(grammar.contains("expression = or_expression ;\n"));
+        assert!(grammar.contains("sum = term , { ( \"+\" | \"-\" ) , term } ;\n"));
+        assert!(grammar.contains("term = power , { ( \"*\" | \"/\" | \"//\" | \"%\" ) , power } ;\n"));
+        assert!(grammar.contains("power = factor , [ \"**\" , power ] ;\n"));
+        assert!(grammar.contains("| \"-\" , power\n"));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::error::Error;
+use crate::parser::{ASTNode, Parser};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// td graph: the import graph of a script, as Graphviz DOT or a Mermaid
+// flowchart. Imports resolve the way the interpreter resolves them: an
+// embedded library first, then name.tdx in the script's directory, which is
+// also where a .tdx library's own imports are looked up. Libraries that
+// can't be found and circular imports are reported as warnings and drawn
+// in red.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Script,
+    Embedded,
+    External,
+    Missing,
+}
+
+struct Node {
+    name: String,
+    kind: Kind,
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    // like >= "1.0"
+    requirement: Option<String>,
+    in_cycle: bool,
+}
+
+struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    warnings: Vec<String>,
+}
+
+// a library import somewhere in a script: name, mode, requirement and line
+type Import = (String, Option<String>, Option<String>, usize);
+
+// imports anywhere in the statements, including inside blocks and functions
+fn imports(nodes: &[ASTNode], line: usize, found: &mut Vec<Import>) {
+    for node in nodes {
+        match node {
+            ASTNode::Located(line, node) => imports(std::slice::from_ref(node.as_ref()), *line, found),
+            ASTNode::Import(name, mode, requirement) => {
+                let requirement = requirement.as_ref().map(|(op, version)| format!("{} \"{}\"", crate::interpreter::op_symbol(op), version));
+                found.push((name.clone(), mode.clone(), requirement, line));
+            }
+            ASTNode::If(_, then, elifs, otherwise) => {
+                imports(then, line, found);
+                for (_, body) in elifs {
+                    imports(body, line, found);
+                }
+                if let Some(body) = otherwise {
+                    imports(body, line, found);
+                }
+            }
+            ASTNode::For(_, _, _, body) | ASTNode::ForEach(_, _, body) | ASTNode::While(_, body) | ASTNode::FunctionDecl(_, _, body) => imports(body, line, found),
+            _ => {}
+        }
+    }
+}
+
+fn build(script: &str, ast: &[ASTNode], embedded: &[&str]) -> Graph {
+    let dir = Path::new(script).parent().map(Path::to_path_buf).unwrap_or_default();
+    let name = Path::new(script).file_name().map_or(script.to_string(), |name| name.to_string_lossy().into_owned());
+    let mut graph = Graph { nodes: vec![Node { name: name.clone(), kind: Kind::Script }], edges: Vec::new(), warnings: Vec::new() };
+
+    let mut found = Vec::new();
+    imports(ast, 0, &mut found);
+    let mut queue = VecDeque::from([(0, name, found)]);
+    while let Some((from, importer, found)) = queue.pop_front() {
+        for (name, mode, requirement, line) in found {
+            let to = match graph.nodes.iter().position(|node| node.kind != Kind::Script && node.name == name) {
+                Some(to) => to,
+                None => {
+                    let path = dir.join(format!("{}.tdx", name));
+                    let is_embedded = embedded.contains(&name.as_str());
+                    let kind = match mode.as_deref() {
+                        Some("embedded") if is_embedded => Kind::Embedded,
+                        Some("external") if path.exists() => Kind::External,
+                        None if is_embedded => Kind::Embedded,
+                        None if path.exists() => Kind::External,
+                        _ => Kind::Missing,
+                    };
+                    if kind == Kind::Missing {
+                        graph.warnings.push(format!("{}:{} imports '{}', which was not found", importer, line, name));
+                    }
+                    graph.nodes.push(Node { name: name.clone(), kind });
+                    let to = graph.nodes.len() - 1;
+                    if kind == Kind::External {
+                        match read_library(&path) {
+                            Ok(ast) => {
+                                let mut found = Vec::new();
+                                imports(&ast, 0, &mut found);
+                                queue.push_back((to, format!("{}.tdx", name), found));
+                            }
+                            Err(e) => graph.warnings.push(format!("{}.tdx cannot be read: {}", name, e)),
+                        }
+                    }
+                    to
+                }
+            };
+            if !graph.edges.iter().any(|edge| edge.from == from && edge.to == to) {
+                graph.edges.push(Edge { from, to, requirement, in_cycle: false });
+            }
+        }
+    }
+    mark_cycles(&mut graph);
+    graph
+}
+
+fn read_library(path: &PathBuf) -> Result<Vec<ASTNode>, Error> {
+    let source = fs::read_to_string(path).map_err(|e| Error::FileNotFound(e.to_string()))?;
+    Parser::new(&source).with_lines().parse()
+}
+
+// marks the edges on every cycle and warns once per cycle found
+fn mark_cycles(graph: &mut Graph) {
+    // 0 = not visited, 1 = on the current path, 2 = done
+    let mut state = vec![0u8; graph.nodes.len()];
+    let mut path = Vec::new();
+    for start in 0..graph.nodes.len() {
+        if state[start] == 0 {
+            visit(graph, start, &mut state, &mut path);
+        }
+    }
+}
+
+fn visit(graph: &mut Graph, node: usize, state: &mut [u8], path: &mut Vec<usize>) {
+    state[node] = 1;
+    path.push(node);
+    for index in 0..graph.edges.len() {
+        if graph.edges[index].from != node {
+            continue;
+        }
+        let to = graph.edges[index].to;
+        match state[to] {
+            0 => visit(graph, to, state, path),
+            1 => {
+                let start = path.iter().position(|&n| n == to).unwrap();
+                let cycle = &path[start..];
+                for edge in graph.edges.iter_mut() {
+                    let on_cycle = cycle.iter().position(|&n| n == edge.from)
+                        .is_some_and(|i| cycle.get(i + 1).copied().unwrap_or(to) == edge.to);
+                    edge.in_cycle |= on_cycle;
+                }
+                let names: Vec<&str> = cycle.iter().chain([&to]).map(|&n| graph.nodes[n].name.as_str()).collect();
+                graph.warnings.push(format!("circular import: {}", names.join(" -> ")));
+            }
+            _ => {}
+        }
+    }
+    path.pop();
+    state[node] = 2;
+}
+
+fn label(node: &Node) -> String {
+    match node.kind {
+        Kind::External => format!("{}.tdx", node.name),
+        _ => node.name.clone(),
+    }
+}
+
+fn dot(graph: &Graph) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut out = String::from("digraph imports {\n");
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let style = match node.kind {
+            Kind::Script => "shape=box, style=bold",
+            Kind::External => "shape=box",
+            Kind::Embedded => "shape=ellipse",
+            Kind::Missing => "shape=box, style=dashed, color=red",
+        };
+        out.push_str(&format!("    n{} [label={}, {}];\n", i, quote(&label(node)), style));
+    }
+    for edge in &graph.edges {
+        let mut attributes = Vec::new();
+        if let Some(requirement) = &edge.requirement {
+            attributes.push(format!("label={}", quote(requirement)));
+        }
+        if edge.in_cycle {
+            attributes.push("color=red".to_string());
+        }
+        let attributes = if attributes.is_empty() { String::new() } else { format!(" [{}]", attributes.join(", ")) };
+        out.push_str(&format!("    n{} -> n{}{};\n", edge.from, edge.to, attributes));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid(graph: &Graph) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+    let mut out = String::from("graph LR\n");
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let shape = match node.kind {
+            Kind::Embedded => format!("([{}])", quote(&label(node))),
+            _ => format!("[{}]", quote(&label(node))),
+        };
+        out.push_str(&format!("    n{}{}\n", i, shape));
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        match &edge.requirement {
+            Some(requirement) => out.push_str(&format!("    n{} -->|{}| n{}\n", edge.from, quote(requirement), edge.to)),
+            None => out.push_str(&format!("    n{} --> n{}\n", edge.from, edge.to)),
+        }
+        if edge.in_cycle {
+            out.push_str(&format!("    linkStyle {} stroke:red\n", i));
+        }
+    }
+    for (i, node) in graph.nodes.iter().enumerate() {
+        if node.kind == Kind::Missing {
+            out.push_str(&format!("    style n{} stroke:red,stroke-dasharray:4\n", i));
+        }
+    }
+    out
+}
+
+// td graph <script> [--dot | --mermaid]
+pub fn run(script: &str, is_mermaid: bool) -> i32 {
+    let ast = match crate::parse_script(script, true, crate::dialect::Choice::ByExtension) {
+        Ok(ast) => ast,
+        Err(e) => {
+            eprintln!("\x1b[31m{}\x1b[0m", e);
+            return 1;
+        }
+    };
+    let graph = build(script, &ast, &crate::libs::embedded_libraries());
+    for warning in &graph.warnings {
+        errln!("\x1b[33mWarning: {}\x1b[0m", warning);
+    }
+    out!("{}", if is_mermaid { mermaid(&graph) } else { dot(&graph) });
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_are_graphed_with_cycles_and_missing_libraries() {
+        let dir = std::env::temp_dir().join(format!("tidal_graph_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ping.tdx"), "import(math);\nfunc f() { import(pong); }").unwrap();
+        std::fs::write(dir.join("pong.tdx"), "import(ping, external);").unwrap();
+        let script = dir.join("main.td");
+        let source = "import(ping >= \"1.0\");\nif (true) { import(nowhere); }\nimport(math);";
+        let ast = Parser::new(source).with_lines().parse().unwrap();
+        let graph = build(script.to_str().unwrap(), &ast, &["std", "math"]);
+
+        assert_eq!(dot(&graph), "digraph imports {
+    n0 [label=\"main.td\", shape=box, style=bold];
+    n1 [label=\"ping.tdx\", shape=box];
+    n2 [label=\"nowhere\", shape=box, style=dashed, color=red];
+    n3 [label=\"math\", shape=ellipse];
+    n4 [label=\"pong.tdx\", shape=box];
+    n0 -> n1 [label=\">= \\\"1.0\\\"\"];
+    n0 -> n2;
+    n0 -> n3;
+    n1 -> n3;
+    n1 -> n4 [color=red];
+    n4 -> n1 [color=red];
+}
+");
+        assert_eq!(graph.warnings, vec![
+            "main.td:2 imports 'nowhere', which was not found",
+            "circular import: ping -> pong -> ping",
+        ]);
+        assert!(mermaid(&graph).contains("    n0 -->|\">= #quot;1.0#quot;\"| n1\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use crate::cli::{Command, Flag, Operand, COMMANDS, GLOBAL_FLAGS, RUN_FLAGS};
+
+// td help: the overview and each command's own page, generated from the
+// tables in cli.rs the way the shell completions are. A command's page is
+// followed by the longer text in src/command_docs/<name>.md.
+
+// environment variables td itself reads
+const ENVIRONMENT: &[(&str, &str)] = &[
+    ("NO_COLOR", "Print errors without color"),
+    ("TIDAL_RELEASES_URL", "Releases API that td upgrade asks, such as a mirror"),
+];
+
+const EXAMPLES: &[(&str, &str)] = &[
+    ("td main.td input.txt", "Run main.td with input.txt as its first argument"),
+    ("td main.td --seed 42 --trace-out trace.jsonl", "Run with repeatable random numbers, tracing each statement"),
+    ("td bench bench.td --baseline base.json", "Time the bench_* functions, failing if one got slower"),
+    ("td help run", "Everything about one command"),
+];
+
+// a long list of choices shows only its first; the command's page lists them
+fn choices(choices: &[&str]) -> String {
+    let all = choices.join("|");
+    if all.len() <= 16 { all } else { format!("{}|...", choices[0]) }
+}
+
+fn operand(command: &Command) -> String {
+    match command.operand {
+        Operand::None => String::new(),
+        Operand::Script => " <script>".to_string(),
+        Operand::Choice(options) => format!(" <{}>", choices(options)),
+        Operand::OptionalChoice(options) => format!(" [{}]", choices(options)),
+        Operand::File(placeholder) => format!(" <{}>", placeholder),
+    }
+}
+
+fn usage(command: &Command) -> String {
+    let mut usage = format!("td {}{}", command.name, operand(command));
+    if !command.flags.is_empty() {
+        usage.push_str(" [options]");
+    }
+    if command.name == "run" {
+        usage.push_str(" [script args]");
+    }
+    usage
+}
+
+fn flag_label(flag: &Flag) -> String {
+    let mut label = format!("--{}", flag.long);
+    if let Some(c) = flag.short {
+        label.push_str(&format!(", -{}", c));
+    }
+    if let Some(placeholder) = flag.value {
+        label.push_str(&format!(" <{}>", placeholder));
+    }
+    label
+}
+
+// two columns, the second lined up after the longest label
+fn table(rows: &[(String, &str)]) -> String {
+    let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0) + 2;
+    rows.iter().map(|(label, text)| format!("  {:width$}{}\n", label, text, width = width)).collect()
+}
+
+fn flag_table(flags: &[Flag]) -> String {
+    table(&flags.iter().map(|flag| (flag_label(flag), flag.help)).collect::<Vec<_>>())
+}
+
+pub fn overview() -> String {
+    let mut out = String::from("\nTidal Programming Language\nMade by Pranav Verma - For the Lagoon Project.\n\n");
+    out.push_str("Usage: td <command> [options] | td <script> [options] [script args]\n\nCommands:\n");
+    out.push_str(&table(&COMMANDS.iter()
+        .map(|command| (format!("{}{}", command.name, operand(command)), command.help))
+        .collect::<Vec<_>>()));
+    out.push_str("\nOptions for run (anywhere before the script's own arguments):\n");
+    out.push_str(&flag_table(RUN_FLAGS));
+    out.push_str("\nOptions for any command:\n");
+    out.push_str(&flag_table(GLOBAL_FLAGS));
+    out.push_str("\nEnvironment:\n");
+    out.push_str(&table(&ENVIRONMENT.iter().map(|(name, text)| (name.to_string(), *text)).collect::<Vec<_>>()));
+    out.push_str("\nExamples:\n");
+    out.push_str(&table(&EXAMPLES.iter().map(|(example, text)| (example.to_string(), *text)).collect::<Vec<_>>()));
+    out.push_str("\nRun 'td help <command>' for a command's options and examples.\n");
+    out
+}
+
+// td help <command>: its usage and options; main prints its
+// src/command_docs page after this
+pub fn command_page(command: &Command) -> String {
+    let mut out = format!("\ntd {}: {}\n\nUsage: {}\n", command.name, command.help, usage(command));
+    if !command.flags.is_empty() {
+        out.push_str("\nOptions:\n");
+        out.push_str(&flag_table(command.flags));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, COMMAND_NAMES};
+
+    #[test]
+    fn help_lists_every_command_and_flag() {
+        let names: Vec<&str> = COMMANDS.iter().map(|command| command.name).collect();
+        assert_eq!(names, COMMAND_NAMES);
+
+        let overview = overview();
+        for command in COMMANDS {
+            assert!(overview.contains(&format!("\n  {}", command.name)), "{}", command.name);
+            for flag in command.flags {
+                assert!(command_page(command).contains(&flag_label(flag)), "td {} --{}", command.name, flag.long);
+            }
+            assert!(crate::docs::command_doc(command.name).is_some(), "{} has no page in src/command_docs", command.name);
+        }
+        assert!(overview.contains("  --debug-dump <file>  If a runtime error"));
+        assert!(overview.contains("  explain <E0001|...>"));
+        assert_eq!(usage(&COMMANDS[0]), "td run <script> [options] [script args]");
+    }
+
+    #[test]
+    fn help_after_a_command_is_that_commands_help() {
+        let args = |line: &str| line.split(' ').map(String::from).collect::<Vec<_>>();
+        let invocation = cli::parse(&args("bench --help")).unwrap();
+        assert_eq!((invocation.command.name, invocation.operands), ("help", vec!["bench".to_string()]));
+        let invocation = cli::parse(&args("help transpile")).unwrap();
+        assert_eq!(invocation.operands, vec!["transpile"]);
+        assert!(cli::parse(&args("help")).unwrap().operands.is_empty());
+        assert!(cli::parse(&args("help nope")).is_err());
+        // after the script, --help is the script's
+        assert_eq!(cli::parse(&args("main.td --help")).unwrap().script_args, vec!["--help"]);
+    }
+}
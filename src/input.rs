@@ -0,0 +1,77 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io;
+
+// Where input() reads lines from. Defaults to stdin; a Session installs its
+// own provider here while it evaluates.
+pub trait InputProvider {
+    // next line without its trailing newline, or None at end of input
+    fn read_line(&mut self) -> Option<String>;
+}
+
+pub struct StdinInput;
+
+impl InputProvider for StdinInput {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+        }
+    }
+}
+
+// Hands out a fixed list of lines, then end of input.
+#[derive(Default)]
+pub struct CannedInput {
+    lines: VecDeque<String>,
+}
+
+impl CannedInput {
+    pub fn new<I, S>(lines: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        CannedInput {
+            lines: lines.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl InputProvider for CannedInput {
+    fn read_line(&mut self) -> Option<String> {
+        self.lines.pop_front()
+    }
+}
+
+thread_local! {
+    static INPUT: RefCell<Box<dyn InputProvider>> = RefCell::new(Box::new(StdinInput));
+    static LINES_READ: Cell<usize> = const { Cell::new(0) };
+}
+
+// returns the previous provider so callers can restore it
+pub fn set_input(input: Box<dyn InputProvider>) -> Box<dyn InputProvider> {
+    INPUT.with(|i| std::mem::replace(&mut *i.borrow_mut(), input))
+}
+
+// prints the prompt, then reads a line; None at end of input
+pub fn read_line(prompt: &str) -> Option<String> {
+    crate::output::write_stdout(prompt);
+    crate::output::flush();
+    LINES_READ.with(|n| n.set(n.get() + 1));
+    INPUT.with(|i| i.borrow_mut().read_line())
+}
+
+// lines asked for so far, so --loop-guard can tell a loop waiting on input
+// from a stuck one
+pub fn lines_read() -> usize {
+    LINES_READ.with(Cell::get)
+}
+
+// like read_line(), but trimmed and with end of input read as ""
+pub fn prompt(prompt: &str) -> String {
+    read_line(prompt)
+        .map(|line| line.trim().to_string())
+        .unwrap_or_default()
+}
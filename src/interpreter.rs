@@ -1,22 +1,164 @@
-use crate::parser::{ASTNode, Value, Parser};
+use crate::parser::{ASTNode, Value, Parser, lock_array, read_array};
 use crate::lexer::Token;
 use crate::error::Error;
-
-use crate::libs::Library;
+use crate::input::{self, CannedInput, InputProvider, StdinInput};
+use crate::output::{self, CaptureOutput, Output, StdStreams};
+use crate::signals;
+use crate::trace;
+use crate::crash_dump;
+use crate::summary;
+
+use crate::libs::{Function, Library};
 use crate::libs::std::StdLib;
-use crate::libs::math::MathLib;
-use crate::libs::sys::SysLib;
-use crate::libs::os::OSLib;
-use crate::libs::io::IOLib;
-use crate::libs::mem::MemLib;
 
+use std::cell::{Cell, RefCell};
 use std::sync::{Arc, Mutex};
-use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashMap, HashSet};
+use indexmap::IndexMap;
 use std::fmt;
+use std::time::{Duration, Instant};
+
+// Stack for the thread scripts run on. Parsing and evaluating a script nested
+// as deeply as the parser allows needs a few MB in debug builds, more than
+// the main thread gets on some platforms.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// How a script runs: what td's run flags set, or what an embedder chooses.
+// Each run() and Session owns its settings and lends them to the thread while
+// its statements run, as a Session does with its streams, so two sessions on
+// one thread don't share them. Library callbacks (http.serve handlers, signal
+// handlers, os.watch) run on the thread of the run that made the call, and
+// with its settings.
+#[derive(Clone)]
+pub struct Settings {
+    // --warn prints warnings; --strict also makes int/float == an error
+    pub warnings: bool,
+    pub strict: bool,
+    // --loop-guard: iterations a loop may run with its condition unchanged
+    pub loop_guard: Option<usize>,
+    pub max_allocation: usize,
+    // the script being run, as given; external libraries are looked up next
+    // to it, and the args and sys libraries report it
+    pub script: Option<String>,
+    pub script_args: Vec<String>,
+    // --seed and --fake-time, for the random and time libraries
+    pub seed: Option<u64>,
+    pub fake_time: Option<f64>,
+    // the token the run checks; None lets it run to the end
+    pub cancellation: Option<CancellationToken>,
+}
+
+// The most bytes one string repetition or array repetition may produce. The
+// size is known before anything is allocated, so a script asking for more
+// gets a ResourceLimit error instead of the process running out of memory.
+pub const DEFAULT_MAX_ALLOCATION: usize = 256 * 1024 * 1024;
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            warnings: false,
+            strict: false,
+            loop_guard: None,
+            max_allocation: DEFAULT_MAX_ALLOCATION,
+            script: None,
+            script_args: Vec::new(),
+            seed: None,
+            fake_time: None,
+            cancellation: None,
+        }
+    }
+}
+
+// the settings of the run in progress on this thread, or the defaults
+pub fn settings<T>(read: impl FnOnce(&Settings) -> T) -> T {
+    SETTINGS.with(|settings| read(&settings.borrow()))
+}
+
+// makes `settings` the thread's own until they are handed back, returning
+// the ones they replace
+fn lend_settings(settings: Settings) -> Settings {
+    SETTINGS.with(|current| std::mem::replace(&mut *current.borrow_mut(), settings))
+}
+
+fn check_allocation(bytes: Option<usize>, what: impl FnOnce() -> String) -> Result<(), Error> {
+    let max = settings(|settings| settings.max_allocation);
+    match bytes {
+        Some(bytes) if bytes <= max => Ok(()),
+        _ => {
+            let max = if max.is_multiple_of(1024 * 1024) { format!("{} MB", max / (1024 * 1024)) } else { format!("{} bytes", max) };
+            Err(Error::ResourceLimit(format!("{} would take more than the {} one value may use", what(), max)))
+        }
+    }
+}
+
+// Lets a host stop a script from another thread, e.g. a playground killing
+// a runaway evaluation: the run ends with Error::Cancelled before the next
+// node it would evaluate. Clones share one flag, so the host keeps a clone
+// and puts the other in the run's Settings. A library call that blocks
+// (http.serve, time.sleep) finishes first.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// What a script function handed to native code needs in order to run: the
+// functions and libraries visible where the library call was made, and the
+// script's top-level variables, lent for the call so callbacks can use
+// `global`. Pushed around library calls that receive a function, read by
+// call_function().
+struct CallContext {
+    functions: IndexMap<String, Value>,
+    libraries: IndexMap<String, Box<dyn Library>>,
+    is_verbose: bool,
+    globals: Option<HashMap<String, (Value, bool)>>,
+}
+
+thread_local! {
+    static CALL_CONTEXT: RefCell<Vec<CallContext>> = const { RefCell::new(Vec::new()) };
+    // external libraries being loaded, outermost first, to catch import cycles
+    static LOADING_LIBRARIES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    // line of the statement being run, when the parser recorded lines
+    static CURRENT_LINE: Cell<usize> = const { Cell::new(0) };
+    static SETTINGS: RefCell<Settings> = RefCell::new(Settings::default());
+    static EMITTED_WARNINGS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    // the std.name stubs a bare builtin name resolves to, made once
+    static STD_FUNCTIONS: IndexMap<String, Value> = StdLib::new().get_function_map().keys()
+        .map(|name| (name.clone(), Value::Function(format!("std.{}", name).into(), Arc::default(), Arc::default())))
+        .collect();
+}
+
+fn std_function(name: &str) -> Option<Value> {
+    STD_FUNCTIONS.with(|functions| functions.get(name).cloned())
+}
+
+// each distinct warning is printed once per run: once per script, per REPL
+// entry and per notebook cell. The warnings already printed are kept per
+// thread, like the state above, and forget_warnings() starts them over as a
+// run or Session entry begins
+fn forget_warnings() {
+    EMITTED_WARNINGS.with(|emitted| emitted.borrow_mut().clear());
+}
 
-lazy_static! {
-    static ref FUNCTION_CACHE: Mutex<HashMap<String, Arc<Box<dyn Fn(Vec<Value>) -> Result<Value, Error> + Send + Sync>>>> = Mutex::new(HashMap::new());
+fn warn(message: &str) {
+    if !settings(|settings| settings.warnings) {
+        return;
+    }
+    if EMITTED_WARNINGS.with(|emitted| emitted.borrow_mut().insert(message.to_string())) {
+        errln!("\x1b[33mWarning: {}\x1b[0m", message);
+    }
 }
 
 impl fmt::Display for Value {
@@ -31,7 +173,7 @@ impl fmt::Display for Value {
             Value::Break => write!(f, "break"),
             Value::Continue => write!(f, "continue"),
             Value::Array(arr) => {
-                let guard = arr.lock().unwrap();
+                let guard = read_array(arr);
                 write!(f, "[")?;
                 for (i, value) in guard.iter().enumerate() {
                     if i > 0 { write!(f, ", ")?; }
@@ -56,10 +198,16 @@ impl Value {
 
 pub struct Environment {
     scopes: Vec<HashMap<String, (Value, bool)>>,
-    functions: HashMap<String, Value>,
+    functions: IndexMap<String, Value>,
     in_function: bool,
-    libraries: HashMap<String, Box<dyn Library>>,
+    libraries: IndexMap<String, Box<dyn Library>>,
     parent: Option<Box<Environment>>,
+    // the script's top-level scope, lent to a running function
+    globals: Option<HashMap<String, (Value, bool)>>,
+    declared: HashSet<String>, // names declared `global` in this function
+    // how many libraries were loaded when each block scope opened; libraries
+    // imported inside the block are dropped when it closes
+    import_marks: Vec<usize>,
 }
 
 impl Clone for Environment {
@@ -70,29 +218,32 @@ impl Clone for Environment {
             in_function: self.in_function,
             libraries: self.libraries.iter().map(|(k, v)| (k.clone(), v.box_clone())).collect(),
             parent: self.parent.clone(),
+            globals: self.globals.clone(),
+            declared: self.declared.clone(),
+            import_marks: self.import_marks.clone(),
         }
     }
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Environment {
     pub fn new() -> Self {
         let mut env = Environment {
             scopes: vec![HashMap::new()],
-            functions: HashMap::new(),
+            functions: IndexMap::new(),
             in_function: false,
-            libraries: HashMap::new(),
+            libraries: IndexMap::new(),
             parent: None,
+            globals: None,
+            declared: HashSet::new(),
+            import_marks: Vec::new(),
         };
 
-        let std_lib = StdLib::new();
-        for (name,_func) in std_lib.get_function_map().iter() {
-            env.functions.insert(name.clone(), Value::Function(
-                format!("std.{}", name), 
-                vec![],
-                vec![]
-            ));
-        }
-
         env.libraries.insert("std".to_string(), Box::new(StdLib::new()));
         env
     }
@@ -101,9 +252,9 @@ impl Environment {
     fn new_with_parent(parent: Environment) -> Self {
         let mut env = Environment {
             scopes: vec![HashMap::new()],
-            functions: HashMap::new(),
+            functions: IndexMap::new(),
             in_function: false,
-            libraries: HashMap::new(),
+            libraries: IndexMap::new(),
             parent: Some(Box::new(parent)),
         };
         env
@@ -112,13 +263,43 @@ impl Environment {
 
     pub fn push_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        self.import_marks.push(self.libraries.len());
     }
 
     pub fn pop_scope(&mut self) {
         self.scopes.pop();
+        // libraries keep their import order, so the block's own imports are the tail
+        if let Some(mark) = self.import_marks.pop() {
+            self.unload_libraries_from(mark);
+        }
+    }
+
+    fn unload_libraries_from(&mut self, mark: usize) {
+        while self.libraries.len() > mark {
+            if let Some((name, lib)) = self.libraries.pop() {
+                if let Err(e) = lib.teardown() {
+                    errln!("\x1b[31mError while unloading library '{}': {}\x1b[0m", name, e);
+                }
+            }
+        }
+    }
+
+    // runs every library's teardown, newest first; the first failure is
+    // returned after the rest have had their turn
+    pub fn teardown_libraries(&mut self) -> Result<(), Error> {
+        let mut first_error = None;
+        while let Some((_, lib)) = self.libraries.pop() {
+            if let Err(e) = lib.teardown() {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
     }
 
     pub fn get(&self, name: &str) -> Option<&(Value, bool)> {
+        if self.declared.contains(name) {
+            return self.globals.as_ref().and_then(|g| g.get(name));
+        }
         for scope in self.scopes.iter().rev() {
             if let Some(value) = scope.get(name) {
                 return Some(value);
@@ -128,6 +309,9 @@ impl Environment {
     }
 
     pub fn get_mut(&mut self, name: &str) -> Option<&mut (Value, bool)> {
+        if self.declared.contains(name) {
+            return self.globals.as_mut().and_then(|g| g.get_mut(name));
+        }
         for scope in self.scopes.iter_mut().rev() {
             if let Some(value) = scope.get_mut(name) {
                 return Some(value);
@@ -136,6 +320,57 @@ impl Environment {
         None
     }
 
+    // move the script's top-level scope into a function env for the duration
+    // of a call, so `global` declarations inside it can reach the variables
+    fn lend_globals_to(&mut self, func_env: &mut Environment) {
+        func_env.globals = self.take_globals();
+    }
+
+    fn restore_globals_from(&mut self, func_env: &mut Environment) {
+        let globals = func_env.globals.take();
+        self.return_globals(globals);
+    }
+
+    fn take_globals(&mut self) -> Option<HashMap<String, (Value, bool)>> {
+        if self.in_function {
+            self.globals.take()
+        } else {
+            self.scopes.first_mut().map(std::mem::take)
+        }
+    }
+
+    fn return_globals(&mut self, globals: Option<HashMap<String, (Value, bool)>>) {
+        if self.in_function {
+            self.globals = globals;
+        } else if let (Some(globals), Some(top)) = (globals, self.scopes.first_mut()) {
+            *top = globals;
+        }
+    }
+
+    fn declare_global(&mut self, name: &str) -> Result<(), Error> {
+        if !self.in_function {
+            return Err(Error::SyntaxError("'global' declaration outside function".to_string()));
+        }
+        if self.scopes.iter().any(|scope| scope.contains_key(name)) {
+            return Err(Error::SyntaxError(format!(
+                "Variable '{}' is a local and cannot also be declared 'global'", name
+            )));
+        }
+        if !self.globals.as_ref().is_some_and(|g| g.contains_key(name)) {
+            return Err(Error::VariableNotDeclared(format!("Variable '{}' not found in global scope", name)));
+        }
+        self.declared.insert(name.to_string());
+        Ok(())
+    }
+
+    // a script-level name used inside a function that never declared it `global`
+    fn not_declared_message(&self, name: &str) -> String {
+        if self.in_function && !self.declared.contains(name) && self.globals.as_ref().is_some_and(|g| g.contains_key(name)) {
+            return format!("{} (declare it with 'global {};' to use the script-level variable)", name, name);
+        }
+        name.to_string()
+    }
+
     pub fn insert_var(&mut self, name: String, value: Value, mutable: bool) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(name, (value, mutable));
@@ -146,6 +381,71 @@ impl Environment {
         self.functions.insert(name, value);
     }
 
+    // What a call to `name` runs: a local holding a function, then a script
+    // function (here or in an enclosing call), then a std builtin. std.name
+    // always reaches the builtin.
+    fn resolve_function(&self, name: &str) -> Option<Value> {
+        if let Some((func @ Value::Function(..), _)) = self.get(name) {
+            return Some(func.clone());
+        }
+        self.user_function(name).cloned().or_else(|| std_function(name))
+    }
+
+    fn user_function(&self, name: &str) -> Option<&Value> {
+        match self.functions.get(name) {
+            Some(func) => Some(func),
+            None => self.parent.as_ref().and_then(|parent| parent.user_function(name)),
+        }
+    }
+
+    // every script function a call made from here can see, for the callee's
+    // parent; builtins aren't copied, they are looked up in STD_FUNCTIONS
+    fn visible_functions(&self) -> IndexMap<String, Value> {
+        let mut functions = match &self.parent {
+            Some(parent) => parent.visible_functions(),
+            None => IndexMap::new(),
+        };
+        functions.extend(self.functions.iter().map(|(name, func)| (name.clone(), func.clone())));
+        functions
+    }
+
+    // the top-level variables, script functions and imports
+    pub fn definitions(&self) -> Definitions {
+        let mut variables: Vec<(String, Value, bool)> = self.scopes.first()
+            .map(|scope| scope.iter().map(|(name, (value, mutable))| (name.clone(), value.clone(), *mutable)).collect())
+            .unwrap_or_default();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        let functions = self.functions.iter()
+            .filter_map(|(name, func)| match func {
+                Value::Function(full_name, params, _) if !full_name.contains('.') => Some((name.clone(), params.len())),
+                _ => None,
+            })
+            .collect();
+        let libraries = self.libraries.keys().filter(|name| *name != "std").cloned().collect();
+        Definitions { variables, functions, libraries }
+    }
+
+    // every variable this environment sees, outermost scope first, for --debug-dump
+    fn dump_scopes(&self) -> Vec<crash_dump::Scope> {
+        let show = |scope: &HashMap<String, (Value, bool)>| {
+            let mut variables: Vec<_> = scope.iter()
+                .map(|(name, (value, is_mutable))| (name.clone(), crash_dump::show(value), *is_mutable))
+                .collect();
+            variables.sort();
+            variables
+        };
+        let mut scopes: Vec<crash_dump::Scope> = self.globals.iter().map(|globals| ("script".to_string(), show(globals))).collect();
+        for (i, scope) in self.scopes.iter().enumerate() {
+            let name = match (i, self.in_function) {
+                (0, false) => "script",
+                (0, true) => "function",
+                _ => "block",
+            };
+            scopes.push((name.to_string(), show(scope)));
+        }
+        scopes
+    }
+
     pub fn has_library(&self, name: &str) -> bool {
         if self.libraries.contains_key(name) {
             return true;
@@ -179,31 +479,18 @@ impl Environment {
             ));
         }
 
+        // a second import is a no-op, so a helper library can import what it
+        // needs without knowing whether the script already did
         if self.has_library(name) {
-            return Err(Error::InterpreterError(format!("Library '{}' is already imported", name)));
+            return Ok(());
         }
 
         match mode {
             Some("embedded") => {
                 if !self.libraries.contains_key(name) {
-                    match name {
-                        "math" => {
-                            self.libraries.insert(name.to_string(), Box::new(MathLib::new()));
-                        }
-                        "sys" => {
-                            self.libraries.insert(name.to_string(), Box::new(SysLib::new()));
-                        }
-                        "os" => {
-                            self.libraries.insert(name.to_string(), Box::new(OSLib::new()));
-                        }
-                        "io" => {
-                            self.libraries.insert(name.to_string(), Box::new(IOLib::new()));
-                        }
-                        "mem" => {
-                            self.libraries.insert(name.to_string(), Box::new(MemLib::new()));
-                        }
-                        _ => return Err(Error::InterpreterError("Embedded library not found".to_string()))
-                    };
+                    let lib = crate::libs::load_embedded(name)
+                        .ok_or_else(|| Error::InterpreterError("Embedded library not found".to_string()))?;
+                    self.libraries.insert(name.to_string(), lib);
                 }
             }
             Some("external") => {
@@ -225,41 +512,113 @@ impl Environment {
         Ok(())
     }
 
-    fn load_external_library(&mut self, name: &str) -> Result<(), Error> {
-        let args: Vec<String> = std::env::args().collect();
-        if args.len() < 2 {
-            return Err(Error::FileNotFound("No source file specified".to_string()));
-        }
-    
-        let source_path = std::path::Path::new(&args[1]);
-        let source_dir = source_path.parent()
-            .ok_or_else(|| Error::FileNotFound("Could not determine source file directory".to_string()))?;
-    
-        let lib_filename = format!("{}.tdx", name);
-        let lib_path = source_dir.join(&lib_filename);
-    
-        if !lib_path.exists() {
-            return Err(Error::FileNotFound(format!("External library '{}' not found", name)));
+    // import(name >= "1.0"): the library states its version with
+    // `novar __version__ = "1.2.0";`
+    pub fn check_library_version(&self, name: &str, op: &Token, required: &str) -> Result<(), Error> {
+        let wanted = parse_version(required)?;
+        let installed = match self.libraries.get(name).and_then(|lib| lib.get_constant("__version__")) {
+            Some(Value::String(version)) => version.clone(),
+            Some(other) => return Err(Error::LibraryError(format!("Library '{}' has a __version__ of type {}, expected str", name, type_str_of_value(other)))),
+            None => return Err(Error::LibraryError(format!("Library '{}' does not declare a __version__, but {} \"{}\" is required", name, op_symbol(op), required))),
+        };
+        let ordering = parse_version(&installed)?.cmp(&wanted);
+        let ok = match op {
+            Token::GreaterEqual => ordering.is_ge(),
+            Token::Greater => ordering.is_gt(),
+            Token::Equal => ordering.is_eq(),
+            Token::LessEqual => ordering.is_le(),
+            Token::Less => ordering.is_lt(),
+            _ => return Err(Error::LibraryError(format!("Unsupported version comparison {:?}", op))),
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(Error::LibraryError(format!(
+                "Library '{}' {} is installed, but {} \"{}\" is required", name, installed, op_symbol(op), required
+            )))
         }
-    
-        let contents = std::fs::read_to_string(&lib_path)
-            .map_err(|_| Error::FileNotFound(format!("Failed to read library file '{}'", lib_path.display())))?;
-    
-        let mut parser = Parser::new(&contents);
-        let ast = parser.parse()?;
-        
-        let mut lib = ExternalLibrary::new(ast);
-        lib.initialize()?;
+    }
 
+    fn load_external_library(&mut self, name: &str) -> Result<(), Error> {
+        let lib = read_external_library(name)?;
         self.libraries.insert(name.to_string(), Box::new(lib));
         Ok(())
     }
+
+    // std.reload("mylib"): the .tdx is read and initialized again, and only
+    // once that succeeds is the old copy torn down and replaced, so a typo
+    // while editing leaves the working version in place
+    pub fn reload_library(&mut self, name: &str) -> Result<(), Error> {
+        if name == "std" || !self.libraries.contains_key(name) {
+            return Err(Error::LibraryError(format!("Library '{}' is not an imported external library", name)));
+        }
+        let lib = read_external_library(name)?;
+        if let Some(old) = self.libraries.insert(name.to_string(), Box::new(lib)) {
+            old.teardown()?;
+        }
+        Ok(())
+    }
+}
+
+// where import(name) looks for an external library; without a script (the
+// REPL) that is the current directory
+fn external_library_path(name: &str) -> std::path::PathBuf {
+    let source_dir = settings(|settings| settings.script.as_ref().and_then(|path| std::path::Path::new(path).parent().map(|dir| dir.to_path_buf())))
+        .unwrap_or_default();
+    source_dir.join(format!("{}.tdx", name))
+}
+
+// lib.name used without import(lib); when an import would have found the
+// library, the hint spells it out so it can be pasted in as it is
+fn library_not_found(lib_name: &str) -> Error {
+    let message = format!("Library '{}' not found", lib_name);
+    if crate::libs::embedded_libraries().contains(&lib_name) || external_library_path(lib_name).exists() {
+        Error::InterpreterError(format!("{}\nhint: add import({}); before using it", message, lib_name))
+    } else {
+        Error::InterpreterError(message)
+    }
+}
+
+fn read_external_library(name: &str) -> Result<ExternalLibrary, Error> {
+    let lib_path = external_library_path(name);
+    let source_dir = lib_path.parent().map(std::path::Path::to_path_buf).unwrap_or_default();
+
+    if !lib_path.exists() {
+        return Err(Error::FileNotFound(format!("External library '{}' not found", name)));
+    }
+
+    let contents = std::fs::read_to_string(&lib_path)
+        .map_err(|_| Error::FileNotFound(format!("Failed to read library file '{}'", lib_path.display())))?;
+    summary::read(&lib_path);
+
+    let mut parser = Parser::new(&contents).with_base_dir(source_dir);
+    let ast = parser.parse()?;
+
+    let cycle = LOADING_LIBRARIES.with(|loading| {
+        let mut loading = loading.borrow_mut();
+        if loading.iter().any(|loaded| loaded == name) {
+            let mut chain = loading.clone();
+            chain.push(name.to_string());
+            return Some(chain.join(" -> "));
+        }
+        loading.push(name.to_string());
+        None
+    });
+    if let Some(chain) = cycle {
+        return Err(Error::LibraryError(format!("Circular library import: {}", chain)));
+    }
+
+    let mut lib = ExternalLibrary::new(ast);
+    let loaded = lib.initialize().and_then(|_| lib.call_hook("__init__"));
+    LOADING_LIBRARIES.with(|loading| loading.borrow_mut().pop());
+    loaded?;
+    Ok(lib)
 }
 
 
 
 pub struct ExternalLibrary {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+    functions: HashMap<String, Function>,
     variables: HashMap<String, (Value, bool)>,
     ast: Vec<ASTNode>,
     is_initialized: bool,
@@ -284,11 +643,19 @@ impl ExternalLibrary {
 
         self.env.in_function = false;
 
+        // imports first, so every function sees the library's dependencies
+        // wherever they appear in the file
+        for node in &self.ast {
+            if let ASTNode::Import(name, mode, requirement) = node {
+                self.env.import_library(name, mode.as_deref())?;
+                if let Some((op, version)) = requirement {
+                    self.env.check_library_version(name, op, version)?;
+                }
+            }
+        }
+
         for node in &self.ast {
             match node {
-                ASTNode::Import(name, mode) => {
-                    self.env.import_library(name, mode.as_deref())?;
-                },
                 ASTNode::FunctionDecl(name, params, body) => {
                     let params = params.clone();
                     let body = body.clone();
@@ -326,9 +693,8 @@ impl ExternalLibrary {
                 }
                 ASTNode::Var(name, expr_opt, is_mutable) => {
                     if let Some(expr) = expr_opt {
-                        if let Ok(value) = interpret_node(expr, &mut self.env, false, false) {
-                            self.variables.insert(name.clone(), (value, *is_mutable));
-                        }
+                        let value = interpret_node(expr, &mut self.env, false, false)?;
+                        self.variables.insert(name.clone(), (value, *is_mutable));
                     } else {
                         self.variables.insert(name.clone(), (Value::Null, *is_mutable));
                     }
@@ -343,8 +709,19 @@ impl ExternalLibrary {
     }
 }
 
+impl ExternalLibrary {
+    // __init__() runs once on import and __teardown__() once on unload;
+    // clones made for function calls run neither
+    fn call_hook(&self, name: &str) -> Result<(), Error> {
+        match self.functions.get(name) {
+            Some(hook) => hook(vec![]).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+}
+
 impl Library for ExternalLibrary {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -352,10 +729,47 @@ impl Library for ExternalLibrary {
         self.variables.get(name).map(|(val, _)| val)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.variables.keys().cloned().collect()
+    }
+
     fn is_mutable(&self, name: &str) -> Option<bool> {
         self.variables.get(name).map(|(_, mutable)| *mutable)
     }
 
+    // a library's `var`s are settable from the script, its `novar`s are not
+    fn set_constant(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        match self.variables.get_mut(name) {
+            Some((slot, true)) => {
+                *slot = value;
+                Ok(())
+            }
+            _ => Err(Error::ImmutableConstant(format!("'{}' cannot be assigned", name))),
+        }
+    }
+
+    fn teardown(&self) -> Result<(), Error> {
+        self.call_hook("__teardown__")
+    }
+
+    // a .tdx function documents itself with a string as its first statement
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        self.ast.iter().find_map(|node| match node {
+            ASTNode::FunctionDecl(fname, params, body) if fname == name => {
+                let doc = match body.first() {
+                    Some(ASTNode::String(doc)) => doc.clone(),
+                    _ => "".into(),
+                };
+                Some((format!("{}({})", fname, params.join(", ")), doc))
+            }
+            _ => None,
+        })
+    }
+
     fn box_clone(&self) -> Box<dyn Library> {
         let mut new_lib = ExternalLibrary::new(self.ast.clone());
         new_lib.variables = self.variables.clone();
@@ -365,7 +779,32 @@ impl Library for ExternalLibrary {
     }
 }
 
-fn type_str_of_value(value: &Value) -> &'static str {
+// "1.2" and "1.2.0" are the same version: missing parts count as 0
+fn parse_version(version: &str) -> Result<[u64; 3], Error> {
+    let mut parts = [0; 3];
+    let pieces: Vec<&str> = version.trim().split('.').collect();
+    if pieces.len() > 3 {
+        return Err(Error::LibraryError(format!("Invalid version \"{}\": expected major.minor.patch", version)));
+    }
+    for (part, piece) in parts.iter_mut().zip(&pieces) {
+        *part = piece.parse().map_err(|_| Error::LibraryError(format!("Invalid version \"{}\": expected major.minor.patch", version)))?;
+    }
+    Ok(parts)
+}
+
+// the comparison in a version requirement like >= "1.0"
+pub fn op_symbol(op: &Token) -> &'static str {
+    match op {
+        Token::GreaterEqual => ">=",
+        Token::Greater => ">",
+        Token::Equal => "==",
+        Token::LessEqual => "<=",
+        Token::Less => "<",
+        _ => "?",
+    }
+}
+
+pub fn type_str_of_value(value: &Value) -> &'static str {
     match value {
         Value::Number(_) => "int",
         Value::String(_) => "str", 
@@ -381,6 +820,175 @@ fn type_str_of_value(value: &Value) -> &'static str {
     }
 }
 
+// dir() lists the variables, script functions and libraries in scope, and
+// dir(math) what an imported library offers; a variable named like a library
+// wins, and dir(value) is left to std
+fn dir_of(env: &Environment, args: &[ASTNode]) -> Option<Value> {
+    let mut names: Vec<String> = match args {
+        [] => {
+            let mut names: Vec<String> = env.scopes.iter().flat_map(|scope| scope.keys().cloned()).collect();
+            names.extend(env.visible_functions().into_keys());
+            names.extend(env.libraries.keys().cloned());
+            names
+        }
+        [ASTNode::Identifier(name)] if env.get(name).is_none() => {
+            let lib = env.libraries.get(name)?;
+            let mut names = lib.function_names();
+            names.extend(lib.constant_names());
+            names
+        }
+        _ => return None,
+    };
+    names.sort();
+    names.dedup();
+    Some(Value::Array(Arc::new(Mutex::new(names.into_iter().map(|name| Value::String(name.into())).collect()))))
+}
+
+// help("math.sqrt"), help("print") for a builtin or script function, or
+// help("math") for a whole library; embedded libraries answer even before
+// they are imported
+fn help_of(env: &Environment, topic: &str) -> Option<String> {
+    if let Some((lib_name, name)) = topic.split_once('.') {
+        return library_help(env, lib_name, |lib| describe(lib_name, lib, name));
+    }
+    if let Some(Value::Function(full_name, params, body)) = env.resolve_function(topic) {
+        if let Some((lib_name, name)) = full_name.split_once('.') {
+            return library_help(env, lib_name, |lib| describe(lib_name, lib, name));
+        }
+        // script functions document themselves with a leading string, as in .tdx libraries
+        let doc = match body.first() {
+            Some(ASTNode::String(doc)) => doc.as_str(),
+            _ => "",
+        };
+        return Some(help_entry(format!("{}({})", topic, params.join(", ")), doc));
+    }
+    library_help(env, topic, |lib| {
+        let mut names = lib.function_names();
+        names.sort();
+        Some(names.iter().filter_map(|name| describe(topic, lib, name)).collect::<Vec<_>>().join("\n"))
+    })
+}
+
+fn library_help(env: &Environment, lib_name: &str, f: impl Fn(&dyn Library) -> Option<String>) -> Option<String> {
+    match env.libraries.get(lib_name) {
+        Some(lib) => f(lib.as_ref()),
+        None => crate::libs::load_embedded(lib_name).and_then(|lib| f(lib.as_ref())),
+    }
+}
+
+fn describe(lib_name: &str, lib: &dyn Library, name: &str) -> Option<String> {
+    let (signature, doc) = lib.help(name)?;
+    let signature = if lib_name == "std" { signature } else { format!("{}.{}", lib_name, signature) };
+    Some(help_entry(signature, &doc))
+}
+
+fn help_entry(signature: String, doc: &str) -> String {
+    if doc.is_empty() {
+        signature
+    } else {
+        format!("{}\n    {}", signature, doc)
+    }
+}
+
+fn help_value(env: &Environment, args: &[Value]) -> Result<Value, Error> {
+    match args {
+        [Value::String(topic)] => help_of(env, topic)
+            .map(|entry| Value::String(entry.into()))
+            .ok_or_else(|| Error::InterpreterError(format!("No help found for '{}'", topic))),
+        _ => Err(Error::TypeError("help() takes 1 string argument, like help(\"math.sqrt\")".to_string())),
+    }
+}
+
+fn reload_value(env: &mut Environment, args: &[Value]) -> Result<Value, Error> {
+    match args {
+        [Value::String(name)] => env.reload_library(name).map(|_| Value::Null),
+        _ => Err(Error::TypeError("reload() takes 1 library name, like reload(\"mylib\")".to_string())),
+    }
+}
+
+// inspect(x) on a variable also says whether it can be reassigned
+fn with_mutability(env: &Environment, arg: &ASTNode, info: Value) -> Result<Value, Error> {
+    if let (ASTNode::Identifier(name), Value::Array(pairs)) = (arg, &info) {
+        if let Some((_, is_mutable)) = env.get(name) {
+            lock_array(pairs)?.push(Value::Array(Arc::new(Mutex::new(vec![
+                Value::String("mutable".into()),
+                Value::Boolean(*is_mutable),
+            ]))));
+        }
+    }
+    Ok(info)
+}
+
+// --loop-guard N stops a loop once N iterations in a row have started with
+// the variables in its condition unchanged and no input read, which is how
+// `while (i < 10) { ... }` without `i = i + 1` usually shows up
+struct LoopGuard {
+    limit: usize,
+    line: usize,
+    names: Vec<String>,
+    last: Vec<String>,
+    unchanged: usize,
+}
+
+impl LoopGuard {
+    fn new(condition: &ASTNode) -> Option<Self> {
+        let limit = settings(|settings| settings.loop_guard)?;
+        let mut names = Vec::new();
+        condition_variables(condition, &mut names);
+        Some(LoopGuard { limit, line: CURRENT_LINE.with(Cell::get), names, last: Vec::new(), unchanged: 0 })
+    }
+
+    fn check(&mut self, env: &Environment) -> Result<(), Error> {
+        // values are compared as text so an array changed in place counts
+        let mut state: Vec<String> = self.names.iter()
+            .map(|name| env.get(name).map(|(value, _)| value.to_string()).unwrap_or_default())
+            .collect();
+        state.push(input::lines_read().to_string());
+        if state == self.last {
+            self.unchanged += 1;
+        } else {
+            self.last = state;
+            self.unchanged = 0;
+        }
+        if self.unchanged < self.limit {
+            return Ok(());
+        }
+
+        let location = if self.line > 0 { format!("Loop at line {}", self.line) } else { "Loop".to_string() };
+        let values: Vec<String> = self.names.iter()
+            .zip(&self.last)
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        let values = if values.is_empty() { "its condition uses no variables".to_string() } else { values.join(", ") };
+        Err(Error::InterpreterError(format!(
+            "{} ran {} iterations without its condition changing ({}); stopped by --loop-guard",
+            location, self.limit, values
+        )))
+    }
+}
+
+fn condition_variables(node: &ASTNode, names: &mut Vec<String>) {
+    let children: Vec<&ASTNode> = match node {
+        ASTNode::Identifier(name) if !names.contains(name) => {
+            names.push(name.clone());
+            return;
+        }
+        ASTNode::BinaryOp(left, _, right) | ASTNode::Index(left, right) => vec![left, right],
+        ASTNode::Comparison(operands, _) | ASTNode::Array(operands) | ASTNode::FunctionCall(_, operands) | ASTNode::LibraryFunctionCall(_, _, operands) => {
+            operands.iter().collect()
+        }
+        ASTNode::UnaryOp(_, operand) | ASTNode::LenCall(operand) | ASTNode::TypeCast(_, operand) | ASTNode::Type(operand)
+        | ASTNode::Spread(operand) | ASTNode::KeywordArg(_, operand) => vec![operand],
+        ASTNode::Slice(target, start, stop, step) => {
+            std::iter::once(target).chain([start, stop, step].into_iter().flatten()).map(|part| &**part).collect()
+        }
+        _ => vec![],
+    };
+    for child in children {
+        condition_variables(child, names);
+    }
+}
+
 fn get_array_name(node: &ASTNode) -> Option<String> {
     if let ASTNode::Identifier(name) = node {
         Some(name.clone())
@@ -389,77 +997,305 @@ fn get_array_name(node: &ASTNode) -> Option<String> {
     }
 }
 
+pub struct ScriptResult {
+    pub value: Option<Value>,
+    pub returned: bool, // script ended with a top-level `return`
+    pub definitions: Definitions,
+}
+
+// What a script left behind at top level, for embedders and notebooks to
+// inspect after a run
+#[derive(Debug, Default)]
+pub struct Definitions {
+    pub variables: Vec<(String, Value, bool)>, // name, value, is_mutable; by name
+    pub functions: Vec<(String, usize)>,       // name, number of parameters; in declaration order
+    pub libraries: Vec<String>,                // imported, in import order; std is left out
+}
+
+// a script run from inside another, as std.eval does, with the settings of
+// the run it was called from
 pub fn interpret(ast: Vec<ASTNode>, is_verbose: bool) -> Result<Option<Value>, Error> {
+    run(ast, is_verbose, settings(Settings::clone)).map(|result| result.value)
+}
+
+pub fn run(ast: Vec<ASTNode>, is_verbose: bool, settings: Settings) -> Result<ScriptResult, Error> {
+    let previous = lend_settings(settings);
+    forget_warnings();
     let mut env = Environment::new();
+    let result = run_in(ast, &mut env, is_verbose);
+    let definitions = env.definitions();
+    // libraries clean up even when the script fails, but its error comes first
+    let teardown = env.teardown_libraries();
+    lend_settings(previous);
+    let (value, returned) = result?;
+    teardown?;
+    Ok(ScriptResult { value, returned, definitions })
+}
+
+// What a script printed and returned, for run_source_captured()
+#[derive(Debug)]
+pub struct CapturedRun {
+    pub stdout: String,
+    pub stderr: String,
+    // the top-level `return` value or last statement's value, or the parse
+    // or runtime error that stopped the script
+    pub value: Result<Option<Value>, Error>,
+    pub duration: Duration,
+}
+
+// Runs a script with its output collected instead of printed and input()
+// reading from `input`, for hosts that embed the interpreter and for tests
+pub fn run_source_captured(source: &str, input: &[&str], settings: Settings) -> CapturedRun {
+    let capture = CaptureOutput::new();
+    let previous_output = output::set_output(Box::new(capture.clone()));
+    let previous_input = input::set_input(Box::new(CannedInput::new(input.iter().copied())));
+    let started = Instant::now();
+    let value = Parser::new(source).parse().and_then(|ast| run(ast, false, settings)).map(|result| result.value);
+    let duration = started.elapsed();
+    output::set_output(previous_output);
+    input::set_input(previous_input);
+    CapturedRun { stdout: capture.stdout(), stderr: capture.stderr(), value, duration }
+}
+
+// the script's value, and whether a top-level `return` gave it
+fn run_in(ast: Vec<ASTNode>, env: &mut Environment, is_verbose: bool) -> Result<(Option<Value>, bool), Error> {
     let mut result = None;
 
     for node in ast {
-        let value = interpret_node(&node, &mut env, is_verbose, false)?;
+        let value = interpret_node(&node, env, is_verbose, false)?;
         /* match node {
             ASTNode::Number(_) | ASTNode::String(_) | ASTNode::Float(_) | 
             ASTNode::Boolean(_) | ASTNode::Array(_) => {
-                println!("{}", value);
+                outln!("{}", value);
             },
             _ => {}
         } */
+        if let Value::ReturnValue(val) = value {
+            return Ok((Some(*val), true));
+        }
         result = Some(value);
     }
 
-    Ok(result)
+    Ok((result, false))
 }
 
-fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_loop: bool) -> Result<Value, Error> {
-    if is_verbose {
-        println!("\x1b[90m[DEBUG] Interpreting node: {:?}\x1b[0m", node);
+// A long-lived environment for the REPL: each eval() runs in the same
+// top-level scope, so variables, functions and imports carry over.
+pub struct Session {
+    env: Environment,
+    is_verbose: bool,
+    settings: Settings,
+    output: Option<Box<dyn Output>>,
+    input: Option<Box<dyn InputProvider>>,
+}
+
+impl Session {
+    pub fn new(is_verbose: bool) -> Self {
+        Self::with_io(is_verbose, Box::new(StdStreams), Box::new(StdinInput))
     }
 
-    let result = match node {
-        ASTNode::Number(val) => Ok(Value::Number(*val)),
-        ASTNode::String(val) => Ok(Value::String(val.clone())),
-        ASTNode::Float(val) => Ok(Value::Float(*val)),
-        ASTNode::Boolean(val) => Ok(Value::Boolean(*val)),
-        ASTNode::Null => Ok(Value::Null),
-        ASTNode::Import(name, mode) => {
-            if is_verbose {
-                println!("\x1b[90m[DEBUG] Importing library '{}' with mode {:?}\x1b[0m", name, mode);
-            }
-            env.import_library(name, mode.as_deref())?;
-            Ok(Value::Null)
-        },
-        ASTNode::LibraryAccess(lib_name, item_name) => {
-            if let Some(lib) = env.libraries.get(lib_name) {
-                if let Some(constant) = lib.get_constant(item_name) {
-                    Ok(constant.clone())
-                } else if let Some(_func) = lib.get_function(item_name) {
-                    Ok(Value::Function(
-                        format!("{}.{}", lib_name, item_name),
-                        vec![],
-                        vec![]
-                    ))
-                } else {
-                    Err(Error::InterpreterError(format!("Item '{}' not found in library '{}'", item_name, lib_name)))
-                }
-            } else {
-                Err(Error::InterpreterError(format!("Library '{}' not found", lib_name)))
+    // everything the session's scripts print goes to `output`, and input()
+    // reads its lines from `input`
+    pub fn with_io(is_verbose: bool, output: Box<dyn Output>, input: Box<dyn InputProvider>) -> Self {
+        Session { env: Environment::new(), is_verbose, settings: Settings::default(), output: Some(output), input: Some(input) }
+    }
+
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    // like run(), a top-level `return` stops evaluation and gives the result
+    pub fn eval(&mut self, ast: Vec<ASTNode>) -> Result<Value, Error> {
+        forget_warnings();
+        // library functions print, read and look up settings through the
+        // thread's current ones, so ours stand in for them while the
+        // statements run
+        let previous_output = output::set_output(self.output.take().expect("session output is only lent during eval"));
+        let previous_input = input::set_input(self.input.take().expect("session input is only lent during eval"));
+        let previous_settings = lend_settings(std::mem::take(&mut self.settings));
+        let result = self.eval_nodes(ast);
+        self.output = Some(output::set_output(previous_output));
+        self.input = Some(input::set_input(previous_input));
+        self.settings = lend_settings(previous_settings);
+        result
+    }
+
+    fn eval_nodes(&mut self, ast: Vec<ASTNode>) -> Result<Value, Error> {
+        let mut result = Value::Null;
+        for node in ast {
+            match interpret_node(&node, &mut self.env, self.is_verbose, false)? {
+                Value::ReturnValue(val) => return Ok(*val),
+                value => result = value,
             }
         }
-        ASTNode::LibraryFunctionCall(lib_name, func_name, args) => {
-            let evaluated_args = {
-                let mut args_vec = Vec::new();
+        Ok(result)
+    }
+
+    pub fn type_of(&mut self, ast: Vec<ASTNode>) -> Result<&'static str, Error> {
+        self.eval(ast).map(|value| type_str_of_value(&value))
+    }
+
+    // (name, value, is_mutable) for every top-level variable, by name
+    pub fn variables(&self) -> Vec<(String, Value, bool)> {
+        self.env.definitions().variables
+    }
+
+    // user-declared functions with their parameter names and bodies
+    pub fn functions(&self) -> Vec<(String, Vec<String>, Vec<ASTNode>)> {
+        self.env.functions.iter()
+            .filter_map(|(name, func)| match func {
+                Value::Function(full_name, params, body) if !full_name.contains('.') => Some((name.clone(), params.to_vec(), body.to_vec())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // what std.help(topic) would say, for the REPL's :help
+    pub fn help(&self, topic: &str) -> Option<String> {
+        help_of(&self.env, topic)
+    }
+
+    pub fn libraries(&self) -> Vec<String> {
+        self.env.definitions().libraries
+    }
+
+    pub fn clear(&mut self) {
+        self.unload_libraries();
+        self.env = Environment::new();
+    }
+
+    fn unload_libraries(&mut self) {
+        if let Err(e) = self.env.teardown_libraries() {
+            errln!("\x1b[31mError while unloading libraries: {}\x1b[0m", e);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.unload_libraries();
+    }
+}
+
+fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_loop: bool) -> Result<Value, Error> {
+    if settings(|settings| settings.cancellation.as_ref().is_some_and(CancellationToken::is_cancelled)) {
+        return Err(Error::Cancelled);
+    }
+
+    if signals::any_pending() {
+        for (signal, handler) in signals::take_pending() {
+            if is_verbose {
+                errln!("\x1b[90m[DEBUG] Handling signal {}\x1b[0m", signal);
+            }
+            with_call_context(env, is_verbose, || call_function(&handler, vec![Value::String(signal.into())]))?;
+        }
+    }
+
+    if is_verbose {
+        errln!("\x1b[90m[DEBUG] Interpreting node: {:?}\x1b[0m", node);
+    }
+
+    let result = match node {
+        ASTNode::Number(val) => Ok(Value::Number(*val)),
+        ASTNode::String(val) => Ok(Value::String(val.as_str().into())),
+        ASTNode::Float(val) => Ok(Value::Float(*val)),
+        ASTNode::Boolean(val) => Ok(Value::Boolean(*val)),
+        ASTNode::Null => Ok(Value::Null),
+        ASTNode::Import(name, mode, requirement) => {
+            if is_verbose {
+                errln!("\x1b[90m[DEBUG] Importing library '{}' with mode {:?}\x1b[0m", name, mode);
+            }
+            env.import_library(name, mode.as_deref())?;
+            if let Some((op, version)) = requirement {
+                env.check_library_version(name, op, version)?;
+            }
+            Ok(Value::Null)
+        },
+        ASTNode::LibraryAccess(lib_name, item_name) => {
+            if let Some(lib) = env.libraries.get(lib_name) {
+                if let Some(constant) = lib.get_constant(item_name) {
+                    Ok(constant.clone())
+                } else if let Some(_func) = lib.get_function(item_name) {
+                    Ok(Value::Function(
+                        format!("{}.{}", lib_name, item_name).into(),
+                        Arc::default(),
+                        Arc::default()
+                    ))
+                } else {
+                    Err(Error::InterpreterError(format!("Item '{}' not found in library '{}'", item_name, lib_name)))
+                }
+            } else {
+                Err(library_not_found(lib_name))
+            }
+        }
+        ASTNode::LibraryAssign(lib_name, item_name, expr) => {
+            let value = interpret_node(expr, env, is_verbose, in_loop)?;
+            let lib = env.libraries.get_mut(lib_name)
+                .ok_or_else(|| library_not_found(lib_name))?;
+            match lib.is_mutable(item_name) {
+                Some(true) => lib.set_constant(item_name, value)?,
+                Some(false) => {
+                    return Err(Error::ImmutableConstant(format!("{}.{} is a constant and cannot be assigned", lib_name, item_name)));
+                }
+                None if lib.get_constant(item_name).is_some() || lib.get_function(item_name).is_some() => {
+                    return Err(Error::ImmutableConstant(format!("{}.{} cannot be assigned", lib_name, item_name)));
+                }
+                None => {
+                    return Err(Error::InterpreterError(format!("Item '{}' not found in library '{}'", item_name, lib_name)));
+                }
+            }
+            if is_verbose {
+                errln!("\x1b[90m[DEBUG] Library assignment: {}.{}\x1b[0m", lib_name, item_name);
+            }
+            Ok(Value::Null)
+        }
+        ASTNode::LibraryFunctionCall(lib_name, func_name, args) => {
+            if lib_name == "std" && func_name == "dir" {
+                if let Some(names) = dir_of(env, args) {
+                    return Ok(names);
+                }
+            }
+            let evaluated_args = {
+                let mut args_vec = Vec::new();
                 for arg in args {
+                    if let ASTNode::KeywordArg(key, _) = arg {
+                        return Err(Error::FunctionCallError(format!(
+                            "'{}.{}' does not accept keyword argument '{}'", lib_name, func_name, key
+                        )));
+                    }
+                    if let ASTNode::Spread(expr) = arg {
+                        args_vec.extend(spread_values(interpret_node(expr, env, is_verbose, in_loop)?)?);
+                        continue;
+                    }
                     args_vec.push(interpret_node(arg, env, is_verbose, in_loop)?);
                 }
                 args_vec
             };
+            if lib_name == "std" && func_name == "help" {
+                return help_value(env, &evaluated_args);
+            }
+            if lib_name == "std" && func_name == "reload" {
+                return reload_value(env, &evaluated_args);
+            }
             
             if let Some(lib) = env.libraries.get(lib_name) {
                 if let Some(func) = lib.get_function(func_name) {
-                    func(evaluated_args)
+                    if let ("std", "inspect", [arg]) = (lib_name.as_str(), func_name.as_str(), args.as_slice()) {
+                        return with_mutability(env, arg, func(evaluated_args)?);
+                    }
+                    if !evaluated_args.iter().any(|arg| matches!(arg, Value::Function(..))) {
+                        return func(evaluated_args);
+                    }
+                    // a clone, so env is free to lend its globals to callbacks
+                    let lib = lib.box_clone();
+                    let func = lib.get_function(func_name).expect("the library was just asked for it");
+                    with_call_context(env, is_verbose, || func(evaluated_args))
                 } else {
                     Err(Error::InterpreterError(format!("Function '{}' not found in library '{}'", func_name, lib_name)))
                 }
             } else {
-                Err(Error::InterpreterError(format!("Library '{}' not found", lib_name)))
+                Err(library_not_found(lib_name))
             }
         }
         ASTNode::LenCall(expr) => {
@@ -467,7 +1303,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
             match value {
                 Value::String(s) => Ok(Value::Number(s.chars().count() as i32)),
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(&arr)?;
                     Ok(Value::Number(guard.len() as i32))
                 },
                 _ => Err(Error::CannotGetLength(type_str_of_value(&value).to_string(), value))
@@ -476,7 +1312,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
         ASTNode::DelCall(expr) => {
             if let ASTNode::Identifier(name) = &**expr {
                 if is_verbose {
-                    println!("delete variable '{}'", name);
+                    errln!("delete variable '{}'", name);
                 }
                 if let Some(scope) = env.scopes.last_mut() {
                     scope.remove(name);
@@ -487,32 +1323,28 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
             }
         },
         ASTNode::Input(prompt) => {
-            use std::io::{self, Write};
-        
             let prompt_value = interpret_node(&prompt, env, is_verbose, in_loop)?;
             if is_verbose {
-                println!("requesting input with prompt: {}", prompt_value);
+                errln!("requesting input with prompt: {}", prompt_value);
             }
-            print!("{}", prompt_value);
-            io::stdout().flush().unwrap();
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let trimmed_input = input.trim().to_string();
+            let trimmed_input = crate::input::prompt(&prompt_value.to_string());
         
             if is_verbose {
-                println!("received input: {}", trimmed_input);
+                errln!("received input: {}", trimmed_input);
             }
         
-            Ok(Value::String(trimmed_input))
+            Ok(Value::String(trimmed_input.into()))
         },
         ASTNode::FunctionDecl(name, params, body) => {
             if is_verbose {
-                println!("\x1b[90m[DEBUG] Declaring function '{}' with parameters {:?}\x1b[0m", name, params);
+                errln!("\x1b[90m[DEBUG] Declaring function '{}' with parameters {:?}\x1b[0m", name, params);
+            }
+            if env.libraries.get("std").is_some_and(|std| std.get_function(name).is_some()) {
+                warn(&format!("function '{}' shadows the std builtin; call std.{} to reach the builtin", name, name));
             }
             env.insert_function(
                 name.clone(),
-                Value::Function(name.clone(), params.clone(), body.clone())
+                Value::Function(name.as_str().into(), Arc::new(params.clone()), Arc::new(body.clone()))
             );
             Ok(Value::Null)
         },
@@ -541,202 +1373,32 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                 },
                 _ => {
                     let right_val = interpret_node(right, env, is_verbose, in_loop)?;
-                    match (left_val, right_val) {
-                        (Value::Number(l), Value::Number(r)) => {
-                            match op {
-                                Token::Plus => Ok(Value::Number(l + r)),
-                                Token::Minus => Ok(Value::Number(l - r)),
-                                Token::Multiply => Ok(Value::Number(l * r)),
-                                Token::Divide => Ok(Value::Float(l as f64 / r as f64)),
-                                Token::Equal => Ok(Value::Boolean(l == r)),
-                                Token::NotEqual => Ok(Value::Boolean(l != r)),
-                                Token::Greater => Ok(Value::Boolean(l > r)),
-                                Token::Less => Ok(Value::Boolean(l < r)),
-                                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
-                                Token::FloorDivide => Ok(Value::Number(l / r)),
-                                Token::LessEqual => Ok(Value::Boolean(l <= r)),
-                                Token::Modulus => Ok(Value::Number(l % r)),
-                                Token::Power => Ok(Value::Number(l.pow(r as u32))),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for numbers"))),
-                            }
-                        }
-                        (Value::Float(l), Value::Float(r)) => {
-                            match op {
-                                Token::Plus => Ok(Value::Float(l + r)),
-                                Token::Minus => Ok(Value::Float(l - r)),
-                                Token::Multiply => Ok(Value::Float(l * r)),
-                                Token::Divide => Ok(Value::Float(l / r)),
-                                Token::Equal => Ok(Value::Boolean(l == r)),
-                                Token::NotEqual => Ok(Value::Boolean(l != r)),
-                                Token::Greater => Ok(Value::Boolean(l > r)),
-                                Token::Modulus => Ok(Value::Float(l % r)),
-                                Token::FloorDivide => Ok(Value::Number((l / r).floor() as i32)),
-                                Token::Less => Ok(Value::Boolean(l < r)),
-                                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
-                                Token::LessEqual => Ok(Value::Boolean(l <= r)),
-                                Token::Power => Ok(Value::Float(l.powf(r))),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for floats"))),
-                            }
-                        }
-                        (Value::Number(l), Value::Float(r)) => {
-                            let l = l as f64;
-                            match op {
-                                Token::Plus => Ok(Value::Float(l + r)),
-                                Token::Minus => Ok(Value::Float(l - r)),
-                                Token::Multiply => Ok(Value::Float(l * r)),
-                                Token::Divide => Ok(Value::Float(l / r)),
-                                Token::Equal => Ok(Value::Boolean(l == r)),
-                                Token::Modulus => Ok(Value::Float(l % r)),
-                                Token::NotEqual => Ok(Value::Boolean(l != r)),
-                                Token::Greater => Ok(Value::Boolean(l > r)),
-                                Token::Less => Ok(Value::Boolean(l < r)),
-                                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
-                                Token::FloorDivide => Ok(Value::Number((l / r).floor() as i32)),
-                                Token::Power => Ok(Value::Float(l.powf(r))),
-                                Token::LessEqual => Ok(Value::Boolean(l <= r)),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for mixed number and float"))),
-                            }
-                        }
-                        (Value::Float(l), Value::Number(r)) => {
-                            let r = r as f64;
-                            match op {
-                                Token::Plus => Ok(Value::Float(l + r)),
-                                Token::Minus => Ok(Value::Float(l - r)),
-                                Token::Multiply => Ok(Value::Float(l * r)),
-                                Token::Divide => Ok(Value::Float(l / r)),
-                                Token::Equal => Ok(Value::Boolean(l == r)),
-                                Token::Modulus => Ok(Value::Float(l % r)),
-                                Token::NotEqual => Ok(Value::Boolean(l != r)),
-                                Token::Greater => Ok(Value::Boolean(l > r)),
-                                Token::Less => Ok(Value::Boolean(l < r)),
-                                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
-                                Token::FloorDivide => Ok(Value::Number((l / r).floor() as i32)),
-                                Token::Power => Ok(Value::Float(l.powf(r))),
-                                Token::LessEqual => Ok(Value::Boolean(l <= r)),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for mixed float and number"))),
-                            }
-                        }
-                        (Value::String(s), Value::String(t)) => {
-                            match op {
-                                Token::Plus => Ok(Value::String(s + &t)),
-                                Token::Multiply => {
-                                    if let Value::Number(n) = interpret_node(right, env, is_verbose, in_loop)? {
-                                        Ok(Value::String(s.repeat(n as usize)))
-                                    } else {
-                                        Err(Error::TypeError(format!("String can only be multiplied by an integer")))
-                                    }
-                                }
-                                Token::Equal => Ok(Value::Boolean(s == t)),
-                                Token::NotEqual => Ok(Value::Boolean(s != t)),
-                                Token::Greater => Ok(Value::Boolean(s > t)),
-                                Token::Less => Ok(Value::Boolean(s < t)),
-                                Token::GreaterEqual => Ok(Value::Boolean(s >= t)),
-                                Token::LessEqual => Ok(Value::Boolean(s <= t)),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for strings"))),
-                            }
-                        }
-
-                        (Value::String(s), Value::Number(n)) => {
-                            match op {
-                                Token::Multiply => Ok(Value::String(s.repeat(n as usize))),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operation between string and number"))),
-                            }
-                        }
-                        (Value::Number(n), Value::String(s)) => {
-                            match op {
-                                Token::Multiply => Ok(Value::String(s.repeat(n as usize))),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operation between number and string"))),
-                            }
-                        }
-                        (Value::Boolean(b1), Value::Boolean(b2)) => {
-                            match op {
-                                Token::Equal => Ok(Value::Boolean(b1 == b2)),
-                                Token::NotEqual => Ok(Value::Boolean(b1 != b2)),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for booleans"))),
-                            }
-                        }
-                        (Value::Type(t1), Value::Type(t2)) => {
-                            match op {
-                                Token::Equal => Ok(Value::Boolean(t1 == t2)),
-                                Token::NotEqual => Ok(Value::Boolean(t1 != t2)),
-                                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for types"))),
-                            }
-                        }
-                        (Value::Array(arr1), Value::Array(arr2)) => {
-                            match op {
-                                Token::Plus => {
-                                    let guard1 = arr1.lock().unwrap();
-                                    let guard2 = arr2.lock().unwrap();
-                                    let mut new_vec = guard1.clone();
-                                    new_vec.extend(guard2.clone());
-                                    Ok(Value::Array(Arc::new(Mutex::new(new_vec))))
-                                },
-                                Token::Equal | Token::NotEqual | 
-                                Token::Greater | Token::Less |
-                                Token::GreaterEqual | Token::LessEqual => {
-                                    let guard1 = arr1.lock().unwrap();
-                                    let guard2 = arr2.lock().unwrap();
-                                    
-                                    if !guard1.is_empty() && !guard2.is_empty() {
-                                        let type1 = type_str_of_value(&guard1[0]);
-                                        let type2 = type_str_of_value(&guard2[0]);
-                                        if type1 != type2 {
-                                            return Err(Error::TypeError(
-                                                format!("Cannot compare arrays of different types: {} and {}", type1, type2)
-                                            ));
-                                        }
-                                    }
-                                    
-                                    match op {
-                                        Token::Equal => Ok(Value::Boolean(guard1.len() == guard2.len() && 
-                                            guard1.iter().zip(guard2.iter()).all(|(a, b)| a == b))),
-                                        Token::NotEqual => Ok(Value::Boolean(!(guard1.len() == guard2.len() && 
-                                            guard1.iter().zip(guard2.iter()).all(|(a, b)| a == b)))),
-                                        Token::Greater => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
-                                            guard1.iter().zip(guard2.iter())
-                                                .find(|(a, b)| a != b)
-                                                .map_or(guard1.len() > guard2.len(), |(a, b)| a > b))),
-                                        Token::Less => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
-                                            guard1.iter().zip(guard2.iter())
-                                                .find(|(a, b)| a != b)
-                                                .map_or(guard1.len() < guard2.len(), |(a, b)| a < b))),
-                                        Token::GreaterEqual => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
-                                            guard1.iter().zip(guard2.iter())
-                                                .find(|(a, b)| a != b)
-                                                .map_or(guard1.len() >= guard2.len(), |(a, b)| a >= b))),
-                                        Token::LessEqual => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
-                                            guard1.iter().zip(guard2.iter())
-                                                .find(|(a, b)| a != b)
-                                                .map_or(guard1.len() <= guard2.len(), |(a, b)| a <= b))),
-                                        _ => unreachable!()
-                                    }
-                                },
-                                _ => Err(Error::UnsupportedOperation("Unsupported operator for arrays".to_string())),
-                            }
-                        },
-                        (Value::Array(arr), Value::Number(n)) | (Value::Number(n), Value::Array(arr)) => {
-                            match op {
-                                Token::Multiply => {
-                                    let guard = arr.lock().unwrap();
-                                    let mut new_vec = Vec::new();
-                                    for _ in 0..n {
-                                        new_vec.extend(guard.clone());
-                                    }
-                                    Ok(Value::Array(Arc::new(Mutex::new(new_vec))))
-                                },
-                                _ => Err(Error::UnsupportedOperation("Arrays can only be multiplied by numbers".to_string())),
-                            }
-                        },
-                        _ => Err(Error::UnsupportedOperation(format!("Unsupported operation for given types"))),
-                    }
+                    binary_op(op, left_val, right_val)
+                }
+            }
+        },
+        ASTNode::Comparison(operands, operators) => {
+            // each operand is evaluated once, and the chain stops at the first false link
+            let mut left_val = interpret_node(&operands[0], env, is_verbose, in_loop)?;
+            for (op, operand) in operators.iter().zip(&operands[1..]) {
+                let right_val = interpret_node(operand, env, is_verbose, in_loop)?;
+                let result = binary_op(op, left_val, right_val.clone())?;
+                if result != Value::Boolean(true) {
+                    return Ok(result);
                 }
+                left_val = right_val;
             }
+            Ok(Value::Boolean(true))
         },
         ASTNode::Array(elements) => {
-            let values: Vec<Value> = elements
-                .iter()
-                .map(|elem| interpret_node(elem, env, is_verbose, in_loop))
-                .collect::<Result<_, _>>()?;
+            let mut values = Vec::with_capacity(elements.len());
+            for elem in elements {
+                if let ASTNode::Spread(expr) = elem {
+                    values.extend(spread_values(interpret_node(expr, env, is_verbose, in_loop)?)?);
+                } else {
+                    values.push(interpret_node(elem, env, is_verbose, in_loop)?);
+                }
+            }
             Ok(Value::Array(Arc::new(Mutex::new(values))))
         },
         ASTNode::Index(expr, index) => {
@@ -745,7 +1407,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
 
             match (array, index) {
                 (Value::Array(arr), Value::Number(i)) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(&arr)?;
                     let len = guard.len() as i32;
                     let idx = if i < 0 { len + i } else { i };
                     if idx < 0 || idx >= len {
@@ -754,13 +1416,18 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                     Ok(guard[idx as usize].clone())
                 },
                 (Value::String(s), Value::Number(i)) => {
-                    let len = s.chars().count() as i32;
+                    // in ASCII text a char is a byte, so s[i] needn't walk to i
+                    let is_ascii = s.is_ascii();
+                    let len = if is_ascii { s.len() } else { s.chars().count() } as i32;
 
                     let idx = if i < 0 { len + i } else { i };
                     if idx < 0 || idx >= len {
                         return Err(Error::IndexOutOfBounds(format!("Index out of bounds")));
                     }
-                    Ok(Value::String(s.chars().nth(idx as usize).unwrap().to_string()))
+                    if is_ascii {
+                        return Ok(Value::String(s[idx as usize..idx as usize + 1].into()));
+                    }
+                    Ok(Value::String(s.chars().nth(idx as usize).unwrap().to_string().into()))
                 },
                 _ => Err(Error::TypeError(format!("Invalid indexing operation"))),
             }
@@ -801,7 +1468,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
 
             match value {
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(&arr)?;
                     let len = guard.len() as i32;
                     let (start, stop) = normalize_slice_indices(start_idx, stop_idx, len);
                     
@@ -829,7 +1496,21 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                 Value::String(s) => {
                     let len = s.chars().count() as i32;
                     let (start, stop) = normalize_slice_indices(start_idx, stop_idx, len);
-                    
+
+                    // s[a:b] is one run of the string, copied without splitting
+                    // it into chars; all of it is the same shared string
+                    if step_val == 1 {
+                        let (from, to) = (start.max(0), stop.min(len));
+                        if from == 0 && to == len {
+                            return Ok(Value::String(s));
+                        }
+                        if from >= to {
+                            return Ok(Value::String("".into()));
+                        }
+                        let byte = |i: i32| s.char_indices().nth(i as usize).map_or(s.len(), |(at, _)| at);
+                        return Ok(Value::String(s[byte(from)..byte(to)].into()));
+                    }
+
                     let chars: Vec<char> = s.chars().collect();
                     let mut result = String::new();
                     
@@ -851,93 +1532,127 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                             i += step_val;
                         }
                     }
-                    Ok(Value::String(result))
+                    Ok(Value::String(result.into()))
                 },
                 _ => Err(Error::TypeError("Cannot slice this type".to_string()))
             }
         },
         ASTNode::FunctionCall(name, args) => {
             if is_verbose {
-                println!("\x1b[90m[DEBUG] Calling function '{}' with {} arguments\x1b[0m", name, args.len());
+                errln!("\x1b[90m[DEBUG] Calling function '{}' with {} arguments\x1b[0m", name, args.len());
+            }
+            let function = env.resolve_function(name);
+            if matches!(&function, Some(Value::Function(full_name, _, _)) if &**full_name == "std.dir") {
+                if let Some(names) = dir_of(env, args) {
+                    return Ok(names);
+                }
             }
             let mut evaluated_args = Vec::new();
+            let mut keyword_args = Vec::new();
             for (i, arg) in args.iter().enumerate() {
+                if let ASTNode::KeywordArg(key, expr) = arg {
+                    let arg_value = interpret_node(expr, env, is_verbose, in_loop)?;
+                    if is_verbose {
+                        errln!("\x1b[90m[DEBUG] Argument {}: {:?}\x1b[0m", key, arg_value);
+                    }
+                    keyword_args.push((key.clone(), arg_value));
+                    continue;
+                }
+                if let ASTNode::Spread(expr) = arg {
+                    let spread = spread_values(interpret_node(expr, env, is_verbose, in_loop)?)?;
+                    if is_verbose {
+                        errln!("\x1b[90m[DEBUG] Argument {}: spread of {} values\x1b[0m", i, spread.len());
+                    }
+                    evaluated_args.extend(spread);
+                    continue;
+                }
                 let arg_value = interpret_node(arg, env, is_verbose, in_loop)?;
                 if is_verbose {
-                    println!("\x1b[90m[DEBUG] Argument {}: {:?}\x1b[0m", i, arg_value);
+                    errln!("\x1b[90m[DEBUG] Argument {}: {:?}\x1b[0m", i, arg_value);
                 }
                 evaluated_args.push(arg_value);
             }
         
-            if let Some(Value::Function(full_name, _, _)) = env.functions.get(name) {
+            if let Some(Value::Function(full_name, _, _)) = &function {
                 if full_name.starts_with("std.") {
+                    if let Some((key, _)) = keyword_args.first() {
+                        return Err(Error::FunctionCallError(format!(
+                            "'{}' does not accept keyword argument '{}'", name, key
+                        )));
+                    }
                     let func_name = &full_name[4..]; // skip std
+                    // these change their first argument in place, which a novar array forbids
+                    if matches!(func_name, "insert" | "sort" | "sort_natural" | "reverse" | "clear" | "extend" | "set_path" | "pop_key") {
+                        if let Some(array_name) = args.first().and_then(get_array_name) {
+                            if let Some((Value::Array(_), false)) = env.get(&array_name) {
+                                return Err(Error::TypeError(
+                                    format!("Cannot modify immutable array '{}'", array_name)
+                                ));
+                            }
+                        }
+                    }
+                    if func_name == "help" {
+                        return help_value(env, &evaluated_args);
+                    }
+                    if func_name == "reload" {
+                        return reload_value(env, &evaluated_args);
+                    }
                     if let Some(lib) = env.libraries.get("std") {
                         if let Some(func) = lib.get_function(func_name) {
                             let result = func(evaluated_args)?;
-
-                            match func_name {
-                                "insert" | "sort" | "reverse" | "clear" => {
-                                    if let Some(array_name) = get_array_name(&args[0]) {
-                                        if let Some((current_value, is_mutable)) = env.get_mut(&array_name) {
-                                            if *is_mutable {
-                                                if let Value::Array(_) = &result {
-                                                    *current_value = result.clone();
-                                                }
-                                                return Ok(Value::Null);
-                                            } else {
-                                                return Err(Error::TypeError(
-                                                    format!("Cannot modify immutable array '{}'", array_name)
-                                                ));
-                                            }
-                                        }
-                                    }
-                                },
-                                _ => {}
-                            }
-                            return Ok(result);
+                            return Ok(match (func_name, args.as_slice()) {
+                                ("inspect", [arg]) => with_mutability(env, arg, result)?,
+                                _ => result,
+                            });
                         }
                     }
                 }
             }
 
-            match env.functions.get(name).cloned() {
+            match function {
                 Some(Value::Function(_, params, body)) => {
+                    summary::function_call();
+                    let _call = crash_dump::enter_call(name, CURRENT_LINE.with(Cell::get));
                     let mut func_env = Environment::new();
                     func_env.in_function = true;
 
                     func_env.parent = Some(Box::new(Environment {
                         scopes: vec![HashMap::new()],
-                        functions: env.functions.clone(),
+                        functions: env.visible_functions(),
                         in_function: true,
-                        libraries: HashMap::new(), 
+                        libraries: IndexMap::new(), 
                         parent: None,
+                        globals: None,
+                        declared: HashSet::new(),
+                        import_marks: Vec::new(),
                     }));
 
-                    for (name, lib) in &env.libraries {
-                        func_env.libraries.insert(name.clone(), lib.box_clone());
-                    }
-
-                    if params.len() != evaluated_args.len() {
-                        return Err(Error::InvalidFunctionArguments(
-                            name.to_string(),
-                            params.len(),
-                            evaluated_args.len()
-                        ));
-                    }
+                    let bound_args = bind_arguments(name, &params, evaluated_args, keyword_args)?;
         
-                    for (param, arg) in params.iter().zip(evaluated_args) {
+                    for (param, arg) in params.iter().zip(bound_args) {
                         func_env.insert_var(param.clone(), arg, true);
                     }
         
-                    let mut result = Value::Null;
-                    for stmt in body {
-                        match interpret_node(&stmt, &mut func_env, is_verbose, in_loop)? {
-                            Value::ReturnValue(val) => return Ok(*val),
-                            val => result = val,
+                    env.lend_globals_to(&mut func_env);
+                    // the caller's libraries are lent too, not copied, so
+                    // `math.tolerance = ...` in the call changes the caller's
+                    func_env.libraries = std::mem::take(&mut env.libraries);
+                    let inherited = func_env.libraries.len();
+                    let result = (|| {
+                        let mut result = Value::Null;
+                        for stmt in body.iter() {
+                            match interpret_node(stmt, &mut func_env, is_verbose, in_loop)? {
+                                Value::ReturnValue(val) => return Ok(*val),
+                                val => result = val,
+                            }
                         }
-                    }
-                    Ok(result)
+                        Ok(result)
+                    })();
+                    // the function's own imports end with the call
+                    func_env.unload_libraries_from(inherited);
+                    env.restore_globals_from(&mut func_env);
+                    env.libraries = std::mem::take(&mut func_env.libraries);
+                    result
                 }
                 _ => Err(Error::InterpreterError(format!(
                     "Function '{}' must be called with library prefix (e.g. std.{})", 
@@ -946,10 +1661,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
             }
         },
         ASTNode::Return(expr) => {
-            if !env.in_function {
-                return Err(Error::ReturnOutsideFunction);
-            }
-            
+            // at top level this ends the script, see run()
             let value = if let Some(expr) = expr {
                 interpret_node(expr, env, is_verbose, in_loop)?
             } else {
@@ -958,34 +1670,62 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
             
             Ok(Value::ReturnValue(Box::new(value)))
         },
-        ASTNode::Print(expr) => {
-            let value = interpret_node(expr, env, is_verbose, in_loop)?;
+        ASTNode::Print(args) => {
+            let mut values = Vec::new();
+            let (mut sep, mut end) = (" ".to_string(), "\n".to_string());
+            for arg in args {
+                match arg {
+                    ASTNode::KeywordArg(key, value) => {
+                        let slot = match key.as_str() {
+                            "sep" => &mut sep,
+                            "end" => &mut end,
+                            _ => return Err(Error::FunctionCallError(format!("print() does not accept keyword argument '{}'", key))),
+                        };
+                        *slot = match interpret_node(value, env, is_verbose, in_loop)? {
+                            Value::String(text) => text.to_string(),
+                            other => return Err(Error::TypeError(format!(
+                                "print() {} must be a string, got {}", key, type_str_of_value(&other)
+                            ))),
+                        };
+                    }
+                    ASTNode::Spread(expr) => values.extend(spread_values(interpret_node(expr, env, is_verbose, in_loop)?)?),
+                    _ => values.push(interpret_node(arg, env, is_verbose, in_loop)?),
+                }
+            }
+            let text = values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(&sep);
             if is_verbose {
-                println!("call print({})", value);
-            } else {
-                println!("{}", value);
+                errln!("call print({})", text);
             }
+            out!("{}{}", text, end);
             Ok(Value::Null)
         },
         ASTNode::UnaryOp(op, expr) => {
             let value = interpret_node(expr, env, is_verbose, in_loop)?;
             match (op, value) {
                 (Token::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+                (Token::Minus, Value::Number(n)) => n.checked_neg().map(Value::Number)
+                    .ok_or_else(|| Error::UnsupportedOperation(format!("Cannot negate {}: the result does not fit in an int", n))),
+                (Token::Minus, Value::Float(f)) => Ok(Value::Float(-f)),
+                (Token::Minus, value) => Err(Error::TypeError(format!("Cannot negate a value of type {}", type_str_of_value(&value)))),
                 _ => Err(Error::UnsupportedOperation(format!("Unsupported unary operation"))),
             }
         },
         ASTNode::While(condition, body) => {
             if is_verbose {
-                println!("\x1b[90m[DEBUG] Entering while loop\x1b[0m");
+                errln!("\x1b[90m[DEBUG] Entering while loop\x1b[0m");
             }
             env.push_scope();
             
+            let mut guard = LoopGuard::new(condition);
             let mut result = Value::Null;
             'outer: loop {
                 let cond_value = interpret_node(condition, env, is_verbose, true)?;
                 if let Value::Boolean(false) = cond_value {
                     break;
                 }
+                if let Some(guard) = &mut guard {
+                    guard.check(env)?;
+                }
         
                 for stmt in body {
                     match interpret_node(stmt, env, is_verbose, true)? {
@@ -995,6 +1735,10 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                         Value::Continue => {
                             continue 'outer;
                         },
+                        Value::ReturnValue(val) => {
+                            env.pop_scope();
+                            return Ok(Value::ReturnValue(val));
+                        },
                         val => result = val,
                     }
                 }
@@ -1002,19 +1746,24 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
         
             env.pop_scope();
             if is_verbose {
-                println!("\x1b[90m[DEBUG] Exiting while loop\x1b[0m");
+                errln!("\x1b[90m[DEBUG] Exiting while loop\x1b[0m");
             }
             Ok(result)
         },
         ASTNode::Var(name, expr, is_mutable) => {
+            if env.declared.contains(name) {
+                return Err(Error::VariableAlreadyDeclared(format!(
+                    "Variable '{}' is declared global in this function", name
+                )));
+            }
             if is_verbose {
-                println!("\x1b[90m[DEBUG] Variable declaration: {} (mutable: {})\x1b[0m", name, is_mutable);
+                errln!("\x1b[90m[DEBUG] Variable declaration: {} (mutable: {})\x1b[0m", name, is_mutable);
             }
             if *is_mutable {
                 if let Some(expr) = expr {
                     let val = interpret_node(expr, env, is_verbose, in_loop)?;
                     if is_verbose {
-                        println!("\x1b[90m[DEBUG] Variable '{}' initialized with value: {:?}\x1b[0m", name, val);
+                        errln!("\x1b[90m[DEBUG] Variable '{}' initialized with value: {:?}\x1b[0m", name, val);
                     }
                     if matches!(val, Value::Array(_)) {
                         check_array_mutability(expr, env, name)?;
@@ -1048,33 +1797,45 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                     *current_value = value.shallow_clone();
                 }
             } else {
-                return Err(Error::VariableNotDeclared(format!("Variable not declared: {}", name)));
+                return Err(Error::VariableNotDeclared(format!("Variable not declared: {}", env.not_declared_message(name))));
             }
             Ok(Value::Null)
         },
-        ASTNode::IndexAssign(array, index, value) => {
-            let array_name = if let ASTNode::Identifier(name) = &**array {
+        ASTNode::IndexAssign(target, index, value) => {
+            // the target is a variable or an index chain into one, as in matrix[i][j] = v;
+            let mut root = &**target;
+            while let ASTNode::Index(inner, _) = root {
+                root = inner;
+            }
+            let array_name = if let ASTNode::Identifier(name) = root {
                 name
             } else {
                 return Err(Error::TypeError(format!("Expected array identifier in index assignment")));
             };
 
+            match env.get(array_name) {
+                Some((_, false)) => return Err(Error::TypeError(format!("Cannot assign to immutable array '{}'", array_name))),
+                Some(_) => {},
+                None => return Err(Error::TypeError(format!("Array '{}' not found or is not mutable", array_name))),
+            }
+
+            // inner arrays are shared, so writing into the one the chain reaches updates the root
+            let array = interpret_node(target, env, is_verbose, in_loop)?;
             let index_value = interpret_node(index, env, is_verbose, in_loop)?;
             let value = interpret_node(value, env, is_verbose, in_loop)?;
 
+            let arr = if let Value::Array(arr) = array {
+                arr
+            } else {
+                return Err(Error::TypeError(format!("Cannot index-assign into a value of type {}", type_str_of_value(&array))));
+            };
+
             if let Value::Number(index) = index_value {
-                if let Some((Value::Array(arr), is_mutable)) = env.get_mut(array_name) {
-                    if !*is_mutable {
-                        return Err(Error::TypeError(format!("Cannot assign to immutable array '{}'", array_name)));
-                    }
-                    let mut guard = arr.lock().unwrap();
-                    if index as usize >= guard.len() {
-                        return Err(Error::IndexOutOfBounds(format!("Index out of bounds for array '{}'", array_name)));
-                    }
-                    guard[index as usize] = value;
-                } else {
-                    return Err(Error::TypeError(format!("Array '{}' not found or is not mutable", array_name)));
+                let mut guard = lock_array(&arr)?;
+                if index as usize >= guard.len() {
+                    return Err(Error::IndexOutOfBounds(format!("Index out of bounds for array '{}'", array_name)));
                 }
+                guard[index as usize] = value;
             } else {
                 return Err(Error::TypeError(format!("Expected integer index in array assignment")));
             }
@@ -1084,12 +1845,15 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
         ASTNode::Identifier(name) => {
             if let Some((value, _)) = env.get(name) {
                 Ok(value.clone())
+            } else if let Some(func) = env.user_function(name).cloned().or_else(|| std_function(name)) {
+                // a bare function name is a value, so it can be passed to libraries
+                Ok(func)
             } else {
-                Err(Error::VariableNotDeclared(format!("Variable not found: {}", name)))
+                Err(Error::VariableNotDeclared(format!("Variable not found: {}", env.not_declared_message(name))))
             }
         },
         ASTNode::TypeLiteral(type_name) => {
-            Ok(Value::Type(type_name.clone()))
+            Ok(Value::Type(type_name.as_str().into()))
         },
         ASTNode::Type(expr) => {
             let value = interpret_node(expr, env, is_verbose, in_loop)?;
@@ -1107,9 +1871,9 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                 Value::ReturnValue(ref val) => type_str_of_value(val),  // Use ref pattern
             };
             if is_verbose {
-                println!("call type({:?}) = {}", value, type_str);
+                errln!("call type({:?}) = {}", value, type_str);
             }
-            Ok(Value::Type(type_str.to_string()))
+            Ok(Value::Type(type_str.into()))
         },
         ASTNode::TypeCast(type_name, expr) => {
             let value = interpret_node(expr, env, is_verbose, in_loop)?;
@@ -1128,11 +1892,11 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                     _ => Err(Error::TypeError(format!("Cannot convert to int"))),
                 },
                 "str" => match value {
-                    Value::Number(n) => Ok(Value::String(n.to_string())),
-                    Value::Float(f) => Ok(Value::String(f.to_string())),
+                    Value::Number(n) => Ok(Value::String(n.to_string().into())),
+                    Value::Float(f) => Ok(Value::String(f.to_string().into())),
                     Value::String(s) => Ok(Value::String(s)),
-                    Value::Boolean(b) => Ok(Value::String(b.to_string())),
-                    Value::Null => Ok(Value::String("null".to_string())),
+                    Value::Boolean(b) => Ok(Value::String(b.to_string().into())),
+                    Value::Null => Ok(Value::String("null".into())),
                     _ => Err(Error::TypeError(format!("Cannot convert to string"))),
                 },
                 "float" => match value {
@@ -1160,14 +1924,14 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
         },
         ASTNode::If(condition, if_block, elif_blocks, else_block) => {
             if is_verbose {
-                println!("\x1b[90m[DEBUG] Evaluating if statement with {} elif blocks and else={}\x1b[0m", 
+                errln!("\x1b[90m[DEBUG] Evaluating if statement with {} elif blocks and else={}\x1b[0m", 
                     elif_blocks.len(), else_block.is_some());
             }
             let condition_value = interpret_node(condition, env, is_verbose, in_loop)?;
             if let Value::Boolean(true) = condition_value {
                 for stmt in if_block {
                     let result = interpret_node(stmt, env, is_verbose, in_loop)?;
-                    if matches!(result, Value::Break | Value::Continue) {
+                    if matches!(result, Value::Break | Value::Continue | Value::ReturnValue(_)) {
                         return Ok(result);
                     }
                 }
@@ -1178,7 +1942,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                     if let Value::Boolean(true) = elif_condition_value {
                         for stmt in elif_statements {
                             let result = interpret_node(stmt, env, is_verbose, in_loop)?;
-                            if matches!(result, Value::Break | Value::Continue) {
+                            if matches!(result, Value::Break | Value::Continue | Value::ReturnValue(_)) {
                                 return Ok(result);
                             }
                         }
@@ -1190,7 +1954,7 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                     if let Some(else_statements) = else_block {
                         for stmt in else_statements {
                             let result = interpret_node(stmt, env, is_verbose, in_loop)?;
-                            if matches!(result, Value::Break | Value::Continue) {
+                            if matches!(result, Value::Break | Value::Continue | Value::ReturnValue(_)) {
                                 return Ok(result);
                             }
                         }
@@ -1204,12 +1968,16 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
             
             interpret_node(init, env, is_verbose, true)?;
             
+            let mut guard = LoopGuard::new(condition);
             let mut result = Value::Null;
             'outer: loop {
                 let cond_value = interpret_node(condition, env, is_verbose, true)?;
                 if let Value::Boolean(false) = cond_value {
                     break;
                 }
+                if let Some(guard) = &mut guard {
+                    guard.check(env)?;
+                }
         
                 for stmt in body {
                     match interpret_node(stmt, env, is_verbose, true)? {
@@ -1219,6 +1987,10 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                         Value::Continue => {
                             continue 'outer;
                         },
+                        Value::ReturnValue(val) => {
+                            env.pop_scope();
+                            return Ok(Value::ReturnValue(val));
+                        },
                         val => result = val,
                     }
                 }
@@ -1229,12 +2001,42 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
             env.pop_scope();
             Ok(result)
         },
+        ASTNode::ForEach(name, values, body) => {
+            let values: Vec<Value> = match interpret_node(values, env, is_verbose, in_loop)? {
+                // a copy, so the body may change the array it walks
+                Value::Array(arr) => lock_array(&arr)?.clone(),
+                Value::String(s) => s.chars().map(|c| Value::String(c.to_string().into())).collect(),
+                other => return Err(Error::TypeError(format!(
+                    "for-in loops over an array or a string, not {}", type_str_of_value(&other)
+                ))),
+            };
+            env.push_scope();
+
+            let mut result = Value::Null;
+            'outer: for value in values {
+                env.insert_var(name.clone(), value, true);
+                for stmt in body {
+                    match interpret_node(stmt, env, is_verbose, true)? {
+                        Value::Break => break 'outer,
+                        Value::Continue => continue 'outer,
+                        Value::ReturnValue(val) => {
+                            env.pop_scope();
+                            return Ok(Value::ReturnValue(val));
+                        },
+                        val => result = val,
+                    }
+                }
+            }
+
+            env.pop_scope();
+            Ok(result)
+        },
         ASTNode::Break => {
             if !in_loop {
                 return Err(Error::BreakOutsideLoop);
             }
             if is_verbose {
-                println!("executing break statement");
+                errln!("executing break statement");
             }
             Ok(Value::Break)
         },
@@ -1243,21 +2045,426 @@ fn interpret_node(node: &ASTNode, env: &mut Environment, is_verbose: bool, in_lo
                 return Err(Error::ContinueOutsideLoop);
             }
             if is_verbose {
-                println!("executing continue statement"); 
+                errln!("executing continue statement"); 
             }
             Ok(Value::Continue)
         },
+        ASTNode::Global(names) => {
+            for name in names {
+                env.declare_global(name)?;
+            }
+            Ok(Value::Null)
+        },
+        ASTNode::KeywordArg(key, _) => {
+            Err(Error::SyntaxError(format!("Keyword argument '{}' is only allowed inside a function call", key)))
+        },
+        ASTNode::Spread(_) => {
+            Err(Error::SyntaxError("Spread '...' is only allowed inside a function call or array literal".to_string()))
+        },
+        ASTNode::Located(line, statement) => {
+            summary::statement();
+            let started = trace::enter();
+            let outer_line = CURRENT_LINE.with(|current| current.replace(*line));
+            let result = interpret_node(statement, env, is_verbose, in_loop);
+            // an error that left its node through ? skipped the capture
+            // below; take it here, while the line is still this statement's
+            if let Err(error) = &result {
+                if crash_dump::is_enabled() {
+                    crash_dump::capture(error, statement, *line, || env.dump_scopes());
+                }
+            }
+            CURRENT_LINE.with(|current| current.set(outer_line));
+            let value_type = match &result {
+                Ok(value) => type_str_of_value(value),
+                Err(_) => "error",
+            };
+            trace::record(*line, statement.kind(), started, value_type);
+            result
+        },
     };
 
     if is_verbose {
         if let Ok(ref val) = result {
-            println!("\x1b[90m[DEBUG] Node evaluation result: {:?}\x1b[0m", val);
+            errln!("\x1b[90m[DEBUG] Node evaluation result: {:?}\x1b[0m", val);
+        }
+    }
+
+    if let Err(error) = &result {
+        if crash_dump::is_enabled() {
+            crash_dump::capture(error, node, CURRENT_LINE.with(Cell::get), || env.dump_scopes());
         }
     }
 
     result
 }
 
+fn with_call_context<T>(env: &mut Environment, is_verbose: bool, f: impl FnOnce() -> T) -> T {
+    let context = CallContext {
+        functions: env.visible_functions(),
+        libraries: std::mem::take(&mut env.libraries),
+        is_verbose,
+        globals: env.take_globals(),
+    };
+    CALL_CONTEXT.with(|c| c.borrow_mut().push(context));
+    let result = f();
+    if let Some(context) = CALL_CONTEXT.with(|c| c.borrow_mut().pop()) {
+        env.return_globals(context.globals);
+        env.libraries = context.libraries;
+    }
+    result
+}
+
+// puts `libraries` in the innermost call context and returns the ones it
+// held; a callback borrows them this way and gives them back when it ends
+fn swap_context_libraries(libraries: IndexMap<String, Box<dyn Library>>) -> IndexMap<String, Box<dyn Library>> {
+    CALL_CONTEXT.with(|c| match c.borrow_mut().last_mut() {
+        Some(ctx) => std::mem::replace(&mut ctx.libraries, libraries),
+        None => IndexMap::new(),
+    })
+}
+
+// Calls a script function (or a library function such as std.print) from
+// native library code, e.g. a request handler passed to http.serve().
+pub fn call_function(func: &Value, args: Vec<Value>) -> Result<Value, Error> {
+    let (functions, is_verbose) = CALL_CONTEXT.with(|c| {
+        c.borrow().last().map(|ctx| (ctx.functions.clone(), ctx.is_verbose))
+    }).ok_or_else(|| Error::InterpreterError("Functions can only be called back during a library call".to_string()))?;
+
+    let (name, params, body) = match func {
+        Value::Function(name, params, body) => (name, params, body),
+        other => return Err(Error::TypeError(format!("Expected a function, got {}", type_str_of_value(other)))),
+    };
+
+    if let Some((lib_name, func_name)) = name.split_once('.') {
+        let libraries = swap_context_libraries(IndexMap::new());
+        let result = match libraries.get(lib_name).and_then(|lib| lib.get_function(func_name)) {
+            Some(native) => native(args),
+            None => Err(Error::InterpreterError(format!("Function '{}' not found", name))),
+        };
+        swap_context_libraries(libraries);
+        return result;
+    }
+
+    summary::function_call();
+    let _call = crash_dump::enter_call(name, CURRENT_LINE.with(Cell::get));
+    let mut func_env = Environment::new();
+    func_env.in_function = true;
+    func_env.parent = Some(Box::new(Environment {
+        scopes: vec![HashMap::new()],
+        functions,
+        in_function: true,
+        libraries: IndexMap::new(),
+        parent: None,
+        globals: None,
+        declared: HashSet::new(),
+        import_marks: Vec::new(),
+    }));
+
+    let bound_args = bind_arguments(name, params, args, Vec::new())?;
+    for (param, arg) in params.iter().zip(bound_args) {
+        func_env.insert_var(param.clone(), arg, true);
+    }
+
+    // the globals and libraries the library call was lent, given back once this call ends
+    func_env.globals = CALL_CONTEXT.with(|c| c.borrow_mut().last_mut().and_then(|ctx| ctx.globals.take()));
+    func_env.libraries = swap_context_libraries(IndexMap::new());
+    let inherited = func_env.libraries.len();
+    let result = (|| {
+        let mut result = Value::Null;
+        for stmt in body.iter() {
+            match interpret_node(stmt, &mut func_env, is_verbose, false)? {
+                Value::ReturnValue(val) => return Ok(*val),
+                val => result = val,
+            }
+        }
+        Ok(result)
+    })();
+    func_env.unload_libraries_from(inherited);
+    let globals = func_env.globals.take();
+    CALL_CONTEXT.with(|c| if let Some(ctx) = c.borrow_mut().last_mut() {
+        ctx.globals = globals;
+    });
+    swap_context_libraries(std::mem::take(&mut func_env.libraries));
+    result
+}
+
+// the value of `left op right` for every operator except the short-circuiting && and ||
+fn binary_op(op: &Token, left_val: Value, right_val: Value) -> Result<Value, Error> {
+    if matches!(op, Token::Equal | Token::NotEqual) {
+        check_float_equality(&left_val, &right_val)?;
+    }
+    match (left_val, right_val) {
+        (Value::Number(l), Value::Number(r)) => {
+            match op {
+                Token::Plus => Ok(Value::Number(l + r)),
+                Token::Minus => Ok(Value::Number(l - r)),
+                Token::Multiply => Ok(Value::Number(l * r)),
+                Token::Divide => Ok(Value::Float(l as f64 / r as f64)),
+                Token::Equal => Ok(Value::Boolean(l == r)),
+                Token::NotEqual => Ok(Value::Boolean(l != r)),
+                Token::Greater => Ok(Value::Boolean(l > r)),
+                Token::Less => Ok(Value::Boolean(l < r)),
+                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
+                Token::FloorDivide => Ok(Value::Number(l / r)),
+                Token::LessEqual => Ok(Value::Boolean(l <= r)),
+                Token::Modulus => Ok(Value::Number(l % r)),
+                // a negative exponent gives a fraction, as in 2 ** -1
+                Token::Power if r < 0 => Ok(Value::Float((l as f64).powi(r))),
+                Token::Power => l.checked_pow(r as u32).map(Value::Number).ok_or_else(|| Error::ResourceLimit(
+                    format!("{} ** {} is too large for an int; use a float base, as in {}.0 ** {}", l, r, l, r)
+                )),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for numbers"))),
+            }
+        }
+        (Value::Float(l), Value::Float(r)) => {
+            match op {
+                Token::Plus => Ok(Value::Float(l + r)),
+                Token::Minus => Ok(Value::Float(l - r)),
+                Token::Multiply => Ok(Value::Float(l * r)),
+                Token::Divide => Ok(Value::Float(l / r)),
+                Token::Equal => Ok(Value::Boolean(l == r)),
+                Token::NotEqual => Ok(Value::Boolean(l != r)),
+                Token::Greater => Ok(Value::Boolean(l > r)),
+                Token::Modulus => Ok(Value::Float(l % r)),
+                Token::FloorDivide => Ok(Value::Number((l / r).floor() as i32)),
+                Token::Less => Ok(Value::Boolean(l < r)),
+                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
+                Token::LessEqual => Ok(Value::Boolean(l <= r)),
+                Token::Power => Ok(Value::Float(l.powf(r))),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for floats"))),
+            }
+        }
+        (Value::Number(l), Value::Float(r)) => {
+            let l = l as f64;
+            match op {
+                Token::Plus => Ok(Value::Float(l + r)),
+                Token::Minus => Ok(Value::Float(l - r)),
+                Token::Multiply => Ok(Value::Float(l * r)),
+                Token::Divide => Ok(Value::Float(l / r)),
+                Token::Equal => Ok(Value::Boolean(l == r)),
+                Token::Modulus => Ok(Value::Float(l % r)),
+                Token::NotEqual => Ok(Value::Boolean(l != r)),
+                Token::Greater => Ok(Value::Boolean(l > r)),
+                Token::Less => Ok(Value::Boolean(l < r)),
+                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
+                Token::FloorDivide => Ok(Value::Number((l / r).floor() as i32)),
+                Token::Power => Ok(Value::Float(l.powf(r))),
+                Token::LessEqual => Ok(Value::Boolean(l <= r)),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for mixed number and float"))),
+            }
+        }
+        (Value::Float(l), Value::Number(r)) => {
+            let r = r as f64;
+            match op {
+                Token::Plus => Ok(Value::Float(l + r)),
+                Token::Minus => Ok(Value::Float(l - r)),
+                Token::Multiply => Ok(Value::Float(l * r)),
+                Token::Divide => Ok(Value::Float(l / r)),
+                Token::Equal => Ok(Value::Boolean(l == r)),
+                Token::Modulus => Ok(Value::Float(l % r)),
+                Token::NotEqual => Ok(Value::Boolean(l != r)),
+                Token::Greater => Ok(Value::Boolean(l > r)),
+                Token::Less => Ok(Value::Boolean(l < r)),
+                Token::GreaterEqual => Ok(Value::Boolean(l >= r)),
+                Token::FloorDivide => Ok(Value::Number((l / r).floor() as i32)),
+                Token::Power => Ok(Value::Float(l.powf(r))),
+                Token::LessEqual => Ok(Value::Boolean(l <= r)),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for mixed float and number"))),
+            }
+        }
+        (Value::String(s), Value::String(t)) => {
+            match op {
+                Token::Plus => Ok(Value::String(format!("{}{}", s, t).into())),
+                Token::Multiply => Err(Error::TypeError(format!("String can only be multiplied by an integer"))),
+                Token::Equal => Ok(Value::Boolean(s == t)),
+                Token::NotEqual => Ok(Value::Boolean(s != t)),
+                Token::Greater => Ok(Value::Boolean(s > t)),
+                Token::Less => Ok(Value::Boolean(s < t)),
+                Token::GreaterEqual => Ok(Value::Boolean(s >= t)),
+                Token::LessEqual => Ok(Value::Boolean(s <= t)),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for strings"))),
+            }
+        }
+
+        (Value::String(s), Value::Number(n)) => {
+            match op {
+                Token::Multiply => repeat_string(&s, n),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operation between string and number"))),
+            }
+        }
+        (Value::Number(n), Value::String(s)) => {
+            match op {
+                Token::Multiply => repeat_string(&s, n),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operation between number and string"))),
+            }
+        }
+        (Value::Boolean(b1), Value::Boolean(b2)) => {
+            match op {
+                Token::Equal => Ok(Value::Boolean(b1 == b2)),
+                Token::NotEqual => Ok(Value::Boolean(b1 != b2)),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for booleans"))),
+            }
+        }
+        (Value::Type(t1), Value::Type(t2)) => {
+            match op {
+                Token::Equal => Ok(Value::Boolean(t1 == t2)),
+                Token::NotEqual => Ok(Value::Boolean(t1 != t2)),
+                _ => Err(Error::UnsupportedOperation(format!("Unsupported operator for types"))),
+            }
+        }
+        (Value::Array(arr1), Value::Array(arr2)) => {
+            match op {
+                Token::Plus => {
+                    let mut new_vec = lock_array(&arr1)?.clone();
+                    new_vec.extend(lock_array(&arr2)?.iter().cloned());
+                    Ok(Value::Array(Arc::new(Mutex::new(new_vec))))
+                },
+                Token::Equal | Token::NotEqual | 
+                Token::Greater | Token::Less |
+                Token::GreaterEqual | Token::LessEqual => {
+                    let guard1 = lock_array(&arr1)?;
+                    // a == a must not lock the same array twice
+                    let other = if Arc::ptr_eq(&arr1, &arr2) { None } else { Some(lock_array(&arr2)?) };
+                    let guard2: &Vec<Value> = other.as_deref().unwrap_or(&guard1);
+                    
+                    if !guard1.is_empty() && !guard2.is_empty() {
+                        let type1 = type_str_of_value(&guard1[0]);
+                        let type2 = type_str_of_value(&guard2[0]);
+                        if type1 != type2 {
+                            return Err(Error::TypeError(
+                                format!("Cannot compare arrays of different types: {} and {}", type1, type2)
+                            ));
+                        }
+                    }
+                    
+                    match op {
+                        Token::Equal => Ok(Value::Boolean(guard1.len() == guard2.len() && 
+                            guard1.iter().zip(guard2.iter()).all(|(a, b)| a == b))),
+                        Token::NotEqual => Ok(Value::Boolean(!(guard1.len() == guard2.len() && 
+                            guard1.iter().zip(guard2.iter()).all(|(a, b)| a == b)))),
+                        Token::Greater => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
+                            guard1.iter().zip(guard2.iter())
+                                .find(|(a, b)| a != b)
+                                .map_or(guard1.len() > guard2.len(), |(a, b)| a > b))),
+                        Token::Less => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
+                            guard1.iter().zip(guard2.iter())
+                                .find(|(a, b)| a != b)
+                                .map_or(guard1.len() < guard2.len(), |(a, b)| a < b))),
+                        Token::GreaterEqual => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
+                            guard1.iter().zip(guard2.iter())
+                                .find(|(a, b)| a != b)
+                                .map_or(guard1.len() >= guard2.len(), |(a, b)| a >= b))),
+                        Token::LessEqual => Ok(Value::Boolean(guard1.len() > 0 && guard2.len() > 0 &&
+                            guard1.iter().zip(guard2.iter())
+                                .find(|(a, b)| a != b)
+                                .map_or(guard1.len() <= guard2.len(), |(a, b)| a <= b))),
+                        _ => unreachable!()
+                    }
+                },
+                _ => Err(Error::UnsupportedOperation("Unsupported operator for arrays".to_string())),
+            }
+        },
+        (Value::Array(arr), Value::Number(n)) | (Value::Number(n), Value::Array(arr)) => {
+            match op {
+                Token::Multiply => {
+                    let guard = lock_array(&arr)?;
+                    let times = n.max(0) as usize;
+                    let length = guard.len().checked_mul(times);
+                    check_allocation(length.and_then(|length| length.checked_mul(std::mem::size_of::<Value>())), || {
+                        format!("repeating an array of {} elements {} times", guard.len(), n)
+                    })?;
+                    let mut new_vec = Vec::with_capacity(length.unwrap_or(0));
+                    for _ in 0..times {
+                        new_vec.extend(guard.iter().cloned());
+                    }
+                    Ok(Value::Array(Arc::new(Mutex::new(new_vec))))
+                },
+                _ => Err(Error::UnsupportedOperation("Arrays can only be multiplied by numbers".to_string())),
+            }
+        },
+        _ => Err(Error::UnsupportedOperation(format!("Unsupported operation for given types"))),
+    }
+}
+
+// s * n; a negative n gives the empty string
+fn repeat_string(s: &str, n: i32) -> Result<Value, Error> {
+    let times = n.max(0) as usize;
+    check_allocation(s.len().checked_mul(times), || format!("repeating a string of {} bytes {} times", s.len(), n))?;
+    Ok(Value::String(s.repeat(times).into()))
+}
+
+// whether `a == b` is true in a script, for library code comparing values:
+// an int equals the float with its value, and values == can't compare, like
+// a string and an int, are unequal. Unlike ==, it never warns
+pub fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(n), Value::Float(f)) | (Value::Float(f), Value::Number(n)) => *n as f64 == *f,
+        _ => a == b,
+    }
+}
+
+fn check_float_equality(left: &Value, right: &Value) -> Result<(), Error> {
+    match (left, right) {
+        (Value::Float(_), Value::Float(_)) => {
+            warn("comparing floats with == or != is unreliable; use math.isclose(a, b) instead");
+        }
+        (Value::Number(_), Value::Float(_)) | (Value::Float(_), Value::Number(_)) => {
+            if settings(|settings| settings.strict) {
+                return Err(Error::TypeError(
+                    "Cannot compare int and float with == or != in strict mode; convert one side with int() or float()".to_string()
+                ));
+            }
+            warn("comparing int and float with == or != converts the int implicitly; use an explicit int() or float() cast");
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn spread_values(value: Value) -> Result<Vec<Value>, Error> {
+    match value {
+        Value::Array(arr) => Ok(lock_array(&arr)?.clone()),
+        other => Err(Error::TypeError(format!("Cannot spread value of type {}", type_str_of_value(&other)))),
+    }
+}
+
+// match positional args in order, then fill the rest by parameter name
+fn bind_arguments(func_name: &str, params: &[String], positional: Vec<Value>, keywords: Vec<(String, Value)>) -> Result<Vec<Value>, Error> {
+    if positional.len() > params.len() || (keywords.is_empty() && positional.len() != params.len()) {
+        return Err(Error::InvalidFunctionArguments(
+            func_name.to_string(),
+            params.len(),
+            positional.len() + keywords.len()
+        ));
+    }
+
+    let mut slots: Vec<Option<Value>> = positional.into_iter().map(Some).collect();
+    slots.resize(params.len(), None);
+
+    for (key, value) in keywords {
+        let idx = params.iter().position(|p| *p == key).ok_or_else(|| Error::FunctionCallError(
+            format!("'{}' got an unexpected keyword argument '{}'", func_name, key)
+        ))?;
+        if slots[idx].is_some() {
+            return Err(Error::FunctionCallError(
+                format!("'{}' got multiple values for argument '{}'", func_name, key)
+            ));
+        }
+        slots[idx] = Some(value);
+    }
+
+    let mut bound = Vec::with_capacity(params.len());
+    for (param, slot) in params.iter().zip(slots) {
+        match slot {
+            Some(value) => bound.push(value),
+            None => return Err(Error::FunctionCallError(
+                format!("'{}' missing required argument '{}'", func_name, param)
+            )),
+        }
+    }
+    Ok(bound)
+}
+
 fn normalize_slice_indices(start: Option<i32>, stop: Option<i32>, len: i32) -> (i32, i32) {
     let start = match start {
         Some(n) if n < 0 => len + n,
@@ -1292,4 +2499,690 @@ fn check_array_mutability(expr: &ASTNode, env: &Environment, target_name: &str)
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::CannedInput;
+    use crate::output::CaptureOutput;
+
+    fn eval(source: &str) -> Result<Value, Error> {
+        let ast = Parser::new(source).parse()?;
+        Session::new(false).eval(ast)
+    }
+
+    // like eval, with statement lines recorded as `td run` does for --trace-out and --loop-guard
+    fn eval_lines(source: &str) -> Result<Value, Error> {
+        let ast = Parser::new(source).with_lines().parse()?;
+        Session::new(false).eval(ast)
+    }
+
+    // like eval_lines, in a session with these settings
+    fn eval_with(settings: &Settings, source: &str) -> Result<Value, Error> {
+        let ast = Parser::new(source).with_lines().parse()?;
+        Session::new(false).with_settings(settings.clone()).eval(ast)
+    }
+
+    // the settings of a script in dir, whose external libraries are found there
+    fn script_in(dir: &std::path::Path) -> Settings {
+        Settings { script: Some(dir.join("main.td").to_string_lossy().into_owned()), ..Settings::default() }
+    }
+
+    fn error(source: &str) -> String {
+        match eval(source) {
+            Ok(value) => panic!("expected an error, got {:?}", value),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    const SUB: &str = "func sub(a, b) { return a - b; }\n";
+
+    #[test]
+    fn keyword_args_bind_by_name() {
+        assert_eq!(eval(&format!("{}sub(b = 1, a = 10);", SUB)).unwrap(), Value::Number(9));
+        assert_eq!(eval(&format!("{}sub(10, b = 1);", SUB)).unwrap(), Value::Number(9));
+    }
+
+    #[test]
+    fn keyword_args_reject_unknown_duplicate_and_missing_names() {
+        assert!(error(&format!("{}sub(1, c = 2);", SUB)).contains("unexpected keyword argument 'c'"));
+        assert!(error(&format!("{}sub(1, a = 2);", SUB)).contains("multiple values for argument 'a'"));
+        assert!(error(&format!("{}sub(b = 2);", SUB)).contains("missing required argument 'a'"));
+        assert!(error(&format!("{}sub(1, 2, 3);", SUB)).contains("expects 2 arguments but got 3"));
+    }
+
+    #[test]
+    fn keyword_args_are_not_passed_to_native_functions() {
+        assert!(error("var a = [2, 1]; sort(a, key = 1);").contains("'sort' does not accept keyword argument 'key'"));
+        assert!(error("import(math, embedded); math.sqrt(x = 4);").contains("does not accept keyword argument 'x'"));
+    }
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    #[test]
+    fn library_values_set_in_a_function_outlive_the_call() {
+        let value = |source: &str| eval(source).unwrap().to_string();
+        assert_eq!(
+            value("import(math);\nimport(http);\nfunc setup() { math.tolerance = 0.5; http.timeout = 2; }\nsetup();\n[math.tolerance, http.timeout, math.isclose(1, 1.3)];"),
+            "[0.5, 2, true]"
+        );
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        let source = "import(math);\nimport(bench);\nfunc loosen() { math.tolerance = 0.25; }\nbench.run(\"loosen\", loosen, 1);\n[math.tolerance];";
+        assert_eq!(session.eval(Parser::new(source).parse().unwrap()).unwrap().to_string(), "[0.25]");
+    }
+
+    #[test]
+    fn a_callback_lends_the_libraries_back_without_its_own_imports() {
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        let source = "import(bench);\nfunc helper() { import(math); return math.abs(-1); }\nbench.run(\"helper\", helper, 1);\nreturn math.pi;";
+        let error = session.eval(Parser::new(source).parse().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("Library 'math' not found"), "{}", error);
+        let source = "func twice() { return bench.run(\"again\", helper, 1); }\ntwice();\n[dir(bench)];";
+        assert!(session.eval(Parser::new(source).parse().unwrap()).unwrap().to_string().contains("run"));
+    }
+
+    #[test]
+    fn callbacks_can_change_globals() {
+        let source = "var calls = 0;\n\
+            func tick() { global calls; calls = calls + 1; }\n\
+            func run() { import(bench); bench.run(\"tick\", tick, 10); }\n\
+            import(bench);\n\
+            bench.run(\"tick\", tick, 10);\n\
+            run();\n\
+            [calls];";
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        // ten timed calls and one warmup each time, from the top level and from inside run()
+        assert_eq!(session.eval(Parser::new(source).parse().unwrap()).unwrap().to_string(), "[22]");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_signal_handler_can_set_a_global_flag() {
+        let mut session = Session::new(false);
+        let mut eval = |source: &str| session.eval(Parser::new(source).parse().unwrap()).unwrap().to_string();
+        eval("var stop = false;\nfunc on_usr2(name) { global stop; stop = name == \"USR2\"; }\nimport(sys);\nsys.on_signal(\"USR2\", on_usr2);");
+        signal_hook::low_level::raise(signal_hook::consts::SIGUSR2).unwrap();
+        assert_eq!(eval("var checked = 1; [stop];"), "[true]");
+    }
+
+    #[test]
+    fn for_in_walks_arrays_and_strings() {
+        let value = |source: &str| eval(source).unwrap().to_string();
+        assert_eq!(value("var out = []; for (x in [1, 2, 3]) { insert(out, x * 2); } [out];"), "[[2, 4, 6]]");
+        assert_eq!(value("var out = \"\"; for (c in \"héllo\") { out = c + out; } [out];"), "[olléh]");
+        // the loop walks a copy, so changing the array doesn't change the loop
+        assert_eq!(value("var a = [1, 2]; var n = 0; for (x in a) { insert(a, x); n = n + 1; } [n, a];"), "[2, [1, 2, 1, 2]]");
+        assert_eq!(value("func f(a) { for (x in a) { if (x > 1) { return x; } } return 0; } f([1, 5, 9]);"), "5");
+        assert_eq!(value("var n = 0; for (x in [1, 2, 3, 4]) { if (x == 2) { continue; } if (x == 4) { break; } n = n + x; } [n];"), "[4]");
+        // `in` is still a name outside the loop header
+        assert_eq!(value("var in = [7]; var out = 0; for (x in in) { out = x; } [out];"), "[7]");
+
+        assert!(error("for (x in 5) { print(x); }").contains("for-in loops over an array or a string, not int"));
+        // the loop variable ends with the loop
+        assert!(error("for (x in [1]) { } print(x);").contains("Variable not found: x"));
+    }
+
+    #[test]
+    fn parameters_can_be_patterns() {
+        let head = "func head([first, ...rest]) { \"docs\"; return [first, rest]; }\n";
+        let value = |source: &str| eval(source).unwrap().to_string();
+        assert_eq!(value(&format!("{}head([1, 2, 3]);", head)), "[1, [2, 3]]");
+        assert_eq!(value(&format!("{}head(\"abc\");", head)), "[a, bc]");
+        assert_eq!(value(&format!("{}head([1]);", head)), "[1, []]");
+        let point = "func point({x, y}, scale) { return [x * scale, y]; }\n";
+        assert_eq!(value(&format!("{}point([[\"y\", 2], [\"x\", 1]], 10);", point)), "[10, 2]");
+        assert_eq!(value("func keys({a, b}) { return [a, b]; }\nkeys([[\"a\", 1]]);"), "[1, null]");
+        assert!(eval_lines(&format!("{}head([1, 2]);", head)).is_ok());
+
+        assert!(error(&format!("{}head([]);", head)).contains("Index out of bounds"));
+        assert!(error("func f([a, a]) { return a; }").contains("'a' is bound twice in the parameters of 'f'"));
+        assert!(error("func f([...a, b]) { return a; }").contains("'...a' has to be the last name"));
+        assert!(error("func f([a]) { var a = 1; }").contains("already been declared"));
+    }
+
+    #[test]
+    fn spread_expands_arrays_in_calls_and_literals() {
+        assert_eq!(eval(&format!("{}var args = [10, 1]; sub(...args);", SUB)).unwrap(), Value::Number(9));
+        assert_eq!(eval(&format!("{}sub(...[10], b = 1);", SUB)).unwrap(), Value::Number(9));
+        assert_eq!(
+            eval("var a = [2, 3]; [1, ...a, ...[], 4];").unwrap(),
+            array(vec![Value::Number(1), Value::Number(2), Value::Number(3), Value::Number(4)])
+        );
+        assert_eq!(eval("import(math, embedded); math.gcd(...[12, 18]);").unwrap(), Value::Number(6));
+    }
+
+    #[test]
+    fn spread_rejects_non_arrays_and_bad_positions() {
+        assert!(error(&format!("{}sub(...5);", SUB)).contains("Cannot spread value of type int"));
+        assert!(error(&format!("{}sub(...[1, 2, 3]);", SUB)).contains("expects 2 arguments but got 3"));
+        assert!(error(&format!("{}sub(a = 1, ...[2]);", SUB)).contains("Spread argument follows keyword argument"));
+        assert!(error("var a = [1]; var b = ...a;").contains("Unexpected token in factor: Ellipsis"));
+    }
+
+    #[test]
+    fn global_reads_and_writes_the_script_scope() {
+        let source = "var total = 1;\nfunc bump(by) { global total; total = total + by; return total; }\nbump(2); bump(3); return total;";
+        assert_eq!(eval(source).unwrap(), Value::Number(6));
+    }
+
+    #[test]
+    fn global_is_required_and_checked() {
+        assert!(error("var total = 1; func f() { return total; } f();")
+            .contains("declare it with 'global total;'"));
+        assert!(error("func f() { global missing; } f();").contains("'missing' not found in global scope"));
+        assert!(error("var x = 1; global x;").contains("'global' declaration outside function"));
+        assert!(error("var x = 1; func f() { var x = 2; global x; } f();").contains("is a local"));
+        assert!(error("var x = 1; func f() { global x; var x = 2; } f();").contains("declared global"));
+    }
+
+    #[test]
+    fn global_only_reaches_the_top_level_scope() {
+        // a function sees the script's variables, not those of the block it was called from
+        let source = "var i = 0;\nfunc f() { global local; return local; }\nwhile (i < 1) { var local = 5; i = i + 1; f(); }";
+        assert!(error(source).contains("'local' not found in global scope"));
+        assert_eq!(eval("var outer = 1; return outer;").unwrap(), Value::Number(1));
+    }
+
+    #[test]
+    fn top_level_return_stops_the_script() {
+        let result = run(Parser::new("var x = 1;\nreturn x + 1;\nx = 10;").parse().unwrap(), false, Settings::default()).unwrap();
+        assert!(result.returned);
+        assert_eq!(result.value, Some(Value::Number(2)));
+
+        let result = run(Parser::new("var x = 1;").parse().unwrap(), false, Settings::default()).unwrap();
+        assert!(!result.returned);
+
+        // from inside a loop, and with no value
+        let source = "var i = 0;\nwhile (true) { i = i + 1; if (i == 3) { return i; } }";
+        assert_eq!(eval(source).unwrap(), Value::Number(3));
+        assert_eq!(eval("return; var x = 1;").unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn session_eval_stops_at_return() {
+        let mut session = Session::new(false);
+        let ast = Parser::new("var x = 1;\nreturn x;\nvar y = 2;").parse().unwrap();
+        assert_eq!(session.eval(ast).unwrap(), Value::Number(1));
+        let names: Vec<String> = session.variables().into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(names, vec!["x".to_string()]);
+    }
+
+    #[test]
+    fn float_equality_warns_once_and_strict_mode_rejects_mixed_comparisons() {
+        let capture = CaptureOutput::new();
+        let session = |strict| Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()))
+            .with_settings(Settings { warnings: true, strict, ..Settings::default() });
+        let eval = |session: &mut Session, source: &str| session.eval(Parser::new(source).parse().unwrap());
+        let warned = eval(&mut session(false), "var a = 0.1 + 0.2 == 0.3; var b = 1.5 != 2.5;");
+        let mut strict_session = session(true);
+        let strict = eval(&mut strict_session, "var c = 1 == 1.0;");
+        let ints = eval(&mut strict_session, "var d = 1 == 1;");
+
+        assert!(warned.is_ok());
+        assert_eq!(capture.stderr().matches("comparing floats with == or != is unreliable").count(), 1);
+        assert!(strict.unwrap_err().to_string().contains("Cannot compare int and float with == or != in strict mode"));
+        assert!(ints.is_ok());
+    }
+
+    #[test]
+    fn each_run_warns_afresh() {
+        let warning = "comparing floats with == or != is unreliable";
+        let capture = CaptureOutput::new();
+        let warnings = Settings { warnings: true, ..Settings::default() };
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default())).with_settings(warnings.clone());
+        let entries = ["var a = 0.5 == 0.5;", "var b = 0.5 == 0.5;"].map(|source| session.eval(Parser::new(source).parse().unwrap()));
+        let runs = [0, 1].map(|_| run_source_captured("var a = 0.5 == 0.5;", &[], warnings.clone()));
+
+        assert!(entries.iter().all(Result::is_ok));
+        assert_eq!(capture.stderr().matches(warning).count(), 2);
+        assert!(runs.iter().all(|run| run.stderr.matches(warning).count() == 1));
+    }
+
+    #[test]
+    fn session_listings_have_a_stable_order() {
+        let mut session = Session::new(false);
+        let source = "import(sys, embedded); import(math, embedded);\nvar b = 1; var c = 2; var a = 3; del(c);\nfunc z() { return 1; }\nfunc y() { return 2; }";
+        session.eval(Parser::new(source).parse().unwrap()).unwrap();
+        let variables: Vec<String> = session.variables().into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(variables, vec!["a", "b"]);
+        let functions: Vec<String> = session.functions().into_iter().map(|(name, _, _)| name).collect();
+        assert_eq!(functions, vec!["z", "y"]);
+        assert_eq!(session.libraries(), vec!["sys", "math"]);
+    }
+
+    #[test]
+    fn session_output_captures_print_and_restores_the_previous_sink() {
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        let ast = Parser::new("print(\"hello\");\nvar x = 2;\nprint(x * 21);").parse().unwrap();
+        session.eval(ast).unwrap();
+        assert_eq!(capture.stdout(), "hello\n42\n");
+
+        // nothing leaks into a capture that is no longer installed
+        let other = CaptureOutput::new();
+        let previous = output::set_output(Box::new(other.clone()));
+        outln!("after eval");
+        output::set_output(previous);
+        assert_eq!(capture.stdout(), "hello\n42\n");
+        assert_eq!(other.stdout(), "after eval\n");
+    }
+
+    #[test]
+    fn print_joins_its_values_with_sep_and_end() {
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        let source = "print(1, \"a\", 2.5);\nprint(1, 2, sep = \", \", end = \"\");\nprint(...[3, 4], sep = \"-\");\nprint();\n\
+                      std.write(\"x\", 1);\nstd.write(\"!\");\nstd.print(\"a\", \"b\");";
+        session.eval(Parser::new(source).parse().unwrap()).unwrap();
+        assert_eq!(capture.stdout(), "1 a 2.5\n1, 23-4\n\nx 1!a b\n");
+
+        assert!(error("print(1, end = 2);").contains("print() end must be a string, got int"));
+        assert!(error("print(1, flush = true);").contains("print() does not accept keyword argument 'flush'"));
+    }
+
+    #[test]
+    fn captured_runs_return_output_and_value() {
+        let run = run_source_captured("var name = input(\"name? \");\nprint(\"hi\", name);\nreturn 7;", &["ada"], Settings::default());
+        assert_eq!(run.stdout, "name? hi ada\n");
+        assert_eq!(run.stderr, "");
+        assert_eq!(run.value.unwrap(), Some(Value::Number(7)));
+
+        let run = run_source_captured("print(1);\nprint(missing);", &[], Settings::default());
+        assert_eq!(run.stdout, "1\n");
+        assert!(run.value.unwrap_err().to_string().contains("missing"));
+        assert!(run_source_captured("print(", &[], Settings::default()).value.is_err());
+
+        // the thread's own sink is back once the run is over
+        let other = CaptureOutput::new();
+        let previous = output::set_output(Box::new(other.clone()));
+        run_source_captured("print(2);", &[], Settings::default());
+        output::set_output(previous);
+        assert_eq!(other.stdout(), "");
+    }
+
+    #[test]
+    fn sessions_on_one_thread_keep_their_own_settings() {
+        let capture = CaptureOutput::new();
+        let session = |settings| Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default())).with_settings(settings);
+        let mut strict = session(Settings { warnings: true, strict: true, seed: Some(7), ..Settings::default() });
+        let mut plain = session(Settings { seed: Some(8), ..Settings::default() });
+        let eval = |session: &mut Session, source: &str| session.eval(Parser::new(source).parse().unwrap());
+
+        let draws = ["import(random); return random.randint(1, 1000000);"; 2];
+        let (seven, eight) = (eval(&mut strict, draws[0]).unwrap(), eval(&mut plain, draws[1]).unwrap());
+        assert!(eval(&mut strict, "var a = 1 == 1.0;").is_err());
+        assert_eq!(eval(&mut plain, "return 1 == 1.0;").unwrap(), Value::Boolean(true));
+        assert!(eval(&mut strict, "var b = 2 == 2.0;").is_err());
+
+        // a callback made by a library call runs with the settings of its session
+        let callback = "import(bench); func f() { var c = 1 == 1.0; } bench.run(\"f\", f, 1);";
+        assert!(eval(&mut strict, callback).unwrap_err().to_string().contains("strict mode"));
+        assert!(eval(&mut plain, callback).is_ok());
+
+        for (seed, draw) in [(7, seven), (8, eight)] {
+            let previous = lend_settings(Settings { seed: Some(seed), ..Settings::default() });
+            let expected = crate::libs::random::RandomLib::new().get_function("randint").unwrap()(vec![Value::Number(1), Value::Number(1000000)]);
+            lend_settings(previous);
+            assert_eq!(draw, expected.unwrap());
+        }
+        // the thread has its defaults back between entries
+        assert!(!settings(|settings| settings.strict || settings.warnings));
+        assert_eq!(settings(|settings| settings.seed), None);
+    }
+
+    #[test]
+    fn scripts_on_separate_threads_do_not_share_state() {
+        let source = "import(random);\nfunc square(n) { return n * n; }\n\
+                      var total = 0; var i = 0;\nwhile (i < 50) { total = total + square(i); i = i + 1; }\n\
+                      if (0.1 + 0.2 == 0.3) { print(\"equal\"); }\nprint(total, random.randint(1, 1000000));\nreturn total;";
+        let expected_draw = |seed: u64| {
+            let previous = lend_settings(Settings { seed: Some(seed), ..Settings::default() });
+            let draw = crate::libs::random::RandomLib::new().get_function("randint").unwrap()(vec![Value::Number(1), Value::Number(1000000)]);
+            lend_settings(previous);
+            draw.unwrap()
+        };
+        let runs: Vec<_> = (0..100u64)
+            .map(|n| {
+                std::thread::Builder::new()
+                    .stack_size(8 * 1024 * 1024)
+                    .spawn(move || {
+                        // every other script warns, and each draws from its own seed
+                        let settings = Settings { warnings: n % 2 == 0, seed: Some(n), ..Settings::default() };
+                        (n, run_source_captured(source, &[], settings))
+                    })
+                    .unwrap()
+            })
+            .collect();
+        for handle in runs {
+            let (n, run) = handle.join().unwrap();
+            assert_eq!(run.value.unwrap(), Some(Value::Number(40425)));
+            assert_eq!(run.stdout, format!("40425 {}\n", expected_draw(n)));
+            assert_eq!(run.stderr.contains("comparing floats"), n % 2 == 0, "script {}: {:?}", n, run.stderr);
+        }
+    }
+
+    #[test]
+    fn a_cancelled_token_stops_the_run() {
+        let token = CancellationToken::new();
+        let host = token.clone();
+        let run = std::thread::spawn(move || {
+            let settings = Settings { cancellation: Some(token), ..Settings::default() };
+            run_source_captured("print(\"started\");\nvar i = 0;\nwhile (true) { i = i + 1; }", &[], settings)
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        host.cancel();
+        let run = run.join().unwrap();
+        assert!(matches!(run.value, Err(Error::Cancelled)));
+        assert_eq!(run.stdout, "started\n");
+
+        // a token cancelled up front stops the script before it prints
+        let token = CancellationToken::new();
+        token.cancel();
+        let run = run_source_captured("print(1);", &[], Settings { cancellation: Some(token), ..Settings::default() });
+        assert_eq!(run.value.unwrap_err().to_string(), "Cancelled: the script was stopped by its host");
+        assert_eq!(run.stdout, "");
+    }
+
+    #[test]
+    fn a_run_reports_what_it_defined() {
+        let ast = Parser::new("import(math);\nvar b = 2;\nnovar a = \"x\";\nfunc area(w, h) { var inner = 1; return w * h; }\nfunc none() { return 0; }").parse().unwrap();
+        let result = run(ast, false, Settings::default()).unwrap();
+        let definitions = result.definitions;
+        assert_eq!(definitions.variables, vec![
+            ("a".to_string(), Value::String("x".into()), false),
+            ("b".to_string(), Value::Number(2), true),
+        ]);
+        assert_eq!(definitions.functions, vec![("area".to_string(), 2), ("none".to_string(), 0)]);
+        assert_eq!(definitions.libraries, vec!["math".to_string()]);
+    }
+
+    #[test]
+    fn verbose_tracing_goes_to_stderr() {
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(true, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        session.eval(Parser::new("var x = 2;\nprint(x * 21);").parse().unwrap()).unwrap();
+        assert_eq!(capture.stdout(), "42\n");
+        assert!(capture.stderr().contains("[DEBUG] Variable declaration: x"));
+        assert!(capture.stderr().contains("call print(42)"));
+    }
+
+    #[test]
+    fn session_input_feeds_input_calls() {
+        let capture = CaptureOutput::new();
+        let lines = CannedInput::new(["  Ada  ", "36"]);
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(lines));
+        let ast = Parser::new("var name = input(\"name? \");\nvar age = int(input(\"age? \"));\nvar rest = input(\"more? \");\nreturn [name, age + 1, rest];").parse().unwrap();
+        let result = session.eval(ast).unwrap();
+
+        let expected = vec![Value::String("Ada".into()), Value::Number(37), Value::String("".into())];
+        assert_eq!(result, Value::Array(Arc::new(Mutex::new(expected))));
+        assert_eq!(capture.stdout(), "name? age? more? ");
+    }
+
+    #[test]
+    fn exit_unwinds_loops_and_flushes_earlier_output() {
+        let capture = CaptureOutput::new();
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        let source = "import(sys, embedded);\nfor (var i = 0; i < 10; i = i + 1) {\n    print(i);\n    if (i == 2) { sys.exit(4); }\n}\nprint(\"unreachable\");";
+        let result = session.eval(Parser::new(source).parse().unwrap());
+        assert!(matches!(result, Err(Error::Exit(4))));
+        assert_eq!(capture.stdout(), "0\n1\n2\n");
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_power() {
+        assert_eq!(eval("return -2 ** 2;").unwrap(), Value::Number(-4));
+        assert_eq!(eval("return (-2) ** 2;").unwrap(), Value::Number(4));
+        assert_eq!(eval("return 2 ** -1.0;").unwrap(), Value::Float(0.5));
+        assert_eq!(eval("return -3 * 2 - -1;").unwrap(), Value::Number(-5));
+        assert_eq!(eval("var x = 1.5; return -x;").unwrap(), Value::Float(-1.5));
+        assert!(error("return -\"a\";").contains("Cannot negate a value of type str"));
+    }
+
+    #[test]
+    fn comparisons_chain() {
+        assert_eq!(eval("var x = 5; return 1 < x < 10;").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("var x = 50; return 1 < x < 10;").unwrap(), Value::Boolean(false));
+        assert_eq!(eval("return 1 <= 1 == 1 < 2;").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("return 3 > 2 > 1 > 1;").unwrap(), Value::Boolean(false));
+
+        // the middle operand runs once, and a false link skips the rest
+        let source = "var calls = 0;\nfunc mid() { global calls; calls = calls + 1; return 5; }\n1 < mid() < 10; 9 < mid() < undefined_name; return calls;";
+        assert_eq!(eval(source).unwrap(), Value::Number(2));
+        assert!(error("return 1 < 2 < \"a\";").contains("Unsupported operation"));
+    }
+
+    #[test]
+    fn nested_index_assignment_writes_through() {
+        let source = "var m = [[1, 2], [3, [4, 5]]];\nvar row = m[0];\nm[0][1] = 20;\nm[1][1][0] = 40;\nreturn [m[0][1], row[1], m[1][1][0]];";
+        assert_eq!(eval(source).unwrap().to_string(), "[20, 20, 40]");
+        assert!(error("novar m = [[1]]; m[0][0] = 2;").contains("Cannot assign to immutable array 'm'"));
+        assert!(error("var m = [[1]]; m[0][5] = 2;").contains("Index out of bounds for array 'm'"));
+        assert!(error("var m = [1]; m[0][0] = 2;").contains("Cannot index-assign into a value of type int"));
+    }
+
+    #[test]
+    fn std_array_mutators_respect_novar() {
+        assert_eq!(eval("var a = [3, 1, 2]; var b = a; sort(a); return b;").unwrap().to_string(), "[1, 2, 3]");
+        assert_eq!(eval("return reverse([1, 2]);").unwrap().to_string(), "[2, 1]");
+        assert!(error("novar a = [2, 1]; sort(a);").contains("Cannot modify immutable array 'a'"));
+        assert!(error("novar a = [1]; extend(a, [2]);").contains("Cannot modify immutable array 'a'"));
+    }
+
+    #[test]
+    fn an_array_compares_and_concatenates_with_itself() {
+        assert_eq!(eval("var a = [1, 2]; return [a == a, a < a, a != a];").unwrap().to_string(), "[true, false, false]");
+        assert_eq!(eval("var a = [1, 2]; return a + a;").unwrap().to_string(), "[1, 2, 1, 2]");
+    }
+
+    #[test]
+    fn a_missing_import_is_suggested() {
+        let hint = "Library 'math' not found\nhint: add import(math); before using it";
+        assert!(error("math.sqrt(4);").ends_with(hint));
+        assert!(error("var pi = math.PI;").ends_with(hint));
+        assert!(error("math.tolerance = 1;").ends_with(hint));
+        assert!(error("nope.f();").ends_with("Library 'nope' not found"));
+    }
+
+    #[test]
+    fn imports_end_with_their_block() {
+        assert_eq!(eval("import(math); import(math); math.abs(-2);").unwrap(), Value::Number(2));
+        assert_eq!(eval("var i = 0; while (i < 1) { import(math); i = math.abs(1); }\nreturn i;").unwrap(), Value::Number(1));
+        assert!(error("var i = 0; while (i < 1) { import(math); i = 1; }\nmath.abs(-2);").contains("Library 'math' not found"));
+        assert!(error("func f() { import(math); return math.abs(-3); }\nf(); math.abs(-2);").contains("Library 'math' not found"));
+        // an import the script already made is left alone by the block's
+        assert_eq!(eval("import(math); var i = 0; while (i < 1) { import(math); i = 1; }\nmath.abs(-2);").unwrap(), Value::Number(2));
+    }
+
+    #[test]
+    fn import_checks_the_library_version() {
+        let mut lib = ExternalLibrary::new(Parser::new("novar __version__ = \"1.2.0\";").parse().unwrap());
+        lib.initialize().unwrap();
+        let mut env = Environment::new();
+        env.libraries.insert("mylib".to_string(), Box::new(lib));
+
+        assert!(env.check_library_version("mylib", &Token::GreaterEqual, "1.0").is_ok());
+        assert!(env.check_library_version("mylib", &Token::Equal, "1.2").is_ok());
+        assert!(env.check_library_version("mylib", &Token::Less, "1.10").is_ok());
+        assert_eq!(
+            env.check_library_version("mylib", &Token::GreaterEqual, "2.0").unwrap_err().to_string(),
+            "Library error: Library 'mylib' 1.2.0 is installed, but >= \"2.0\" is required"
+        );
+        assert!(env.check_library_version("mylib", &Token::Greater, "1.x").unwrap_err().to_string().contains("Invalid version \"1.x\""));
+
+        assert!(error("import(math >= \"1.0\");").contains("Library 'math' does not declare a __version__"));
+        assert!(error("import(math >= 1);").contains("Expected a version string"));
+    }
+
+    #[test]
+    fn library_hooks_run_once_each() {
+        let capture = CaptureOutput::new();
+        let previous = output::set_output(Box::new(capture.clone()));
+
+        let source = "func __init__() { print(\"init\"); }\nfunc __teardown__() { print(\"teardown\"); }\nfunc one() { return 1; }";
+        let mut lib = ExternalLibrary::new(Parser::new(source).parse().unwrap());
+        lib.initialize().unwrap();
+        lib.call_hook("__init__").unwrap();
+        let mut env = Environment::new();
+        env.libraries.insert("hooks".to_string(), Box::new(lib));
+
+        // calling a function lends the library, which must not run the hooks again
+        for node in Parser::new("func f() { return hooks.one(); }\nf(); f();").parse().unwrap() {
+            interpret_node(&node, &mut env, false, false).unwrap();
+        }
+        env.teardown_libraries().unwrap();
+
+        output::set_output(previous);
+        assert_eq!(capture.stdout(), "init\nteardown\n");
+    }
+
+    #[test]
+    fn external_libraries_import_their_own_dependencies() {
+        let dir = std::env::temp_dir().join(format!("tidal-layered-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+        // the function is declared before the imports it uses
+        write("shapes.tdx", "func area(r) { return geometry.square(r) * math.PI; }\nimport(math);\nimport(geometry);");
+        write("geometry.tdx", "import(math);\nfunc square(x) { return math.pow(x, 2); }\nnovar UNIT = math.abs(-1);");
+        write("ping.tdx", "import(pong);");
+        write("pong.tdx", "import(ping);");
+        let script = script_in(&dir);
+
+        let area = eval_with(&script, "import(shapes); return shapes.area(2);").unwrap();
+        assert!(matches!(area, Value::Float(a) if (a - 4.0 * std::f64::consts::PI).abs() < 1e-9));
+        assert_eq!(eval_with(&script, "import(geometry); return geometry.UNIT;").unwrap(), Value::Number(1));
+        assert!(eval_with(&script, "import(ping);").unwrap_err().to_string().contains("Circular library import: ping -> pong -> ping"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn library_constants_are_assigned_only_when_mutable() {
+        assert_eq!(eval("import(math); math.tolerance = 0.01; return math.isclose(1.0, 1.001);").unwrap(), Value::Boolean(true));
+        assert_eq!(eval("import(math); if (true) { math.tolerance = 0.5; }\nreturn math.tolerance;").unwrap(), Value::Float(0.5));
+        assert_eq!(error("import(math); math.PI = 3;"), "ImmutableConstant: math.PI is a constant and cannot be assigned");
+        assert_eq!(error("import(math); math.sqrt = 3;"), "ImmutableConstant: math.sqrt cannot be assigned");
+        assert!(error("import(math); math.nope = 3;").contains("Item 'nope' not found in library 'math'"));
+        assert!(error("math.tolerance = 1;").contains("Library 'math' not found"));
+        assert!(error("import(math); math.tolerance = \"tight\";").contains("math.tolerance must be a number"));
+
+        let dir = std::env::temp_dir().join(format!("tidal-settings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("settings.tdx"), "var retries = 3;\nnovar NAME = \"settings\";").unwrap();
+        let script = script_in(&dir);
+
+        assert_eq!(eval_with(&script, "import(settings); settings.retries = 5; return settings.retries;").unwrap(), Value::Number(5));
+        assert_eq!(eval_with(&script, "import(settings); settings.NAME = \"x\";").unwrap_err().to_string(), "ImmutableConstant: settings.NAME is a constant and cannot be assigned");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reload_picks_up_an_edited_library() {
+        let dir = std::env::temp_dir().join(format!("tidal-reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |source: &str| std::fs::write(dir.join("counter.tdx"), source).unwrap();
+        let mut session = Session::new(false).with_settings(script_in(&dir));
+        let mut run = |source: &str| session.eval(Parser::new(source).parse().unwrap());
+        write("func value() { return 1; }");
+        assert_eq!(run("import(counter); return counter.value();").unwrap(), Value::Number(1));
+        write("func value() { return 2; }");
+        assert_eq!(run("std.reload(\"counter\"); return counter.value();").unwrap(), Value::Number(2));
+
+        // a library that no longer parses leaves the loaded one in place
+        write("func value( {");
+        assert!(run("reload(\"counter\");").is_err());
+        assert_eq!(run("return counter.value();").unwrap(), Value::Number(2));
+        assert!(run("reload(\"missing\");").unwrap_err().to_string().contains("not an imported external library"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loop_guard_stops_loops_whose_condition_never_changes() {
+        let guarded = Settings { loop_guard: Some(50), ..Settings::default() };
+        let stuck = eval_with(&guarded, "var i = 0;\nvar t = 0;\nwhile (i < 10) {\n    t = t + 1;\n}");
+        let counting = eval_with(&guarded, "var i = 0;\nwhile (i < 1000) {\n    i = i + 1;\n}\nreturn i;");
+
+        let message = stuck.unwrap_err().to_string();
+        assert!(message.contains("Loop at line 3 ran 50 iterations without its condition changing (i = 0)"), "{}", message);
+        assert_eq!(counting.unwrap(), Value::Number(1000));
+    }
+
+    #[test]
+    fn calls_resolve_locals_then_script_functions_then_builtins() {
+        let fact = "func fact(n) { if (n <= 1) { return 1; } return n * fact(n - 1); }\n";
+        assert_eq!(eval(&format!("{}return fact(5);", fact)).unwrap(), Value::Number(120));
+
+        // a script function named like a builtin wins everywhere, and std.count still reaches the builtin
+        let shadow = "func count(a, x) { return 42; }\nfunc inner() { return count([1], 1); }\n";
+        assert_eq!(eval(&format!("{}return [count([1], 1), inner(), std.count([1], 1)];", shadow)).unwrap().to_string(), "[42, 42, 1]");
+
+        // a local holding a function comes before both
+        let local = "func one() { return 1; }\nfunc two() { return 2; }\nfunc pick() { var two = one; return two(); }\n";
+        assert_eq!(eval(&format!("{}return pick();", local)).unwrap(), Value::Number(1));
+    }
+
+    #[test]
+    fn dir_lists_what_is_in_scope() {
+        let names = eval("import(time); var x = 1; func f() { return 1; }\nreturn dir();").unwrap().to_string();
+        assert_eq!(names, "[f, std, time, x]");
+        assert_eq!(eval("import(time); return std.dir(time);").unwrap().to_string(), "[elapsed, now, sleep]");
+        assert_eq!(eval("import(time); var time = 1; return dir(time);").unwrap().to_string(), "[]");
+        assert!(error("return dir(nothing);").contains("Variable not found: nothing"));
+    }
+
+    #[test]
+    fn inspect_describes_a_value() {
+        assert_eq!(eval("novar a = [1, 2]; return inspect(a);").unwrap().to_string(), format!("[[type, array], [length, 2], [bytes, {}], [mutable, false]]", 2 * std::mem::size_of::<Value>()));
+        assert_eq!(eval("return std.inspect(1.5);").unwrap().to_string(), "[[type, float], [length, null], [bytes, 8]]");
+        assert_eq!(eval("var s = \"héllo\"; return inspect(s);").unwrap().to_string(), "[[type, str], [length, 5], [bytes, 6], [mutable, true]]");
+    }
+
+    #[test]
+    fn help_describes_functions() {
+        assert_eq!(eval("return std.help(\"math.sqrt\");").unwrap().to_string(), "math.sqrt(x)\n    Square root, as a float.");
+        assert_eq!(eval("return help(\"len\");").unwrap().to_string(), "len(value)\n    Number of characters in a string or elements in an array.");
+        assert_eq!(eval("func add(a, b) { \"Adds two numbers.\"; return a + b; }\nreturn help(\"add\");").unwrap().to_string(), "add(a, b)\n    Adds two numbers.");
+        assert!(eval("return help(\"time\");").unwrap().to_string().starts_with("time.elapsed()"));
+        assert_eq!(error("return help(\"math.nope\");"), "InterpreterError: No help found for 'math.nope'");
+        assert!(error("return help(1);").contains("help() takes 1 string argument"));
+    }
+
+    #[test]
+    fn failed_assert_stops_the_script() {
+        assert_eq!(error("var x = 1;\nassert(x > 1, \"x must be big\");\nvar y = 2;"), "AssertionFailed: x must be big");
+    }
+
+    #[test]
+    fn oversized_values_are_refused_before_they_are_made() {
+        assert_eq!(eval("return \"ab\" * 3;").unwrap(), Value::String("ababab".into()));
+        assert_eq!(eval("return \"ab\" * -2;").unwrap(), Value::String("".into()));
+        assert!(error("\"a\" * 2000000000;").starts_with("ResourceLimit: repeating a string of 1 bytes 2000000000 times would take more than the 256 MB"));
+        assert!(error("[1, 2] * 2000000000;").starts_with("ResourceLimit: repeating an array of 2 elements 2000000000 times"));
+
+        assert_eq!(eval("return 2 ** 30;").unwrap(), Value::Number(1 << 30));
+        assert_eq!(eval("return 2 ** -1;").unwrap(), Value::Float(0.5));
+        assert_eq!(error("2 ** 40;"), "ResourceLimit: 2 ** 40 is too large for an int; use a float base, as in 2.0 ** 40");
+        assert_eq!(eval("return 1 ** 2000000000;").unwrap(), Value::Number(1));
+
+        let limited = eval_with(&Settings { max_allocation: 1024, ..Settings::default() }, "var line = \"-\" * 2048;").unwrap_err().to_string();
+        assert!(limited.contains("repeating a string of 1 bytes 2048 times would take more than the 1024 bytes"), "{}", limited);
+        assert_eq!(eval("return len(\"-\" * 2048);").unwrap(), Value::Number(2048));
+    }
+
+    #[test]
+    fn strings_index_and_slice_by_character() {
+        let string = |s: &str| Value::String(s.into());
+        assert_eq!(eval("var s = \"hello\"; return s[1] + s[-1];").unwrap(), string("eo"));
+        assert_eq!(eval("var s = \"héllo wörld\"; return s[7] + s[-4];").unwrap(), string("öö"));
+        assert_eq!(eval("var s = \"héllo wörld\"; return s[1:4] + \"|\" + s[6:] + \"|\" + s[:-6];").unwrap(), string("éll|wörld|héllo"));
+        assert_eq!(eval("var s = \"hello\"; return s[3:1] + s[0:100] + s[0::2] + s[::-1];").unwrap(), string("hellohloolleh"));
+        assert!(error("var s = \"héllo\"; var c = s[5];").contains("Index out of bounds"));
+    }
+}
@@ -1,5 +1,7 @@
 use std::str::Chars;
 use std::iter::Peekable;
+use std::collections::VecDeque;
+use unicode_ident::{is_xid_continue, is_xid_start};
 use crate::error::Error;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -54,17 +56,73 @@ pub enum Token {
     Len,
     Del,
     Import,
+    Global,
     External,
     Embedded,
+    IncludeStr,
     Dot,
+    Ellipsis,
     Colon,
     EOF,
 }
 
+// Every reserved word. The parser reads this list too, so the words it
+// refuses as names are exactly the ones the lexer never reads as identifiers.
+pub const KEYWORDS: &[&str] = &[
+    "var", "novar", "print", "type", "if", "elif", "else", "null", "true", "false",
+    "for", "while", "break", "continue", "int", "str", "float", "bool", "func", "return",
+    "input", "len", "del", "import", "global", "external", "embedded", "include_str",
+];
+
+pub fn keyword(word: &str) -> Option<Token> {
+    Some(match word {
+        "var" => Token::Var,
+        "novar" => Token::NoVar,
+        "print" => Token::Print,
+        "type" => Token::Type,
+        "if" => Token::If,
+        "elif" => Token::Elif,
+        "else" => Token::Else,
+        "null" => Token::Null,
+        "true" => Token::Boolean(true),
+        "false" => Token::Boolean(false),
+        "for" => Token::For,
+        "while" => Token::While,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
+        "int" | "str" | "float" | "bool" => Token::TypeLiteral(word.to_string()),
+        "func" => Token::Func,
+        "return" => Token::Return,
+        "input" => Token::Input,
+        "len" => Token::Len,
+        "del" => Token::Del,
+        "import" => Token::Import,
+        "global" => Token::Global,
+        "external" => Token::External,
+        "embedded" => Token::Embedded,
+        "include_str" => Token::IncludeStr,
+        _ => return None,
+    })
+}
+
+// the reserved word a token was read from, if it was one
+pub fn keyword_text(token: &Token) -> Option<&'static str> {
+    let token = match token {
+        Token::TypeCast(name) => Token::TypeLiteral(name.clone()),
+        other => other.clone(),
+    };
+    KEYWORDS.iter().copied().find(|word| keyword(word).as_ref() == Some(&token))
+}
+
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     pub line: usize,
-    column: usize,
+    pub column: usize,
+    // where the token last returned by next_token starts
+    pub token_line: usize,
+    pub token_column: usize,
+    // tokens read ahead by peek_token, with where each starts
+    lookahead: VecDeque<(Token, usize, usize)>,
 }
 
 impl<'a> Lexer<'a> {
@@ -73,77 +131,134 @@ impl<'a> Lexer<'a> {
             input: input.chars().peekable(),
             line: 1,
             column: 1,
+            token_line: 1,
+            token_column: 1,
+            lookahead: VecDeque::new(),
+        }
+    }
+
+    // every character is read through here, so line and column stay right
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.input.next()?;
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(ch)
+    }
+
+    fn bump_if(&mut self, expected: char) -> bool {
+        self.bump_matching(|ch| *ch == expected).is_some()
+    }
+
+    fn bump_matching(&mut self, matches: impl Fn(&char) -> bool) -> Option<char> {
+        if self.input.peek().is_some_and(matches) {
+            self.bump()
+        } else {
+            None
         }
     }
 
     pub fn next_token(&mut self) -> Result<Token, Error> {
+        match self.lookahead.pop_front() {
+            Some((token, line, column)) => {
+                self.token_line = line;
+                self.token_column = column;
+                Ok(token)
+            }
+            None => self.read_token(),
+        }
+    }
+
+    // The token n places after the one next_token last returned, without
+    // consuming anything: peek_token(0) is what next_token will return next
+    pub fn peek_token(&mut self, n: usize) -> Result<&Token, Error> {
+        let (line, column) = (self.token_line, self.token_column);
+        while self.lookahead.len() <= n {
+            let token = self.read_token()?;
+            self.lookahead.push_back((token, self.token_line, self.token_column));
+        }
+        self.token_line = line;
+        self.token_column = column;
+        Ok(&self.lookahead[n].0)
+    }
+
+    fn read_token(&mut self) -> Result<Token, Error> {
         self.skip_whitespace();
+        self.token_line = self.line;
+        self.token_column = self.column;
 
         if let Some(token) = self.handle_comment() {
-            return Ok(token);
+            return token;
         }
 
-        match self.input.next() {
+        match self.bump() {
             Some(',') => Ok(Token::Comma),
-            Some('/') => {
-                if self.input.peek() == Some(&'/') {
-                    self.input.next();
-                    Ok(Token::FloorDivide)
-                } else {
-                    Ok(Token::Divide)
-                }
-            },
             Some('*') => {
                 if self.input.peek() == Some(&'*') {
-                    self.input.next();
+                    self.bump();
                     Ok(Token::Power)
                 } else {
                     Ok(Token::Multiply)
                 }
             },
             Some('&') => {
-                if self.input.next_if_eq(&'&').is_some() {
+                if self.bump_if('&') {
                     Ok(Token::And)
                 } else {
-                    Err(Error::LexerError(format!("Unexpected character: & at line {}, column {}", self.line, self.column)))
+                    Err(Error::LexerError(format!("Unexpected character: & at line {}, column {}", self.token_line, self.token_column)))
                 }
             },
             Some('|') => {
-                if self.input.next_if_eq(&'|').is_some() {
+                if self.bump_if('|') {
                     Ok(Token::Or)
                 } else {
-                    Err(Error::LexerError(format!("Unexpected character: | at line {}, column {}", self.line, self.column)))
+                    Err(Error::LexerError(format!("Unexpected character: | at line {}, column {}", self.token_line, self.token_column)))
                 }
             },
             Some('!') => {
-                if self.input.next_if_eq(&'=').is_some() {
+                if self.bump_if('=') {
                     Ok(Token::NotEqual)
                 } else {
                     Ok(Token::Not)
                 }
             },
-            Some('.') => Ok(Token::Dot),
+            Some('.') => {
+                if self.input.peek().is_some_and(char::is_ascii_digit) {
+                    self.read_number('.')
+                } else if self.bump_if('.') {
+                    if self.bump_if('.') {
+                        Ok(Token::Ellipsis)
+                    } else {
+                        Err(Error::LexerError(format!("Unexpected character: .. at line {}, column {}", self.token_line, self.token_column)))
+                    }
+                } else {
+                    Ok(Token::Dot)
+                }
+            },
             Some(':') => Ok(Token::Colon),
             Some(ch) => match ch {
                 '0'..='9' => self.read_number(ch),
                 '+' => Ok(Token::Plus),
                 '-' => Ok(Token::Minus),
                 '=' => {
-                    if self.input.next_if_eq(&'=').is_some() {
+                    if self.bump_if('=') {
                         Ok(Token::Equal)
                     } else {
                         Ok(Token::Assign)
                     }
                 },
                 '>' => {
-                    if self.input.next_if_eq(&'=').is_some() {
+                    if self.bump_if('=') {
                         Ok(Token::GreaterEqual)
                     } else {
                         Ok(Token::Greater)
                     }
                 },
                 '<' => {
-                    if self.input.next_if_eq(&'=').is_some() {
+                    if self.bump_if('=') {
                         Ok(Token::LessEqual)
                     } else {
                         Ok(Token::Less)
@@ -158,101 +273,111 @@ impl<'a> Lexer<'a> {
                 ']' => Ok(Token::RBracket),
                 '%' => Ok(Token::Modulus),
                 '"' => self.read_string(),
-                'a'..='z' | 'A'..='Z' | '_' => self.read_identifier_or_keyword(ch),
-                _ => Err(Error::LexerError(format!("Unexpected character: {} at line {}, column {}", ch, self.line, self.column))),
+                // strings never interpret backslashes, so r"C:\dir" is the
+                // same string as "C:\dir"; the prefix is accepted for clarity
+                'r' if self.input.peek() == Some(&'"') => {
+                    self.bump();
+                    self.read_string()
+                },
+                // names may use any script, as in Python: `var größe = 3;`
+                _ if ch == '_' || is_xid_start(ch) => self.read_identifier_or_keyword(ch),
+                _ => Err(Error::LexerError(format!("Unexpected character: {} at line {}, column {}", ch, self.token_line, self.token_column))),
             },
             None => Ok(Token::EOF),
         }
     }
 
-    fn read_number(&mut self, first_digit: char) -> Result<Token, Error> {
-        let mut number = first_digit.to_string();
-        let mut is_float = false;
-        while let Some(&ch) = self.input.peek() {
-            if ch.is_digit(10) {
-                number.push(ch);
-                self.input.next();
-            } else if ch == '.' && !is_float {
-                is_float = true;
-                number.push(ch);
-                self.input.next();
-            } else {
-                break;
+    // 12, 1.5, .5, 5., 1e3 and 2.5e-3; anything with a dot or an exponent is a float
+    fn read_number(&mut self, first: char) -> Result<Token, Error> {
+        let mut number = first.to_string();
+        let mut is_float = first == '.';
+        self.read_digits(&mut number);
+        if !is_float && self.bump_if('.') {
+            number.push('.');
+            is_float = true;
+            self.read_digits(&mut number);
+        }
+        if let Some(e) = self.bump_matching(|ch| matches!(ch, 'e' | 'E')) {
+            number.push(e);
+            if let Some(sign) = self.bump_matching(|ch| matches!(ch, '+' | '-')) {
+                number.push(sign);
+            }
+            let before = number.len();
+            self.read_digits(&mut number);
+            if number.len() == before {
+                return Err(self.malformed_number(number));
             }
+            is_float = true;
+        }
+        // a second dot, as in 1.2.3
+        if self.input.peek() == Some(&'.') {
+            return Err(self.malformed_number(number));
         }
+
         if is_float {
-            Ok(Token::Float(number.parse().unwrap()))
+            number.parse().map(Token::Float).map_err(|_| self.malformed_number(number))
         } else {
-            Ok(Token::Number(number.parse().unwrap()))
+            number.parse().map(Token::Number).map_err(|_| Error::LexerError(format!(
+                "Integer {} is too large at line {}, column {}; integers go up to {}, write {}.0 for a float",
+                number, self.token_line, self.token_column, i32::MAX, number
+            )))
+        }
+    }
+
+    fn read_digits(&mut self, number: &mut String) {
+        while let Some(ch) = self.bump_matching(char::is_ascii_digit) {
+            number.push(ch);
+        }
+    }
+
+    // reads the rest of the digits and dots so the error shows the whole number
+    fn malformed_number(&mut self, mut number: String) -> Error {
+        while let Some(ch) = self.bump_matching(|ch| ch.is_ascii_alphanumeric() || *ch == '.') {
+            number.push(ch);
         }
+        Error::LexerError(format!("Malformed number '{}' at line {}, column {}", number, self.token_line, self.token_column))
     }
 
     fn read_identifier_or_keyword(&mut self, first_char: char) -> Result<Token, Error> {
         let mut identifier = first_char.to_string();
-        while let Some(&ch) = self.input.peek() {
-            if ch.is_alphanumeric() || ch == '_' {
-                identifier.push(ch);
-                self.input.next();
-            } else {
-                break;
-            }
+        while let Some(ch) = self.bump_matching(|&ch| is_xid_continue(ch)) {
+            identifier.push(ch);
         }
-        match identifier.as_str() {
-            "var" => Ok(Token::Var),
-            "novar" => Ok(Token::NoVar),
-            "print" => Ok(Token::Print),
-            "type" => Ok(Token::Type),
-            "if" => Ok(Token::If),
-            "elif" => Ok(Token::Elif),
-            "else" => Ok(Token::Else),
-            "null" => Ok(Token::Null),
-            "true" => Ok(Token::Boolean(true)),
-            "false" => Ok(Token::Boolean(false)),
-            "for" => Ok(Token::For),
-            "while" => Ok(Token::While),
-            "break" => Ok(Token::Break),
-            "continue" => Ok(Token::Continue),
-            "int" | "str" | "float" | "bool" => {
-                if self.input.peek() == Some(&'(') {
-                    Ok(Token::TypeCast(identifier))
-                } else {
-                    Ok(Token::TypeLiteral(identifier))
-                }
-            },
-            "func" => Ok(Token::Func),
-            "return" => Ok(Token::Return),
-            "input" => Ok(Token::Input),
-            "len" => Ok(Token::Len),
-            "del" => Ok(Token::Del),
-            "import" => Ok(Token::Import),
-            "external" => Ok(Token::External), 
-            "embedded" => Ok(Token::Embedded),
-            _ => Ok(Token::Identifier(identifier)),
+        // keywords are all ASCII, so a look-alike letter from another script
+        // makes an ordinary name
+        match keyword(&identifier) {
+            // int(x) is a cast, int on its own names the type
+            Some(Token::TypeLiteral(name)) if self.input.peek() == Some(&'(') => Ok(Token::TypeCast(name)),
+            Some(token) => Ok(token),
+            None => Ok(Token::Identifier(identifier)),
         }
     }
 
-    fn handle_comment(&mut self) -> Option<Token> {
-        if self.input.next_if(|&ch| ch == '/').is_some() {
-            if self.input.next_if(|&ch| ch == '*').is_some() {
-                self.skip_multiline_comment();
-                return Some(self.next_token().unwrap());
-            } else {
-                return Some(Token::Divide);
-            }
+    // a slash starts /* comments */ as well as / and //
+    fn handle_comment(&mut self) -> Option<Result<Token, Error>> {
+        if !self.bump_if('/') {
+            return None;
+        }
+        if self.bump_if('*') {
+            self.skip_multiline_comment();
+            Some(self.read_token())
+        } else if self.bump_if('/') {
+            Some(Ok(Token::FloorDivide))
+        } else {
+            Some(Ok(Token::Divide))
         }
-        None
     }
 
     fn skip_multiline_comment(&mut self) {
         let mut depth = 1;
         while depth > 0 {
-            match (self.input.next(), self.input.peek()) {
+            match (self.bump(), self.input.peek()) {
                 (Some('*'), Some(&'/')) => {
-                    self.input.next();
+                    self.bump();
                     depth -= 1;
                 },
                 (Some('/'), Some(&'*')) => {
-                    self.input.next();
+                    self.bump();
                     depth += 1;
                 },
                 (Some(_), _) => {},
@@ -261,32 +386,205 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // "..." and """...""" strings, taken verbatim up to the closing quotes
     fn read_string(&mut self) -> Result<Token, Error> {
+        let start = (self.token_line, self.token_column);
+        if self.bump_if('"') {
+            if self.bump_if('"') {
+                return self.read_triple_quoted_string(start);
+            }
+            return Ok(Token::String(String::new()));
+        }
+
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(Token::String(string)),
+                Some(ch) => string.push(ch),
+                None => return Err(Error::LexerError(format!("Unterminated string starting at line {}, column {}", start.0, start.1))),
+            }
+        }
+    }
+
+    fn read_triple_quoted_string(&mut self, start: (usize, usize)) -> Result<Token, Error> {
         let mut string = String::new();
-        while let Some(&ch) = self.input.peek() {
-            if ch == '"' {
-                self.input.next();
-                break;
+        loop {
+            match self.bump() {
+                Some('"') => {
+                    if self.bump_if('"') {
+                        if self.bump_if('"') {
+                            return Ok(Token::String(string));
+                        }
+                        string.push_str("\"\"");
+                    } else {
+                        string.push('"');
+                    }
+                },
+                Some(ch) => string.push(ch),
+                None => return Err(Error::LexerError(format!("Unterminated multiline string starting at line {}, column {}", start.0, start.1))),
             }
-            string.push(ch);
-            self.input.next();
         }
-        Ok(Token::String(string))
     }
 
     fn skip_whitespace(&mut self) {
-        while let Some(&ch) = self.input.peek() {
-            if ch.is_whitespace() {
-                if ch == '\n' {
-                    self.line += 1;
-                    self.column = 1;
-                } else {
-                    self.column += 1;
-                }
-                self.input.next();
-            } else {
-                break;
+        while self.input.peek().is_some_and(|ch| ch.is_whitespace()) {
+            self.bump();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Result<Vec<Token>, Error> {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        loop {
+            match lexer.next_token()? {
+                Token::EOF => return Ok(tokens),
+                token => tokens.push(token),
             }
         }
     }
+
+    fn string(source: &str) -> String {
+        match tokens(source).unwrap().as_slice() {
+            [Token::String(s)] => s.clone(),
+            other => panic!("expected one string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ordinary_strings_are_verbatim() {
+        assert_eq!(string(r#""a\nb""#), r"a\nb");
+        assert_eq!(string(r#""C:\dir\""#), r"C:\dir\");
+        assert_eq!(string(r#""""#), "");
+    }
+
+    #[test]
+    fn triple_quoted_strings_keep_newlines_and_quotes() {
+        assert_eq!(string("\"\"\"line 1\nline \"2\"\n\"\"\""), "line 1\nline \"2\"\n");
+        assert_eq!(string("\"\"\"a\"\"b\"\"\""), "a\"\"b");
+    }
+
+    #[test]
+    fn raw_strings_read_like_ordinary_ones() {
+        assert_eq!(string(r#"r"C:\path\no\escapes""#), r"C:\path\no\escapes");
+        assert_eq!(string("r\"\"\"x\ny\"\"\""), "x\ny");
+        // r on its own is still an identifier
+        assert_eq!(tokens("r").unwrap(), vec![Token::Identifier("r".to_string())]);
+    }
+
+    #[test]
+    fn lines_are_counted_inside_strings() {
+        let mut lexer = Lexer::new("\"\"\"a\nb\nc\"\"\" x");
+        lexer.next_token().unwrap();
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("x".to_string()));
+        assert_eq!(lexer.line, 3);
+    }
+
+    #[test]
+    fn tokens_record_where_they_start() {
+        let mut lexer = Lexer::new("var x = 10;\n  print(\"\"\"a\nb\"\"\" + x);");
+        let mut starts = Vec::new();
+        while lexer.next_token().unwrap() != Token::EOF {
+            starts.push((lexer.token_line, lexer.token_column));
+        }
+        assert_eq!(starts, vec![(1, 1), (1, 5), (1, 7), (1, 9), (1, 11), (2, 3), (2, 8), (2, 9), (3, 6), (3, 8), (3, 9), (3, 10)]);
+
+        let message = tokens("var a = 1;\nvar b = 2 & 3;").unwrap_err().to_string();
+        assert!(message.contains("Unexpected character: & at line 2, column 11"), "{}", message);
+    }
+
+    #[test]
+    fn peeking_does_not_consume_tokens() {
+        let mut lexer = Lexer::new("a = 7 // 2;");
+        assert_eq!(lexer.next_token().unwrap(), Token::Identifier("a".to_string()));
+        assert_eq!(lexer.peek_token(2).unwrap(), &Token::FloorDivide);
+        assert_eq!(lexer.peek_token(0).unwrap(), &Token::Assign);
+        // the current token's position is still the one next_token returned
+        assert_eq!((lexer.token_line, lexer.token_column), (1, 1));
+
+        assert_eq!(lexer.next_token().unwrap(), Token::Assign);
+        assert_eq!(lexer.token_column, 3);
+        assert_eq!(tokens("7 // 2 / 1 /* x */ ;").unwrap(), vec![
+            Token::Number(7), Token::FloorDivide, Token::Number(2), Token::Divide, Token::Number(1), Token::Semicolon,
+        ]);
+    }
+
+    #[test]
+    fn every_keyword_is_read_as_one() {
+        for word in KEYWORDS {
+            let token = tokens(word).unwrap().remove(0);
+            assert_eq!(keyword_text(&token), Some(*word));
+        }
+        assert_eq!(tokens("int(x)").unwrap()[0], Token::TypeCast("int".to_string()));
+        assert_eq!(keyword_text(&Token::Identifier("length".to_string())), None);
+    }
+
+    #[test]
+    fn names_can_use_any_script() {
+        let names = |source: &str| -> Vec<Token> { tokens(source).unwrap() };
+        let ident = |name: &str| Token::Identifier(name.to_string());
+        assert_eq!(names("größe _x1 名前 число"), vec![ident("größe"), ident("_x1"), ident("名前"), ident("число")]);
+        assert_eq!(names("café2 = 1;")[0], ident("café2"));
+        // a keyword spelled with a Cyrillic 'а' is just a name
+        assert_eq!(names("vаr"), vec![ident("vаr")]);
+        // symbols and digits still can't start a name
+        assert!(tokens("€x").is_err());
+        assert_eq!(names("2x"), vec![Token::Number(2), ident("x")]);
+    }
+
+    #[test]
+    fn numbers_in_every_form() {
+        let cases = [
+            ("0", Token::Number(0)),
+            ("42", Token::Number(42)),
+            ("2147483647", Token::Number(i32::MAX)),
+            ("1.5", Token::Float(1.5)),
+            (".5", Token::Float(0.5)),
+            ("5.", Token::Float(5.0)),
+            ("1e3", Token::Float(1000.0)),
+            ("1E3", Token::Float(1000.0)),
+            ("2.5e-3", Token::Float(0.0025)),
+            ("1e+2", Token::Float(100.0)),
+            (".5e1", Token::Float(5.0)),
+            ("5.e1", Token::Float(50.0)),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(tokens(source).unwrap(), vec![expected], "{}", source);
+        }
+        // the dot only starts a number when a digit follows it
+        assert_eq!(tokens("a.b").unwrap()[1], Token::Dot);
+        assert_eq!(tokens("...a").unwrap()[0], Token::Ellipsis);
+        assert_eq!(tokens("-.5").unwrap(), vec![Token::Minus, Token::Float(0.5)]);
+        assert_eq!(tokens("x = 3.;").unwrap()[2], Token::Float(3.0));
+    }
+
+    #[test]
+    fn malformed_numbers_are_errors() {
+        let cases = [
+            ("1.2.3", "Malformed number '1.2.3' at line 1, column 1"),
+            ("x = 5..", "Malformed number '5..' at line 1, column 5"),
+            ("1e", "Malformed number '1e' at line 1, column 1"),
+            ("2e+;", "Malformed number '2e+' at line 1, column 1"),
+            ("1e3.5", "Malformed number '1e3.5' at line 1, column 1"),
+            ("\n 3ex", "Malformed number '3ex' at line 2, column 2"),
+        ];
+        for (source, expected) in cases {
+            let message = tokens(source).unwrap_err().to_string();
+            assert!(message.contains(expected), "{}: {}", source, message);
+        }
+        let message = tokens("var n = 2147483648;").unwrap_err().to_string();
+        assert!(message.contains("Integer 2147483648 is too large at line 1, column 9"), "{}", message);
+    }
+
+    #[test]
+    fn unterminated_strings_report_where_they_start() {
+        let message = tokens("var a = 1;\nvar b = \"open\n").unwrap_err().to_string();
+        assert!(message.contains("Unterminated string starting at line 2, column 9"), "{}", message);
+        let message = tokens("\"\"\"open\"\"").unwrap_err().to_string();
+        assert!(message.contains("Unterminated multiline string starting at line 1, column 1"), "{}", message);
+    }
 }
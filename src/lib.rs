@@ -0,0 +1,80 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+// The interpreter as a library, for hosts that embed Tidal: run a script
+// with interpreter::run or run_source_captured, keep state between runs with
+// interpreter::Session, and stop a run from another thread with a
+// CancellationToken. Each run and Session has its own interpreter::Settings.
+// The --trace-out, --debug-dump and --summary instrumentation is per thread
+// instead, covering everything run on the thread that turned it on. td
+// itself, in main.rs, is built on the same modules.
+
+#[macro_use]
+pub mod output;
+pub mod input;
+pub mod signals;
+pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod error;
+pub mod libs;
+pub mod docs;
+pub mod repl;
+pub mod notebook;
+pub mod snapshot;
+pub mod diff;
+pub mod upgrade;
+pub mod cli;
+pub mod completions;
+pub mod trace;
+pub mod crash_dump;
+pub mod bench;
+pub mod dialect;
+pub mod doctest;
+pub mod docgen;
+pub mod graph;
+pub mod minify;
+pub mod transpile;
+pub mod grammar;
+pub mod help;
+pub mod summary;
+
+// a .td file, or a dialect translated to Tidal, parsed with its statement
+// lines recorded when with_lines is set
+pub fn parse_script(path: &str, with_lines: bool, dialect: dialect::Choice) -> Result<Vec<parser::ASTNode>, error::Error> {
+    if !cli::is_script(path) {
+        return Err(error::Error::FileNotFound(format!("'{}' must be {}", path, cli::script_kinds())));
+    }
+    if !Path::new(path).exists() {
+        return Err(error::Error::FileNotFound(format!("File '{}' not found", path)));
+    }
+    let contents = fs::read_to_string(path)
+        .map_err(|e| error::Error::FileNotFound(format!("Failed to read '{}': {}", path, e)))?;
+    let parse = |source: &str| {
+        let dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut parser = parser::Parser::new(source).with_base_dir(dir);
+        if with_lines {
+            parser = parser.with_lines();
+        }
+        parser.parse()
+    };
+    match dialect::for_script(path, dialect) {
+        Some(dialect) => {
+            let (source, map) = dialect.translate(&contents);
+            parse(&source).map_err(|e| map.remap_error(e))
+        }
+        None => parse(&contents),
+    }
+}
+
+// an error as td shows it, after what the script printed
+pub fn print_error(error: &error::Error) {
+    // the script's output up to the error comes first
+    output::flush();
+    let stderr = io::stderr();
+    let mut handle = stderr.lock();
+
+    writeln!(handle, "\x1b[31m[{}] {}\x1b[0m", error.code(), error).unwrap();
+    writeln!(handle, "Run 'td explain {}' for more about this error.", error.code()).unwrap();
+}
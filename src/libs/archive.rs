@@ -0,0 +1,337 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use crate::summary;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use zip::write::FileOptions;
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("zip(paths, out)", "Writes a .zip of the files and directories in paths."),
+    ("unzip(path, dest)", "Extracts a .zip into dest."),
+    ("tar(paths, out)", "Writes a tar archive, gzip compressed when out ends in .tar.gz or .tgz."),
+    ("untar(path, dest)", "Extracts a .tar, .tar.gz or .tgz into dest."),
+    ("list(path)", "Entry names of an archive."),
+];
+
+pub(crate) struct ArchiveLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+}
+
+impl Library for ArchiveLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = ArchiveLib::new();
+        new_lib.constants = self.constants.clone();
+        Box::new(new_lib)
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl ArchiveLib {
+    pub fn new() -> Self {
+        let mut lib = ArchiveLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // zip(paths, out) - directories are added recursively under their own name
+        self.functions.insert("zip".to_string(), Box::new(|args| {
+            let (paths, out) = paths_and_target(&args, "zip")?;
+            write_zip(&paths, &out)
+                .map_err(|e| Error::LibraryError(format!("zip() failed to write '{}': {}", out, e)))?;
+            record(&paths, &out);
+            Ok(Value::Null)
+        }));
+
+        // unzip(path, dest)
+        self.functions.insert("unzip".to_string(), Box::new(|args| {
+            let (path, dest) = two_strings(&args, "unzip")?;
+            let file = open(&path, "unzip")?;
+            zip::ZipArchive::new(file)
+                .and_then(|mut archive| archive.extract(&dest))
+                .map_err(|e| Error::LibraryError(format!("unzip() failed to extract '{}': {}", path, e)))?;
+            record(&[path], &dest);
+            Ok(Value::Null)
+        }));
+
+        // tar(paths, out) - gzip compressed when out ends in .tar.gz or .tgz
+        self.functions.insert("tar".to_string(), Box::new(|args| {
+            let (paths, out) = paths_and_target(&args, "tar")?;
+            write_tar(&paths, &out)
+                .map_err(|e| Error::LibraryError(format!("tar() failed to write '{}': {}", out, e)))?;
+            record(&paths, &out);
+            Ok(Value::Null)
+        }));
+
+        // untar(path, dest)
+        self.functions.insert("untar".to_string(), Box::new(|args| {
+            let (path, dest) = two_strings(&args, "untar")?;
+            let reader = tar_reader(&path)?;
+            tar::Archive::new(reader).unpack(&dest)
+                .map_err(|e| Error::LibraryError(format!("untar() failed to extract '{}': {}", path, e)))?;
+            record(&[path], &dest);
+            Ok(Value::Null)
+        }));
+
+        // list(path) - entry names of a .zip, .tar, .tar.gz or .tgz archive
+        self.functions.insert("list".to_string(), Box::new(|args| {
+            let path = match args.as_slice() {
+                [Value::String(path)] => path.clone(),
+                _ => return Err(Error::TypeError("list() requires 1 string argument".to_string())),
+            };
+            let names = if path.ends_with(".zip") {
+                list_zip(&path)
+            } else {
+                list_tar(&path)
+            }.map_err(|e| Error::LibraryError(format!("list() failed to read '{}': {}", path, e)))?;
+            let names = names.into_iter().map(|name| Value::String(name.into())).collect();
+            Ok(Value::Array(Arc::new(Mutex::new(names))))
+        }));
+    }
+}
+
+// for --summary: what an archive was made from or extracted to
+fn record(read: &[String], written: &str) {
+    for path in read {
+        summary::read(Path::new(path));
+    }
+    summary::written(Path::new(written));
+}
+
+fn two_strings(args: &[Value], fname: &str) -> Result<(String, String), Error> {
+    match args {
+        [Value::String(a), Value::String(b)] => Ok((a.to_string(), b.to_string())),
+        _ => Err(Error::TypeError(format!("{}() requires 2 string arguments", fname))),
+    }
+}
+
+// paths may be a single string or an array of strings
+fn paths_and_target(args: &[Value], fname: &str) -> Result<(Vec<String>, String), Error> {
+    let (paths, out) = match args {
+        [paths, Value::String(out)] => (paths, out.to_string()),
+        _ => return Err(Error::TypeError(format!("{}() requires paths and an output file", fname))),
+    };
+    let paths = match paths {
+        Value::String(p) => vec![p.to_string()],
+        Value::Array(arr) => lock_array(arr)?.iter().map(|v| match v {
+            Value::String(p) => Ok(p.to_string()),
+            _ => Err(Error::TypeError(format!("{}() paths must be strings", fname))),
+        }).collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(Error::TypeError(format!("{}() paths must be a string or an array of strings", fname))),
+    };
+    for path in &paths {
+        if !Path::new(path).exists() {
+            return Err(Error::FileNotFound(format!("{}() cannot find '{}'", fname, path)));
+        }
+    }
+    Ok((paths, out))
+}
+
+fn open(path: &str, fname: &str) -> Result<File, Error> {
+    File::open(path).map_err(|e| Error::FileNotFound(format!("{}() cannot open '{}': {}", fname, path, e)))
+}
+
+fn is_gzip(path: &str) -> bool {
+    path.ends_with(".tar.gz") || path.ends_with(".tgz")
+}
+
+fn entry_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| ".".to_string())
+}
+
+fn write_zip(paths: &[String], out: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut writer = zip::ZipWriter::new(File::create(out)?);
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    // (path on disk, name inside the archive)
+    let mut pending: Vec<(std::path::PathBuf, String)> = paths.iter()
+        .map(|p| (Path::new(p).to_path_buf(), entry_name(Path::new(p))))
+        .collect();
+    while let Some((path, name)) = pending.pop() {
+        if path.is_dir() {
+            writer.add_directory(format!("{}/", name), options)?;
+            let mut children: Vec<_> = fs::read_dir(&path)?.collect::<Result<Vec<_>, _>>()?;
+            children.sort_by_key(|e| e.file_name());
+            for child in children.into_iter().rev() {
+                let child_name = format!("{}/{}", name, child.file_name().to_string_lossy());
+                pending.push((child.path(), child_name));
+            }
+        } else {
+            writer.start_file(name, options)?;
+            let mut contents = Vec::new();
+            File::open(&path)?.read_to_end(&mut contents)?;
+            writer.write_all(&contents)?;
+        }
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_tar(paths: &[String], out: &str) -> io::Result<()> {
+    let file = File::create(out)?;
+    if is_gzip(out) {
+        let encoder = append_all(tar::Builder::new(GzEncoder::new(file, Compression::default())), paths)?;
+        encoder.finish()?;
+    } else {
+        append_all(tar::Builder::new(file), paths)?;
+    }
+    Ok(())
+}
+
+fn append_all<W: Write>(mut builder: tar::Builder<W>, paths: &[String]) -> io::Result<W> {
+    for path in paths {
+        let path = Path::new(path);
+        let name = entry_name(path);
+        if path.is_dir() {
+            builder.append_dir_all(&name, path)?;
+        } else {
+            builder.append_path_with_name(path, &name)?;
+        }
+    }
+    builder.into_inner()
+}
+
+fn tar_reader(path: &str) -> Result<Box<dyn Read>, Error> {
+    let file = open(path, "untar")?;
+    if is_gzip(path) {
+        Ok(Box::new(GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+fn list_zip(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let mut names = Vec::new();
+    for i in 0..archive.len() {
+        names.push(archive.by_index(i)?.name().to_string());
+    }
+    Ok(names)
+}
+
+fn list_tar(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let reader = tar_reader(path).map_err(|e| e.to_string())?;
+    let mut archive = tar::Archive::new(reader);
+    let mut names = Vec::new();
+    for entry in archive.entries()? {
+        names.push(entry?.path()?.to_string_lossy().into_owned());
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        ArchiveLib::new().get_function(name).unwrap()(args)
+    }
+
+    fn string(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    fn path(dir: &Path, name: &str) -> Value {
+        Value::String(dir.join(name).to_string_lossy().into_owned().into())
+    }
+
+    fn names(value: Value) -> Vec<String> {
+        match value {
+            Value::Array(arr) => arr.lock().unwrap().iter().map(|v| v.to_string()).collect(),
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    // a scratch directory holding data/a.txt and data/b.txt
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tidal-archive-{}-{}", test, std::process::id()));
+        fs::create_dir_all(dir.join("data")).unwrap();
+        fs::write(dir.join("data").join("a.txt"), "alpha").unwrap();
+        fs::write(dir.join("data").join("b.txt"), "beta").unwrap();
+        dir
+    }
+
+    #[test]
+    fn zip_round_trips_a_directory() {
+        let dir = scratch("zip");
+        call("zip", vec![path(&dir, "data"), path(&dir, "out.zip")]).unwrap();
+        let listed = names(call("list", vec![path(&dir, "out.zip")]).unwrap());
+        call("unzip", vec![path(&dir, "out.zip"), path(&dir, "restored")]).unwrap();
+        let restored = fs::read_to_string(dir.join("restored").join("data").join("b.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(listed, vec!["data/", "data/a.txt", "data/b.txt"]);
+        assert_eq!(restored.unwrap(), "beta");
+    }
+
+    #[test]
+    fn gzipped_tar_round_trips_files() {
+        let dir = scratch("tar");
+        let file = path(&dir, "data/a.txt");
+        call("tar", vec![Value::Array(Arc::new(Mutex::new(vec![file]))), path(&dir, "out.tgz")]).unwrap();
+        let listed = names(call("list", vec![path(&dir, "out.tgz")]).unwrap());
+        call("untar", vec![path(&dir, "out.tgz"), path(&dir, "restored")]).unwrap();
+        let restored = fs::read_to_string(dir.join("restored").join("a.txt"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(listed, vec!["a.txt"]);
+        assert_eq!(restored.unwrap(), "alpha");
+    }
+
+    #[test]
+    fn missing_inputs_and_bad_arguments_are_errors() {
+        let err = call("zip", vec![string("no/such/file"), string("out.zip")]).unwrap_err().to_string();
+        assert!(err.contains("zip() cannot find 'no/such/file'"));
+        let err = call("tar", vec![Value::Number(1), string("out.tar")]).unwrap_err().to_string();
+        assert!(err.contains("tar() paths must be a string or an array of strings"));
+        let err = call("untar", vec![string("no/such/archive.tar"), string("dest")]).unwrap_err().to_string();
+        assert!(err.contains("untar() cannot open 'no/such/archive.tar'"));
+        let err = call("unzip", vec![string("a.zip")]).unwrap_err().to_string();
+        assert!(err.contains("unzip() requires 2 string arguments"));
+    }
+
+    #[test]
+    fn entries_are_named_after_the_last_path_component() {
+        assert_eq!(entry_name(Path::new("some/dir/file.txt")), "file.txt");
+        assert_eq!(entry_name(Path::new("some/dir/")), "dir");
+        assert!(is_gzip("backup.tar.gz") && is_gzip("backup.tgz") && !is_gzip("backup.tar"));
+    }
+}
@@ -0,0 +1,395 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct ArgSpec {
+    description: Option<String>,
+    flags: Vec<(String, Option<String>)>,                 // name, short
+    options: Vec<(String, Option<String>, Value)>,         // name, short, default
+    positionals: Vec<String>,
+}
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("description(text)", "Sets the text shown at the top of help()."),
+    ("flag(name, short?)", "Declares a boolean --name option."),
+    ("option(name, short, default)", "Declares a --name option taking a value; short may be null."),
+    ("positional(name)", "Declares a positional argument."),
+    ("help()", "The generated usage text."),
+    ("parse()", "[[name, value], ...] for the script's arguments; --help prints usage and exits."),
+];
+
+pub(crate) struct ArgsLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    spec: Arc<Mutex<ArgSpec>>,
+}
+
+impl Library for ArgsLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn is_mutable(&self, _name: &str) -> Option<bool> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        // clones share the declared spec so functions see the same flags
+        Box::new(ArgsLib::with_spec(Arc::clone(&self.spec)))
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl ArgsLib {
+    pub fn new() -> Self {
+        Self::with_spec(Arc::new(Mutex::new(ArgSpec::default())))
+    }
+
+    fn with_spec(spec: Arc<Mutex<ArgSpec>>) -> Self {
+        let mut lib = ArgsLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            spec,
+        };
+        lib.register_constants();
+        lib.register_functions();
+        lib
+    }
+
+    fn register_constants(&mut self) {
+        let args: Vec<Value> = invocation().1.into_iter().map(|arg| Value::String(arg.into())).collect();
+        self.constants.insert("RAW".to_string(), Value::Array(Arc::new(Mutex::new(args))));
+    }
+
+    fn register_functions(&mut self) {
+        let spec = Arc::clone(&self.spec);
+        self.functions.insert("description".to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::String(text)] => {
+                    spec.lock().unwrap().description = Some(text.to_string());
+                    Ok(Value::Null)
+                }
+                _ => Err(Error::TypeError("description() requires 1 string argument".to_string()))
+            }
+        }));
+
+        // flag(name) or flag(name, short)
+        let spec = Arc::clone(&self.spec);
+        self.functions.insert("flag".to_string(), Box::new(move |args| {
+            let (name, short) = match args.as_slice() {
+                [Value::String(name)] => (name.to_string(), None),
+                [Value::String(name), Value::String(short)] => (name.to_string(), Some(short.to_string())),
+                _ => return Err(Error::TypeError("flag() takes a name and an optional short name".to_string()))
+            };
+            let mut spec = spec.lock().unwrap();
+            check_unique(&spec, &name, short.as_deref())?;
+            spec.flags.push((name, short));
+            Ok(Value::Null)
+        }));
+
+        // option(name, short, default) - short may be null
+        let spec = Arc::clone(&self.spec);
+        self.functions.insert("option".to_string(), Box::new(move |args| {
+            let (name, short, default) = match args.as_slice() {
+                [Value::String(name)] => (name.to_string(), None, Value::Null),
+                [Value::String(name), Value::String(short)] => (name.to_string(), Some(short.to_string()), Value::Null),
+                [Value::String(name), Value::Null] => (name.to_string(), None, Value::Null),
+                [Value::String(name), Value::String(short), default] => (name.to_string(), Some(short.to_string()), default.clone()),
+                [Value::String(name), Value::Null, default] => (name.to_string(), None, default.clone()),
+                _ => return Err(Error::TypeError("option() takes a name, an optional short name and an optional default".to_string()))
+            };
+            let mut spec = spec.lock().unwrap();
+            check_unique(&spec, &name, short.as_deref())?;
+            spec.options.push((name, short, default));
+            Ok(Value::Null)
+        }));
+
+        let spec = Arc::clone(&self.spec);
+        self.functions.insert("positional".to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::String(name)] => {
+                    let mut spec = spec.lock().unwrap();
+                    check_unique(&spec, name, None)?;
+                    spec.positionals.push(name.to_string());
+                    Ok(Value::Null)
+                }
+                _ => Err(Error::TypeError("positional() requires 1 string argument".to_string()))
+            }
+        }));
+
+        let spec = Arc::clone(&self.spec);
+        self.functions.insert("help".to_string(), Box::new(move |args| {
+            if !args.is_empty() {
+                return Err(Error::TypeError("help() takes no arguments".to_string()));
+            }
+            Ok(Value::String(help_text(&spec.lock().unwrap()).into()))
+        }));
+
+        // parse() -> [[name, value], ...]; prints help and exits on --help/-h
+        let spec = Arc::clone(&self.spec);
+        self.functions.insert("parse".to_string(), Box::new(move |args| {
+            let argv = match args.as_slice() {
+                [] => invocation().1,
+                [Value::Array(arr)] => lock_array(arr)?.iter().map(|v| v.to_string()).collect(),
+                _ => return Err(Error::TypeError("parse() takes an optional array of arguments".to_string()))
+            };
+            let spec = spec.lock().unwrap();
+            if argv.iter().any(|a| a == "--help" || a == "-h") {
+                out!("{}", help_text(&spec));
+                return Err(Error::Exit(0));
+            }
+            let parsed = parse_args(&spec, &argv)
+                .map_err(|msg| Error::LibraryError(format!("{}\n{}", msg, usage_line(&spec))))?;
+            let pairs: Vec<Value> = parsed.into_iter()
+                .map(|(name, value)| Value::Array(Arc::new(Mutex::new(vec![Value::String(name.into()), value]))))
+                .collect();
+            Ok(Value::Array(Arc::new(Mutex::new(pairs))))
+        }));
+    }
+}
+
+// (script path, arguments after it), from the settings of the running script
+pub fn invocation() -> (String, Vec<String>) {
+    crate::interpreter::settings(|settings| {
+        (settings.script.clone().unwrap_or_else(|| "script.td".to_string()), settings.script_args.clone())
+    })
+}
+
+fn check_unique(spec: &ArgSpec, name: &str, short: Option<&str>) -> Result<(), Error> {
+    let names = spec.flags.iter().map(|(n, _)| n)
+        .chain(spec.options.iter().map(|(n, _, _)| n))
+        .chain(spec.positionals.iter());
+    for existing in names {
+        if existing == name {
+            return Err(Error::LibraryError(format!("Argument '{}' is already declared", name)));
+        }
+    }
+    if let Some(short) = short {
+        let shorts = spec.flags.iter().filter_map(|(_, s)| s.as_deref())
+            .chain(spec.options.iter().filter_map(|(_, s, _)| s.as_deref()));
+        for existing in shorts {
+            if existing == short {
+                return Err(Error::LibraryError(format!("Short name '-{}' is already declared", short)));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_args(spec: &ArgSpec, argv: &[String]) -> Result<Vec<(String, Value)>, String> {
+    let mut flags: HashMap<&str, bool> = spec.flags.iter().map(|(n, _)| (n.as_str(), false)).collect();
+    let mut options: HashMap<&str, Value> = spec.options.iter().map(|(n, _, d)| (n.as_str(), d.clone())).collect();
+    let mut positionals = Vec::new();
+
+    let mut i = 0;
+    while i < argv.len() {
+        let arg = &argv[i];
+        i += 1;
+
+        if arg == "--" {
+            positionals.extend(argv[i..].iter().cloned());
+            break;
+        }
+
+        let (key, inline_value) = if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((k, v)) => (lookup(spec, k, false), Some(v.to_string())),
+                None => (lookup(spec, long, false), None),
+            }
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            (lookup(spec, &arg[1..], true), None)
+        } else {
+            positionals.push(arg.clone());
+            continue;
+        };
+
+        match key {
+            Some((name, true)) => {
+                if inline_value.is_some() {
+                    return Err(format!("Flag '--{}' does not take a value", name));
+                }
+                flags.insert(name, true);
+            }
+            Some((name, false)) => {
+                let value = match inline_value {
+                    Some(v) => v,
+                    None => {
+                        if i >= argv.len() {
+                            return Err(format!("Option '--{}' requires a value", name));
+                        }
+                        i += 1;
+                        argv[i - 1].clone()
+                    }
+                };
+                options.insert(name, Value::String(value.into()));
+            }
+            None => return Err(format!("Unknown argument '{}'", arg)),
+        }
+    }
+
+    if positionals.len() < spec.positionals.len() {
+        return Err(format!("Missing required argument '{}'", spec.positionals[positionals.len()]));
+    }
+    if positionals.len() > spec.positionals.len() {
+        return Err(format!("Unexpected argument '{}'", positionals[spec.positionals.len()]));
+    }
+
+    let mut result = Vec::new();
+    for (name, _) in &spec.flags {
+        result.push((name.clone(), Value::Boolean(flags[name.as_str()])));
+    }
+    for (name, _, _) in &spec.options {
+        result.push((name.clone(), options[name.as_str()].clone()));
+    }
+    for (name, value) in spec.positionals.iter().zip(positionals) {
+        result.push((name.clone(), Value::String(value.into())));
+    }
+    Ok(result)
+}
+
+// returns (declared name, is_flag)
+fn lookup<'a>(spec: &'a ArgSpec, key: &str, short: bool) -> Option<(&'a str, bool)> {
+    for (name, s) in &spec.flags {
+        if (!short && name == key) || (short && s.as_deref() == Some(key)) {
+            return Some((name, true));
+        }
+    }
+    for (name, s, _) in &spec.options {
+        if (!short && name == key) || (short && s.as_deref() == Some(key)) {
+            return Some((name, false));
+        }
+    }
+    None
+}
+
+fn usage_line(spec: &ArgSpec) -> String {
+    let program = invocation().0;
+    let mut usage = format!("Usage: td {}", program);
+    if !spec.flags.is_empty() || !spec.options.is_empty() {
+        usage.push_str(" [options]");
+    }
+    for name in &spec.positionals {
+        usage.push_str(&format!(" <{}>", name));
+    }
+    usage
+}
+
+fn help_text(spec: &ArgSpec) -> String {
+    let mut text = String::new();
+    if let Some(description) = &spec.description {
+        text.push_str(&format!("{}\n\n", description));
+    }
+    text.push_str(&format!("{}\n", usage_line(spec)));
+
+    if !spec.positionals.is_empty() {
+        text.push_str("\nArguments:\n");
+        for name in &spec.positionals {
+            text.push_str(&format!("  {}\n", name));
+        }
+    }
+
+    text.push_str("\nOptions:\n");
+    for (name, short) in &spec.flags {
+        match short {
+            Some(s) => text.push_str(&format!("  -{}, --{}\n", s, name)),
+            None => text.push_str(&format!("      --{}\n", name)),
+        }
+    }
+    for (name, short, default) in &spec.options {
+        let names = match short {
+            Some(s) => format!("  -{}, --{} <value>", s, name),
+            None => format!("      --{} <value>", name),
+        };
+        match default {
+            Value::Null => text.push_str(&format!("{}\n", names)),
+            d => text.push_str(&format!("{}  (default: {})\n", names, d)),
+        }
+    }
+    text.push_str("  -h, --help  Show this help message\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> ArgSpec {
+        ArgSpec {
+            description: Some("Copies files".to_string()),
+            flags: vec![("force".to_string(), Some("f".to_string()))],
+            options: vec![("mode".to_string(), Some("m".to_string()), Value::String("fast".into()))],
+            positionals: vec!["src".to_string()],
+        }
+    }
+
+    fn parse(argv: &[&str]) -> Result<Vec<(String, Value)>, String> {
+        let argv: Vec<String> = argv.iter().map(|a| a.to_string()).collect();
+        parse_args(&spec(), &argv)
+    }
+
+    fn string(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    #[test]
+    fn parses_flags_options_and_positionals() {
+        assert_eq!(parse(&["a.txt"]).unwrap(), vec![
+            ("force".to_string(), Value::Boolean(false)),
+            ("mode".to_string(), string("fast")),
+            ("src".to_string(), string("a.txt")),
+        ]);
+        let parsed = parse(&["-f", "--mode=slow", "a.txt"]).unwrap();
+        assert_eq!(parsed[0].1, Value::Boolean(true));
+        assert_eq!(parsed[1].1, string("slow"));
+        assert_eq!(parse(&["-m", "slow", "a.txt"]).unwrap()[1].1, string("slow"));
+        // after -- everything is positional
+        assert_eq!(parse(&["--", "-f"]).unwrap()[2].1, string("-f"));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(parse(&[]).unwrap_err(), "Missing required argument 'src'");
+        assert_eq!(parse(&["a", "b"]).unwrap_err(), "Unexpected argument 'b'");
+        assert_eq!(parse(&["--nope", "a"]).unwrap_err(), "Unknown argument '--nope'");
+        assert_eq!(parse(&["--force=yes", "a"]).unwrap_err(), "Flag '--force' does not take a value");
+        assert_eq!(parse(&["a", "--mode"]).unwrap_err(), "Option '--mode' requires a value");
+    }
+
+    #[test]
+    fn declarations_must_be_unique() {
+        let spec = spec();
+        assert!(check_unique(&spec, "force", None).is_err());
+        assert!(check_unique(&spec, "src", None).is_err());
+        assert!(check_unique(&spec, "verbose", Some("m")).is_err());
+        assert!(check_unique(&spec, "verbose", Some("v")).is_ok());
+    }
+
+    #[test]
+    fn help_lists_everything_declared() {
+        let help = help_text(&spec());
+        assert!(help.starts_with("Copies files\n\n"));
+        assert!(help.contains("[options] <src>"));
+        assert!(help.contains("  -f, --force\n"));
+        assert!(help.contains("  -m, --mode <value>  (default: fast)\n"));
+        assert!(help.contains("  -h, --help"));
+    }
+}
@@ -0,0 +1,158 @@
+use super::{find_help, map_to_pairs, Library, Function};
+use crate::error::Error;
+use crate::parser::Value;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::time::Instant;
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("run(name, fn, iterations?)", "Times fn() over iterations runs (default 100), prints min/mean/p95 and returns them in seconds."),
+];
+
+pub const DEFAULT_ITERATIONS: usize = 100;
+
+// timings of one benchmark, in seconds
+#[derive(Debug)]
+pub struct Stats {
+    pub iterations: usize,
+    pub min: f64,
+    pub mean: f64,
+    pub p95: f64,
+}
+
+impl Stats {
+    pub fn report(&self, name: &str) -> String {
+        format!(
+            "{}: min {}  mean {}  p95 {}  ({} runs)",
+            name, format_seconds(self.min), format_seconds(self.mean), format_seconds(self.p95), self.iterations
+        )
+    }
+}
+
+// Runs `body` untimed for a tenth of the iterations first, so caches and
+// allocations have settled, then times each of the `iterations` runs
+pub fn measure(iterations: usize, mut body: impl FnMut() -> Result<(), Error>) -> Result<Stats, Error> {
+    for _ in 0..(iterations / 10).max(1) {
+        body()?;
+    }
+    let mut times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let started = Instant::now();
+        body()?;
+        times.push(started.elapsed().as_secs_f64());
+    }
+    times.sort_by(|a, b| a.total_cmp(b));
+    let p95 = times[((times.len() as f64 * 0.95).ceil() as usize).max(1) - 1];
+    Ok(Stats {
+        iterations,
+        min: times[0],
+        mean: times.iter().sum::<f64>() / times.len() as f64,
+        p95,
+    })
+}
+
+pub fn format_seconds(seconds: f64) -> String {
+    if seconds >= 1.0 {
+        format!("{:.3}s", seconds)
+    } else if seconds >= 1e-3 {
+        format!("{:.3}ms", seconds * 1e3)
+    } else {
+        format!("{:.3}µs", seconds * 1e6)
+    }
+}
+
+pub(crate) struct BenchLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+}
+
+impl Library for BenchLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn is_mutable(&self, _name: &str) -> Option<bool> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        Box::new(BenchLib::new())
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl BenchLib {
+    pub fn new() -> Self {
+        let mut lib = BenchLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // run(name, fn, iterations?) -> [["min", secs], ["mean", secs], ["p95", secs], ["iterations", n]]
+        self.functions.insert("run".to_string(), Box::new(|args| {
+            let (name, callback, iterations) = match args.as_slice() {
+                [Value::String(name), callback] => (name, callback, DEFAULT_ITERATIONS),
+                [Value::String(name), callback, Value::Number(n)] if *n > 0 => (name, callback, *n as usize),
+                _ => return Err(Error::TypeError("run() takes a name, a function and an optional positive number of iterations".to_string())),
+            };
+            if !matches!(callback, Value::Function(..)) {
+                return Err(Error::TypeError("run() second argument must be a function".to_string()));
+            }
+            let stats = measure(iterations, || crate::interpreter::call_function(callback, vec![]).map(|_| ()))?;
+            outln!("{}", stats.report(name));
+
+            let mut result = IndexMap::new();
+            result.insert("min".to_string(), Value::Float(stats.min));
+            result.insert("mean".to_string(), Value::Float(stats.mean));
+            result.insert("p95".to_string(), Value::Float(stats.p95));
+            result.insert("iterations".to_string(), Value::Number(stats.iterations as i32));
+            Ok(map_to_pairs(result))
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_cover_every_timed_run() {
+        let mut runs = 0;
+        let stats = measure(20, || {
+            runs += 1;
+            Ok(())
+        }).unwrap();
+        // two warmup runs, then the twenty timed ones
+        assert_eq!(runs, 22);
+        assert_eq!(stats.iterations, 20);
+        assert!(stats.min <= stats.mean && stats.min <= stats.p95);
+    }
+
+    #[test]
+    fn errors_stop_the_benchmark() {
+        let err = measure(5, || Err(Error::InterpreterError("boom".to_string()))).unwrap_err();
+        assert_eq!(err.to_string(), "InterpreterError: boom");
+        assert_eq!(format_seconds(0.0025), "2.500ms");
+        assert_eq!(format_seconds(2.0), "2.000s");
+    }
+}
@@ -0,0 +1,202 @@
+use super::{find_help, map_to_pairs, pairs_to_map, Library, Function};
+use crate::error::Error;
+use crate::parser::Value;
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, Server};
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("serve(address, handler, limit?)", "Serves HTTP on a port or \"host:port\", calling handler(request) per request; returns after http.timeout idle seconds if set."),
+];
+
+pub(crate) struct HttpLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    // http.timeout, shared with the serve closure; None waits forever
+    timeout: Arc<Mutex<Option<f64>>>,
+}
+
+impl Library for HttpLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        (name == "timeout").then_some(true)
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = HttpLib::new();
+        new_lib.constants = self.constants.clone();
+        *new_lib.timeout.lock().unwrap() = *self.timeout.lock().unwrap();
+        Box::new(new_lib)
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+
+    fn set_constant(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        if name != "timeout" {
+            return Err(Error::ImmutableConstant(format!("http.{} cannot be assigned", name)));
+        }
+        let timeout = match &value {
+            Value::Null => None,
+            Value::Number(n) if *n > 0 => Some(*n as f64),
+            Value::Float(f) if *f > 0.0 && f.is_finite() => Some(*f),
+            _ => return Err(Error::TypeError("http.timeout must be a positive number of seconds or null".to_string())),
+        };
+        *self.timeout.lock().unwrap() = timeout;
+        self.constants.insert(name.to_string(), value);
+        Ok(())
+    }
+}
+
+impl HttpLib {
+    pub fn new() -> Self {
+        let mut lib = HttpLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            timeout: Arc::new(Mutex::new(None)),
+        };
+        lib.constants.insert("timeout".to_string(), Value::Null);
+        lib.register_functions();
+        lib
+    }
+
+    // serve(port or "host:port", handler, limit?) - blocks, calling handler(request)
+    // for each request; stops after `limit` requests if given. A bare port binds
+    // to 127.0.0.1. The request is [[method, ...], [path, ...], [query, ...],
+    // [headers, [[name, value], ...]], [body, ...]]; the handler returns a string
+    // body or [[status, 200], [headers, [...]], [body, "..."]]. With
+    // http.timeout set, it also returns once no request came for that long.
+    fn register_functions(&mut self) {
+        let timeout = Arc::clone(&self.timeout);
+        self.functions.insert("serve".to_string(), Box::new(move |args| {
+            let (addr, handler, limit) = match args.as_slice() {
+                [addr, handler] => (addr, handler, None),
+                [addr, handler, Value::Number(n)] if *n > 0 => (addr, handler, Some(*n as usize)),
+                _ => return Err(Error::TypeError("serve() takes an address, a handler function and an optional request limit".to_string())),
+            };
+            let addr = match addr {
+                Value::Number(port) => format!("127.0.0.1:{}", port),
+                Value::String(addr) => addr.to_string(),
+                _ => return Err(Error::TypeError("serve() address must be a port number or a 'host:port' string".to_string())),
+            };
+            if !matches!(handler, Value::Function(..)) {
+                return Err(Error::TypeError("serve() handler must be a function".to_string()));
+            }
+
+            let server = Server::http(&addr)
+                .map_err(|e| Error::LibraryError(format!("serve() cannot listen on '{}': {}", addr, e)))?;
+            let mut handled = 0;
+            loop {
+                let idle = *timeout.lock().unwrap();
+                let next = match idle {
+                    Some(seconds) => server.recv_timeout(Duration::from_secs_f64(seconds)),
+                    None => server.recv().map(Some),
+                };
+                let mut request = match next {
+                    Ok(Some(request)) => request,
+                    Ok(None) => break,
+                    Err(e) => return Err(Error::LibraryError(format!("serve() stopped receiving on '{}': {}", addr, e))),
+                };
+                let response = match request_value(&mut request) {
+                    Ok(value) => crate::interpreter::call_function(handler, vec![value]),
+                    Err(e) => Err(e),
+                };
+                let response = match response {
+                    Ok(value) => build_response(value),
+                    Err(e) => Err(e),
+                };
+                match response {
+                    Ok(response) => {
+                        let _ = request.respond(response);
+                    }
+                    Err(Error::Exit(code)) => {
+                        let _ = request.respond(Response::from_string("").with_status_code(503));
+                        return Err(Error::Exit(code));
+                    }
+                    // a failing handler answers 500 but keeps the server up
+                    Err(e) => {
+                        errln!("\x1b[31m{}\x1b[0m", e);
+                        let _ = request.respond(Response::from_string(e.to_string()).with_status_code(500));
+                    }
+                }
+                handled += 1;
+                if limit.is_some_and(|limit| handled >= limit) {
+                    break;
+                }
+            }
+            Ok(Value::Null)
+        }));
+    }
+}
+
+fn request_value(request: &mut Request) -> Result<Value, Error> {
+    let url = request.url().to_string();
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (url, String::new()),
+    };
+
+    let mut headers = IndexMap::new();
+    for header in request.headers() {
+        headers.insert(header.field.as_str().to_string(), Value::String(header.value.as_str().into()));
+    }
+
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)
+        .map_err(|e| Error::LibraryError(format!("serve() could not read request body: {}", e)))?;
+
+    let mut fields = IndexMap::new();
+    fields.insert("method".to_string(), Value::String(request.method().to_string().into()));
+    fields.insert("path".to_string(), Value::String(path.into()));
+    fields.insert("query".to_string(), Value::String(query.into()));
+    fields.insert("headers".to_string(), map_to_pairs(headers));
+    fields.insert("body".to_string(), Value::String(body.into()));
+    Ok(map_to_pairs(fields))
+}
+
+fn build_response(value: Value) -> Result<Response<std::io::Cursor<Vec<u8>>>, Error> {
+    let fields = match value {
+        Value::Null => return Ok(Response::from_string("")),
+        Value::String(body) => return Ok(Response::from_string(&*body)),
+        other => pairs_to_map(&other, "serve")?,
+    };
+
+    let status = match fields.get("status") {
+        None => 200,
+        Some(Value::Number(code)) if (100..=999).contains(code) => *code as u16,
+        Some(_) => return Err(Error::TypeError("serve() response status must be an integer HTTP status".to_string())),
+    };
+    let body = match fields.get("body") {
+        None | Some(Value::Null) => String::new(),
+        Some(body) => body.to_string(),
+    };
+
+    let mut response = Response::from_string(body).with_status_code(status);
+    if let Some(headers) = fields.get("headers") {
+        for (name, value) in pairs_to_map(headers, "serve")? {
+            let header = Header::from_bytes(name.as_bytes(), value.to_string().as_bytes())
+                .map_err(|_| Error::LibraryError(format!("serve() invalid response header '{}'", name)))?;
+            response.add_header(header);
+        }
+    }
+    Ok(response)
+}
@@ -0,0 +1,356 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::Value;
+use ::image::imageops::FilterType;
+use ::image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Images live here and scripts hold integer handles to them; operations that
+// change an image return a new handle and leave the original untouched.
+#[derive(Default)]
+struct ImageStore {
+    next_id: i32,
+    images: HashMap<i32, DynamicImage>,
+}
+
+impl ImageStore {
+    fn add(&mut self, image: DynamicImage) -> Value {
+        self.next_id += 1;
+        self.images.insert(self.next_id, image);
+        Value::Number(self.next_id)
+    }
+}
+
+type Store = Arc<Mutex<ImageStore>>;
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("open(path)", "Loads an image and returns its handle."),
+    ("new(width, height)", "A transparent canvas."),
+    ("save(img, path)", "Writes the image; the format follows the extension."),
+    ("width(img)", "Width in pixels."),
+    ("height(img)", "Height in pixels."),
+    ("resize(img, width, height)", "A resized copy of the image."),
+    ("crop(img, x, y, width, height)", "A cropped copy of the image."),
+    ("rotate(img, degrees)", "A copy rotated clockwise by a multiple of 90 degrees."),
+    ("grayscale(img)", "A grayscale copy of the image."),
+    ("get_pixel(img, x, y)", "[r, g, b, a] at x, y."),
+    ("set_pixel(img, x, y, color)", "Sets the pixel to [r, g, b] or [r, g, b, a]."),
+    ("close(img)", "Frees the image."),
+];
+
+pub(crate) struct ImageLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    store: Store,
+}
+
+impl Library for ImageLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn is_mutable(&self, _name: &str) -> Option<bool> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        // clones share the store so handles stay valid inside functions
+        Box::new(ImageLib::with_store(Arc::clone(&self.store)))
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl ImageLib {
+    pub fn new() -> Self {
+        Self::with_store(Arc::new(Mutex::new(ImageStore::default())))
+    }
+
+    fn with_store(store: Store) -> Self {
+        let mut lib = ImageLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            store,
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn add<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&mut ImageStore, &[Value]) -> Result<Value, Error> + 'static,
+    {
+        let store = Arc::clone(&self.store);
+        self.functions.insert(name.to_string(), Box::new(move |args| f(&mut store.lock().unwrap(), &args)));
+    }
+
+    fn register_functions(&mut self) {
+        // open(path)
+        self.add("open", |store, args| match args {
+            [Value::String(path)] => {
+                let img = ::image::open(&**path)
+                    .map_err(|e| Error::LibraryError(format!("open() failed to read '{}': {}", path, e)))?;
+                Ok(store.add(img))
+            }
+            _ => Err(Error::TypeError("open() requires 1 string argument".to_string())),
+        });
+
+        // new(width, height) - transparent canvas
+        self.add("new", |store, args| match args {
+            [w, h] => {
+                let (w, h) = (dimension(w, "new")?, dimension(h, "new")?);
+                Ok(store.add(DynamicImage::new_rgba8(w, h)))
+            }
+            _ => Err(Error::TypeError("new() takes exactly 2 arguments".to_string())),
+        });
+
+        // save(img, path) - format follows the file extension
+        self.add("save", |store, args| match args {
+            [handle, Value::String(path)] => {
+                get(store, handle, "save")?.save(&**path)
+                    .map_err(|e| Error::LibraryError(format!("save() failed to write '{}': {}", path, e)))?;
+                Ok(Value::Null)
+            }
+            _ => Err(Error::TypeError("save() requires an image and a path".to_string())),
+        });
+
+        self.add("width", |store, args| match args {
+            [handle] => Ok(Value::Number(get(store, handle, "width")?.width() as i32)),
+            _ => Err(Error::TypeError("width() takes exactly 1 argument".to_string())),
+        });
+
+        self.add("height", |store, args| match args {
+            [handle] => Ok(Value::Number(get(store, handle, "height")?.height() as i32)),
+            _ => Err(Error::TypeError("height() takes exactly 1 argument".to_string())),
+        });
+
+        // resize(img, width, height)
+        self.add("resize", |store, args| match args {
+            [handle, w, h] => {
+                let (w, h) = (dimension(w, "resize")?, dimension(h, "resize")?);
+                let resized = get(store, handle, "resize")?.resize_exact(w, h, FilterType::Triangle);
+                Ok(store.add(resized))
+            }
+            _ => Err(Error::TypeError("resize() takes exactly 3 arguments".to_string())),
+        });
+
+        // crop(img, x, y, width, height)
+        self.add("crop", |store, args| match args {
+            [handle, x, y, w, h] => {
+                let img = get(store, handle, "crop")?;
+                let (x, y) = (coordinate(x, "crop")?, coordinate(y, "crop")?);
+                let (w, h) = (dimension(w, "crop")?, dimension(h, "crop")?);
+                if x + w > img.width() || y + h > img.height() {
+                    return Err(Error::LibraryError(format!(
+                        "crop() region {}x{} at ({}, {}) is outside the {}x{} image",
+                        w, h, x, y, img.width(), img.height()
+                    )));
+                }
+                let cropped = img.crop_imm(x, y, w, h);
+                Ok(store.add(cropped))
+            }
+            _ => Err(Error::TypeError("crop() takes exactly 5 arguments".to_string())),
+        });
+
+        // rotate(img, degrees) - clockwise, multiples of 90
+        self.add("rotate", |store, args| match args {
+            [handle, Value::Number(degrees)] => {
+                let img = get(store, handle, "rotate")?;
+                let rotated = match degrees.rem_euclid(360) {
+                    0 => img.clone(),
+                    90 => img.rotate90(),
+                    180 => img.rotate180(),
+                    270 => img.rotate270(),
+                    _ => return Err(Error::LibraryError("rotate() only supports multiples of 90 degrees".to_string())),
+                };
+                Ok(store.add(rotated))
+            }
+            _ => Err(Error::TypeError("rotate() requires an image and an integer angle".to_string())),
+        });
+
+        self.add("grayscale", |store, args| match args {
+            [handle] => {
+                let gray = get(store, handle, "grayscale")?.grayscale();
+                Ok(store.add(gray))
+            }
+            _ => Err(Error::TypeError("grayscale() takes exactly 1 argument".to_string())),
+        });
+
+        // get_pixel(img, x, y) -> [r, g, b, a]
+        self.add("get_pixel", |store, args| match args {
+            [handle, x, y] => {
+                let img = get(store, handle, "get_pixel")?;
+                let (x, y) = in_bounds(img, x, y, "get_pixel")?;
+                let Rgba(channels) = img.get_pixel(x, y);
+                let values = channels.iter().map(|&c| Value::Number(c as i32)).collect();
+                Ok(Value::Array(Arc::new(Mutex::new(values))))
+            }
+            _ => Err(Error::TypeError("get_pixel() takes exactly 3 arguments".to_string())),
+        });
+
+        // set_pixel(img, x, y, [r, g, b] or [r, g, b, a]) - modifies the image in place
+        self.add("set_pixel", |store, args| match args {
+            [handle, x, y, Value::Array(color)] => {
+                let pixel = rgba(&color.lock().unwrap())?;
+                let img = get_mut(store, handle, "set_pixel")?;
+                let (x, y) = in_bounds(img, x, y, "set_pixel")?;
+                img.put_pixel(x, y, pixel);
+                Ok(Value::Null)
+            }
+            _ => Err(Error::TypeError("set_pixel() requires an image, x, y and a color array".to_string())),
+        });
+
+        // close(img) - frees the image
+        self.add("close", |store, args| match args {
+            [Value::Number(id)] => match store.images.remove(id) {
+                Some(_) => Ok(Value::Null),
+                None => Err(Error::LibraryError(format!("close() got unknown image handle {}", id))),
+            },
+            _ => Err(Error::TypeError("close() requires an image handle".to_string())),
+        });
+    }
+}
+
+fn get<'a>(store: &'a ImageStore, handle: &Value, fname: &str) -> Result<&'a DynamicImage, Error> {
+    match handle {
+        Value::Number(id) => store.images.get(id)
+            .ok_or_else(|| Error::LibraryError(format!("{}() got unknown image handle {}", fname, id))),
+        _ => Err(Error::TypeError(format!("{}() requires an image handle", fname))),
+    }
+}
+
+fn get_mut<'a>(store: &'a mut ImageStore, handle: &Value, fname: &str) -> Result<&'a mut DynamicImage, Error> {
+    match handle {
+        Value::Number(id) => store.images.get_mut(id)
+            .ok_or_else(|| Error::LibraryError(format!("{}() got unknown image handle {}", fname, id))),
+        _ => Err(Error::TypeError(format!("{}() requires an image handle", fname))),
+    }
+}
+
+fn coordinate(value: &Value, fname: &str) -> Result<u32, Error> {
+    match value {
+        Value::Number(n) if *n >= 0 => Ok(*n as u32),
+        _ => Err(Error::TypeError(format!("{}() requires non-negative integer coordinates", fname))),
+    }
+}
+
+fn dimension(value: &Value, fname: &str) -> Result<u32, Error> {
+    match value {
+        Value::Number(n) if *n > 0 => Ok(*n as u32),
+        _ => Err(Error::TypeError(format!("{}() requires positive integer sizes", fname))),
+    }
+}
+
+fn in_bounds(img: &DynamicImage, x: &Value, y: &Value, fname: &str) -> Result<(u32, u32), Error> {
+    let (x, y) = (coordinate(x, fname)?, coordinate(y, fname)?);
+    if x >= img.width() || y >= img.height() {
+        return Err(Error::IndexOutOfBounds(format!(
+            "{}() pixel ({}, {}) is outside the {}x{} image", fname, x, y, img.width(), img.height()
+        )));
+    }
+    Ok((x, y))
+}
+
+fn rgba(color: &[Value]) -> Result<Rgba<u8>, Error> {
+    if color.len() != 3 && color.len() != 4 {
+        return Err(Error::TypeError("set_pixel() color must have 3 or 4 channels".to_string()));
+    }
+    let mut channels = [255u8; 4];
+    for (i, c) in color.iter().enumerate() {
+        channels[i] = match c {
+            Value::Number(n) if (0..=255).contains(n) => *n as u8,
+            _ => return Err(Error::TypeError("set_pixel() channels must be integers from 0 to 255".to_string())),
+        };
+    }
+    Ok(Rgba(channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn n(value: i32) -> Value {
+        Value::Number(value)
+    }
+
+    fn color(channels: &[i32]) -> Value {
+        Value::Array(Arc::new(Mutex::new(channels.iter().map(|&c| n(c)).collect())))
+    }
+
+    struct Script(ImageLib);
+
+    impl Script {
+        fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+            self.0.get_function(name).unwrap()(args)
+        }
+
+        fn size(&self, handle: &Value) -> (Value, Value) {
+            (self.call("width", vec![handle.clone()]).unwrap(), self.call("height", vec![handle.clone()]).unwrap())
+        }
+    }
+
+    #[test]
+    fn pixels_round_trip_with_opaque_default_alpha() {
+        let lib = Script(ImageLib::new());
+        let img = lib.call("new", vec![n(3), n(2)]).unwrap();
+        assert_eq!(lib.size(&img), (n(3), n(2)));
+        assert_eq!(lib.call("get_pixel", vec![img.clone(), n(2), n(1)]).unwrap(), color(&[0, 0, 0, 0]));
+        lib.call("set_pixel", vec![img.clone(), n(2), n(1), color(&[10, 20, 30])]).unwrap();
+        assert_eq!(lib.call("get_pixel", vec![img.clone(), n(2), n(1)]).unwrap(), color(&[10, 20, 30, 255]));
+
+        let err = lib.call("get_pixel", vec![img.clone(), n(3), n(0)]).unwrap_err().to_string();
+        assert!(err.contains("get_pixel() pixel (3, 0) is outside the 3x2 image"));
+        let err = lib.call("set_pixel", vec![img, n(0), n(0), color(&[256, 0, 0])]).unwrap_err().to_string();
+        assert!(err.contains("set_pixel() channels must be integers from 0 to 255"));
+    }
+
+    #[test]
+    fn transforms_return_new_handles() {
+        let lib = Script(ImageLib::new());
+        let img = lib.call("new", vec![n(4), n(2)]).unwrap();
+        let rotated = lib.call("rotate", vec![img.clone(), n(-90)]).unwrap();
+        assert_ne!(rotated, img);
+        assert_eq!(lib.size(&rotated), (n(2), n(4)));
+        assert_eq!(lib.size(&img), (n(4), n(2)));
+
+        let resized = lib.call("resize", vec![img.clone(), n(8), n(1)]).unwrap();
+        assert_eq!(lib.size(&resized), (n(8), n(1)));
+        let cropped = lib.call("crop", vec![img.clone(), n(1), n(0), n(3), n(2)]).unwrap();
+        assert_eq!(lib.size(&cropped), (n(3), n(2)));
+
+        let err = lib.call("crop", vec![img.clone(), n(2), n(0), n(3), n(2)]).unwrap_err().to_string();
+        assert!(err.contains("crop() region 3x2 at (2, 0) is outside the 4x2 image"));
+        let err = lib.call("rotate", vec![img, n(45)]).unwrap_err().to_string();
+        assert!(err.contains("rotate() only supports multiples of 90 degrees"));
+    }
+
+    #[test]
+    fn closed_and_unknown_handles_are_errors() {
+        let lib = Script(ImageLib::new());
+        let img = lib.call("new", vec![n(1), n(1)]).unwrap();
+        // clones share the store, as they do inside script functions
+        let clone = lib.0.box_clone();
+        assert_eq!(clone.get_function("width").unwrap()(vec![img.clone()]).unwrap(), n(1));
+
+        lib.call("close", vec![img.clone()]).unwrap();
+        assert!(lib.call("width", vec![img.clone()]).unwrap_err().to_string().contains("width() got unknown image handle 1"));
+        assert!(lib.call("close", vec![img]).unwrap_err().to_string().contains("close() got unknown image handle 1"));
+        assert!(lib.call("new", vec![n(0), n(1)]).unwrap_err().to_string().contains("new() requires positive integer sizes"));
+    }
+}
@@ -6,15 +6,27 @@ use std::io::{Write};
 
 use crate::error::Error;
 use crate::parser::Value;
-use super::Library;
-
-pub struct IOLib {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+use crate::summary;
+use super::{find_help, Library, Function};
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("open(path, mode)", "Opens or creates a file with mode r, w, w+ or a and returns its absolute path."),
+    ("write(path, text)", "Replaces the file's contents with text."),
+    ("read(path)", "The file's contents as a string."),
+    ("append(path, text)", "Adds text to the end of the file."),
+    ("exists(path)", "Whether the path exists."),
+    ("remove(path)", "Deletes a file."),
+    ("rename(old, new)", "Renames a file."),
+];
+
+pub(crate) struct IOLib {
+    functions: HashMap<String, Function>,
     constants: HashMap<String, Value>,
 }
 
 impl Library for IOLib {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -22,6 +34,14 @@ impl Library for IOLib {
         self.constants.get(name)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
     fn is_mutable(&self, _name: &str) -> Option<bool> {
         None
     }
@@ -31,6 +51,10 @@ impl Library for IOLib {
         new_lib.constants = self.constants.clone();
         Box::new(new_lib)
     }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
 }
 
 impl IOLib {
@@ -84,7 +108,7 @@ impl IOLib {
             }
 
             let mut options = OpenOptions::new();
-            match mode.as_str() {
+            match &*mode {
                 "r" => { options.read(true); }
                 "w" => { options.write(true).create(true).truncate(true); }
                 "w+" => { options.read(true).write(true).create(true).truncate(true); }
@@ -95,8 +119,14 @@ impl IOLib {
 
             options.open(&abs_path)
                 .map_err(|e| Error::FileNotFound(format!("Failed to open file: {}", e)))?;
+            if mode.starts_with('r') || mode.ends_with('+') {
+                summary::read(&abs_path);
+            }
+            if !mode.starts_with('r') {
+                summary::written(&abs_path);
+            }
 
-            Ok(Value::String(abs_path.to_string_lossy().into_owned()))
+            Ok(Value::String(abs_path.to_string_lossy().into_owned().into()))
         }));
 
         lib.functions.insert("write".to_string(), Box::new(|args| {
@@ -111,7 +141,7 @@ impl IOLib {
 
             let content = match &args[1] {
                 Value::String(s) => s.clone(),
-                _ => format!("{}", args[1]),
+                _ => format!("{}", args[1]).into(),
             };
 
             let abs_path = IOLib::get_absolute_path(&path)?;
@@ -131,6 +161,7 @@ impl IOLib {
                 .open(&abs_path)
                 .and_then(|mut file| file.write_all(content.as_bytes()))
                 .map_err(|e| Error::FileNotFound(format!("Failed to write to file: {}", e)))?;
+            summary::written(&abs_path);
 
             Ok(Value::Null)
         }));
@@ -151,9 +182,10 @@ impl IOLib {
                 return Err(Error::FileNotFound(format!("File does not exist: {}", abs_path.display())));
             }
 
-            fs::read_to_string(&abs_path)
-                .map(Value::String)
-                .map_err(|e| Error::FileNotFound(format!("Failed to read file: {}", e)))
+            let contents = fs::read_to_string(&abs_path)
+                .map_err(|e| Error::FileNotFound(format!("Failed to read file: {}", e)))?;
+            summary::read(&abs_path);
+            Ok(Value::String(contents.into()))
         }));
 
         lib.functions.insert("append".to_string(), Box::new(|args| {
@@ -168,7 +200,7 @@ impl IOLib {
 
             let content = match &args[1] {
                 Value::String(s) => s.clone(),
-                _ => format!("{}", args[1]),
+                _ => format!("{}", args[1]).into(),
             };
 
             let abs_path = IOLib::get_absolute_path(&path)?;
@@ -189,6 +221,7 @@ impl IOLib {
 
             file.write_all(content.as_bytes())
                 .map_err(|e| Error::FileNotFound(format!("Failed to append to file: {}", e)))?;
+            summary::written(&abs_path);
 
             Ok(Value::Null)
         }));
@@ -0,0 +1,456 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Scripts pass plain arrays: [1, 2, 3] is a vector, [[1, 2], [3, 4]] a matrix.
+// Each call reads its arguments once into a flat row-major f64 buffer, does
+// all of its arithmetic there and only builds arrays again for the result.
+#[derive(Clone)]
+struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+    vector: bool,
+}
+
+impl Matrix {
+    fn new(rows: usize, cols: usize, fill: f64) -> Self {
+        Matrix { rows, cols, data: vec![fill; rows * cols], vector: false }
+    }
+
+    fn get(&self, r: usize, c: usize) -> f64 {
+        self.data[r * self.cols + c]
+    }
+
+    // reads the elements in place rather than cloning the nested arrays
+    fn from_value(value: &Value, fname: &str) -> Result<Self, Error> {
+        let outer = match value {
+            Value::Array(arr) => arr,
+            other => return Err(Error::TypeError(format!("{}() requires array arguments, got {}", fname, other))),
+        };
+        let arr = lock_array(outer)?;
+        if arr.is_empty() {
+            return Err(Error::LibraryError(format!("{}() cannot operate on an empty array", fname)));
+        }
+
+        if !matches!(arr[0], Value::Array(_)) {
+            let data = arr.iter().map(|v| to_f64(v, fname)).collect::<Result<Vec<_>, _>>()?;
+            return Ok(Matrix { rows: 1, cols: data.len(), data, vector: true });
+        }
+
+        let mut data = Vec::new();
+        let mut cols = None;
+        for row in arr.iter() {
+            let row = match row {
+                // locking it again would deadlock
+                Value::Array(r) if Arc::ptr_eq(r, outer) => {
+                    return Err(Error::LibraryError(format!("{}() cannot operate on an array that contains itself", fname)));
+                }
+                Value::Array(r) => lock_array(r)?,
+                _ => return Err(Error::TypeError(format!("{}() matrix rows must all be arrays", fname))),
+            };
+            match cols {
+                None => {
+                    cols = Some(row.len());
+                    data.reserve(arr.len() * row.len());
+                }
+                Some(c) if c != row.len() => {
+                    return Err(Error::LibraryError(format!("{}() matrix rows must have the same length", fname)));
+                }
+                _ => {}
+            }
+            for v in row.iter() {
+                data.push(to_f64(v, fname)?);
+            }
+        }
+        Ok(Matrix { rows: arr.len(), cols: cols.unwrap_or(0), data, vector: false })
+    }
+
+    fn to_value(&self) -> Value {
+        let wrap = |vals: Vec<Value>| Value::Array(Arc::new(Mutex::new(vals)));
+        if self.vector {
+            return wrap(self.data.iter().map(|&x| Value::Float(x)).collect());
+        }
+        let rows = self.data.chunks(self.cols.max(1))
+            .take(self.rows)
+            .map(|row| wrap(row.iter().map(|&x| Value::Float(x)).collect()))
+            .collect();
+        wrap(rows)
+    }
+
+    fn shape(&self) -> String {
+        if self.vector {
+            format!("({})", self.cols)
+        } else {
+            format!("({}x{})", self.rows, self.cols)
+        }
+    }
+}
+
+fn to_f64(value: &Value, fname: &str) -> Result<f64, Error> {
+    match value {
+        Value::Number(n) => Ok(*n as f64),
+        Value::Float(f) => Ok(*f),
+        other => Err(Error::TypeError(format!("{}() requires numeric elements, got {}", fname, other))),
+    }
+}
+
+fn to_size(value: &Value, fname: &str) -> Result<usize, Error> {
+    match value {
+        Value::Number(n) if *n > 0 => Ok(*n as usize),
+        _ => Err(Error::TypeError(format!("{}() requires positive integer dimensions", fname))),
+    }
+}
+
+// rows * cols elements, or an error when that many f64s could never be allocated
+fn element_count(rows: usize, cols: usize, fname: &str) -> Result<usize, Error> {
+    rows.checked_mul(cols)
+        .filter(|&n| n.checked_mul(std::mem::size_of::<f64>()).is_some_and(|bytes| bytes <= isize::MAX as usize))
+        .ok_or_else(|| Error::LibraryError(format!("{}() dimensions {}x{} are too large", fname, rows, cols)))
+}
+
+fn matmul(a: &Matrix, b: &Matrix) -> Result<Matrix, Error> {
+    // a vector on the right is treated as a column
+    let (b_rows, b_cols) = if b.vector { (b.cols, 1) } else { (b.rows, b.cols) };
+    if a.cols != b_rows {
+        return Err(Error::LibraryError(format!("matmul() shape mismatch: {} and {}", a.shape(), b.shape())));
+    }
+    let mut out = Matrix::new(a.rows, b_cols, 0.0);
+    for i in 0..a.rows {
+        for k in 0..a.cols {
+            let aik = a.get(i, k);
+            for j in 0..b_cols {
+                out.data[i * b_cols + j] += aik * b.data[k * b_cols + j];
+            }
+        }
+    }
+    out.vector = a.vector || b.vector;
+    if out.vector {
+        out.cols = out.data.len();
+        out.rows = 1;
+    }
+    Ok(out)
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    if m.vector {
+        // a vector becomes a single column matrix
+        return Matrix { rows: m.cols, cols: 1, data: m.data.clone(), vector: false };
+    }
+    let mut out = Matrix::new(m.cols, m.rows, 0.0);
+    for i in 0..m.rows {
+        for j in 0..m.cols {
+            out.data[j * m.rows + i] = m.get(i, j);
+        }
+    }
+    out
+}
+
+fn elementwise(args: &[Value], fname: &str, op: fn(f64, f64) -> f64) -> Result<Value, Error> {
+    if args.len() != 2 {
+        return Err(Error::TypeError(format!("{}() takes exactly 2 arguments", fname)));
+    }
+    let result = match (&args[0], &args[1]) {
+        (Value::Array(_), Value::Array(_)) => {
+            let a = Matrix::from_value(&args[0], fname)?;
+            let b = Matrix::from_value(&args[1], fname)?;
+            if a.rows != b.rows || a.cols != b.cols {
+                return Err(Error::LibraryError(format!("{}() shape mismatch: {} and {}", fname, a.shape(), b.shape())));
+            }
+            let data = a.data.iter().zip(&b.data).map(|(&x, &y)| op(x, y)).collect();
+            Matrix { data, ..a }
+        }
+        (Value::Array(_), scalar) => {
+            let a = Matrix::from_value(&args[0], fname)?;
+            let s = to_f64(scalar, fname)?;
+            Matrix { data: a.data.iter().map(|&x| op(x, s)).collect(), ..a }
+        }
+        (scalar, Value::Array(_)) => {
+            let b = Matrix::from_value(&args[1], fname)?;
+            let s = to_f64(scalar, fname)?;
+            Matrix { data: b.data.iter().map(|&x| op(s, x)).collect(), ..b }
+        }
+        _ => return Err(Error::TypeError(format!("{}() requires at least one array argument", fname))),
+    };
+    Ok(result.to_value())
+}
+
+fn format_number(x: f64) -> String {
+    if x.fract() == 0.0 && x.abs() < 1e15 {
+        format!("{}", x as i64)
+    } else {
+        format!("{:.4}", x).trim_end_matches('0').to_string()
+    }
+}
+
+fn pretty(m: &Matrix) -> String {
+    let cells: Vec<String> = m.data.iter().map(|&x| format_number(x)).collect();
+    let width = cells.iter().map(|c| c.len()).max().unwrap_or(0);
+    let rows: Vec<String> = cells.chunks(m.cols.max(1))
+        .map(|row| {
+            let padded: Vec<String> = row.iter().map(|c| format!("{:>width$}", c, width = width)).collect();
+            format!("[ {} ]", padded.join("  "))
+        })
+        .collect();
+    rows.join("\n")
+}
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("matrix(rows, cols, fill?)", "A rows x cols matrix filled with fill, 0 by default."),
+    ("identity(n)", "The n x n identity matrix."),
+    ("shape(a)", "[rows, cols] of a matrix, or [len] of a vector."),
+    ("matmul(a, b)", "Matrix product of a and b."),
+    ("transpose(a)", "The transposed matrix."),
+    ("dot(a, b)", "Dot product of two vectors."),
+    ("norm(a)", "Euclidean length of a vector, Frobenius norm of a matrix."),
+    ("add(a, b)", "Element-wise sum; either side may be a scalar."),
+    ("sub(a, b)", "Element-wise difference; either side may be a scalar."),
+    ("mul(a, b)", "Element-wise product; either side may be a scalar."),
+    ("div(a, b)", "Element-wise quotient; either side may be a scalar."),
+    ("format(a)", "The matrix as aligned text."),
+    ("show(a)", "Prints the matrix as aligned text."),
+];
+
+pub(crate) struct LinalgLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+}
+
+impl Library for LinalgLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = LinalgLib::new();
+        new_lib.constants = self.constants.clone();
+        Box::new(new_lib)
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl LinalgLib {
+    pub fn new() -> Self {
+        let mut lib = LinalgLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // matrix(rows, cols, fill?) - fill defaults to 0
+        self.functions.insert("matrix".to_string(), Box::new(|args| {
+            let fill = match args.len() {
+                2 => 0.0,
+                3 => to_f64(&args[2], "matrix")?,
+                _ => return Err(Error::TypeError("matrix() takes 2 or 3 arguments".to_string())),
+            };
+            let rows = to_size(&args[0], "matrix")?;
+            let cols = to_size(&args[1], "matrix")?;
+            element_count(rows, cols, "matrix")?;
+            Ok(Matrix::new(rows, cols, fill).to_value())
+        }));
+
+        self.functions.insert("identity".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("identity() takes exactly 1 argument".to_string()));
+            }
+            let n = to_size(&args[0], "identity")?;
+            element_count(n, n, "identity")?;
+            let mut m = Matrix::new(n, n, 0.0);
+            for i in 0..n {
+                m.data[i * n + i] = 1.0;
+            }
+            Ok(m.to_value())
+        }));
+
+        // shape(a) -> [rows, cols], or [len] for a vector
+        self.functions.insert("shape".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("shape() takes exactly 1 argument".to_string()));
+            }
+            let m = Matrix::from_value(&args[0], "shape")?;
+            let dims = if m.vector {
+                vec![Value::Number(m.cols as i32)]
+            } else {
+                vec![Value::Number(m.rows as i32), Value::Number(m.cols as i32)]
+            };
+            Ok(Value::Array(Arc::new(Mutex::new(dims))))
+        }));
+
+        self.functions.insert("matmul".to_string(), Box::new(|args| {
+            if args.len() != 2 {
+                return Err(Error::TypeError("matmul() takes exactly 2 arguments".to_string()));
+            }
+            let a = Matrix::from_value(&args[0], "matmul")?;
+            let b = Matrix::from_value(&args[1], "matmul")?;
+            Ok(matmul(&a, &b)?.to_value())
+        }));
+
+        self.functions.insert("transpose".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("transpose() takes exactly 1 argument".to_string()));
+            }
+            Ok(transpose(&Matrix::from_value(&args[0], "transpose")?).to_value())
+        }));
+
+        self.functions.insert("dot".to_string(), Box::new(|args| {
+            if args.len() != 2 {
+                return Err(Error::TypeError("dot() takes exactly 2 arguments".to_string()));
+            }
+            let a = Matrix::from_value(&args[0], "dot")?;
+            let b = Matrix::from_value(&args[1], "dot")?;
+            if !a.vector || !b.vector {
+                return Err(Error::TypeError("dot() requires two vectors; use matmul() for matrices".to_string()));
+            }
+            if a.cols != b.cols {
+                return Err(Error::LibraryError(format!("dot() length mismatch: {} and {}", a.cols, b.cols)));
+            }
+            Ok(Value::Float(a.data.iter().zip(&b.data).map(|(x, y)| x * y).sum()))
+        }));
+
+        // norm(a) - euclidean length of a vector, frobenius norm of a matrix
+        self.functions.insert("norm".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("norm() takes exactly 1 argument".to_string()));
+            }
+            let m = Matrix::from_value(&args[0], "norm")?;
+            Ok(Value::Float(m.data.iter().map(|x| x * x).sum::<f64>().sqrt()))
+        }));
+
+        // element-wise arithmetic; either side may be a scalar
+        self.functions.insert("add".to_string(), Box::new(|args| elementwise(&args, "add", |x, y| x + y)));
+        self.functions.insert("sub".to_string(), Box::new(|args| elementwise(&args, "sub", |x, y| x - y)));
+        self.functions.insert("mul".to_string(), Box::new(|args| elementwise(&args, "mul", |x, y| x * y)));
+        self.functions.insert("div".to_string(), Box::new(|args| elementwise(&args, "div", |x, y| x / y)));
+
+        self.functions.insert("format".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("format() takes exactly 1 argument".to_string()));
+            }
+            Ok(Value::String(pretty(&Matrix::from_value(&args[0], "format")?).into()))
+        }));
+
+        self.functions.insert("show".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("show() takes exactly 1 argument".to_string()));
+            }
+            outln!("{}", pretty(&Matrix::from_value(&args[0], "show")?));
+            Ok(Value::Null)
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        LinalgLib::new().get_function(name).unwrap()(args)
+    }
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    fn numbers(values: &[i32]) -> Value {
+        array(values.iter().map(|&n| Value::Number(n)).collect())
+    }
+
+    fn floats(values: &[f64]) -> Value {
+        array(values.iter().map(|&x| Value::Float(x)).collect())
+    }
+
+    #[test]
+    fn matmul_multiplies_matrices_and_vectors() {
+        let a = array(vec![numbers(&[1, 2]), numbers(&[3, 4])]);
+        let b = array(vec![numbers(&[5, 6]), numbers(&[7, 8])]);
+        let product = call("matmul", vec![a.clone(), b]).unwrap();
+        assert_eq!(product, array(vec![floats(&[19.0, 22.0]), floats(&[43.0, 50.0])]));
+        assert_eq!(call("matmul", vec![a, numbers(&[1, 1])]).unwrap(), floats(&[3.0, 7.0]));
+    }
+
+    #[test]
+    fn matmul_rejects_mismatched_shapes() {
+        let a = array(vec![numbers(&[1, 2, 3])]);
+        let err = call("matmul", vec![a.clone(), a]).unwrap_err().to_string();
+        assert!(err.contains("matmul() shape mismatch: (1x3) and (1x3)"));
+    }
+
+    #[test]
+    fn transpose_dot_and_norm() {
+        let m = array(vec![numbers(&[1, 2, 3]), numbers(&[4, 5, 6])]);
+        let t = call("transpose", vec![m]).unwrap();
+        assert_eq!(t, array(vec![floats(&[1.0, 4.0]), floats(&[2.0, 5.0]), floats(&[3.0, 6.0])]));
+        assert_eq!(call("dot", vec![numbers(&[1, 2, 3]), numbers(&[4, 5, 6])]).unwrap(), Value::Float(32.0));
+        assert_eq!(call("norm", vec![numbers(&[3, 4])]).unwrap(), Value::Float(5.0));
+    }
+
+    #[test]
+    fn elementwise_accepts_a_scalar_on_either_side() {
+        assert_eq!(call("add", vec![numbers(&[1, 2]), numbers(&[10, 20])]).unwrap(), floats(&[11.0, 22.0]));
+        assert_eq!(call("sub", vec![Value::Number(10), numbers(&[1, 2])]).unwrap(), floats(&[9.0, 8.0]));
+        assert_eq!(call("mul", vec![numbers(&[1, 2]), Value::Float(0.5)]).unwrap(), floats(&[0.5, 1.0]));
+        let err = call("add", vec![numbers(&[1, 2]), numbers(&[1, 2, 3])]).unwrap_err().to_string();
+        assert!(err.contains("add() shape mismatch: (2) and (3)"));
+    }
+
+    #[test]
+    fn ragged_and_non_numeric_input_is_rejected() {
+        let ragged = array(vec![numbers(&[1, 2]), numbers(&[3])]);
+        assert!(call("norm", vec![ragged]).unwrap_err().to_string().contains("norm() matrix rows must have the same length"));
+        let nested = array(vec![numbers(&[1])]);
+        if let Value::Array(rows) = &nested {
+            rows.lock().unwrap().push(nested.clone());
+        }
+        assert!(call("norm", vec![nested]).unwrap_err().to_string().contains("norm() cannot operate on an array that contains itself"));
+        let text = array(vec![Value::String("1".into())]);
+        assert!(call("norm", vec![text]).unwrap_err().to_string().contains("norm() requires numeric elements"));
+    }
+
+    #[test]
+    fn matrix_and_identity_build_filled_matrices() {
+        assert_eq!(call("matrix", vec![Value::Number(1), Value::Number(2), Value::Number(7)]).unwrap(), array(vec![floats(&[7.0, 7.0])]));
+        assert_eq!(call("identity", vec![Value::Number(2)]).unwrap(), array(vec![floats(&[1.0, 0.0]), floats(&[0.0, 1.0])]));
+        assert!(call("matrix", vec![Value::Number(0), Value::Number(2)]).unwrap_err().to_string().contains("positive integer dimensions"));
+    }
+
+    #[test]
+    fn oversized_dimensions_are_an_error() {
+        assert!(element_count(usize::MAX, 2, "matrix").unwrap_err().to_string().contains("matrix() dimensions"));
+        assert!(element_count(usize::MAX / 4, 1, "matrix").is_err());
+        assert_eq!(element_count(3, 4, "matrix").unwrap(), 12);
+    }
+
+    #[test]
+    fn format_aligns_columns() {
+        let m = array(vec![numbers(&[1, 10]), floats(&[2.5, 3.0])]);
+        assert_eq!(call("format", vec![m]).unwrap(), Value::String("[   1   10 ]\n[ 2.5    3 ]".into()));
+    }
+}
@@ -0,0 +1,231 @@
+use super::{find_help, pairs_to_map, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use indexmap::IndexMap;
+use std::collections::HashMap;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, Message, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+use std::fs;
+use std::path::Path;
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("send(message)", "Sends an email described by [[from, ...], [to, ...], [subject, ...], [body, ...], [smtp, ...]]."),
+];
+
+pub(crate) struct MailLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+}
+
+impl Library for MailLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = MailLib::new();
+        new_lib.constants = self.constants.clone();
+        Box::new(new_lib)
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl MailLib {
+    pub fn new() -> Self {
+        let mut lib = MailLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    // send([["from", ...], ["to", addr or [addrs]], ["subject", ...], ["body", ...],
+    //       ["attachments", [paths]], ["smtp", [["host", ...], ["port", 587],
+    //       ["user", ...], ["password", ...], ["tls", "starttls" | "tls" | "none"]]]])
+    fn register_functions(&mut self) {
+        self.functions.insert("send".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("send() takes exactly 1 argument".to_string()));
+            }
+            let options = pairs_to_map(&args[0], "send")?;
+            let smtp = match options.get("smtp") {
+                Some(value) => pairs_to_map(value, "send")?,
+                None => return Err(Error::LibraryError("send() requires an 'smtp' entry".to_string())),
+            };
+            let message = build_message(&options)?;
+            transport(&smtp)?.send(&message)
+                .map_err(|e| Error::LibraryError(format!("send() failed: {}", e)))?;
+            Ok(Value::Null)
+        }));
+    }
+}
+
+fn string_field(map: &IndexMap<String, Value>, key: &str) -> Result<Option<String>, Error> {
+    match map.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.to_string())),
+        Some(_) => Err(Error::TypeError(format!("send() '{}' must be a string", key))),
+    }
+}
+
+fn required(map: &IndexMap<String, Value>, key: &str) -> Result<String, Error> {
+    string_field(map, key)?.ok_or_else(|| Error::LibraryError(format!("send() requires a '{}' entry", key)))
+}
+
+// a single string or an array of strings
+fn string_list(map: &IndexMap<String, Value>, key: &str) -> Result<Vec<String>, Error> {
+    match map.get(key) {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::String(s)) => Ok(vec![s.to_string()]),
+        Some(Value::Array(arr)) => lock_array(arr)?.iter().map(|v| match v {
+            Value::String(s) => Ok(s.to_string()),
+            _ => Err(Error::TypeError(format!("send() '{}' must only contain strings", key))),
+        }).collect(),
+        Some(_) => Err(Error::TypeError(format!("send() '{}' must be a string or an array of strings", key))),
+    }
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, Error> {
+    address.parse()
+        .map_err(|e| Error::LibraryError(format!("send() invalid address '{}': {}", address, e)))
+}
+
+fn build_message(options: &IndexMap<String, Value>) -> Result<Message, Error> {
+    let recipients = string_list(options, "to")?;
+    if recipients.is_empty() {
+        return Err(Error::LibraryError("send() requires at least one 'to' address".to_string()));
+    }
+
+    let mut builder = Message::builder()
+        .from(mailbox(&required(options, "from")?)?)
+        .subject(string_field(options, "subject")?.unwrap_or_default());
+    for to in &recipients {
+        builder = builder.to(mailbox(to)?);
+    }
+    for cc in string_list(options, "cc")? {
+        builder = builder.cc(mailbox(&cc)?);
+    }
+
+    let mut parts = MultiPart::mixed().singlepart(SinglePart::plain(string_field(options, "body")?.unwrap_or_default()));
+    for path in string_list(options, "attachments")? {
+        let contents = fs::read(&path)
+            .map_err(|e| Error::FileNotFound(format!("send() cannot read attachment '{}': {}", path, e)))?;
+        let filename = Path::new(&path).file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.clone());
+        let content_type = ContentType::parse("application/octet-stream").unwrap();
+        parts = parts.singlepart(Attachment::new(filename).body(contents, content_type));
+    }
+
+    builder.multipart(parts)
+        .map_err(|e| Error::LibraryError(format!("send() could not build message: {}", e)))
+}
+
+fn transport(smtp: &IndexMap<String, Value>) -> Result<SmtpTransport, Error> {
+    let host = required(smtp, "host")?;
+    let tls = string_field(smtp, "tls")?.unwrap_or_else(|| "starttls".to_string());
+    let connect_error = |e: lettre::transport::smtp::Error| Error::LibraryError(format!("send() cannot connect to '{}': {}", host, e));
+
+    let mut builder = match tls.as_str() {
+        "starttls" => SmtpTransport::starttls_relay(&host).map_err(connect_error)?,
+        "tls" => SmtpTransport::relay(&host).map_err(connect_error)?,
+        "none" => SmtpTransport::builder_dangerous(&host),
+        other => return Err(Error::LibraryError(format!("send() unknown tls mode '{}', expected starttls, tls or none", other))),
+    };
+
+    match smtp.get("port") {
+        None | Some(Value::Null) => {}
+        Some(Value::Number(port)) if (1..=65535).contains(port) => builder = builder.port(*port as u16),
+        Some(_) => return Err(Error::TypeError("send() smtp 'port' must be an integer from 1 to 65535".to_string())),
+    }
+
+    if let Some(user) = string_field(smtp, "user")? {
+        let password = string_field(smtp, "password")?.unwrap_or_default();
+        builder = builder.credentials(Credentials::new(user, password));
+    }
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn string(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    fn options(entries: &[(&str, Value)]) -> IndexMap<String, Value> {
+        entries.iter().map(|(key, value)| (key.to_string(), value.clone())).collect()
+    }
+
+    fn send(entries: &[(&str, Value)]) -> String {
+        let pairs = array(entries.iter().map(|(key, value)| array(vec![string(key), value.clone()])).collect());
+        MailLib::new().get_function("send").unwrap()(vec![pairs]).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn recipients_may_be_one_string_or_several() {
+        let map = options(&[("to", string("a@example.com")), ("cc", array(vec![string("b@example.com"), string("c@example.com")]))]);
+        assert_eq!(string_list(&map, "to").unwrap(), vec!["a@example.com"]);
+        assert_eq!(string_list(&map, "cc").unwrap(), vec!["b@example.com", "c@example.com"]);
+        assert!(string_list(&map, "bcc").unwrap().is_empty());
+
+        let bad = options(&[("to", array(vec![Value::Number(1)]))]);
+        assert!(string_list(&bad, "to").unwrap_err().to_string().contains("send() 'to' must only contain strings"));
+    }
+
+    #[test]
+    fn missing_fields_are_reported_before_connecting() {
+        assert!(send(&[("to", string("a@example.com"))]).contains("send() requires an 'smtp' entry"));
+        let smtp = array(vec![array(vec![string("host"), string("localhost")])]);
+        assert!(send(&[("smtp", smtp.clone()), ("from", string("me@example.com"))]).contains("send() requires at least one 'to' address"));
+        assert!(send(&[("smtp", smtp.clone()), ("to", string("a@example.com"))]).contains("send() requires a 'from' entry"));
+        assert!(send(&[("smtp", smtp), ("to", string("a@example.com")), ("from", Value::Number(1))]).contains("send() 'from' must be a string"));
+    }
+
+    fn transport_error(smtp: &IndexMap<String, Value>) -> String {
+        match transport(smtp) {
+            Ok(_) => panic!("expected an error for {:?}", smtp),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn smtp_settings_are_validated() {
+        let unknown_tls = options(&[("host", string("localhost")), ("tls", string("ssl"))]);
+        assert!(transport_error(&unknown_tls).contains("send() unknown tls mode 'ssl'"));
+        let bad_port = options(&[("host", string("localhost")), ("tls", string("none")), ("port", Value::Number(70000))]);
+        assert!(transport_error(&bad_port).contains("send() smtp 'port' must be an integer from 1 to 65535"));
+        assert!(transport_error(&options(&[])).contains("send() requires a 'host' entry"));
+    }
+}
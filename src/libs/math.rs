@@ -1,16 +1,40 @@
-use super::Library;
+use super::{find_help, Library, Function};
 use crate::error::Error;
 use crate::parser::Value;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-pub struct MathLib {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+// isclose()'s tolerance when none is passed; scripts can change it with
+// math.tolerance = 1e-6;
+const DEFAULT_TOLERANCE: f64 = 1e-9;
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("abs(x)", "Absolute value."),
+    ("pow(x, y)", "x raised to the power y."),
+    ("gcd(a, b)", "Greatest common divisor of two integers."),
+    ("sqrt(x)", "Square root, as a float."),
+    ("sin(x)", "Sine of x radians."),
+    ("cos(x)", "Cosine of x radians."),
+    ("tan(x)", "Tangent of x radians."),
+    ("log(x, base)", "Logarithm of x in the given base."),
+    ("ln(x)", "Natural logarithm."),
+    ("ceil(x)", "Smallest integer not below x."),
+    ("floor(x)", "Largest integer not above x."),
+    ("round(x)", "x rounded to the nearest integer."),
+    ("isclose(a, b, tol?)", "Whether a and b are equal within a tolerance (math.tolerance by default), relative for large values."),
+];
+
+pub(crate) struct MathLib {
+    functions: HashMap<String, Function>,
     constants: HashMap<String, Value>,
     var_mutability: HashMap<String, bool>,
+    // math.tolerance, shared with the isclose closure
+    tolerance: Arc<Mutex<f64>>,
 }
 
 impl Library for MathLib {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -18,15 +42,46 @@ impl Library for MathLib {
         self.constants.get(name)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
     fn box_clone(&self) -> Box<dyn Library> {
         let mut new_lib = MathLib::new();
         new_lib.constants = self.constants.clone();
+        new_lib.var_mutability = self.var_mutability.clone();
+        *new_lib.tolerance.lock().unwrap() = *self.tolerance.lock().unwrap();
         Box::new(new_lib)
     }
 
     fn is_mutable(&self, name: &str) -> Option<bool> {
         self.var_mutability.get(name).copied()
     }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+
+    fn set_constant(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        if name != "tolerance" {
+            return Err(Error::ImmutableConstant(format!("math.{} cannot be assigned", name)));
+        }
+        let tolerance = match &value {
+            Value::Number(n) => *n as f64,
+            Value::Float(f) => *f,
+            _ => return Err(Error::TypeError("math.tolerance must be a number".to_string())),
+        };
+        if tolerance < 0.0 {
+            return Err(Error::TypeError("math.tolerance must not be negative".to_string()));
+        }
+        *self.tolerance.lock().unwrap() = tolerance;
+        self.constants.insert(name.to_string(), value);
+        Ok(())
+    }
 }
 
 impl MathLib {
@@ -35,6 +90,7 @@ impl MathLib {
             functions: HashMap::new(),
             constants: HashMap::new(),
             var_mutability: HashMap::new(),
+            tolerance: Arc::new(Mutex::new(DEFAULT_TOLERANCE)),
         };
         
         lib.register_functions();
@@ -48,6 +104,11 @@ impl MathLib {
             self.constants.insert("E".to_string(), Value::Float(std::f64::consts::E));
             self.constants.insert("TAU".to_string(), Value::Float(std::f64::consts::TAU));
             self.constants.insert("INF".to_string(), Value::Float(f64::INFINITY));
+            for name in ["PI", "E", "TAU", "INF"] {
+                self.var_mutability.insert(name.to_string(), false);
+            }
+            self.constants.insert("tolerance".to_string(), Value::Float(DEFAULT_TOLERANCE));
+            self.var_mutability.insert("tolerance".to_string(), true);
         }
     
         fn register_functions(&mut self) {
@@ -198,6 +259,35 @@ impl MathLib {
                 }
             }));
     
+            // isclose(a, b, tol?) - relative tolerance for large values, absolute near zero
+            let tolerance = Arc::clone(&self.tolerance);
+            self.functions.insert("isclose".to_string(), Box::new(move |args| {
+                if args.len() < 2 || args.len() > 3 {
+                    return Err(Error::TypeError("isclose() takes 2 or 3 arguments".to_string()));
+                }
+                fn as_f64(value: &Value) -> Result<f64, Error> {
+                    match value {
+                        Value::Number(n) => Ok(*n as f64),
+                        Value::Float(f) => Ok(*f),
+                        _ => Err(Error::TypeError("isclose() requires numeric arguments".to_string()))
+                    }
+                }
+                let a = as_f64(&args[0])?;
+                let b = as_f64(&args[1])?;
+                let tol = match args.get(2) {
+                    Some(value) => as_f64(value)?,
+                    None => *tolerance.lock().unwrap(),
+                };
+                if tol < 0.0 {
+                    return Err(Error::TypeError("isclose() tolerance must not be negative".to_string()));
+                }
+                if a == b {
+                    return Ok(Value::Boolean(true));
+                }
+                let diff = (a - b).abs();
+                Ok(Value::Boolean(diff <= tol.max(tol * a.abs().max(b.abs()))))
+            }));
+
             self.functions.insert("round".to_string(), Box::new(|args| {
                 if args.len() != 1 {
                     return Err(Error::TypeError("round() takes exactly 1 argument".to_string()));
@@ -209,4 +299,52 @@ impl MathLib {
                 }
             }));
         }
-    }
\ No newline at end of file
+    }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn isclose(args: Vec<Value>) -> Result<Value, Error> {
+        MathLib::new().get_function("isclose").unwrap()(args)
+    }
+
+    #[test]
+    fn isclose_compares_with_a_tolerance() {
+        assert_eq!(isclose(vec![Value::Float(0.1 + 0.2), Value::Float(0.3)]).unwrap(), Value::Boolean(true));
+        assert_eq!(isclose(vec![Value::Float(1.0), Value::Float(1.1)]).unwrap(), Value::Boolean(false));
+        assert_eq!(isclose(vec![Value::Float(1.0), Value::Float(1.1), Value::Float(0.2)]).unwrap(), Value::Boolean(true));
+        // relative to the magnitude for large values
+        assert_eq!(isclose(vec![Value::Float(1e12), Value::Float(1e12 + 1.0)]).unwrap(), Value::Boolean(true));
+        assert_eq!(isclose(vec![Value::Number(2), Value::Float(2.0)]).unwrap(), Value::Boolean(true));
+    }
+
+    #[test]
+    fn tolerance_is_the_only_assignable_constant() {
+        let mut lib = MathLib::new();
+        assert_eq!(lib.is_mutable("tolerance"), Some(true));
+        assert_eq!(lib.is_mutable("PI"), Some(false));
+        let close = |lib: &MathLib| lib.get_function("isclose").unwrap()(vec![Value::Float(1.0), Value::Float(1.001)]).unwrap();
+        assert_eq!(close(&lib), Value::Boolean(false));
+
+        lib.set_constant("tolerance", Value::Float(0.01)).unwrap();
+        assert_eq!(lib.get_constant("tolerance"), Some(&Value::Float(0.01)));
+        assert_eq!(close(&lib), Value::Boolean(true));
+        // clones, as a function call gets, start from the current value
+        let clone = lib.box_clone();
+        assert_eq!(clone.get_function("isclose").unwrap()(vec![Value::Float(1.0), Value::Float(1.001)]).unwrap(), Value::Boolean(true));
+        assert_eq!(clone.is_mutable("E"), Some(false));
+
+        assert!(lib.set_constant("tolerance", Value::Float(-1.0)).is_err());
+        assert!(lib.set_constant("tolerance", Value::String("x".into())).is_err());
+        assert!(lib.set_constant("PI", Value::Float(3.0)).unwrap_err().to_string().contains("math.PI cannot be assigned"));
+        assert_eq!(lib.get_constant("PI"), Some(&Value::Float(std::f64::consts::PI)));
+    }
+
+    #[test]
+    fn isclose_rejects_bad_arguments() {
+        assert!(isclose(vec![Value::Float(1.0)]).is_err());
+        assert!(isclose(vec![Value::String("1".into()), Value::Float(1.0)]).is_err());
+        assert!(isclose(vec![Value::Float(1.0), Value::Float(1.0), Value::Float(-1.0)]).is_err());
+    }
+}
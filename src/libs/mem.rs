@@ -1,18 +1,54 @@
-use super::Library;
+use super::{find_help, Library, Function};
 use crate::error::Error;
-use crate::parser::Value;
+use crate::parser::{Value, lock_array};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::mem;
 
-pub struct MemLib {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+// bytes the value's data takes, as sizeof() and std.inspect() report it
+pub fn size_of_value(value: &Value) -> Result<usize, Error> {
+    Ok(match value {
+        Value::Number(_) => mem::size_of::<i32>(),
+        Value::Float(_) => mem::size_of::<f64>(),
+        Value::Boolean(_) => mem::size_of::<bool>(),
+        Value::String(s) => s.len(),
+        Value::Array(arr) => {
+            let guard = lock_array(arr)?;
+            guard.capacity() * mem::size_of::<Value>()
+        },
+        Value::Function(_, _, _) => mem::size_of::<Value>(),
+        _ => mem::size_of::<Value>(),
+    })
+}
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("sizeof(value)", "Bytes the value's data takes."),
+    ("deepcopy(array)", "A copy of the array whose nested arrays are copied too."),
+    ("getrefcount(array)", "How many references share the array."),
+    ("allocated()", "Allocation statistics, currently always [0, 0]."),
+    ("id(value)", "An identity number for the value; shared arrays have the same id."),
+    ("getsizeof(value)", "Bytes the value takes including its header."),
+    ("is(a, b)", "Whether a and b are the same array."),
+    ("getalign(value)", "Memory alignment of the value's type."),
+    ("isshared(array)", "Whether more than one reference holds the array."),
+    ("memdiff(a, b)", "Distance in bytes between the two values' addresses."),
+    ("meminfo(array)", "[length, capacity, bytes, references] of an array."),
+    ("fraginfo(array)", "[unused slots, percent unused] of an array's capacity."),
+    ("shrink(array)", "Releases the array's unused capacity."),
+    ("reserve(array, n)", "Makes room for n more elements."),
+    ("sharemem(a, b)", "Whether two arrays share storage."),
+    ("memrange(array)", "[start, end] addresses of the array's elements."),
+];
+
+pub(crate) struct MemLib {
+    functions: HashMap<String, Function>,
     constants: HashMap<String, Value>,
     var_mutability: HashMap<String, bool>,
 }
 
 impl Library for MemLib {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -20,6 +56,14 @@ impl Library for MemLib {
         self.constants.get(name)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
     fn box_clone(&self) -> Box<dyn Library> {
         let mut new_lib = MemLib::new();
         new_lib.constants = self.constants.clone();
@@ -30,6 +74,10 @@ impl Library for MemLib {
     fn is_mutable(&self, name: &str) -> Option<bool> {
         self.var_mutability.get(name).copied()
     }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
 }
 
 impl MemLib {
@@ -60,20 +108,7 @@ impl MemLib {
                 return Err(Error::TypeError("sizeof() takes exactly 1 argument".to_string()));
             }
             
-            let size = match &args[0] {
-                Value::Number(_) => mem::size_of::<i32>(),
-                Value::Float(_) => mem::size_of::<f64>(),
-                Value::Boolean(_) => mem::size_of::<bool>(),
-                Value::String(s) => s.capacity(),
-                Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
-                    guard.capacity() * mem::size_of::<Value>()
-                },
-                Value::Function(_, _, _) => mem::size_of::<Value>(),
-                _ => mem::size_of::<Value>(),
-            };
-            
-            Ok(Value::Number(size as i32))
+            Ok(Value::Number(size_of_value(&args[0])? as i32))
         }));
 
         self.functions.insert("deepcopy".to_string(), Box::new(|args| {
@@ -83,13 +118,13 @@ impl MemLib {
             
             match &args[0] {
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(arr)?;
                     let mut new_arr = Vec::new();
                     
                     for item in guard.iter() {
                         new_arr.push(match item {
                             Value::Array(nested_arr) => {
-                                let nested_guard = nested_arr.lock().unwrap();
+                                let nested_guard = lock_array(nested_arr)?;
                                 Value::Array(Arc::new(Mutex::new(nested_guard.clone())))
                             },
                             _ => item.clone(),
@@ -154,9 +189,9 @@ impl MemLib {
             }
             
             let size = match &args[0] {
-                Value::String(s) => s.capacity() + mem::size_of::<String>(),
+                Value::String(s) => s.len() + mem::size_of::<Arc<str>>(),
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(arr)?;
                     guard.capacity() * mem::size_of::<Value>() + mem::size_of::<Vec<Value>>()
                 },
                 _ => mem::size_of::<Value>(),
@@ -230,7 +265,7 @@ impl MemLib {
             
             match &args[0] {
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(arr)?;
                     let info = vec![
                         Value::Number(guard.len() as i32),        // length
                         Value::Number(guard.capacity() as i32),   // capacity
@@ -250,7 +285,7 @@ impl MemLib {
             
             match &args[0] {
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(arr)?;
                     let capacity = guard.capacity();
                     let length = guard.len();
                     let wasted = capacity - length;
@@ -277,7 +312,7 @@ impl MemLib {
             
             match &args[0] {
                 Value::Array(arr) => {
-                    let mut guard = arr.lock().unwrap();
+                    let mut guard = lock_array(arr)?;
                     let mut new_vec = Vec::new();
                     new_vec.extend(guard.iter().cloned());
                     new_vec.shrink_to_fit();
@@ -295,7 +330,7 @@ impl MemLib {
             
             match (&args[0], &args[1]) {
                 (Value::Array(arr), Value::Number(additional)) => {
-                    let mut guard = arr.lock().unwrap();
+                    let mut guard = lock_array(arr)?;
                     guard.reserve(*additional as usize);
                     Ok(Value::Null)
                 },
@@ -325,7 +360,7 @@ impl MemLib {
             
             match &args[0] {
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(arr)?;
                     let start = guard.as_ptr() as usize;
                     let end = start + (guard.len() * mem::size_of::<Value>());
                     
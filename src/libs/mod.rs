@@ -4,14 +4,174 @@ pub mod sys;
 pub mod os;
 pub mod io;
 pub mod mem;
+pub mod args;
+pub mod linalg;
+pub mod plot;
+pub mod archive;
+pub mod notify;
+pub mod net;
+pub mod http;
+pub mod random;
+pub mod time;
+pub mod bench;
+#[cfg(feature = "imaging")]
+pub mod image;
+#[cfg(feature = "mail")]
+pub mod mail;
 
 use crate::error::Error;
-use crate::parser::Value;
+use crate::parser::{Value, lock_array};
+use indexmap::IndexMap;
+use ::std::sync::{Arc, Mutex};
+
+// a library function: the evaluated arguments in, a value or an error out
+pub type Function = Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>;
 
 #[allow(dead_code)]
 pub trait Library {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>;
+    fn get_function(&self, name: &str) -> Option<&Function>;
     fn get_constant(&self, name: &str) -> Option<&Value>;
+    // for dir(): every name reachable as lib.name, in no particular order
+    fn function_names(&self) -> Vec<String>;
+    fn constant_names(&self) -> Vec<String>;
     fn is_mutable(&self, name: &str) -> Option<bool>;
     fn box_clone(&self) -> Box<dyn Library>;
-}
\ No newline at end of file
+    // called once when the library is unloaded: at the end of the run, or
+    // when the block that imported it closes
+    fn teardown(&self) -> Result<(), Error> {
+        Ok(())
+    }
+    // (signature, description) of a function, for std.help() and :help
+    fn help(&self, _name: &str) -> Option<(String, String)> {
+        None
+    }
+    // lib.name = value, for the constants is_mutable reports as Some(true)
+    fn set_constant(&mut self, name: &str, _value: Value) -> Result<(), Error> {
+        Err(Error::ImmutableConstant(format!("'{}' cannot be assigned", name)))
+    }
+}
+
+// Libraries import(name, embedded) can load in this build, for --version
+// and sys.FEATURES
+pub fn embedded_libraries() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut names = vec![
+        "std", "math", "sys", "os", "io", "mem", "args", "linalg", "plot",
+        "archive", "notify", "dialog", "net", "http", "random", "time", "bench",
+    ];
+    #[cfg(feature = "imaging")]
+    names.push("image");
+    #[cfg(feature = "mail")]
+    names.push("mail");
+    names
+}
+
+// A fresh instance of an embedded library, or None if this build lacks it
+pub fn load_embedded(name: &str) -> Option<Box<dyn Library>> {
+    let lib: Box<dyn Library> = match name {
+        "std" => Box::new(std::StdLib::new()),
+        "math" => Box::new(math::MathLib::new()),
+        "sys" => Box::new(sys::SysLib::new()),
+        "os" => Box::new(os::OSLib::new()),
+        "io" => Box::new(io::IOLib::new()),
+        "mem" => Box::new(mem::MemLib::new()),
+        "args" => Box::new(args::ArgsLib::new()),
+        "linalg" => Box::new(linalg::LinalgLib::new()),
+        "plot" => Box::new(plot::PlotLib::new()),
+        "archive" => Box::new(archive::ArchiveLib::new()),
+        "notify" => Box::new(notify::NotifyLib::new()),
+        "dialog" => Box::new(notify::DialogLib::new()),
+        "net" => Box::new(net::NetLib::new()),
+        "http" => Box::new(http::HttpLib::new()),
+        "random" => Box::new(random::RandomLib::new()),
+        "time" => Box::new(time::TimeLib::new()),
+        "bench" => Box::new(bench::BenchLib::new()),
+        #[cfg(feature = "imaging")]
+        "image" => Box::new(image::ImageLib::new()),
+        #[cfg(feature = "mail")]
+        "mail" => Box::new(mail::MailLib::new()),
+        _ => return None,
+    };
+    Some(lib)
+}
+
+// Looks a function up in a library's help table by the name before the '('
+pub fn find_help(table: &[(&str, &str)], name: &str) -> Option<(String, String)> {
+    table.iter()
+        .find(|(signature, _)| signature.split('(').next() == Some(name))
+        .map(|(signature, doc)| (signature.to_string(), doc.to_string()))
+}
+
+// Optional cargo features this binary was built with
+pub fn enabled_features() -> Vec<&'static str> {
+    [("unix", cfg!(feature = "unix")), ("imaging", cfg!(feature = "imaging")), ("mail", cfg!(feature = "mail"))]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// Scripts have no dict type, so key/value data travels as [[key, value], ...]
+pub fn pairs_to_map(pairs: &Value, fname: &str) -> Result<IndexMap<String, Value>, Error> {
+    let arr = match pairs {
+        Value::Array(arr) => arr,
+        _ => return Err(Error::TypeError(format!("{}() requires an array of [key, value] pairs", fname)))
+    };
+    let mut values = IndexMap::new();
+    for pair in lock_array(arr)?.iter() {
+        let entry = match pair {
+            Value::Array(entry) => lock_array(entry)?.clone(),
+            _ => return Err(Error::TypeError(format!("{}() entries must be [key, value] arrays", fname)))
+        };
+        match entry.as_slice() {
+            [Value::String(key), value] => {
+                values.insert(key.to_string(), value.clone());
+            }
+            _ => return Err(Error::TypeError(format!("{}() entries must be [key, value] arrays with a string key", fname)))
+        }
+    }
+    Ok(values)
+}
+
+pub fn map_to_pairs(map: IndexMap<String, Value>) -> Value {
+    let pairs = map.into_iter()
+        .map(|(key, value)| Value::Array(Arc::new(Mutex::new(vec![Value::String(key.into()), value]))))
+        .collect();
+    Value::Array(Arc::new(Mutex::new(pairs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(key: &str, value: i32) -> Value {
+        Value::Array(Arc::new(Mutex::new(vec![Value::String(key.into()), Value::Number(value)])))
+    }
+
+    #[test]
+    fn pairs_keep_insertion_order() {
+        let pairs = Value::Array(Arc::new(Mutex::new(vec![pair("b", 1), pair("a", 2), pair("c", 3)])));
+        let map = pairs_to_map(&pairs, "test").unwrap();
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["b", "a", "c"]);
+        assert_eq!(map_to_pairs(map), pairs);
+    }
+
+    #[test]
+    fn every_embedded_function_has_help() {
+        for name in embedded_libraries() {
+            let lib = load_embedded(name).unwrap();
+            for function in lib.function_names() {
+                assert!(lib.help(&function).is_some(), "{}.{} has no help text", name, function);
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_must_have_string_keys() {
+        let pairs = Value::Array(Arc::new(Mutex::new(vec![
+            Value::Array(Arc::new(Mutex::new(vec![Value::Number(1), Value::Number(2)]))),
+        ])));
+        let err = pairs_to_map(&pairs, "test").unwrap_err();
+        assert!(err.to_string().contains("test() entries must be [key, value] arrays with a string key"));
+    }
+}
@@ -0,0 +1,225 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+// Open websockets, addressed by the integer handle ws_connect() returns
+#[derive(Default)]
+struct SocketStore {
+    next_id: i32,
+    sockets: HashMap<i32, Socket>,
+}
+
+type Store = Arc<Mutex<SocketStore>>;
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("ws_connect(url)", "Opens a ws:// or wss:// websocket and returns its handle."),
+    ("ws_send(handle, message)", "Sends a string as text or an array of bytes as binary."),
+    ("ws_recv(handle, timeout?)", "The next message, or null on timeout or when the server closed."),
+    ("ws_close(handle)", "Closes the websocket."),
+];
+
+pub(crate) struct NetLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    store: Store,
+}
+
+impl Library for NetLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn is_mutable(&self, _name: &str) -> Option<bool> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        // clones share the store so handles stay valid inside functions
+        Box::new(NetLib::with_store(Arc::clone(&self.store)))
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl NetLib {
+    pub fn new() -> Self {
+        Self::with_store(Arc::new(Mutex::new(SocketStore::default())))
+    }
+
+    fn with_store(store: Store) -> Self {
+        let mut lib = NetLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            store,
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn add<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&mut SocketStore, &[Value]) -> Result<Value, Error> + 'static,
+    {
+        let store = Arc::clone(&self.store);
+        self.functions.insert(name.to_string(), Box::new(move |args| f(&mut store.lock().unwrap(), &args)));
+    }
+
+    fn register_functions(&mut self) {
+        // ws_connect(url) -> handle; url is ws:// or wss://
+        self.add("ws_connect", |store, args| match args {
+            [Value::String(url)] => {
+                let (socket, _) = tungstenite::connect(&**url)
+                    .map_err(|e| Error::LibraryError(format!("ws_connect() failed to connect to '{}': {}", url, e)))?;
+                store.next_id += 1;
+                store.sockets.insert(store.next_id, socket);
+                Ok(Value::Number(store.next_id))
+            }
+            _ => Err(Error::TypeError("ws_connect() requires 1 string argument".to_string())),
+        });
+
+        // ws_send(handle, msg) - strings are sent as text, arrays of bytes as binary
+        self.add("ws_send", |store, args| match args {
+            [handle, msg] => {
+                let message = match msg {
+                    Value::String(text) => Message::Text(text.to_string()),
+                    Value::Array(arr) => Message::Binary(bytes(&lock_array(arr)?)?),
+                    _ => return Err(Error::TypeError("ws_send() message must be a string or an array of bytes".to_string())),
+                };
+                socket(store, handle, "ws_send")?.send(message)
+                    .map_err(|e| Error::LibraryError(format!("ws_send() failed: {}", e)))?;
+                Ok(Value::Null)
+            }
+            _ => Err(Error::TypeError("ws_send() takes exactly 2 arguments".to_string())),
+        });
+
+        // ws_recv(handle, timeout?) -> message, or null on timeout or when the server closed
+        self.add("ws_recv", |store, args| {
+            let (handle, timeout) = match args {
+                [handle] => (handle, None),
+                [handle, Value::Number(secs)] if *secs > 0 => (handle, Some(Duration::from_secs(*secs as u64))),
+                [handle, Value::Float(secs)] if *secs > 0.0 => (handle, Some(Duration::from_secs_f64(*secs))),
+                _ => return Err(Error::TypeError("ws_recv() takes a handle and an optional positive timeout in seconds".to_string())),
+            };
+            let socket = socket(store, handle, "ws_recv")?;
+            set_read_timeout(socket, timeout)?;
+            loop {
+                match socket.read() {
+                    Ok(Message::Text(text)) => return Ok(Value::String(text.into())),
+                    Ok(Message::Binary(data)) => {
+                        let data = data.into_iter().map(|b| Value::Number(b as i32)).collect();
+                        return Ok(Value::Array(Arc::new(Mutex::new(data))));
+                    }
+                    Ok(Message::Close(_)) => return Ok(Value::Null),
+                    // pings are answered by tungstenite itself
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                        return Ok(Value::Null);
+                    }
+                    Err(tungstenite::Error::ConnectionClosed) => return Ok(Value::Null),
+                    Err(e) => return Err(Error::LibraryError(format!("ws_recv() failed: {}", e))),
+                }
+            }
+        });
+
+        self.add("ws_close", |store, args| match args {
+            [Value::Number(id)] => {
+                let mut socket = store.sockets.remove(id)
+                    .ok_or_else(|| Error::LibraryError(format!("ws_close() got unknown websocket handle {}", id)))?;
+                // the peer may already be gone; the handle is released either way
+                let _ = socket.close(None);
+                let _ = socket.flush();
+                Ok(Value::Null)
+            }
+            _ => Err(Error::TypeError("ws_close() requires a websocket handle".to_string())),
+        });
+    }
+}
+
+fn socket<'a>(store: &'a mut SocketStore, handle: &Value, fname: &str) -> Result<&'a mut Socket, Error> {
+    match handle {
+        Value::Number(id) => store.sockets.get_mut(id)
+            .ok_or_else(|| Error::LibraryError(format!("{}() got unknown websocket handle {}", fname, id))),
+        _ => Err(Error::TypeError(format!("{}() requires a websocket handle", fname))),
+    }
+}
+
+fn bytes(values: &[Value]) -> Result<Vec<u8>, Error> {
+    values.iter().map(|v| match v {
+        Value::Number(n) if (0..=255).contains(n) => Ok(*n as u8),
+        _ => Err(Error::TypeError("ws_send() bytes must be integers from 0 to 255".to_string())),
+    }).collect()
+}
+
+fn set_read_timeout(socket: &mut Socket, timeout: Option<Duration>) -> Result<(), Error> {
+    let stream = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream,
+        MaybeTlsStream::Rustls(stream) => stream.get_ref(),
+        _ => return Ok(()),
+    };
+    stream.set_read_timeout(timeout)
+        .map_err(|e| Error::LibraryError(format!("ws_recv() could not set timeout: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn websockets_send_and_receive_through_a_handle() {
+        // echoes two messages, then closes
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut socket = tungstenite::accept(listener.accept().unwrap().0).unwrap();
+            for _ in 0..2 {
+                let message = socket.read().unwrap();
+                socket.send(message).unwrap();
+            }
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+        });
+
+        let net = NetLib::new();
+        let call = |name: &str, args: Vec<Value>| net.get_function(name).unwrap()(args);
+        let handle = call("ws_connect", vec![Value::String(format!("ws://127.0.0.1:{}", port).into())]).unwrap();
+        call("ws_send", vec![handle.clone(), Value::String("hello".into())]).unwrap();
+        assert_eq!(call("ws_recv", vec![handle.clone(), Value::Number(5)]).unwrap(), Value::String("hello".into()));
+        let data = Value::Array(Arc::new(Mutex::new(vec![Value::Number(0), Value::Number(255)])));
+        call("ws_send", vec![handle.clone(), data.clone()]).unwrap();
+        assert_eq!(call("ws_recv", vec![handle.clone(), Value::Number(5)]).unwrap(), data);
+        // the server closing reads as null
+        assert_eq!(call("ws_recv", vec![handle.clone(), Value::Number(5)]).unwrap(), Value::Null);
+        call("ws_close", vec![handle.clone()]).unwrap();
+        server.join().unwrap();
+
+        let closed = call("ws_send", vec![handle, Value::String("again".into())]).unwrap_err();
+        assert!(closed.to_string().contains("unknown websocket handle 1"), "{}", closed);
+        assert!(bytes(&[Value::Number(256)]).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::Value;
+use std::collections::HashMap;
+use std::process::{Command, Output};
+
+// Desktop notifications and dialogs go through the platform's own tools
+// (notify-send/zenity, osascript, PowerShell). When none is available the
+// terminal is used instead so scripts still work over ssh or in CI.
+
+// signature and description of each function, for std.help() and :help
+const NOTIFY_HELP: &[(&str, &str)] = &[
+    ("send(title, body)", "Shows a desktop notification; true if it was shown."),
+];
+
+pub(crate) struct NotifyLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+}
+
+impl Library for NotifyLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = NotifyLib::new();
+        new_lib.constants = self.constants.clone();
+        Box::new(new_lib)
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(NOTIFY_HELP, name)
+    }
+}
+
+impl NotifyLib {
+    pub fn new() -> Self {
+        let mut lib = NotifyLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // send(title, body) - returns true if a desktop notification was shown
+        self.functions.insert("send".to_string(), Box::new(|args| {
+            let (title, body) = match args.as_slice() {
+                [title, body] => (title.to_string(), body.to_string()),
+                _ => return Err(Error::TypeError("send() takes exactly 2 arguments".to_string())),
+            };
+            if let Some(mut cmd) = notification_command(&title, &body) {
+                // the Windows balloon only shows while PowerShell keeps running,
+                // so it is left to finish on its own instead of being waited for
+                let shown = if cfg!(target_os = "windows") {
+                    cmd.spawn().is_ok()
+                } else {
+                    run(&mut cmd).is_some_and(|output| output.status.success())
+                };
+                if shown {
+                    return Ok(Value::Boolean(true));
+                }
+            }
+            outln!("[{}] {}", title, body);
+            Ok(Value::Boolean(false))
+        }));
+    }
+}
+
+const DIALOG_HELP: &[(&str, &str)] = &[
+    ("confirm(message)", "Asks a yes/no question; true for OK or yes."),
+    ("prompt(message)", "Asks for text; null if the dialog was cancelled."),
+];
+
+pub(crate) struct DialogLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+}
+
+impl Library for DialogLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = DialogLib::new();
+        new_lib.constants = self.constants.clone();
+        Box::new(new_lib)
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(DIALOG_HELP, name)
+    }
+}
+
+impl DialogLib {
+    pub fn new() -> Self {
+        let mut lib = DialogLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // confirm(msg) -> true for OK/yes, false for cancel/no
+        self.functions.insert("confirm".to_string(), Box::new(|args| {
+            let message = match args.as_slice() {
+                [msg] => msg.to_string(),
+                _ => return Err(Error::TypeError("confirm() takes exactly 1 argument".to_string())),
+            };
+            if let Some(output) = confirm_command(&message).and_then(|mut cmd| run(&mut cmd)) {
+                // PowerShell reports the answer on stdout, the others via the exit code
+                match output.status.code() {
+                    Some(0) if cfg!(target_os = "windows") => {
+                        return Ok(Value::Boolean(String::from_utf8_lossy(&output.stdout).trim() == "True"));
+                    }
+                    Some(0) => return Ok(Value::Boolean(true)),
+                    Some(1) => return Ok(Value::Boolean(false)),
+                    _ => {}
+                }
+            }
+            let answer = crate::input::prompt(&format!("{} [y/N] ", message));
+            Ok(Value::Boolean(matches!(answer.to_lowercase().as_str(), "y" | "yes")))
+        }));
+
+        // prompt(msg) -> entered text, or null if the dialog was cancelled
+        self.functions.insert("prompt".to_string(), Box::new(|args| {
+            let message = match args.as_slice() {
+                [msg] => msg.to_string(),
+                _ => return Err(Error::TypeError("prompt() takes exactly 1 argument".to_string())),
+            };
+            if let Some(output) = prompt_command(&message).and_then(|mut cmd| run(&mut cmd)) {
+                match output.status.code() {
+                    Some(0) => {
+                        let text = String::from_utf8_lossy(&output.stdout);
+                        return Ok(Value::String(text.trim_end_matches(['\n', '\r']).into()));
+                    }
+                    Some(1) => return Ok(Value::Null),
+                    _ => {}
+                }
+            }
+            Ok(Value::String(crate::input::prompt(&format!("{} ", message)).into()))
+        }));
+    }
+}
+
+// None when the tool is missing; exit codes other than 0 (ok) and 1 (cancel)
+// mean the tool could not show anything, e.g. no display. Both fall back to the terminal.
+fn run(cmd: &mut Command) -> Option<Output> {
+    cmd.output().ok()
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// text reaches PowerShell through environment variables so it is never parsed as code
+fn powershell(script: &str, title: &str, body: &str) -> Command {
+    let mut cmd = Command::new("powershell");
+    cmd.args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .env("TD_TITLE", title)
+        .env("TD_BODY", body);
+    cmd
+}
+
+fn notification_command(title: &str, body: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("osascript");
+        cmd.args(["-e", &format!("display notification {} with title {}", applescript_string(body), applescript_string(title))]);
+        Some(cmd)
+    } else if cfg!(target_os = "windows") {
+        Some(powershell(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Information; $n.Visible = $true; \
+             $n.ShowBalloonTip(5000, $env:TD_TITLE, $env:TD_BODY, 'None'); \
+             Start-Sleep -Seconds 5; $n.Dispose()",
+            title, body,
+        ))
+    } else if cfg!(unix) {
+        let mut cmd = Command::new("notify-send");
+        // text starting with '-' must not be read as an option
+        cmd.args(["--", title, body]);
+        Some(cmd)
+    } else {
+        None
+    }
+}
+
+fn confirm_command(message: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("osascript");
+        cmd.args(["-e", &format!("display dialog {} buttons {{\"Cancel\", \"OK\"}} default button \"OK\"", applescript_string(message))]);
+        Some(cmd)
+    } else if cfg!(target_os = "windows") {
+        Some(powershell(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             [System.Windows.Forms.MessageBox]::Show($env:TD_BODY, 'Tidal', 'OKCancel') -eq 'OK'",
+            "", message,
+        ))
+    } else if cfg!(unix) {
+        let mut cmd = Command::new("zenity");
+        cmd.args(["--question", &format!("--text={}", message)]);
+        Some(cmd)
+    } else {
+        None
+    }
+}
+
+fn prompt_command(message: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("osascript");
+        cmd.args(["-e", &format!("text returned of (display dialog {} default answer \"\")", applescript_string(message))]);
+        Some(cmd)
+    } else if cfg!(target_os = "windows") {
+        Some(powershell(
+            "Add-Type -AssemblyName Microsoft.VisualBasic; \
+             [Microsoft.VisualBasic.Interaction]::InputBox($env:TD_BODY, 'Tidal')",
+            "", message,
+        ))
+    } else if cfg!(unix) {
+        let mut cmd = Command::new("zenity");
+        cmd.args(["--entry", &format!("--text={}", message)]);
+        Some(cmd)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn option_like_text_stays_an_argument() {
+        let cmd = notification_command("-t", "--help").unwrap();
+        assert_eq!(cmd.get_program(), "notify-send");
+        assert_eq!(args(&cmd), vec!["--", "-t", "--help"]);
+        assert_eq!(args(&confirm_command("--help").unwrap()), vec!["--question", "--text=--help"]);
+        assert_eq!(args(&prompt_command("-x").unwrap()), vec!["--entry", "--text=-x"]);
+    }
+
+    #[test]
+    fn applescript_strings_are_quoted_and_escaped() {
+        assert_eq!(applescript_string(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+    }
+
+    #[test]
+    fn powershell_gets_text_through_the_environment() {
+        let cmd = powershell("Write-Output $env:TD_BODY", "title", "body; rm -r /");
+        let envs: Vec<_> = cmd.get_envs().map(|(k, v)| (k.to_string_lossy().into_owned(), v.map(|v| v.to_string_lossy().into_owned()))).collect();
+        assert!(envs.contains(&("TD_BODY".to_string(), Some("body; rm -r /".to_string()))));
+        assert!(!args(&cmd).iter().any(|arg| arg.contains("rm -r")));
+    }
+
+    #[test]
+    fn arguments_are_checked_before_anything_is_shown() {
+        let send = NotifyLib::new();
+        let err = send.get_function("send").unwrap()(vec![Value::String("only a title".into())]).unwrap_err();
+        assert!(err.to_string().contains("send() takes exactly 2 arguments"));
+        let dialog = DialogLib::new();
+        let err = dialog.get_function("confirm").unwrap()(vec![]).unwrap_err();
+        assert!(err.to_string().contains("confirm() takes exactly 1 argument"));
+        assert_eq!(dialog.is_mutable("confirm"), None);
+    }
+}
@@ -1,21 +1,47 @@
-use super::Library;
+use super::{find_help, map_to_pairs, Library, Function};
 use crate::error::Error;
 use crate::parser::Value;
+use indexmap::IndexMap;
 use std::collections::HashMap;
-use std::fs;
+use std::fs::{self, File};
+use std::io::Read;
 use std::path::Path;
 use std::process::Command;
 use std::env;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
 
-pub struct OSLib {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("makedirs(path)", "Creates a directory and any missing parents."),
+    ("system(command)", "Runs a shell command and returns its exit code."),
+    ("rename(old, new)", "Renames a file or directory."),
+    ("remove(path)", "Deletes a file."),
+    ("removedirs(path)", "Deletes a directory and everything in it."),
+    ("listdir(path?)", "Names of the entries in a directory, the current one by default."),
+    ("scandir(path?)", "One [[\"name\", ...], [\"is_dir\", bool], [\"size\", bytes], [\"modified\", secs]] per entry, sorted by name."),
+    ("chdir(path)", "Changes the current working directory."),
+    ("exists(path)", "Whether the path exists."),
+    ("isfile(path)", "Whether the path is a file."),
+    ("isdir(path)", "Whether the path is a directory."),
+    ("mimetype(path)", "The file's MIME type, sniffed from its contents or extension."),
+    ("filesize(path)", "Size of the file in bytes."),
+    ("modified_time(path)", "When the file last changed, in seconds since the unix epoch."),
+    ("watch(path, callback, interval?)", "Calls callback for each change under path until it returns false."),
+    ("changes(path, since)", "Files under path modified after since (seconds since the epoch)."),
+    ("checksum(path, algorithm?)", "Hex digest of the file: sha224, sha256, sha384 or sha512."),
+];
+
+pub(crate) struct OSLib {
+    functions: HashMap<String, Function>,
     constants: HashMap<String, Value>,
     var_mutability: HashMap<String, bool>,
 }
 
 impl Library for OSLib {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -23,6 +49,14 @@ impl Library for OSLib {
         self.constants.get(name)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
     fn box_clone(&self) -> Box<dyn Library> {
         let mut new_lib = OSLib::new();
         new_lib.constants = self.constants.clone();
@@ -32,6 +66,10 @@ impl Library for OSLib {
     fn is_mutable(&self, name: &str) -> Option<bool> {
         self.var_mutability.get(name).copied()
     }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
 }
 
 impl OSLib {
@@ -49,12 +87,12 @@ impl OSLib {
     fn register_constants(&mut self) {
         self.constants.insert("name".to_string(), Value::String(
             if cfg!(target_os = "windows") { "nt" }
-            else { "posix" }.to_string()
+            else { "posix" }.into()
         ));
         
         self.constants.insert("linesep".to_string(), Value::String(
             if cfg!(target_os = "windows") { "\r\n" }
-            else { "\n" }.to_string()
+            else { "\n" }.into()
         ));
     }
 
@@ -67,7 +105,7 @@ impl OSLib {
                 Value::String(path) => {
                     let abs_path = std::env::current_dir()
                         .map_err(|e| Error::InterpreterError(e.to_string()))?
-                        .join(path);
+                        .join(&**path);
                     
                     fs::create_dir_all(&abs_path)
                         .map_err(|e| Error::InterpreterError(e.to_string()))?;
@@ -118,7 +156,7 @@ impl OSLib {
             }
             match (&args[0], &args[1]) {
                 (Value::String(src), Value::String(dst)) => {
-                    fs::rename(src, dst)
+                    fs::rename(&**src, &**dst)
                         .map_err(|e| Error::InterpreterError(e.to_string()))?;
                     Ok(Value::Null)
                 }
@@ -132,7 +170,7 @@ impl OSLib {
             }
             match &args[0] {
                 Value::String(path) => {
-                    fs::remove_file(path)
+                    fs::remove_file(&**path)
                         .map_err(|e| Error::InterpreterError(e.to_string()))?;
                     Ok(Value::Null)
                 }
@@ -155,24 +193,66 @@ impl OSLib {
             let entries = fs::read_dir(path)
                 .map_err(|e| Error::InterpreterError(e.to_string()))?;
             
-            let files: Vec<Value> = entries
+            let mut names: Vec<String> = entries
                 .filter_map(|entry| {
                     entry.ok().and_then(|e| 
-                        e.file_name().into_string().ok().map(Value::String)
+                        e.file_name().into_string().ok()
                     )
                 })
                 .collect();
+            // read_dir order depends on the filesystem
+            names.sort();
+            let files: Vec<Value> = names.into_iter().map(|name| Value::String(name.into())).collect();
 
             Ok(Value::Array(Arc::new(Mutex::new(files))))
         }));
 
+        // scandir(path?) - listdir with each entry's type, size and modified time;
+        // size is null for directories
+        self.functions.insert("scandir".to_string(), Box::new(|args| {
+            let path = match args.as_slice() {
+                [] => ".",
+                [Value::String(path)] => &**path,
+                _ => return Err(Error::TypeError("scandir() takes an optional string path".to_string()))
+            };
+            let entries = fs::read_dir(path)
+                .map_err(|e| Error::InterpreterError(format!("scandir() cannot read '{}': {}", path, e)))?;
+
+            let mut found = Vec::new();
+            for entry in entries.flatten() {
+                let Ok(name) = entry.file_name().into_string() else { continue };
+                // follow symlinks, but still list a broken one
+                let Ok(metadata) = fs::metadata(entry.path()).or_else(|_| entry.metadata()) else { continue };
+                found.push((name, metadata));
+            }
+            found.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let entries = found.into_iter().map(|(name, metadata)| {
+                let mut fields = IndexMap::new();
+                fields.insert("name".to_string(), Value::String(name.into()));
+                fields.insert("is_dir".to_string(), Value::Boolean(metadata.is_dir()));
+                let size = if metadata.is_dir() {
+                    Value::Null
+                } else {
+                    i32::try_from(metadata.len()).map(Value::Number).unwrap_or(Value::Float(metadata.len() as f64))
+                };
+                fields.insert("size".to_string(), size);
+                let modified = metadata.modified().ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(Value::Null, |d| Value::Float(d.as_secs_f64()));
+                fields.insert("modified".to_string(), modified);
+                map_to_pairs(fields)
+            }).collect();
+            Ok(Value::Array(Arc::new(Mutex::new(entries))))
+        }));
+
         self.functions.insert("chdir".to_string(), Box::new(|args| {
             if args.len() != 1 {
                 return Err(Error::TypeError("chdir() takes exactly 1 argument".to_string()));
             }
             match &args[0] {
                 Value::String(path) => {
-                    env::set_current_dir(path)
+                    env::set_current_dir(&**path)
                         .map_err(|e| Error::InterpreterError(e.to_string()))?;
                     Ok(Value::Null)
                 }
@@ -186,7 +266,7 @@ impl OSLib {
             }
             match &args[0] {
                 Value::String(path) => {
-                    Ok(Value::Boolean(Path::new(path).exists()))
+                    Ok(Value::Boolean(Path::new(&**path).exists()))
                 }
                 _ => Err(Error::TypeError("exists() requires string argument".to_string()))
             }
@@ -198,7 +278,7 @@ impl OSLib {
             }
             match &args[0] {
                 Value::String(path) => {
-                    Ok(Value::Boolean(Path::new(path).is_file()))
+                    Ok(Value::Boolean(Path::new(&**path).is_file()))
                 }
                 _ => Err(Error::TypeError("isfile() requires string argument".to_string()))
             }
@@ -210,7 +290,7 @@ impl OSLib {
             }
             match &args[0] {
                 Value::String(path) => {
-                    Ok(Value::Boolean(Path::new(path).is_dir()))
+                    Ok(Value::Boolean(Path::new(&**path).is_dir()))
                 }
                 _ => Err(Error::TypeError("isdir() requires string argument".to_string()))
             }
@@ -247,7 +327,7 @@ impl OSLib {
             }
             match &args[0] {
                 Value::String(path) => {
-                    let path = Path::new(path);
+                    let path = Path::new(&**path);
                     
 
                     fn remove_parents(path: &Path) -> std::io::Result<()> {
@@ -278,5 +358,374 @@ impl OSLib {
                 _ => Err(Error::TypeError("removedirs() requires string argument".to_string()))
             }
         }));
+
+        // mimetype(path) - sniffs magic bytes first, then falls back to the
+        // extension; a text extension wins, since text has no magic bytes
+        self.functions.insert("mimetype".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("mimetype() takes exactly 1 argument".to_string()));
+            }
+            match &args[0] {
+                Value::String(path) => {
+                    let mut head = [0u8; 512];
+                    let read = File::open(&**path)
+                        .and_then(|mut f| f.read(&mut head))
+                        .map_err(|e| Error::InterpreterError(format!("mimetype() cannot read '{}': {}", path, e)))?;
+                    let head = &head[..read];
+                    let mime = match (sniff_mimetype(head), mimetype_from_extension(path)) {
+                        (_, Some(text)) if text.starts_with("text/") => Some(text),
+                        // zip is also the container for office documents and jars
+                        (Some("application/zip"), by_extension) => by_extension.or(Some("application/zip")),
+                        (sniffed, by_extension) => sniffed.or(by_extension),
+                    }.unwrap_or(if std::str::from_utf8(head).is_ok() { "text/plain" } else { "application/octet-stream" });
+                    Ok(Value::String(mime.into()))
+                }
+                _ => Err(Error::TypeError("mimetype() requires string argument".to_string()))
+            }
+        }));
+
+        // filesize(path) - in bytes; sizes past the int range come back as a float
+        self.functions.insert("filesize".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("filesize() takes exactly 1 argument".to_string()));
+            }
+            match &args[0] {
+                Value::String(path) => {
+                    let len = fs::metadata(&**path)
+                        .map_err(|e| Error::InterpreterError(format!("filesize() cannot read '{}': {}", path, e)))?
+                        .len();
+                    Ok(i32::try_from(len).map(Value::Number).unwrap_or(Value::Float(len as f64)))
+                }
+                _ => Err(Error::TypeError("filesize() requires string argument".to_string()))
+            }
+        }));
+
+        // modified_time(path) - seconds since the unix epoch
+        self.functions.insert("modified_time".to_string(), Box::new(|args| {
+            if args.len() != 1 {
+                return Err(Error::TypeError("modified_time() takes exactly 1 argument".to_string()));
+            }
+            match &args[0] {
+                Value::String(path) => {
+                    let modified = fs::metadata(&**path)
+                        .and_then(|m| m.modified())
+                        .map_err(|e| Error::InterpreterError(format!("modified_time() cannot read '{}': {}", path, e)))?;
+                    let secs = match modified.duration_since(UNIX_EPOCH) {
+                        Ok(d) => d.as_secs_f64(),
+                        Err(e) => -e.duration().as_secs_f64(),
+                    };
+                    Ok(Value::Float(secs))
+                }
+                _ => Err(Error::TypeError("modified_time() requires string argument".to_string()))
+            }
+        }));
+
+        // watch(path, callback, interval?) - polls every `interval` seconds (default 0.5)
+        // and calls callback([["type", "create" | "modify" | "delete"], ["path", ...]])
+        // per change; returning false from the callback stops watching
+        self.functions.insert("watch".to_string(), Box::new(|args| {
+            let (path, callback, interval) = match args.as_slice() {
+                [Value::String(path), callback] => (path, callback, 0.5),
+                [Value::String(path), callback, Value::Number(secs)] if *secs > 0 => (path, callback, *secs as f64),
+                [Value::String(path), callback, Value::Float(secs)] if *secs > 0.0 => (path, callback, *secs),
+                _ => return Err(Error::TypeError("watch() takes a path, a callback function and an optional positive interval".to_string()))
+            };
+            if !matches!(callback, Value::Function(..)) {
+                return Err(Error::TypeError("watch() callback must be a function".to_string()));
+            }
+            if !Path::new(&**path).exists() {
+                return Err(Error::FileNotFound(format!("watch() cannot find '{}'", path)));
+            }
+
+            let mut previous = snapshot(Path::new(&**path));
+            loop {
+                thread::sleep(Duration::from_secs_f64(interval));
+                let current = snapshot(Path::new(&**path));
+                for (kind, changed) in diff_snapshots(&previous, &current) {
+                    let event = Value::Array(Arc::new(Mutex::new(vec![
+                        Value::Array(Arc::new(Mutex::new(vec![Value::String("type".into()), Value::String(kind.into())]))),
+                        Value::Array(Arc::new(Mutex::new(vec![Value::String("path".into()), Value::String(changed.into())]))),
+                    ])));
+                    if let Value::Boolean(false) = crate::interpreter::call_function(callback, vec![event])? {
+                        return Ok(Value::Null);
+                    }
+                }
+                previous = current;
+            }
+        }));
+
+        // changes(path, since) - files under path modified after `since` (seconds since the epoch)
+        self.functions.insert("changes".to_string(), Box::new(|args| {
+            let (path, since) = match args.as_slice() {
+                [Value::String(path), Value::Number(since)] => (path, *since as f64),
+                [Value::String(path), Value::Float(since)] => (path, *since),
+                _ => return Err(Error::TypeError("changes() requires a path and a timestamp".to_string()))
+            };
+            if !Path::new(&**path).exists() {
+                return Err(Error::FileNotFound(format!("changes() cannot find '{}'", path)));
+            }
+            let changed = snapshot(Path::new(&**path)).into_iter()
+                .filter(|(_, modified)| *modified > since)
+                .map(|(p, _)| Value::String(p.into()))
+                .collect();
+            Ok(Value::Array(Arc::new(Mutex::new(changed))))
+        }));
+
+        // checksum(path, algorithm) - hex digest; sha224, sha256, sha384 or sha512
+        self.functions.insert("checksum".to_string(), Box::new(|args| {
+            let (path, algorithm) = match args.as_slice() {
+                [Value::String(path)] => (path, "sha256"),
+                [Value::String(path), Value::String(algorithm)] => (path, &**algorithm),
+                _ => return Err(Error::TypeError("checksum() requires a path and an optional algorithm name".to_string()))
+            };
+            let file = File::open(&**path)
+                .map_err(|e| Error::InterpreterError(format!("checksum() cannot read '{}': {}", path, e)))?;
+            let digest = match algorithm.to_lowercase().as_str() {
+                "sha224" => hash_file::<Sha224>(file),
+                "sha256" => hash_file::<Sha256>(file),
+                "sha384" => hash_file::<Sha384>(file),
+                "sha512" => hash_file::<Sha512>(file),
+                other => return Err(Error::TypeError(format!(
+                    "checksum() unknown algorithm '{}', expected sha224, sha256, sha384 or sha512", other
+                )))
+            }.map_err(|e| Error::InterpreterError(format!("checksum() cannot read '{}': {}", path, e)))?;
+            Ok(Value::String(digest.into()))
+        }));
+    }
+}
+
+fn hash_file<D: Digest>(mut file: File) -> std::io::Result<String> {
+    let mut hasher = D::new();
+    let mut buffer = [0u8; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn sniff_mimetype(head: &[u8]) -> Option<&'static str> {
+    let signatures: [(&[u8], &str); 10] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"OggS", "audio/ogg"),
+        (b"\x7fELF", "application/x-elf"),
+        (b"\x00asm", "application/wasm"),
+    ];
+    for (magic, mime) in signatures {
+        if head.starts_with(magic) {
+            return Some(mime);
+        }
+    }
+    // "BM" and "ID3" start plenty of text, so their headers have to add up:
+    // a BMP's reserved bytes are zero and its info header has a known size,
+    // and an ID3 tag has version 2.2 to 2.4
+    if head.len() >= 18 && head.starts_with(b"BM") && head[6..10] == [0; 4] {
+        let info_size = u32::from_le_bytes([head[14], head[15], head[16], head[17]]);
+        if matches!(info_size, 12 | 40 | 52 | 56 | 64 | 108 | 124) {
+            return Some("image/bmp");
+        }
+    }
+    if head.len() >= 5 && head.starts_with(b"ID3") && (2..=4).contains(&head[3]) && head[4] != 0xff {
+        return Some("audio/mpeg");
     }
-}
\ No newline at end of file
+    if head.len() >= 12 && &head[..4] == b"RIFF" {
+        match &head[8..12] {
+            b"WEBP" => return Some("image/webp"),
+            b"WAVE" => return Some("audio/wav"),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn mimetype_from_extension(path: &str) -> Option<&'static str> {
+    let ext = Path::new(path).extension()?.to_str()?.to_lowercase();
+    let mime = match ext.as_str() {
+        "td" | "txt" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/vnd.microsoft.icon",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "jar" => "application/java-archive",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "wasm" => "application/wasm",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+fn modified_secs(metadata: &fs::Metadata) -> f64 {
+    metadata.modified().ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+// every file under path (or path itself) with its modification time, sorted by path
+fn snapshot(path: &Path) -> IndexMap<String, f64> {
+    let mut files = Vec::new();
+    let mut pending = vec![path.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let metadata = match fs::metadata(&current) {
+            Ok(m) => m,
+            Err(_) => continue, // removed while scanning
+        };
+        if metadata.is_dir() {
+            if let Ok(entries) = fs::read_dir(&current) {
+                pending.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+            }
+        } else {
+            files.push((current.to_string_lossy().into_owned(), modified_secs(&metadata)));
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files.into_iter().collect()
+}
+
+fn diff_snapshots(before: &IndexMap<String, f64>, after: &IndexMap<String, f64>) -> Vec<(&'static str, String)> {
+    let mut events = Vec::new();
+    for (path, modified) in after {
+        match before.get(path) {
+            None => events.push(("create", path.clone())),
+            Some(old) if old != modified => events.push(("modify", path.clone())),
+            _ => {}
+        }
+    }
+    for path in before.keys() {
+        if !after.contains_key(path) {
+            events.push(("delete", path.clone()));
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listdir_is_sorted_by_name() {
+        let dir = env::temp_dir().join(format!("tidal-listdir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["b.td", "c.td", "a.td"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let os = OSLib::new();
+        let listdir = os.get_function("listdir").unwrap();
+        let result = listdir(vec![Value::String(dir.to_string_lossy().into_owned().into())]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<Value> = ["a.td", "b.td", "c.td"].iter().map(|n| Value::String(n.to_string().into())).collect();
+        assert_eq!(result.unwrap(), Value::Array(Arc::new(Mutex::new(names))));
+    }
+
+    #[test]
+    fn scandir_describes_each_entry() {
+        let dir = env::temp_dir().join(format!("tidal-scandir-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("notes.txt"), "hello").unwrap();
+        let os = OSLib::new();
+        let result = os.get_function("scandir").unwrap()(vec![Value::String(dir.to_string_lossy().into_owned().into())]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let entries = match result.unwrap() {
+            Value::Array(entries) => entries.lock().unwrap().clone(),
+            other => panic!("expected an array, got {:?}", other),
+        };
+        let fields: Vec<IndexMap<String, Value>> = entries.iter().map(|e| super::super::pairs_to_map(e, "scandir").unwrap()).collect();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[0]["name"], Value::String("notes.txt".into()));
+        assert_eq!(fields[0]["is_dir"], Value::Boolean(false));
+        assert_eq!(fields[0]["size"], Value::Number(5));
+        assert!(matches!(fields[0]["modified"], Value::Float(secs) if secs > 0.0));
+        assert_eq!(fields[1]["name"], Value::String("sub".into()));
+        assert_eq!(fields[1]["is_dir"], Value::Boolean(true));
+        assert_eq!(fields[1]["size"], Value::Null);
+    }
+
+    #[test]
+    fn mimetype_needs_more_than_a_lookalike_start() {
+        let dir = env::temp_dir().join(format!("tidal-mimetype-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut bmp = b"BM\x3a\0\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0".to_vec();
+        bmp.resize(58, 0);
+        let files: [(&str, &[u8]); 5] = [
+            ("cars.txt", b"BMW, Audi and Volvo"),
+            ("cars", b"BMW, Audi and Volvo"),
+            ("notes.md", b"ID3 tags sit at the start of mp3 files"),
+            ("song", b"ID3\x03\x00\x00\x00\x00\x00\x00"),
+            ("picture", &bmp),
+        ];
+        let os = OSLib::new();
+        let mimetype = os.get_function("mimetype").unwrap();
+        let types: Vec<String> = files.iter().map(|(name, contents)| {
+            fs::write(dir.join(name), contents).unwrap();
+            mimetype(vec![Value::String(dir.join(name).to_string_lossy().into_owned().into())]).unwrap().to_string()
+        }).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(types, ["text/plain", "text/plain", "text/markdown", "audio/mpeg", "image/bmp"]);
+    }
+
+    #[test]
+    fn changes_and_watch_report_file_events() {
+        let dir = env::temp_dir().join(format!("tidal-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, modified) in [("old.txt", 1000), ("new.txt", 5000)] {
+            let file = File::create(dir.join(name)).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(modified)).unwrap();
+        }
+        let os = OSLib::new();
+        let changed = os.get_function("changes").unwrap()(vec![
+            Value::String(dir.to_string_lossy().into_owned().into()),
+            Value::Number(3000),
+        ]);
+
+        // the watcher sees a file made after it started, and the callback returning false ends it
+        let made = dir.join("made.txt");
+        let maker = thread::spawn({
+            let made = made.clone();
+            move || {
+                thread::sleep(Duration::from_millis(200));
+                fs::write(made, "hi").unwrap();
+            }
+        });
+        let script = format!(
+            "import(os); func seen(event) {{ print(event); return false; }} os.watch(\"{}\", seen, 0.05);",
+            dir.display()
+        );
+        let run = crate::interpreter::run_source_captured(&script, &[], crate::interpreter::Settings::default());
+        maker.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let new = Value::String(dir.join("new.txt").to_string_lossy().into_owned().into());
+        assert_eq!(changed.unwrap(), Value::Array(Arc::new(Mutex::new(vec![new]))));
+        assert!(run.value.is_ok(), "{:?}", run.value);
+        assert_eq!(run.stdout, format!("[[type, create], [path, {}]]\n", made.display()));
+    }
+
+}
@@ -0,0 +1,339 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use crate::summary;
+use std::collections::HashMap;
+use std::fs;
+
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 15;
+const SVG_WIDTH: f64 = 640.0;
+const SVG_HEIGHT: f64 = 400.0;
+const SVG_MARGIN: f64 = 50.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Line,
+    Bar,
+    Scatter,
+}
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("line(xs, ys, path?)", "Line chart, printed, or written to path (.svg for SVG)."),
+    ("bar(labels, ys, path?)", "Bar chart, printed, or written to path (.svg for SVG)."),
+    ("scatter(xs, ys, path?)", "Scatter plot, printed, or written to path (.svg for SVG)."),
+];
+
+pub(crate) struct PlotLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+}
+
+impl Library for PlotLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        let mut new_lib = PlotLib::new();
+        new_lib.constants = self.constants.clone();
+        Box::new(new_lib)
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl PlotLib {
+    pub fn new() -> Self {
+        let mut lib = PlotLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+        };
+        lib.register_functions();
+        lib
+    }
+
+    // line(xs, ys, path?), bar(labels, ys, path?), scatter(xs, ys, path?)
+    // without a path the chart is printed; paths ending in .svg are written as SVG
+    fn register_functions(&mut self) {
+        self.functions.insert("line".to_string(), Box::new(|args| plot(args, "line", Kind::Line)));
+        self.functions.insert("bar".to_string(), Box::new(|args| plot(args, "bar", Kind::Bar)));
+        self.functions.insert("scatter".to_string(), Box::new(|args| plot(args, "scatter", Kind::Scatter)));
+    }
+}
+
+fn plot(args: Vec<Value>, fname: &str, kind: Kind) -> Result<Value, Error> {
+    let path = match args.len() {
+        2 => None,
+        3 => match &args[2] {
+            Value::String(p) => Some(p.clone()),
+            _ => return Err(Error::TypeError(format!("{}() path must be a string", fname))),
+        },
+        _ => return Err(Error::TypeError(format!("{}() takes 2 or 3 arguments", fname))),
+    };
+
+    let ys = numbers(&args[1], fname)?;
+    // bar charts take labels of any type; other plots need numeric xs
+    let (xs, labels) = if kind == Kind::Bar {
+        let labels = items(&args[0], fname)?.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        ((0..labels.len()).map(|i| i as f64).collect::<Vec<_>>(), labels)
+    } else {
+        (numbers(&args[0], fname)?, Vec::new())
+    };
+
+    if xs.is_empty() {
+        return Err(Error::LibraryError(format!("{}() requires at least one point", fname)));
+    }
+    if xs.len() != ys.len() {
+        return Err(Error::LibraryError(format!("{}() got {} x values but {} y values", fname, xs.len(), ys.len())));
+    }
+
+    match path {
+        None => {
+            let chart = if kind == Kind::Bar { ascii_bars(&labels, &ys) } else { ascii_chart(&xs, &ys, kind) };
+            out!("{}", chart);
+            Ok(Value::Null)
+        }
+        Some(path) => {
+            if !path.ends_with(".svg") {
+                return Err(Error::LibraryError(format!("{}() can only export .svg files, got '{}'", fname, path)));
+            }
+            fs::write(&*path, svg(&xs, &ys, &labels, kind))
+                .map_err(|e| Error::LibraryError(format!("{}() failed to write '{}': {}", fname, path, e)))?;
+            summary::written(std::path::Path::new(&*path));
+            Ok(Value::Null)
+        }
+    }
+}
+
+fn items(value: &Value, fname: &str) -> Result<Vec<Value>, Error> {
+    match value {
+        Value::Array(arr) => Ok(lock_array(arr)?.clone()),
+        _ => Err(Error::TypeError(format!("{}() requires array arguments", fname))),
+    }
+}
+
+fn numbers(value: &Value, fname: &str) -> Result<Vec<f64>, Error> {
+    items(value, fname)?.iter().map(|v| match v {
+        Value::Number(n) => Ok(*n as f64),
+        Value::Float(f) => Ok(*f),
+        other => Err(Error::TypeError(format!("{}() requires numeric values, got {}", fname, other))),
+    }).collect()
+}
+
+// (min, max) widened so a flat series still has a non-zero span
+fn bounds(values: &[f64]) -> (f64, f64) {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if min == max {
+        (min - 1.0, max + 1.0)
+    } else {
+        (min, max)
+    }
+}
+
+fn label(x: f64) -> String {
+    if x.fract() == 0.0 {
+        format!("{}", x as i64)
+    } else {
+        format!("{:.2}", x)
+    }
+}
+
+fn ascii_chart(xs: &[f64], ys: &[f64], kind: Kind) -> String {
+    let (x_min, x_max) = bounds(xs);
+    let (y_min, y_max) = bounds(ys);
+    let col = |x: f64| (((x - x_min) / (x_max - x_min)) * (CHART_WIDTH - 1) as f64).round() as usize;
+    let row = |y: f64| (((y_max - y) / (y_max - y_min)) * (CHART_HEIGHT - 1) as f64).round() as usize;
+
+    let mut grid = vec![vec![' '; CHART_WIDTH]; CHART_HEIGHT];
+    let mut points: Vec<(f64, f64)> = xs.iter().cloned().zip(ys.iter().cloned()).collect();
+    if kind == Kind::Line {
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        // fill the columns between neighbouring points
+        for pair in points.windows(2) {
+            let (c0, span) = (col(pair[0].0), col(pair[1].0) - col(pair[0].0));
+            for step in 0..=span {
+                let t = if span == 0 { 0.0 } else { step as f64 / span as f64 };
+                let y = pair[0].1 + t * (pair[1].1 - pair[0].1);
+                grid[row(y)][c0 + step] = '.';
+            }
+        }
+    }
+    for &(x, y) in &points {
+        grid[row(y)][col(x)] = '*';
+    }
+
+    let top = label(y_max);
+    let bottom = label(y_min);
+    let pad = top.len().max(bottom.len());
+    let mut text = String::new();
+    for (i, line) in grid.iter().enumerate() {
+        let axis_label = if i == 0 { &top } else if i == CHART_HEIGHT - 1 { &bottom } else { "" };
+        text.push_str(&format!("{:>pad$} |{}\n", axis_label, line.iter().collect::<String>().trim_end(), pad = pad));
+    }
+    text.push_str(&format!("{:>pad$} +{}\n", "", "-".repeat(CHART_WIDTH), pad = pad));
+    let (left, right) = (label(x_min), label(x_max));
+    let gap = CHART_WIDTH.saturating_sub(left.len() + right.len());
+    text.push_str(&format!("{:>pad$}  {}{}{}\n", "", left, " ".repeat(gap), right, pad = pad));
+    text
+}
+
+fn ascii_bars(labels: &[String], ys: &[f64]) -> String {
+    let max = ys.iter().cloned().fold(0.0, f64::max);
+    let pad = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    let mut text = String::new();
+    for (name, &y) in labels.iter().zip(ys) {
+        let len = if max > 0.0 && y > 0.0 { ((y / max) * CHART_WIDTH as f64).round() as usize } else { 0 };
+        text.push_str(&format!("{:>pad$} | {} {}\n", name, "#".repeat(len), label(y), pad = pad));
+    }
+    text
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn svg(xs: &[f64], ys: &[f64], labels: &[String], kind: Kind) -> String {
+    let (x_min, x_max) = if kind == Kind::Bar { (-0.5, xs.len() as f64 - 0.5) } else { bounds(xs) };
+    let (y_min, y_max) = if kind == Kind::Bar { (0.0_f64.min(bounds(ys).0), bounds(ys).1.max(0.0)) } else { bounds(ys) };
+    let plot_w = SVG_WIDTH - 2.0 * SVG_MARGIN;
+    let plot_h = SVG_HEIGHT - 2.0 * SVG_MARGIN;
+    let sx = |x: f64| SVG_MARGIN + (x - x_min) / (x_max - x_min) * plot_w;
+    let sy = |y: f64| SVG_HEIGHT - SVG_MARGIN - (y - y_min) / (y_max - y_min) * plot_h;
+
+    let mut body = String::new();
+    body.push_str(&format!(
+        "  <line x1=\"{m}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"black\"/>\n  <line x1=\"{m}\" y1=\"{m}\" x2=\"{m}\" y2=\"{b}\" stroke=\"black\"/>\n",
+        m = SVG_MARGIN, b = SVG_HEIGHT - SVG_MARGIN, r = SVG_WIDTH - SVG_MARGIN
+    ));
+    body.push_str(&format!("  <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" font-size=\"12\">{}</text>\n", SVG_MARGIN - 5.0, sy(y_max) + 4.0, label(y_max)));
+    body.push_str(&format!("  <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" font-size=\"12\">{}</text>\n", SVG_MARGIN - 5.0, sy(y_min) + 4.0, label(y_min)));
+
+    match kind {
+        Kind::Line => {
+            let mut points: Vec<(f64, f64)> = xs.iter().cloned().zip(ys.iter().cloned()).collect();
+            points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            let coords: Vec<String> = points.iter().map(|&(x, y)| format!("{:.1},{:.1}", sx(x), sy(y))).collect();
+            body.push_str(&format!("  <polyline points=\"{}\" fill=\"none\" stroke=\"steelblue\" stroke-width=\"2\"/>\n", coords.join(" ")));
+        }
+        Kind::Scatter => {
+            for (&x, &y) in xs.iter().zip(ys) {
+                body.push_str(&format!("  <circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"steelblue\"/>\n", sx(x), sy(y)));
+            }
+        }
+        Kind::Bar => {
+            let slot = plot_w / xs.len() as f64;
+            for ((&x, &y), name) in xs.iter().zip(ys).zip(labels) {
+                let (top, bottom) = (sy(y.max(0.0)), sy(y.min(0.0)));
+                body.push_str(&format!(
+                    "  <rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"steelblue\"/>\n",
+                    sx(x) - slot * 0.4, top, slot * 0.8, bottom - top
+                ));
+                body.push_str(&format!(
+                    "  <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" font-size=\"12\">{}</text>\n",
+                    sx(x), SVG_HEIGHT - SVG_MARGIN + 16.0, escape_xml(name)
+                ));
+            }
+        }
+    }
+
+    if kind != Kind::Bar {
+        body.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"12\">{}</text>\n", SVG_MARGIN, SVG_HEIGHT - SVG_MARGIN + 16.0, label(x_min)));
+        body.push_str(&format!("  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"12\">{}</text>\n", SVG_WIDTH - SVG_MARGIN, SVG_HEIGHT - SVG_MARGIN + 16.0, label(x_max)));
+    }
+
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n  <rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n{body}</svg>\n",
+        w = SVG_WIDTH, h = SVG_HEIGHT, body = body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        PlotLib::new().get_function(name).unwrap()(args)
+    }
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    fn numbers(values: &[i32]) -> Value {
+        array(values.iter().map(|&n| Value::Number(n)).collect())
+    }
+
+    #[test]
+    fn bars_scale_to_the_largest_value() {
+        let labels = vec!["a".to_string(), "bcd".to_string()];
+        let chart = ascii_bars(&labels, &[1.0, 2.0]);
+        let lines: Vec<&str> = chart.lines().collect();
+        assert_eq!(lines[0], format!("  a | {} 1", "#".repeat(CHART_WIDTH / 2)));
+        assert_eq!(lines[1], format!("bcd | {} 2", "#".repeat(CHART_WIDTH)));
+    }
+
+    #[test]
+    fn chart_marks_every_point_and_labels_the_axes() {
+        let chart = ascii_chart(&[0.0, 1.0, 2.0], &[5.0, 10.0, 5.0], Kind::Scatter);
+        assert_eq!(chart.matches('*').count(), 3);
+        let lines: Vec<&str> = chart.lines().collect();
+        assert!(lines[0].starts_with("10 |"));
+        assert!(lines[CHART_HEIGHT - 1].starts_with(" 5 |"));
+        assert!(lines[CHART_HEIGHT + 1].trim_start().starts_with('0') && lines[CHART_HEIGHT + 1].ends_with('2'));
+
+        // a line also joins neighbouring points
+        let line = ascii_chart(&[0.0, 2.0], &[0.0, 2.0], Kind::Line);
+        assert_eq!(line.matches('*').count(), 2);
+        assert!(line.contains('.'));
+    }
+
+    #[test]
+    fn svg_export_writes_escaped_labels() {
+        let path = std::env::temp_dir().join(format!("tidal-plot-{}.svg", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+        let labels = array(vec![Value::String("<a&b>".into()), Value::String("c".into())]);
+        call("bar", vec![labels, numbers(&[3, -1]), Value::String(path_str.into())]).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(contents.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert_eq!(contents.matches("<rect x=").count(), 2);
+        assert!(contents.contains("&lt;a&amp;b&gt;"));
+    }
+
+    #[test]
+    fn mismatched_or_unsupported_input_is_rejected() {
+        let err = call("line", vec![numbers(&[1, 2]), numbers(&[1])]).unwrap_err().to_string();
+        assert!(err.contains("line() got 2 x values but 1 y values"));
+        let err = call("scatter", vec![numbers(&[]), numbers(&[])]).unwrap_err().to_string();
+        assert!(err.contains("scatter() requires at least one point"));
+        let err = call("line", vec![numbers(&[1]), numbers(&[1]), Value::String("out.png".into())]).unwrap_err().to_string();
+        assert!(err.contains("line() can only export .svg files, got 'out.png'"));
+        let err = call("line", vec![numbers(&[1]), array(vec![Value::Boolean(true)])]).unwrap_err().to_string();
+        assert!(err.contains("line() requires numeric values"));
+    }
+}
@@ -0,0 +1,250 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::{Value, lock_array};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// splitmix64: tiny, and the same seed gives the same numbers on every
+// platform and build, which is the point of --seed
+struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    fn new(seed: u64) -> Self {
+        Generator { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    fn next_float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // uniform in [low, high]
+    fn next_range(&mut self, low: i64, high: i64) -> i64 {
+        let span = (high - low) as u64 + 1;
+        low + (self.next_u64() % span) as i64
+    }
+}
+
+fn clock_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+}
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("random()", "A float in [0, 1)."),
+    ("randint(low, high)", "An integer from low to high, both included."),
+    ("choice(array)", "One element of the array."),
+    ("shuffle(array)", "A new array with the elements in random order."),
+    ("seed(n)", "Restarts the sequence from seed n."),
+];
+
+pub(crate) struct RandomLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+    generator: Arc<Mutex<Generator>>,
+}
+
+impl Library for RandomLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        // clones draw from the same sequence, so calls inside functions don't
+        // repeat numbers the caller already got
+        Box::new(RandomLib::with_generator(Arc::clone(&self.generator)))
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl RandomLib {
+    pub fn new() -> Self {
+        // --seed, in the settings of the run importing the library
+        let seed = crate::interpreter::settings(|settings| settings.seed).unwrap_or_else(clock_seed);
+        Self::with_generator(Arc::new(Mutex::new(Generator::new(seed))))
+    }
+
+    fn with_generator(generator: Arc<Mutex<Generator>>) -> Self {
+        let mut lib = RandomLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+            generator,
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // random() -> float in [0, 1)
+        let generator = Arc::clone(&self.generator);
+        self.functions.insert("random".to_string(), Box::new(move |args| {
+            if !args.is_empty() {
+                return Err(Error::TypeError("random() takes no arguments".to_string()));
+            }
+            Ok(Value::Float(generator.lock().unwrap().next_float()))
+        }));
+
+        // randint(low, high) -> int, both ends included
+        let generator = Arc::clone(&self.generator);
+        self.functions.insert("randint".to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::Number(low), Value::Number(high)] if low <= high => {
+                    let n = generator.lock().unwrap().next_range(*low as i64, *high as i64);
+                    Ok(Value::Number(n as i32))
+                }
+                [Value::Number(_), Value::Number(_)] => Err(Error::TypeError("randint() low must not be greater than high".to_string())),
+                _ => Err(Error::TypeError("randint() requires 2 integer arguments".to_string()))
+            }
+        }));
+
+        // choice(array) -> one element
+        let generator = Arc::clone(&self.generator);
+        self.functions.insert("choice".to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::Array(arr)] => {
+                    let arr = lock_array(arr)?;
+                    if arr.is_empty() {
+                        return Err(Error::IndexOutOfBounds("choice() requires a non-empty array".to_string()));
+                    }
+                    let i = generator.lock().unwrap().next_range(0, arr.len() as i64 - 1);
+                    Ok(arr[i as usize].clone())
+                }
+                _ => Err(Error::TypeError("choice() requires 1 array argument".to_string()))
+            }
+        }));
+
+        // shuffle(array) -> a new array with the elements in random order
+        let generator = Arc::clone(&self.generator);
+        self.functions.insert("shuffle".to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::Array(arr)] => {
+                    let mut values = lock_array(arr)?.clone();
+                    let mut generator = generator.lock().unwrap();
+                    for i in (1..values.len()).rev() {
+                        let j = generator.next_range(0, i as i64) as usize;
+                        values.swap(i, j);
+                    }
+                    Ok(Value::Array(Arc::new(Mutex::new(values))))
+                }
+                _ => Err(Error::TypeError("shuffle() requires 1 array argument".to_string()))
+            }
+        }));
+
+        // seed(n) restarts the sequence, as --seed does for the whole run
+        let generator = Arc::clone(&self.generator);
+        self.functions.insert("seed".to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::Number(seed)] => {
+                    *generator.lock().unwrap() = Generator::new(*seed as i64 as u64);
+                    Ok(Value::Null)
+                }
+                _ => Err(Error::TypeError("seed() requires 1 integer argument".to_string()))
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded(seed: u64) -> RandomLib {
+        RandomLib::with_generator(Arc::new(Mutex::new(Generator::new(seed))))
+    }
+
+    fn call(lib: &RandomLib, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        lib.get_function(name).unwrap()(args)
+    }
+
+    fn draws(lib: &RandomLib) -> Vec<Value> {
+        (0..5).map(|_| call(lib, "randint", vec![Value::Number(1), Value::Number(100)]).unwrap()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_sequence() {
+        assert_eq!(draws(&seeded(42)), draws(&seeded(42)));
+        assert_ne!(draws(&seeded(42)), draws(&seeded(43)));
+
+        let lib = seeded(7);
+        let first = draws(&lib);
+        call(&lib, "seed", vec![Value::Number(7)]).unwrap();
+        assert_eq!(draws(&lib), first);
+    }
+
+    #[test]
+    fn clones_share_one_sequence() {
+        let lib = seeded(1);
+        let clone = lib.box_clone();
+        let a = call(&lib, "random", vec![]).unwrap();
+        let b = clone.get_function("random").unwrap()(vec![]).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn values_stay_in_range() {
+        let lib = seeded(3);
+        for _ in 0..200 {
+            match call(&lib, "random", vec![]).unwrap() {
+                Value::Float(f) => assert!((0.0..1.0).contains(&f)),
+                other => panic!("expected a float, got {:?}", other),
+            }
+            match call(&lib, "randint", vec![Value::Number(-2), Value::Number(2)]).unwrap() {
+                Value::Number(n) => assert!((-2..=2).contains(&n)),
+                other => panic!("expected an int, got {:?}", other),
+            }
+        }
+        assert_eq!(call(&lib, "randint", vec![Value::Number(5), Value::Number(5)]).unwrap(), Value::Number(5));
+    }
+
+    #[test]
+    fn shuffle_keeps_the_elements() {
+        let values: Vec<Value> = (0..10).map(Value::Number).collect();
+        let shuffled = call(&seeded(9), "shuffle", vec![Value::Array(Arc::new(Mutex::new(values.clone())))]).unwrap();
+        let mut sorted = match shuffled {
+            Value::Array(arr) => arr.lock().unwrap().clone(),
+            other => panic!("expected an array, got {:?}", other),
+        };
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(sorted, values);
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        let lib = seeded(0);
+        assert!(call(&lib, "randint", vec![Value::Number(2), Value::Number(1)]).unwrap_err().to_string().contains("low must not be greater"));
+        assert!(call(&lib, "choice", vec![Value::Array(Arc::new(Mutex::new(vec![])))]).unwrap_err().to_string().contains("non-empty array"));
+        assert!(call(&lib, "random", vec![Value::Number(1)]).is_err());
+    }
+}
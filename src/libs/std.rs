@@ -1,19 +1,79 @@
 use std::sync::{Arc, Mutex};
 #[allow(dead_code)]
-use super::Library;
+use super::{find_help, Library, map_to_pairs, Function};
+use super::mem::size_of_value;
 use crate::error::Error;
-use crate::parser::Value;
-use std::collections::HashMap;
+use crate::parser::{Value, lock_array};
+use crate::interpreter::values_equal;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use crate::parser::Parser;
 
-pub struct StdLib {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("print(values...)", "Prints the values separated by spaces, followed by a newline."),
+    ("write(values...)", "Prints the values separated by spaces, without a newline."),
+    ("flush()", "Writes out buffered output now, e.g. before a long computation when piped."),
+    ("len(value)", "Number of characters in a string or elements in an array."),
+    ("del(name)", "Removes a variable from scope."),
+    ("type(value)", "The type of value: int, float, str, bool, array, null, ..."),
+    ("input(prompt)", "Prints prompt and returns the line the user types."),
+    ("exit(code?)", "Stops the script with the given exit code, 0 by default."),
+    ("assert(condition, message?)", "Stops the script with an AssertionFailed error when condition is false."),
+    ("assert_eq(actual, expected, message?)", "Stops the script with an AssertionFailed error listing where actual differs from expected."),
+    ("dir(library?)", "Names in scope, or the functions and constants of an imported library."),
+    ("inspect(value)", "[key, value] pairs describing the value's type, length, size and mutability."),
+    ("help(name)", "Signature and description of a function, like help(\"math.sqrt\")."),
+    ("copy(array)", "A new array with the same elements."),
+    ("extend(array, other)", "Appends the elements of other to array, in place."),
+    ("insert(array, value, index?)", "Inserts value at index, or appends it, in place."),
+    ("sort(array)", "Sorts the array in place."),
+    ("sort_natural(array, options?)", "Sorts strings in place with numbers in order, so \"file2\" comes before \"file10\"; options as in compare()."),
+    ("compare(a, b, options?)", "-1, 0 or 1 as a sorts before, with or after b; options are [[\"case_insensitive\", bool], [\"natural\", bool]]."),
+    ("reverse(array)", "Reverses the array in place."),
+    ("clear(array)", "Removes every element from the array, in place."),
+    ("count(array, value)", "How many elements of the array equal value."),
+    ("upper(str)", "The string in upper case."),
+    ("lower(str)", "The string in lower case."),
+    ("strip(str)", "The string without leading and trailing whitespace."),
+    ("template(tmpl, pairs, escape?)", "Replaces {{key}} with values from [[key, value], ...]; escape is \"none\" or \"html\"."),
+    ("get_path(value, path, default?)", "Digs into nested [key, value] pairs and arrays, like get_path(data, \"users[0].name\"); default (null) when any step is missing."),
+    ("set_path(value, path, new)", "Sets the value at a path like \"a.b[2]\" in place, adding missing keys; returns value."),
+    ("merge(pairs, other)", "New [key, value] pairs with other's added; for a key both have, other's value wins."),
+    ("has_key(pairs, key)", "Whether [[key, value], ...] has a pair for key."),
+    ("pop_key(pairs, key, default?)", "Removes key's pair in place and returns its value, or default (null) if there is none."),
+    ("items(pairs)", "A new [[key, value], ...] list of pairs' entries; for a repeated key, the last value wins."),
+    ("unique(array)", "A new array with each value once, in the order first seen."),
+    ("set(array)", "The array as a set: a new array with each value once, in the order first seen."),
+    ("union(a, b)", "A new array with each value in a or b once."),
+    ("intersection(a, b)", "A new array with each value in both a and b once, in a's order."),
+    ("difference(a, b)", "A new array with each value in a but not in b once."),
+    ("url_parse(url)", "[key, value] pairs for the scheme, host, port, path, query and fragment of a URL."),
+    ("url_encode(str)", "Percent-encodes a string for use in a URL."),
+    ("url_decode(str)", "Decodes a percent-encoded string; '+' decodes to a space, as in url_parse() queries."),
+    ("query_string(pairs)", "Builds key=value&... from [[key, value], ...]; array values repeat the key."),
+    ("reload(name)", "Reads an imported .tdx library again, running its __teardown__ and __init__ hooks."),
+    ("eval(code)", "Runs a string of Tidal code and returns its result."),
+];
+
+type Functions = HashMap<String, Function>;
+
+thread_local! {
+    // Every function call makes an Environment, and every Environment has a
+    // StdLib; the closures are the same each time, so they are built once
+    static FUNCTIONS: Rc<Functions> = Rc::new(functions());
+}
+
+pub(crate) struct StdLib {
+    functions: Rc<Functions>,
     constants: HashMap<String, Value>,
     var_mutability: HashMap<String, bool>,
 }
 
 impl Library for StdLib {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -21,6 +81,14 @@ impl Library for StdLib {
         self.constants.get(name)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
     fn is_mutable(&self, name: &str) -> Option<bool> {
         self.var_mutability.get(name).copied()
     }
@@ -31,255 +99,929 @@ impl Library for StdLib {
         new_lib.var_mutability = self.var_mutability.clone();
         Box::new(new_lib)
     }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
 }
 
 impl StdLib {
     pub fn new() -> Self {
-        let mut lib = StdLib {
-            functions: HashMap::new(),
+        StdLib {
+            functions: FUNCTIONS.with(Rc::clone),
             constants: HashMap::new(),
             var_mutability: HashMap::new(),
-        };
-        
-        lib.register_functions();
-        lib
+        }
     }
 
-    pub fn get_function_map(&self) -> &HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    pub fn get_function_map(&self) -> &Functions {
         &self.functions
     }
+}
 
-    fn register_functions(&mut self) {
-        // print() function
-        self.functions.insert("print".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("print() takes exactly 1 argument".to_string()));
-            }
-            println!("{}", args[0]);
-            Ok(Value::Null)
-        }));
+fn functions() -> Functions {
+    let mut functions: Functions = HashMap::new();
+    // print(values...) - the values separated by spaces, then a newline
+    functions.insert("print".to_string(), Box::new(|args| {
+        outln!("{}", join_values(&args));
+        Ok(Value::Null)
+    }));
 
-        // len() function
-        self.functions.insert("len".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("len() takes exactly 1 argument".to_string()));
-            }
-            match &args[0] {
-                Value::String(s) => Ok(Value::Number(s.chars().count() as i32)),
-                Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
-                    Ok(Value::Number(guard.len() as i32))
-                },
-                _ => Err(Error::TypeError(format!(
-                    "len() requires string or array argument, got {}", 
-                    type_str_of_value(&args[0])
-                )))
-            }
-        }));
+    // write(values...) - like print() without the newline, for output
+    // built up a piece at a time
+    functions.insert("write".to_string(), Box::new(|args| {
+        out!("{}", join_values(&args));
+        Ok(Value::Null)
+    }));
 
-        // del() function
-        self.functions.insert("del".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("del() takes exactly 1 argument".to_string()));
-            }
-            // Actual deletion happens in interpreter
+    // flush() - output to a pipe or file is buffered until the buffer
+    // fills, input() is called or the script ends
+    functions.insert("flush".to_string(), Box::new(|args| {
+        if !args.is_empty() {
+            return Err(Error::TypeError("flush() takes no arguments".to_string()));
+        }
+        crate::output::flush();
+        Ok(Value::Null)
+    }));
+
+    // len() function
+    functions.insert("len".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("len() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::String(s) => Ok(Value::Number(s.chars().count() as i32)),
+            Value::Array(arr) => {
+                let guard = lock_array(arr)?;
+                Ok(Value::Number(guard.len() as i32))
+            },
+            _ => Err(Error::TypeError(format!(
+                "len() requires string or array argument, got {}", 
+                type_str_of_value(&args[0])
+            )))
+        }
+    }));
+
+    // del() function
+    functions.insert("del".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("del() takes exactly 1 argument".to_string()));
+        }
+        // Actual deletion happens in interpreter
+        Ok(Value::Null)
+    }));
+
+    // type() function
+    functions.insert("type".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("type() takes exactly 1 argument".to_string()));
+        }
+        Ok(Value::Type(type_str_of_value(&args[0]).into()))
+    }));
+
+    // input() function
+    functions.insert("input".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("input() takes exactly 1 argument".to_string()));
+        }
+        Ok(Value::String(crate::input::prompt(&args[0].to_string()).into()))
+    }));
+
+    // exit() function - exit(code?) unwinds the script, default code 0
+    functions.insert("exit".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [] => Err(Error::Exit(0)),
+            [Value::Number(code)] => Err(Error::Exit(*code)),
+            [_] => Err(Error::TypeError("exit() requires integer argument".to_string())),
+            _ => Err(Error::TypeError("exit() takes at most 1 argument".to_string())),
+        }
+    }));
+
+    // assert() function - assert(condition, message?)
+    functions.insert("assert".to_string(), Box::new(|args| {
+        let (condition, message) = match args.as_slice() {
+            [Value::Boolean(b)] => (*b, "assertion failed".to_string()),
+            [Value::Boolean(b), msg] => (*b, msg.to_string()),
+            [_] | [_, _] => return Err(Error::TypeError("assert() requires a boolean condition".to_string())),
+            _ => return Err(Error::TypeError("assert() takes 1 or 2 arguments".to_string())),
+        };
+        if condition {
             Ok(Value::Null)
-        }));
+        } else {
+            Err(Error::AssertionFailed(message))
+        }
+    }));
 
-        // type() function
-        self.functions.insert("type".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("type() takes exactly 1 argument".to_string()));
-            }
-            Ok(Value::Type(type_str_of_value(&args[0]).to_string()))
-        }));
+    // assert_eq(actual, expected, message?) - the error lists each difference by path
+    functions.insert("assert_eq".to_string(), Box::new(|args| {
+        let (actual, expected, message) = match args.as_slice() {
+            [actual, expected] => (actual, expected, None),
+            [actual, expected, message] => (actual, expected, Some(message.to_string())),
+            _ => return Err(Error::TypeError("assert_eq() takes 2 or 3 arguments".to_string())),
+        };
+        let differences = crate::diff::diff(actual, expected);
+        if differences.is_empty() {
+            return Ok(Value::Null);
+        }
+        let heading = message.unwrap_or_else(|| "values are not equal".to_string());
+        Err(Error::AssertionFailed(format!("{}\n  {}", heading, differences.join("\n  "))))
+    }));
 
-        // input() function
-        self.functions.insert("input".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("input() takes exactly 1 argument".to_string()));
-            }
-            use std::io::{self, Write};
-            
-            print!("{}", args[0]);
-            io::stdout().flush().unwrap();
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            Ok(Value::String(input.trim().to_string()))
-        }));
+    // dir() function - dir(library) and dir() are answered by the
+    // interpreter, which can see what is imported; values have no fields
+    functions.insert("dir".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [_] => Ok(Value::Array(Arc::new(Mutex::new(vec![])))),
+            _ => Err(Error::TypeError("dir() takes at most 1 argument".to_string())),
+        }
+    }));
 
-        // copy() function - deep clone arrays
-        self.functions.insert("copy".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("copy() takes exactly 1 argument".to_string()));
-            }
-            match &args[0] {
-                Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
-                    Ok(Value::Array(Arc::new(Mutex::new(guard.clone()))))
-                },
-                _ => Err(Error::TypeError("copy() requires array argument".to_string()))
-            }
-        }));
+    // help() function - the interpreter answers help("lib.fn") and help("lib")
+    // for every library it can see; called indirectly, only std is known
+    functions.insert("help".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [Value::String(topic)] => find_help(HELP, topic.strip_prefix("std.").unwrap_or(topic))
+                .map(|(signature, doc)| Value::String(format!("{}\n    {}", signature, doc).into()))
+                .ok_or_else(|| Error::InterpreterError(format!("No help found for '{}'", topic))),
+            _ => Err(Error::TypeError("help() takes 1 string argument, like help(\"math.sqrt\")".to_string())),
+        }
+    }));
 
-        // extend() function
-        self.functions.insert("extend".to_string(), Box::new(|args| {
-            if args.len() != 2 {
-                return Err(Error::TypeError("extend() takes exactly 2 arguments".to_string()));
-            }
-            match (&args[0], &args[1]) {
-                (Value::Array(arr1), Value::Array(arr2)) => {
-                    let mut new_vec = arr1.lock().unwrap().clone();
-                    new_vec.extend(arr2.lock().unwrap().clone());
-                    Ok(Value::Array(Arc::new(Mutex::new(new_vec))))
-                },
-                _ => Err(Error::TypeError("extend() requires two array arguments".to_string()))
-            }
-        }));
+    // reload() function - re-reads an imported .tdx library; the interpreter
+    // handles it, since only it can replace the library in scope
+    functions.insert("reload".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [Value::String(name)] => Err(Error::LibraryError(format!("reload(\"{}\") must be called directly, not through a function value", name))),
+            _ => Err(Error::TypeError("reload() takes 1 library name, like reload(\"mylib\")".to_string())),
+        }
+    }));
 
-        // insert() function - modify array in place
-        self.functions.insert("insert".to_string(), Box::new(|args| {
-            if args.len() < 2 || args.len() > 3 {
-                return Err(Error::TypeError("insert() takes 2 or 3 arguments".to_string()));
-            }
-            
-            match &args[0] {
-                Value::Array(arr) => {
-                    let mut guard = arr.lock().unwrap();
-                    let value = args[1].clone();
-                    
-                    if args.len() == 3 {
-                        if let Value::Number(index) = args[2] {
-                            if index < 0 || index > guard.len() as i32 {
-                                return Err(Error::IndexOutOfBounds("Insert index out of bounds".to_string()));
-                            }
-                            guard.insert(index as usize, value);
-                        } else {
-                            return Err(Error::TypeError("Index must be a number".to_string()));
+    // inspect() function - [["type", ...], ["length", ...], ["bytes", ...]],
+    // plus ["mutable", ...] when called on a variable
+    functions.insert("inspect".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("inspect() takes exactly 1 argument".to_string()));
+        }
+        let value = &args[0];
+        let length = match value {
+            Value::String(s) => Value::Number(s.chars().count() as i32),
+            Value::Array(arr) => Value::Number(lock_array(arr)?.len() as i32),
+            _ => Value::Null,
+        };
+        let mut info = IndexMap::new();
+        info.insert("type".to_string(), Value::String(type_str_of_value(value).into()));
+        info.insert("length".to_string(), length);
+        info.insert("bytes".to_string(), Value::Number(size_of_value(value)? as i32));
+        Ok(map_to_pairs(info))
+    }));
+
+    // copy() function - deep clone arrays
+    functions.insert("copy".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("copy() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::Array(arr) => {
+                let guard = lock_array(arr)?;
+                Ok(Value::Array(Arc::new(Mutex::new(guard.clone()))))
+            },
+            _ => Err(Error::TypeError("copy() requires array argument".to_string()))
+        }
+    }));
+
+    // extend() function - append the second array to the first in place
+    functions.insert("extend".to_string(), Box::new(|args| {
+        if args.len() != 2 {
+            return Err(Error::TypeError("extend() takes exactly 2 arguments".to_string()));
+        }
+        match (&args[0], &args[1]) {
+            (Value::Array(arr1), Value::Array(arr2)) => {
+                if Arc::ptr_eq(arr1, arr2) {
+                    // extend(a, a): one lock, doubling the contents
+                    let mut guard = lock_array(arr1)?;
+                    let len = guard.len();
+                    guard.extend_from_within(..len);
+                } else {
+                    lock_array(arr1)?.extend(lock_array(arr2)?.iter().cloned());
+                }
+                Ok(Value::Array(Arc::clone(arr1)))
+            },
+            _ => Err(Error::TypeError("extend() requires two array arguments".to_string()))
+        }
+    }));
+
+    // insert() function - modify array in place
+    functions.insert("insert".to_string(), Box::new(|args| {
+        if args.len() < 2 || args.len() > 3 {
+            return Err(Error::TypeError("insert() takes 2 or 3 arguments".to_string()));
+        }
+        
+        match &args[0] {
+            Value::Array(arr) => {
+                let mut guard = lock_array(arr)?;
+                let value = args[1].clone();
+                
+                if args.len() == 3 {
+                    if let Value::Number(index) = args[2] {
+                        if index < 0 || index > guard.len() as i32 {
+                            return Err(Error::IndexOutOfBounds("Insert index out of bounds".to_string()));
                         }
+                        guard.insert(index as usize, value);
                     } else {
-                        guard.push(value);
+                        return Err(Error::TypeError("Index must be a number".to_string()));
                     }
-                    Ok(Value::Array(Arc::clone(arr)))
-                },
-                _ => Err(Error::TypeError("First argument must be an array".to_string()))
-            }
-        }));
+                } else {
+                    guard.push(value);
+                }
+                Ok(Value::Array(Arc::clone(arr)))
+            },
+            _ => Err(Error::TypeError("First argument must be an array".to_string()))
+        }
+    }));
+
+    // sort() function - sort array in place
+    functions.insert("sort".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("sort() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::Array(arr) => {
+                lock_array(arr)?.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                Ok(Value::Array(Arc::clone(arr)))
+            },
+            _ => Err(Error::TypeError("sort() requires array argument".to_string()))
+        }
+    }));
+
+    // sort_natural(array, options?) - in place, digit runs compared by value
+    functions.insert("sort_natural".to_string(), Box::new(|args| {
+        let (arr, options) = match args.as_slice() {
+            [Value::Array(arr)] => (arr, None),
+            [Value::Array(arr), options] => (arr, Some(options)),
+            _ => return Err(Error::TypeError("sort_natural() requires an array and optional options".to_string()))
+        };
+        let collation = Collation { natural: true, ..collation(options, "sort_natural")? };
+        let mut values = lock_array(arr)?;
+        if values.iter().any(|value| !matches!(value, Value::String(_))) {
+            return Err(Error::TypeError("sort_natural() requires an array of strings".to_string()));
+        }
+        values.sort_by(|a, b| match (a, b) {
+            (Value::String(a), Value::String(b)) => collate(a, b, collation),
+            _ => Ordering::Equal,
+        });
+        drop(values);
+        Ok(Value::Array(Arc::clone(arr)))
+    }));
 
-        // sort() function
-        self.functions.insert("sort".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("sort() takes exactly 1 argument".to_string()));
+    // compare(a, b, options?) -> -1, 0 or 1
+    functions.insert("compare".to_string(), Box::new(|args| {
+        let (a, b, options) = match args.as_slice() {
+            [a, b] => (a, b, None),
+            [a, b, options] => (a, b, Some(options)),
+            _ => return Err(Error::TypeError("compare() takes 2 or 3 arguments".to_string()))
+        };
+        let order = match (a, b) {
+            (Value::String(a), Value::String(b)) => collate(a, b, collation(options, "compare")?),
+            (Value::Number(_) | Value::Float(_), Value::Number(_) | Value::Float(_)) => {
+                let (a, b) = (as_f64(a), as_f64(b));
+                a.partial_cmp(&b).ok_or_else(|| Error::TypeError("compare() cannot order NaN".to_string()))?
             }
-            match &args[0] {
-                Value::Array(arr) => {
-                    let mut vec = arr.lock().unwrap().clone();
-                    vec.sort_by(|a, b| a.partial_cmp(b).unwrap());
-                    Ok(Value::Array(Arc::new(Mutex::new(vec))))
-                },
-                _ => Err(Error::TypeError("sort() requires array argument".to_string()))
+            _ => return Err(Error::TypeError("compare() requires two strings or two numbers".to_string()))
+        };
+        Ok(Value::Number(order as i32))
+    }));
+
+    // reverse() function - reverse array in place
+    functions.insert("reverse".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("reverse() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::Array(arr) => {
+                lock_array(arr)?.reverse();
+                Ok(Value::Array(Arc::clone(arr)))
+            },
+            _ => Err(Error::TypeError("reverse() requires array argument".to_string()))
+        }
+    }));
+
+    // clear() function - empty array in place
+    functions.insert("clear".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("clear() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::Array(arr) => {
+                lock_array(arr)?.clear();
+                Ok(Value::Array(Arc::clone(arr)))
+            },
+            _ => Err(Error::TypeError("clear() requires array argument".to_string()))
+        }
+    }));
+
+    // count() function
+    functions.insert("count".to_string(), Box::new(|args| {
+        if args.len() != 2 {
+            return Err(Error::TypeError("count() takes exactly 2 arguments".to_string()));
+        }
+        match (&args[0], &args[1]) {
+            (Value::Array(arr), value) => {
+                let guard = lock_array(arr)?;
+                Ok(Value::Number(guard.iter().filter(|x| *x == value).count() as i32))
+            },
+            (Value::String(s), Value::String(substr)) => {
+                Ok(Value::Number(s.matches(&**substr).count() as i32))
+            },
+            _ => Err(Error::TypeError("count() requires (array, value) or (string, string) arguments".to_string()))
+        }
+    }));
+
+    // upper() function
+    functions.insert("upper".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("upper() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::String(s) => Ok(Value::String(s.to_uppercase().into())),
+            _ => Err(Error::TypeError("upper() requires string argument".to_string()))
+        }
+    }));
+
+    // lower() function
+    functions.insert("lower".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("lower() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::String(s) => Ok(Value::String(s.to_lowercase().into())),
+            _ => Err(Error::TypeError("lower() requires string argument".to_string()))
+        }
+    }));
+
+    // strip() function
+    functions.insert("strip".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("strip() takes exactly 1 argument".to_string()));
+        }
+        match &args[0] {
+            Value::String(s) => Ok(Value::String(s.trim().into())),
+            _ => Err(Error::TypeError("strip() requires string argument".to_string()))
+        }
+    }));
+
+    // template(tmpl, [[key, value], ...], escape?) - {{key}} substitution
+    functions.insert("template".to_string(), Box::new(|args| {
+        if args.len() < 2 || args.len() > 3 {
+            return Err(Error::TypeError("template() takes 2 or 3 arguments".to_string()));
+        }
+        let tmpl = match &args[0] {
+            Value::String(s) => s,
+            _ => return Err(Error::TypeError("template() requires a string template".to_string()))
+        };
+        let escape = match args.get(2) {
+            None => "none",
+            Some(Value::String(mode)) if matches!(&**mode, "none" | "html") => &**mode,
+            Some(_) => return Err(Error::TypeError("template() escape mode must be \"none\" or \"html\"".to_string()))
+        };
+        let values = super::pairs_to_map(&args[1], "template")?;
+
+        let mut result = String::with_capacity(tmpl.len());
+        let mut rest = &**tmpl;
+        while let Some(start) = rest.find("{{") {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after.find("}}").ok_or_else(|| Error::LibraryError(
+                "template() found '{{' without a closing '}}'".to_string()
+            ))?;
+            let key = after[..end].trim();
+            let value = values.get(key).ok_or_else(|| Error::LibraryError(
+                format!("template() has no value for '{}'", key)
+            ))?;
+            let text = value.to_string();
+            if escape == "html" {
+                result.push_str(&escape_html(&text));
+            } else {
+                result.push_str(&text);
             }
-        }));
+            rest = &after[end + 2..];
+        }
+        result.push_str(rest);
+        Ok(Value::String(result.into()))
+    }));
 
-        // reverse() function
-        self.functions.insert("reverse".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("reverse() takes exactly 1 argument".to_string()));
+    // get_path(value, "a.b[2].c", default?) - default when any step is missing
+    functions.insert("get_path".to_string(), Box::new(|args| {
+        let (root, path, default) = match args.as_slice() {
+            [root, Value::String(path)] => (root, path, Value::Null),
+            [root, Value::String(path), default] => (root, path, default.clone()),
+            _ => return Err(Error::TypeError("get_path() requires a value, a path string and an optional default".to_string()))
+        };
+        let mut current = root.clone();
+        for step in parse_path(path, "get_path")? {
+            match step_into(&current, &step)? {
+                Some(next) => current = next,
+                None => return Ok(default),
             }
-            match &args[0] {
-                Value::Array(arr) => {
-                    let mut vec = arr.lock().unwrap().clone();
-                    vec.reverse();
-                    Ok(Value::Array(Arc::new(Mutex::new(vec))))
-                },
-                _ => Err(Error::TypeError("reverse() requires array argument".to_string()))
+        }
+        Ok(current)
+    }));
+
+    // set_path(value, "a.b[2].c", new) - in place; missing keys are added
+    functions.insert("set_path".to_string(), Box::new(|args| {
+        let (root, path, new) = match args.as_slice() {
+            [root, Value::String(path), new] => (root, path, new),
+            _ => return Err(Error::TypeError("set_path() requires a value, a path string and the new value".to_string()))
+        };
+        let steps = parse_path(path, "set_path")?;
+        let Some((last, steps)) = steps.split_last() else {
+            return Err(Error::LibraryError("set_path() needs a non-empty path".to_string()));
+        };
+        let mut current = root.clone();
+        for step in steps {
+            current = match step_into(&current, step)? {
+                Some(next) => next,
+                None => set_step(&current, step, Value::Array(Arc::new(Mutex::new(Vec::new()))), path)?,
+            };
+        }
+        set_step(&current, last, new.clone(), path)?;
+        Ok(root.clone())
+    }));
+
+    // url_parse(url) -> [[scheme, ...], [host, ...], [port, int|null], [path, ...],
+    //                    [query, [[key, value], ...]], [fragment, str|null]]
+    functions.insert("url_parse".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [Value::String(url)] => parse_url(url),
+            _ => Err(Error::TypeError("url_parse() requires 1 string argument".to_string()))
+        }
+    }));
+
+    functions.insert("url_encode".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [value] => Ok(Value::String(percent_encode(&value.to_string()).into())),
+            _ => Err(Error::TypeError("url_encode() takes exactly 1 argument".to_string()))
+        }
+    }));
+
+    // url_decode(str) - '+' is a space, as in a url_parse() query; url_encode
+    // writes '+' as %2B, so url_decode(url_encode(s)) is s
+    functions.insert("url_decode".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [Value::String(text)] => Ok(Value::String(percent_decode(text, true, "url_decode")?.into())),
+            _ => Err(Error::TypeError("url_decode() requires 1 string argument".to_string()))
+        }
+    }));
+
+    // query_string([[key, value], ...]) - array values repeat the key
+    functions.insert("query_string".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("query_string() takes exactly 1 argument".to_string()));
+        }
+        let mut parts = Vec::new();
+        for (key, value) in super::pairs_to_map(&args[0], "query_string")? {
+            let values = match value {
+                Value::Array(arr) => lock_array(&arr)?.clone(),
+                other => vec![other],
+            };
+            for value in values {
+                parts.push(format!("{}={}", percent_encode(&key), percent_encode(&value.to_string())));
             }
-        }));
+        }
+        Ok(Value::String(parts.join("&").into()))
+    }));
 
-        // clear() function
-        self.functions.insert("clear".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("clear() takes exactly 1 argument".to_string()));
+    // merge(pairs, other) - keys both have keep their place in pairs
+    functions.insert("merge".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [pairs, other] => {
+                let mut merged = super::pairs_to_map(pairs, "merge")?;
+                merged.extend(super::pairs_to_map(other, "merge")?);
+                Ok(map_to_pairs(merged))
             }
-            match args[0].clone() {
-                Value::Array(_) => Ok(Value::Array(Arc::new(Mutex::new(vec![])))),
-                _ => Err(Error::TypeError("clear() requires array argument".to_string()))
+            _ => Err(Error::TypeError("merge() takes exactly 2 arguments".to_string()))
+        }
+    }));
+
+    functions.insert("has_key".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [Value::Array(pairs), Value::String(key)] => Ok(Value::Boolean(find_pair(&lock_array(pairs)?, key)?.is_some())),
+            _ => Err(Error::TypeError("has_key() requires [key, value] pairs and a string key".to_string()))
+        }
+    }));
+
+    // pop_key(pairs, key, default?) - in place; default (null) when the key is missing
+    functions.insert("pop_key".to_string(), Box::new(|args| {
+        let (pairs, key, default) = match args.as_slice() {
+            [Value::Array(pairs), Value::String(key)] => (pairs, key, Value::Null),
+            [Value::Array(pairs), Value::String(key), default] => (pairs, key, default.clone()),
+            _ => return Err(Error::TypeError("pop_key() requires [key, value] pairs, a string key and an optional default".to_string()))
+        };
+        let mut values = lock_array(pairs)?;
+        let Some(entry) = find_pair(&values, key)? else {
+            return Ok(default);
+        };
+        values.retain(|pair| !matches!(pair, Value::Array(other) if Arc::ptr_eq(other, &entry)));
+        let value = lock_array(&entry)?.get(1).cloned().unwrap_or(Value::Null);
+        Ok(value)
+    }));
+
+    // items(pairs) - fresh pairs, so changing them leaves pairs alone
+    functions.insert("items".to_string(), Box::new(|args| {
+        match args.as_slice() {
+            [pairs] => Ok(map_to_pairs(super::pairs_to_map(pairs, "items")?)),
+            _ => Err(Error::TypeError("items() takes exactly 1 argument".to_string()))
+        }
+    }));
+
+    // unique() and the set operations return new arrays with each value
+    // once, in the order first seen. Scripts have no set type: set(arr) is
+    // an array without repeats, which is what the set operations take
+    for name in ["unique", "set"] {
+        functions.insert(name.to_string(), Box::new(move |args| {
+            match args.as_slice() {
+                [Value::Array(arr)] => Ok(distinct(lock_array(arr)?.iter(), |_| true)),
+                _ => Err(Error::TypeError(format!("{}() requires 1 array argument", name)))
             }
         }));
+    }
 
-        // count() function
-        self.functions.insert("count".to_string(), Box::new(|args| {
-            if args.len() != 2 {
-                return Err(Error::TypeError("count() takes exactly 2 arguments".to_string()));
-            }
-            match (&args[0], &args[1]) {
-                (Value::Array(arr), value) => {
-                    let guard = arr.lock().unwrap();
-                    Ok(Value::Number(guard.iter().filter(|x| *x == value).count() as i32))
-                },
-                (Value::String(s), Value::String(substr)) => {
-                    Ok(Value::Number(s.matches(substr).count() as i32))
-                },
-                _ => Err(Error::TypeError("count() requires (array, value) or (string, string) arguments".to_string()))
-            }
+    for name in ["union", "intersection", "difference"] {
+        functions.insert(name.to_string(), Box::new(move |args| {
+            let [Value::Array(a), Value::Array(b)] = args.as_slice() else {
+                return Err(Error::TypeError(format!("{}() requires 2 array arguments", name)));
+            };
+            // copies, each in its own statement so union(a, a) doesn't
+            // hold a's lock while taking it again
+            let a = lock_array(a)?.clone();
+            let b = lock_array(b)?.clone();
+            let in_b = ValueSet::from_values(&b);
+            Ok(match name {
+                "union" => distinct(a.iter().chain(&b), |_| true),
+                "intersection" => distinct(a.iter(), |value| in_b.contains(value)),
+                _ => distinct(a.iter(), |value| !in_b.contains(value)),
+            })
         }));
+    }
 
-        // upper() function
-        self.functions.insert("upper".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("upper() takes exactly 1 argument".to_string()));
-            }
-            match &args[0] {
-                Value::String(s) => Ok(Value::String(s.to_uppercase())),
-                _ => Err(Error::TypeError("upper() requires string argument".to_string()))
+    functions.insert("eval".to_string(), Box::new(|args| {
+        if args.len() != 1 {
+            return Err(Error::TypeError("eval() takes exactly 1 argument".to_string()));
+        }
+
+        match &args[0] {
+            Value::String(code) => {
+                let mut parser = Parser::new(code);
+                match parser.parse() {
+                    Ok(ast) => {
+                        match crate::interpreter::interpret(ast, false) {
+                            Ok(Some(val)) => Ok(val),
+                            Ok(None) => Ok(Value::Null),
+                            Err(e) => Err(Error::InterpreterError(format!("Eval failed: {}", e)))
+                        }
+                    },
+                    Err(e) => Err(Error::InterpreterError(format!("Eval parsing failed: {}", e)))
+                }
+            },
+            _ => Err(Error::TypeError("eval() requires a string argument".to_string()))
+        }
+    }));
+    functions
+}
+
+// Membership for unique() and the set operations, by a script's ==, so 1 and
+// 1.0 are the same value. Ints, strings, bools, null and whole floats are
+// hashed; anything else, arrays included, is compared with values_equal()
+// against the others seen so far.
+#[derive(Default)]
+struct ValueSet {
+    hashed: HashSet<SetKey>,
+    others: Vec<Value>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum SetKey {
+    Number(i32),
+    String(Arc<str>),
+    Boolean(bool),
+    Null,
+}
+
+impl ValueSet {
+    fn from_values(values: &[Value]) -> Self {
+        let mut set = ValueSet::default();
+        for value in values {
+            set.insert(value);
+        }
+        set
+    }
+
+    fn key(value: &Value) -> Option<SetKey> {
+        Some(match value {
+            Value::Number(n) => SetKey::Number(*n),
+            // 2.0 == 2, so it is looked up as 2
+            Value::Float(f) if f.fract() == 0.0 && *f >= i32::MIN as f64 && *f <= i32::MAX as f64 => SetKey::Number(*f as i32),
+            Value::String(s) => SetKey::String(Arc::clone(s)),
+            Value::Boolean(b) => SetKey::Boolean(*b),
+            Value::Null => SetKey::Null,
+            _ => return None,
+        })
+    }
+
+    fn contains(&self, value: &Value) -> bool {
+        match ValueSet::key(value) {
+            Some(key) => self.hashed.contains(&key),
+            None => self.others.iter().any(|other| values_equal(other, value)),
+        }
+    }
+
+    // false if the value was already there
+    fn insert(&mut self, value: &Value) -> bool {
+        match ValueSet::key(value) {
+            Some(key) => self.hashed.insert(key),
+            None if self.others.iter().any(|other| values_equal(other, value)) => false,
+            None => {
+                self.others.push(value.clone());
+                true
             }
-        }));
+        }
+    }
+}
 
-        // lower() function
-        self.functions.insert("lower".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("lower() takes exactly 1 argument".to_string()));
+// the values keep() accepts, each once
+fn distinct<'a>(values: impl Iterator<Item = &'a Value>, keep: impl Fn(&Value) -> bool) -> Value {
+    let mut seen = ValueSet::default();
+    let kept = values.filter(|value| keep(value) && seen.insert(value)).cloned().collect();
+    Value::Array(Arc::new(Mutex::new(kept)))
+}
+
+// print() and write() separate their values with a space
+fn join_values(values: &[Value]) -> String {
+    values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn parse_url(url: &str) -> Result<Value, Error> {
+    let (scheme, rest) = url.split_once("://").ok_or_else(|| Error::LibraryError(
+        format!("url_parse() expected 'scheme://' in '{}'", url)
+    ))?;
+    let (rest, fragment) = match rest.split_once('#') {
+        Some((rest, fragment)) => (rest, Value::String(percent_decode(fragment, false, "url_parse")?.into())),
+        None => (rest, Value::Null),
+    };
+    let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+
+    // drop user:password@ and split off the port; [::1]:8080 style hosts keep their brackets
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, hp)| hp);
+    let (host, port) = match host_port.rfind(':') {
+        Some(i) if !host_port[i..].contains(']') => {
+            let port = host_port[i + 1..].parse::<u16>().map_err(|_| Error::LibraryError(
+                format!("url_parse() invalid port in '{}'", url)
+            ))?;
+            (&host_port[..i], Value::Number(port as i32))
+        }
+        _ => (host_port, Value::Null),
+    };
+
+    let mut query_pairs = Vec::new();
+    for part in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = part.split_once('=').unwrap_or((part, ""));
+        query_pairs.push(Value::Array(Arc::new(Mutex::new(vec![
+            Value::String(percent_decode(key, true, "url_parse")?.into()),
+            Value::String(percent_decode(value, true, "url_parse")?.into()),
+        ]))));
+    }
+
+    let mut fields = IndexMap::new();
+    fields.insert("scheme".to_string(), Value::String(scheme.to_lowercase().into()));
+    fields.insert("host".to_string(), Value::String(host.into()));
+    fields.insert("port".to_string(), port);
+    fields.insert("path".to_string(), Value::String(path.into()));
+    fields.insert("query".to_string(), Value::Array(Arc::new(Mutex::new(query_pairs))));
+    fields.insert("fragment".to_string(), fragment);
+    Ok(super::map_to_pairs(fields))
+}
+
+// leaves only RFC 3986 unreserved characters as-is
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// plus_as_space is for query strings and url_decode(), where '+' encodes a
+// space; a fragment keeps its '+'s
+fn percent_decode(text: &str, plus_as_space: bool, fname: &str) -> Result<String, Error> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = text.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+                match hex {
+                    Some(byte) => decoded.push(byte),
+                    None => return Err(Error::LibraryError(format!("{}() invalid percent escape in '{}'", fname, text))),
+                }
+                i += 3;
             }
-            match &args[0] {
-                Value::String(s) => Ok(Value::String(s.to_lowercase())),
-                _ => Err(Error::TypeError("lower() requires string argument".to_string()))
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
             }
-        }));
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| Error::LibraryError(format!("{}() decoded text is not valid UTF-8", fname)))
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+// one step of a get_path/set_path path: a key in [key, value] pairs, or an
+// array index (negative counts from the end)
+enum PathStep {
+    Key(String),
+    Index(i32),
+}
 
-        // strip() function
-        self.functions.insert("strip".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("strip() takes exactly 1 argument".to_string()));
+// "users[0].name" -> Key(users), Index(0), Key(name)
+fn parse_path(path: &str, fname: &str) -> Result<Vec<PathStep>, Error> {
+    let bad = || Error::LibraryError(format!("{}() cannot read path '{}'", fname, path));
+    let mut steps = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('[') {
+            let end = after.find(']').ok_or_else(bad)?;
+            steps.push(PathStep::Index(after[..end].trim().parse().map_err(|_| bad())?));
+            rest = &after[end + 1..];
+        } else {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(bad());
             }
-            match &args[0] {
-                Value::String(s) => Ok(Value::String(s.trim().to_string())),
-                _ => Err(Error::TypeError("strip() requires string argument".to_string()))
+            steps.push(PathStep::Key(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        // keys after the first are separated by dots; "a..b" and a trailing dot are errors
+        if let Some(after) = rest.strip_prefix('.') {
+            if after.is_empty() || after.starts_with(['.', '[']) {
+                return Err(bad());
             }
-        }));
+            rest = after;
+        }
+    }
+    Ok(steps)
+}
 
-        self.functions.insert("eval".to_string(), Box::new(|args| {
-            if args.len() != 1 {
-                return Err(Error::TypeError("eval() takes exactly 1 argument".to_string()));
+fn array_slot(len: usize, index: i32) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index as i64 } else { index as i64 };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+// the [key, value] entry for key, if value is a pairs array that has one
+fn find_pair(pairs: &[Value], key: &str) -> Result<Option<Arc<Mutex<Vec<Value>>>>, Error> {
+    for pair in pairs {
+        if let Value::Array(entry) = pair {
+            if matches!(lock_array(entry)?.first(), Some(Value::String(k)) if **k == *key) {
+                return Ok(Some(Arc::clone(entry)));
             }
+        }
+    }
+    Ok(None)
+}
 
-            match &args[0] {
-                Value::String(code) => {
-                    let mut parser = Parser::new(code);
-                    match parser.parse() {
-                        Ok(ast) => {
-                            match crate::interpreter::interpret(ast, false) {
-                                Ok(Some(val)) => Ok(val),
-                                Ok(None) => Ok(Value::Null),
-                                Err(e) => Err(Error::InterpreterError(format!("Eval failed: {}", e)))
-                            }
-                        },
-                        Err(e) => Err(Error::InterpreterError(format!("Eval parsing failed: {}", e)))
-                    }
-                },
-                _ => Err(Error::TypeError("eval() requires a string argument".to_string()))
+// None when the step leads nowhere: a missing key, an index out of range,
+// or a value that can't be stepped into
+fn step_into(value: &Value, step: &PathStep) -> Result<Option<Value>, Error> {
+    let Value::Array(arr) = value else {
+        return Ok(None);
+    };
+    let values = lock_array(arr)?;
+    Ok(match step {
+        PathStep::Index(index) => array_slot(values.len(), *index).map(|i| values[i].clone()),
+        PathStep::Key(key) => match find_pair(&values, key)? {
+            Some(entry) => lock_array(&entry)?.get(1).cloned(),
+            None => None,
+        },
+    })
+}
+
+// sets one step in place and returns the value now there
+fn set_step(value: &Value, step: &PathStep, new: Value, path: &str) -> Result<Value, Error> {
+    let Value::Array(arr) = value else {
+        return Err(Error::TypeError(format!("set_path() cannot step into {} value on path '{}'", type_str_of_value(value), path)));
+    };
+    let mut values = lock_array(arr)?;
+    match step {
+        PathStep::Index(index) => {
+            let i = array_slot(values.len(), *index).ok_or_else(|| Error::IndexOutOfBounds(
+                format!("set_path() index {} is out of range on path '{}'", index, path)
+            ))?;
+            values[i] = new.clone();
+        }
+        PathStep::Key(key) => match find_pair(&values, key)? {
+            Some(entry) => {
+                let mut entry = lock_array(&entry)?;
+                entry.truncate(1);
+                entry.push(new.clone());
             }
-        }));
+            None => values.push(Value::Array(Arc::new(Mutex::new(vec![Value::String(key.clone().into()), new.clone()])))),
+        },
+    }
+    Ok(new)
+}
+
+fn as_f64(value: &Value) -> f64 {
+    match value {
+        Value::Number(n) => *n as f64,
+        Value::Float(f) => *f,
+        _ => f64::NAN,
+    }
+}
+
+// how compare() and sort_natural() order two strings
+#[derive(Clone, Copy, Default)]
+struct Collation {
+    case_insensitive: bool,
+    natural: bool,
+}
+
+// reads [["case_insensitive", bool], ["natural", bool]]
+fn collation(options: Option<&Value>, fname: &str) -> Result<Collation, Error> {
+    let mut collation = Collation::default();
+    let Some(options) = options else {
+        return Ok(collation);
+    };
+    for (key, value) in super::pairs_to_map(options, fname)? {
+        let Value::Boolean(flag) = value else {
+            return Err(Error::TypeError(format!("{}() option '{}' must be true or false", fname, key)));
+        };
+        match key.as_str() {
+            "case_insensitive" => collation.case_insensitive = flag,
+            "natural" => collation.natural = flag,
+            _ => return Err(Error::LibraryError(format!("{}() has no option '{}'; use case_insensitive or natural", fname, key))),
+        }
+    }
+    Ok(collation)
+}
+
+fn collate(a: &str, b: &str, collation: Collation) -> Ordering {
+    let (a, b) = if collation.case_insensitive { (a.to_lowercase(), b.to_lowercase()) } else { (a.to_string(), b.to_string()) };
+    if collation.natural { natural_cmp(&a, &b) } else { a.cmp(&b) }
+}
+
+// "file2" < "file10": runs of digits compare by their value, and "07"
+// comes after "7" only when the values tie
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x: String = std::iter::from_fn(|| a.next_if(char::is_ascii_digit)).collect();
+                let y: String = std::iter::from_fn(|| b.next_if(char::is_ascii_digit)).collect();
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let order = x_value.len().cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let order = x.cmp(y);
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a.next();
+                b.next();
+            }
+        }
     }
 }
 
@@ -297,4 +1039,233 @@ fn type_str_of_value(value: &Value) -> &'static str {
         Value::Function(_, _, _) => "function",
         Value::ReturnValue(val) => type_str_of_value(val),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        StdLib::new().get_function(name).unwrap()(args)
+    }
+
+    fn string(text: &str) -> Value {
+        Value::String(text.into())
+    }
+
+    fn array(values: Vec<Value>) -> Value {
+        Value::Array(Arc::new(Mutex::new(values)))
+    }
+
+    fn pairs(entries: &[(&str, Value)]) -> Value {
+        array(entries.iter().map(|(key, value)| array(vec![string(key), value.clone()])).collect())
+    }
+
+    #[test]
+    fn template_substitutes_keys() {
+        let values = pairs(&[("name", string("Ada")), ("n", Value::Number(3))]);
+        let result = call("template", vec![string("Hi {{name}}, {{ n }} new"), values]).unwrap();
+        assert_eq!(result, string("Hi Ada, 3 new"));
+    }
+
+    #[test]
+    fn template_escapes_html_on_request() {
+        let values = pairs(&[("x", string("<b>\"Tom & 'Jerry'\"</b>"))]);
+        let plain = call("template", vec![string("{{x}}"), values.clone(), string("none")]).unwrap();
+        assert_eq!(plain, string("<b>\"Tom & 'Jerry'\"</b>"));
+        let escaped = call("template", vec![string("{{x}}"), values, string("html")]).unwrap();
+        assert_eq!(escaped, string("&lt;b&gt;&quot;Tom &amp; &#39;Jerry&#39;&quot;&lt;/b&gt;"));
+    }
+
+    #[test]
+    fn template_reports_bad_input() {
+        let values = pairs(&[("a", Value::Number(1))]);
+        let error = |tmpl: &str, extra: Vec<Value>| {
+            let mut args = vec![string(tmpl), values.clone()];
+            args.extend(extra);
+            call("template", args).unwrap_err().to_string()
+        };
+        assert!(error("{{b}}", vec![]).contains("no value for 'b'"));
+        assert!(error("{{a", vec![]).contains("without a closing '}}'"));
+        assert!(error("{{a}}", vec![string("xml")]).contains("must be \"none\" or \"html\""));
+        assert!(call("template", vec![string("{{a}}"), array(vec![Value::Number(1)])]).is_err());
+    }
+
+    #[test]
+    fn exit_carries_its_code() {
+        assert!(matches!(call("exit", vec![]), Err(Error::Exit(0))));
+        assert!(matches!(call("exit", vec![Value::Number(3)]), Err(Error::Exit(3))));
+        assert!(call("exit", vec![string("3")]).unwrap_err().to_string().contains("exit() requires integer argument"));
+        assert!(call("exit", vec![Value::Number(1), Value::Number(2)]).unwrap_err().to_string().contains("exit() takes at most 1 argument"));
+    }
+
+    #[test]
+    fn assert_eq_lists_the_differences() {
+        let values = |items: &[i32]| array(items.iter().map(|n| Value::Number(*n)).collect());
+        assert_eq!(call("assert_eq", vec![values(&[1, 2]), values(&[1, 2])]).unwrap(), Value::Null);
+        assert_eq!(
+            call("assert_eq", vec![values(&[1, 5, 3]), values(&[1, 2, 3])]).unwrap_err().to_string(),
+            "AssertionFailed: values are not equal\n  [1]: expected 2, got 5",
+        );
+        assert_eq!(
+            call("assert_eq", vec![string("ab"), string("ac"), string("greeting")]).unwrap_err().to_string(),
+            "AssertionFailed: greeting\n  value: strings differ at index 1\n    expected: \"ac\"\n    actual:   \"ab\"\n                ^",
+        );
+        assert!(call("assert_eq", vec![Value::Null]).unwrap_err().to_string().contains("assert_eq() takes 2 or 3 arguments"));
+    }
+
+    #[test]
+    fn assert_fails_with_its_message() {
+        assert_eq!(call("assert", vec![Value::Boolean(true)]).unwrap(), Value::Null);
+        assert_eq!(call("assert", vec![Value::Boolean(false)]).unwrap_err().to_string(), "AssertionFailed: assertion failed");
+        assert_eq!(call("assert", vec![Value::Boolean(false), string("x > 0")]).unwrap_err().to_string(), "AssertionFailed: x > 0");
+        assert!(call("assert", vec![Value::Number(1)]).unwrap_err().to_string().contains("assert() requires a boolean condition"));
+        assert!(call("assert", vec![]).unwrap_err().to_string().contains("assert() takes 1 or 2 arguments"));
+    }
+
+    #[test]
+    fn natural_order_compares_numbers_by_value() {
+        let names = array(["file10.txt", "file2.txt", "File1.txt", "file02.txt", "file1.txt"].iter().map(|s| string(s)).collect());
+        call("sort_natural", vec![names.clone()]).unwrap();
+        assert_eq!(names.to_string(), "[File1.txt, file1.txt, file2.txt, file02.txt, file10.txt]");
+        call("sort_natural", vec![names.clone(), pairs(&[("case_insensitive", Value::Boolean(true))])]).unwrap();
+        assert_eq!(names.to_string(), "[File1.txt, file1.txt, file2.txt, file02.txt, file10.txt]");
+
+        let compare = |a: &str, b: &str, options: &[(&str, Value)]| {
+            call("compare", vec![string(a), string(b), pairs(options)]).unwrap()
+        };
+        assert_eq!(compare("file10", "file2", &[]), Value::Number(-1));
+        assert_eq!(compare("file10", "file2", &[("natural", Value::Boolean(true))]), Value::Number(1));
+        assert_eq!(compare("ABC", "abc", &[("case_insensitive", Value::Boolean(true))]), Value::Number(0));
+        assert_eq!(compare("B", "a", &[]), Value::Number(-1));
+        assert_eq!(call("compare", vec![Value::Number(2), Value::Float(1.5)]).unwrap(), Value::Number(1));
+    }
+
+    #[test]
+    fn compare_rejects_bad_options() {
+        let bad = |args: Vec<Value>| call("compare", args).unwrap_err().to_string();
+        assert!(bad(vec![string("a"), string("b"), pairs(&[("locale", Value::Boolean(true))])]).contains("has no option 'locale'"));
+        assert!(bad(vec![string("a"), string("b"), pairs(&[("natural", Value::Number(1))])]).contains("must be true or false"));
+        assert!(bad(vec![string("a"), Value::Number(1)]).contains("two strings or two numbers"));
+        assert!(call("sort_natural", vec![array(vec![Value::Number(1)])]).unwrap_err().to_string().contains("array of strings"));
+    }
+
+    #[test]
+    fn paths_reach_into_pairs_and_arrays() {
+        let user = |name: &str| pairs(&[("name", string(name))]);
+        let data = pairs(&[("users", array(vec![user("Ada"), user("Alan")])), ("count", Value::Number(2))]);
+        let get = |path: &str| call("get_path", vec![data.clone(), string(path), string("none")]).unwrap();
+
+        assert_eq!(get("users[1].name"), string("Alan"));
+        assert_eq!(get("users[-1].name"), string("Alan"));
+        assert_eq!(get("count"), Value::Number(2));
+        assert_eq!(get(""), data);
+        assert_eq!(get("users[5].name"), string("none"));
+        assert_eq!(get("count.x"), string("none"));
+        assert_eq!(call("get_path", vec![data.clone(), string("missing")]).unwrap(), Value::Null);
+
+        call("set_path", vec![data.clone(), string("users[0].name"), string("Grace")]).unwrap();
+        call("set_path", vec![data.clone(), string("meta.tags"), array(vec![])]).unwrap();
+        call("set_path", vec![data.clone(), string("meta.tags"), array(vec![string("x")])]).unwrap();
+        assert_eq!(get("users[0].name"), string("Grace"));
+        assert_eq!(get("meta.tags[0]"), string("x"));
+    }
+
+    #[test]
+    fn bad_paths_are_errors() {
+        let data = pairs(&[("a", array(vec![Value::Number(1)]))]);
+        let set = |path: &str| call("set_path", vec![data.clone(), string(path), Value::Null]).unwrap_err().to_string();
+        assert!(set("a[3]").contains("index 3 is out of range on path 'a[3]'"));
+        assert!(set("a[0].b").contains("cannot step into int value"));
+        assert!(set("").contains("non-empty path"));
+        for path in ["a..b", "a.", "a[x]", "a[0", ".a"] {
+            assert!(set(path).contains("cannot read path"), "{}", path);
+        }
+    }
+
+    #[test]
+    fn array_mutators_work_in_place() {
+        let handle = Arc::new(Mutex::new(vec![Value::Number(3), Value::Number(1), Value::Number(2)]));
+        let arr = Value::Array(Arc::clone(&handle));
+        let same = |result: Value| matches!(result, Value::Array(a) if Arc::ptr_eq(&a, &handle));
+
+        assert!(same(call("sort", vec![arr.clone()]).unwrap()));
+        assert_eq!(arr.to_string(), "[1, 2, 3]");
+        assert!(same(call("reverse", vec![arr.clone()]).unwrap()));
+        assert_eq!(arr.to_string(), "[3, 2, 1]");
+        assert!(same(call("extend", vec![arr.clone(), array(vec![Value::Number(0)])]).unwrap()));
+        assert!(same(call("extend", vec![arr.clone(), arr.clone()]).unwrap()));
+        assert_eq!(arr.to_string(), "[3, 2, 1, 0, 3, 2, 1, 0]");
+        assert!(same(call("clear", vec![arr.clone()]).unwrap()));
+        assert_eq!(arr.to_string(), "[]");
+    }
+
+    #[test]
+    fn pair_lists_merge_and_give_up_keys() {
+        let a = pairs(&[("x", Value::Number(1)), ("y", Value::Number(2))]);
+        let b = pairs(&[("y", Value::Number(3)), ("z", Value::Number(4))]);
+        let merged = call("merge", vec![a.clone(), b]).unwrap();
+        assert_eq!(merged, pairs(&[("x", Value::Number(1)), ("y", Value::Number(3)), ("z", Value::Number(4))]));
+        assert_eq!(call("has_key", vec![a.clone(), string("y")]).unwrap(), Value::Boolean(true));
+        assert_eq!(call("has_key", vec![a.clone(), string("z")]).unwrap(), Value::Boolean(false));
+        assert_eq!(call("pop_key", vec![a.clone(), string("x")]).unwrap(), Value::Number(1));
+        assert_eq!(a, pairs(&[("y", Value::Number(2))]));
+        assert_eq!(call("pop_key", vec![a.clone(), string("x")]).unwrap(), Value::Null);
+        assert_eq!(call("pop_key", vec![a, string("x"), Value::Number(0)]).unwrap(), Value::Number(0));
+
+        let repeated = pairs(&[("x", Value::Number(1)), ("y", Value::Number(2)), ("x", Value::Number(3))]);
+        let items = call("items", vec![repeated.clone()]).unwrap();
+        assert_eq!(items, pairs(&[("x", Value::Number(3)), ("y", Value::Number(2))]));
+        call("pop_key", vec![items, string("y")]).unwrap();
+        assert_eq!(call("has_key", vec![repeated, string("y")]).unwrap(), Value::Boolean(true));
+    }
+
+    #[test]
+    fn set_operations_keep_first_seen_order() {
+        let n = Value::Number;
+        let a = array(vec![n(3), n(1), n(3), string("a"), array(vec![n(1)]), array(vec![n(1)])]);
+        let b = array(vec![n(1), n(2), string("a")]);
+        assert_eq!(call("unique", vec![a.clone()]).unwrap(), array(vec![n(3), n(1), string("a"), array(vec![n(1)])]));
+        assert_eq!(call("union", vec![a.clone(), b.clone()]).unwrap(), array(vec![n(3), n(1), string("a"), array(vec![n(1)]), n(2)]));
+        assert_eq!(call("intersection", vec![a.clone(), b.clone()]).unwrap(), array(vec![n(1), string("a")]));
+        assert_eq!(call("difference", vec![a.clone(), b]).unwrap(), array(vec![n(3), array(vec![n(1)])]));
+        assert_eq!(call("set", vec![a.clone()]).unwrap(), call("unique", vec![a.clone()]).unwrap());
+        // 1 == 1.0 in a script, so they are one value; the first seen stays
+        let f = Value::Float;
+        assert_eq!(call("unique", vec![array(vec![n(1), f(1.0), f(1.5), f(1.5), n(2)])]).unwrap(), array(vec![n(1), f(1.5), n(2)]));
+        assert_eq!(call("intersection", vec![array(vec![f(2.0), f(0.5)]), array(vec![n(2)])]).unwrap(), array(vec![f(2.0)]));
+        assert_eq!(call("difference", vec![array(vec![array(vec![n(1)]), f(0.5)]), array(vec![f(0.5)])]).unwrap(), array(vec![array(vec![n(1)])]));
+        // the same array twice doesn't deadlock
+        assert_eq!(call("union", vec![a.clone(), a.clone()]).unwrap(), call("unique", vec![a]).unwrap());
+    }
+
+    #[test]
+    fn urls_decode_plus_as_a_space() {
+        let url = string("https://u:p@example.com:8080/a%20b?q=a+b&x=%2B&x=2#top+1");
+        assert_eq!(
+            call("url_parse", vec![url]).unwrap().to_string(),
+            "[[scheme, https], [host, example.com], [port, 8080], [path, /a%20b], [query, [[q, a b], [x, +], [x, 2]]], [fragment, top+1]]"
+        );
+        assert_eq!(call("url_decode", vec![string("a+b%2B%C3%A9")]).unwrap(), string("a b+é"));
+        let encoded = call("url_encode", vec![string("a b+c/é")]).unwrap();
+        assert_eq!(encoded, string("a%20b%2Bc%2F%C3%A9"));
+        assert_eq!(call("url_decode", vec![encoded]).unwrap(), string("a b+c/é"));
+        assert!(call("url_decode", vec![string("%zz")]).is_err());
+        let query = pairs(&[("q", string("a b")), ("x", array(vec![Value::Number(1), Value::Number(2)]))]);
+        assert_eq!(call("query_string", vec![query]).unwrap(), string("q=a%20b&x=1&x=2"));
+    }
+
+    #[test]
+    fn std_libraries_share_one_function_table() {
+        let first = StdLib::new();
+        let mut second = StdLib::new();
+        assert!(Rc::ptr_eq(&first.functions, &second.functions));
+        second.constants.insert("answer".to_string(), Value::Number(42));
+        let copy = second.box_clone();
+        assert!(std::ptr::eq(first.get_function("print").unwrap(), copy.get_function("print").unwrap()));
+        // the table is shared, the constants are each library's own
+        assert_eq!(copy.get_constant("answer"), Some(&Value::Number(42)));
+        assert_eq!(first.get_constant("answer"), None);
+    }
+
+}
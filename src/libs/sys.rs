@@ -1,6 +1,6 @@
-use super::Library;
+use super::{find_help, Library, Function};
 use crate::error::Error;
-use crate::parser::Value;
+use crate::parser::{Value, lock_array};
 use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
@@ -9,14 +9,28 @@ use std::sync::{Arc, Mutex};
 #[cfg(target_family = "unix")]
 use sys_info;
 
-pub struct SysLib {
-    functions: HashMap<String, Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>>,
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("exit(code)", "Stops the script with the given exit code."),
+    ("getpid()", "The process id."),
+    ("on_signal(name, handler)", "Runs handler(name) when the signal (\"INT\", \"TERM\", ...) arrives."),
+    ("getenv(name)", "The environment variable's value, or null."),
+    ("setenv(name, value)", "Sets an environment variable."),
+    ("unsetenv(name)", "Removes an environment variable."),
+    ("getcwd()", "The current working directory."),
+    ("abspath(path)", "The absolute form of path."),
+    ("getloadavg()", "[1, 5, 15] minute load averages, or null where unsupported."),
+    ("getsizeof(value)", "Bytes the value takes."),
+];
+
+pub(crate) struct SysLib {
+    functions: HashMap<String, Function>,
     constants: HashMap<String, Value>,
     var_mutability: HashMap<String, bool>,
 }
 
 impl Library for SysLib {
-    fn get_function(&self, name: &str) -> Option<&Box<dyn Fn(Vec<Value>) -> Result<Value, Error>>> {
+    fn get_function(&self, name: &str) -> Option<&Function> {
         self.functions.get(name)
     }
 
@@ -24,6 +38,14 @@ impl Library for SysLib {
         self.constants.get(name)
     }
 
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
     fn box_clone(&self) -> Box<dyn Library> {
         let mut new_lib = SysLib::new();
         new_lib.constants = self.constants.clone();
@@ -33,6 +55,10 @@ impl Library for SysLib {
     fn is_mutable(&self, name: &str) -> Option<bool> {
         self.var_mutability.get(name).copied()
     }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
 }
 
 impl SysLib {
@@ -53,12 +79,13 @@ impl SysLib {
             if cfg!(target_os = "windows") { "windows" }
             else if cfg!(target_os = "linux") { "linux" }
             else if cfg!(target_os = "macos") { "darwin" }
-            else { "unknown" }.to_string()
+            else { "unknown" }.into()
         ));
 
-        // Command line arguments
-        let args: Vec<Value> = env::args()
-            .map(|arg| Value::String(arg))
+        // the script's path and its arguments, as args.RAW has them
+        let (script, script_args) = super::args::invocation();
+        let args: Vec<Value> = std::iter::once(script).chain(script_args)
+            .map(|name| Value::String(name.into()))
             .collect();
         self.constants.insert("ARGV".to_string(), Value::Array(Arc::new(Mutex::new(args))));
 
@@ -66,17 +93,24 @@ impl SysLib {
         if let Ok(exe_path) = env::current_exe() {
             if let Some(path_str) = exe_path.to_str() {
                 self.constants.insert("EXECUTABLE".to_string(), 
-                    Value::String(path_str.to_string()));
+                    Value::String(path_str.into()));
             }
         }
 
         // Version info
         self.constants.insert("VERSION".to_string(), 
-            Value::String(env!("CARGO_PKG_VERSION").to_string()));
+            Value::String(env!("CARGO_PKG_VERSION").into()));
+
+        // Compiled-in libraries followed by cargo features, for feature detection
+        let features = super::embedded_libraries().into_iter()
+            .chain(super::enabled_features())
+            .map(|name| Value::String(name.into()))
+            .collect();
+        self.constants.insert("FEATURES".to_string(), Value::Array(Arc::new(Mutex::new(features))));
 
         // Path separator
         self.constants.insert("PATH_SEP".to_string(), 
-            Value::String(std::path::MAIN_SEPARATOR.to_string()));
+            Value::String(std::path::MAIN_SEPARATOR.to_string().into()));
 
         // Max sizes
         self.constants.insert("MAXSIZE".to_string(), 
@@ -84,16 +118,16 @@ impl SysLib {
 
         // Platform details
         self.constants.insert("OS_NAME".to_string(), Value::String(
-            std::env::consts::OS.to_string()
+            std::env::consts::OS.into()
         ));
         self.constants.insert("ARCH".to_string(), Value::String(
-            std::env::consts::ARCH.to_string()
+            std::env::consts::ARCH.into()
         ));
 
         // Environment paths
         if let Ok(path) = env::var("PATH") {
             let path_array: Vec<Value> = path.split(':')
-                .map(|s| Value::String(s.to_string()))
+                .map(|s| Value::String(s.into()))
                 .collect();
             self.constants.insert("PATH".to_string(), Value::Array(Arc::new(Mutex::new(path_array))));
         }
@@ -114,10 +148,8 @@ impl SysLib {
                 return Err(Error::TypeError("exit() takes exactly 1 argument".to_string()));
             }
             match &args[0] {
-                Value::Number(code) => {
-                    std::process::exit(*code);
-                }
-                _ => return Err(Error::TypeError("exit() requires integer argument".to_string()))
+                Value::Number(code) => Err(Error::Exit(*code)),
+                _ => Err(Error::TypeError("exit() requires integer argument".to_string()))
             }
         }));
 
@@ -128,6 +160,18 @@ impl SysLib {
             }
             Ok(Value::Number(std::process::id() as i32))
         }));
+
+        // on_signal(name, handler) - name is "INT", "TERM", "HUP", ... ("SIG" prefix optional);
+        // handler(name) runs between statements, and may call exit() to stop
+        self.functions.insert("on_signal".to_string(), Box::new(|args| {
+            match args.as_slice() {
+                [Value::String(name), handler @ Value::Function(..)] => {
+                    crate::signals::register(name, handler.clone())?;
+                    Ok(Value::Null)
+                }
+                _ => Err(Error::TypeError("on_signal() requires a signal name and a handler function".to_string()))
+            }
+        }));
     }
 
     fn register_env_functions(&mut self) {
@@ -138,8 +182,8 @@ impl SysLib {
             }
             match &args[0] {
                 Value::String(name) => {
-                    match env::var(name) {
-                        Ok(val) => Ok(Value::String(val)),
+                    match env::var(&**name) {
+                        Ok(val) => Ok(Value::String(val.into())),
                         Err(_) => Ok(Value::Null)
                     }
                 }
@@ -154,7 +198,7 @@ impl SysLib {
             }
             match (&args[0], &args[1]) {
                 (Value::String(name), Value::String(value)) => {
-                    env::set_var(name, value);
+                    env::set_var(&**name, &**value);
                     Ok(Value::Null)
                 }
                 _ => Err(Error::TypeError("setenv() requires string arguments".to_string()))
@@ -168,7 +212,7 @@ impl SysLib {
             }
             match &args[0] {
                 Value::String(name) => {
-                    env::remove_var(name);
+                    env::remove_var(&**name);
                     Ok(Value::Null)
                 }
                 _ => Err(Error::TypeError("unsetenv() requires string argument".to_string()))
@@ -185,7 +229,7 @@ impl SysLib {
             match env::current_dir() {
                 Ok(path) => {
                     if let Some(path_str) = path.to_str() {
-                        Ok(Value::String(path_str.to_string()))
+                        Ok(Value::String(path_str.into()))
                     } else {
                         Ok(Value::Null)
                     }
@@ -201,11 +245,11 @@ impl SysLib {
             }
             match &args[0] {
                 Value::String(path) => {
-                    let path_buf = PathBuf::from(path);
+                    let path_buf = PathBuf::from(&**path);
                     match path_buf.canonicalize() {
                         Ok(abs_path) => {
                             if let Some(path_str) = abs_path.to_str() {
-                                Ok(Value::String(path_str.to_string()))
+                                Ok(Value::String(path_str.into()))
                             } else {
                                 Ok(Value::Null)
                             }
@@ -252,7 +296,7 @@ impl SysLib {
             }
             match &args[0] {
                 Value::Array(arr) => {
-                    let guard = arr.lock().unwrap();
+                    let guard = lock_array(arr)?;
                     Ok(Value::Number(guard.len() as i32))
                 },
                 Value::String(s) => Ok(Value::Number(s.len() as i32)),
@@ -260,4 +304,26 @@ impl SysLib {
             }
         }));
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::{embedded_libraries, enabled_features, load_embedded};
+
+    #[test]
+    fn features_list_the_compiled_in_libraries_then_the_cargo_features() {
+        let lib = SysLib::new();
+        let Some(Value::Array(features)) = lib.get_constant("FEATURES") else { panic!("sys.FEATURES is an array") };
+        let features: Vec<String> = lock_array(features).unwrap().iter().map(|name| name.to_string()).collect();
+        let expected: Vec<&str> = embedded_libraries().into_iter().chain(enabled_features()).collect();
+        assert_eq!(features, expected);
+
+        assert!(features.iter().take(3).eq(["std", "math", "sys"].iter()));
+        for name in embedded_libraries() {
+            assert!(load_embedded(name).is_some(), "{} is listed but can't be imported", name);
+        }
+        assert_eq!(features.contains(&"image".to_string()), cfg!(feature = "imaging"));
+        assert_eq!(features.contains(&"mail".to_string()), cfg!(feature = "mail"));
+        assert_eq!(features.contains(&"unix".to_string()), cfg!(feature = "unix"));
+    }
+}
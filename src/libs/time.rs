@@ -0,0 +1,174 @@
+use super::{find_help, Library, Function};
+use crate::error::Error;
+use crate::parser::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+enum Clock {
+    Real(Instant),
+    // a fake clock stands still except when the script sleeps, and sleeping
+    // moves it forward without waiting
+    Fake { start: f64, elapsed: f64 },
+}
+
+impl Clock {
+    fn now(&self) -> f64 {
+        match self {
+            Clock::Real(_) => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0),
+            Clock::Fake { start, elapsed } => start + elapsed,
+        }
+    }
+
+    fn elapsed(&self) -> f64 {
+        match self {
+            Clock::Real(started) => started.elapsed().as_secs_f64(),
+            Clock::Fake { elapsed, .. } => *elapsed,
+        }
+    }
+
+    fn sleep(&mut self, seconds: f64) {
+        match self {
+            Clock::Real(_) => thread::sleep(Duration::from_secs_f64(seconds)),
+            Clock::Fake { elapsed, .. } => *elapsed += seconds,
+        }
+    }
+}
+
+// signature and description of each function, for std.help() and :help
+const HELP: &[(&str, &str)] = &[
+    ("now()", "Seconds since the unix epoch."),
+    ("elapsed()", "Seconds since the library was imported."),
+    ("sleep(seconds)", "Pauses the script."),
+];
+
+pub(crate) struct TimeLib {
+    functions: HashMap<String, Function>,
+    constants: HashMap<String, Value>,
+    var_mutability: HashMap<String, bool>,
+    clock: Arc<Mutex<Clock>>,
+}
+
+impl Library for TimeLib {
+    fn get_function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    fn get_constant(&self, name: &str) -> Option<&Value> {
+        self.constants.get(name)
+    }
+
+    fn function_names(&self) -> Vec<String> {
+        self.functions.keys().cloned().collect()
+    }
+
+    fn constant_names(&self) -> Vec<String> {
+        self.constants.keys().cloned().collect()
+    }
+
+    fn box_clone(&self) -> Box<dyn Library> {
+        // clones share the clock, so a fake clock keeps the time slept inside functions
+        Box::new(TimeLib::with_clock(Arc::clone(&self.clock)))
+    }
+
+    fn is_mutable(&self, name: &str) -> Option<bool> {
+        self.var_mutability.get(name).copied()
+    }
+
+    fn help(&self, name: &str) -> Option<(String, String)> {
+        find_help(HELP, name)
+    }
+}
+
+impl TimeLib {
+    pub fn new() -> Self {
+        // --fake-time, the unix time the importing run starts at
+        let clock = match crate::interpreter::settings(|settings| settings.fake_time) {
+            Some(start) => Clock::Fake { start, elapsed: 0.0 },
+            None => Clock::Real(Instant::now()),
+        };
+        Self::with_clock(Arc::new(Mutex::new(clock)))
+    }
+
+    fn with_clock(clock: Arc<Mutex<Clock>>) -> Self {
+        let mut lib = TimeLib {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+            var_mutability: HashMap::new(),
+            clock,
+        };
+        lib.register_functions();
+        lib
+    }
+
+    fn register_functions(&mut self) {
+        // now() -> seconds since the unix epoch
+        let clock = Arc::clone(&self.clock);
+        self.functions.insert("now".to_string(), Box::new(move |args| {
+            if !args.is_empty() {
+                return Err(Error::TypeError("now() takes no arguments".to_string()));
+            }
+            Ok(Value::Float(clock.lock().unwrap().now()))
+        }));
+
+        // elapsed() -> seconds since the library was imported, for timing code
+        let clock = Arc::clone(&self.clock);
+        self.functions.insert("elapsed".to_string(), Box::new(move |args| {
+            if !args.is_empty() {
+                return Err(Error::TypeError("elapsed() takes no arguments".to_string()));
+            }
+            Ok(Value::Float(clock.lock().unwrap().elapsed()))
+        }));
+
+        // sleep(seconds)
+        let clock = Arc::clone(&self.clock);
+        self.functions.insert("sleep".to_string(), Box::new(move |args| {
+            let seconds = match args.as_slice() {
+                [Value::Number(n)] => *n as f64,
+                [Value::Float(f)] => *f,
+                _ => return Err(Error::TypeError("sleep() requires 1 numeric argument".to_string()))
+            };
+            if !seconds.is_finite() || seconds < 0.0 {
+                return Err(Error::TypeError("sleep() requires a non-negative number of seconds".to_string()));
+            }
+            clock.lock().unwrap().sleep(seconds);
+            Ok(Value::Null)
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake(start: f64) -> TimeLib {
+        TimeLib::with_clock(Arc::new(Mutex::new(Clock::Fake { start, elapsed: 0.0 })))
+    }
+
+    fn call(lib: &dyn Library, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        lib.get_function(name).unwrap()(args)
+    }
+
+    #[test]
+    fn a_fake_clock_only_moves_when_sleeping() {
+        let lib = fake(1_000.0);
+        assert_eq!(call(&lib, "now", vec![]).unwrap(), Value::Float(1_000.0));
+        assert_eq!(call(&lib, "now", vec![]).unwrap(), Value::Float(1_000.0));
+
+        // sleeping through a clone advances the shared clock
+        let clone = lib.box_clone();
+        call(clone.as_ref(), "sleep", vec![Value::Number(60)]).unwrap();
+        call(&lib, "sleep", vec![Value::Float(0.5)]).unwrap();
+        assert_eq!(call(&lib, "now", vec![]).unwrap(), Value::Float(1_060.5));
+        assert_eq!(call(&lib, "elapsed", vec![]).unwrap(), Value::Float(60.5));
+    }
+
+    #[test]
+    fn sleep_rejects_bad_durations() {
+        let lib = fake(0.0);
+        assert!(call(&lib, "sleep", vec![Value::Number(-1)]).unwrap_err().to_string().contains("non-negative"));
+        assert!(call(&lib, "sleep", vec![Value::String("1".into())]).is_err());
+        assert!(call(&lib, "now", vec![Value::Number(1)]).is_err());
+    }
+}
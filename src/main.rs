@@ -1,194 +1,320 @@
 use std::env;
-use std::fs;
-use std::path::Path;
-use std::collections::HashMap;
 use std::process;
-use std::io::{self, Write};
+use std::thread;
 
-mod interpreter;
-mod lexer;
-mod parser;
-mod error;
-mod libs;
-mod docs;
+use tidal::{output, interpreter, parser, error, libs, docs, repl, notebook, upgrade, cli, completions, trace, crash_dump, bench, dialect, doctest, docgen, graph, minify, transpile, grammar, help, summary};
+use tidal::{parse_script, print_error};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    // docs command
-    if args.contains(&String::from("docs")) {
-        match docs::fetch_docs() {
-            Ok(pages) => {
-                if let Some(pg_idx) = args.iter().position(|x| x == "--pg") {
-                    if let Some(pg_num) = args.get(pg_idx + 1) {
-                        if let Ok(num) = pg_num.parse::<usize>() {
-                            docs::display_docs(&pages, num);
-                            return;
-                        }
-                    }
-                }
-                docs::list_pages(&pages);
-                return;
-            }
-            Err(e) => {
-                eprintln!("Error fetching documentation: {}", e);
-                process::exit(1);
-            }
-        }
+    // parsing and evaluation recurse once per nesting level, so td runs on a
+    // thread whose stack fits the parser's nesting limit on every platform
+    let code = thread::Builder::new()
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(td)
+        .expect("failed to start the interpreter thread")
+        .join()
+        // the panic message has been printed already
+        .unwrap_or(101);
+    // what a panicking script printed is still in the stdout buffer
+    output::flush();
+    if code != 0 {
+        process::exit(code);
     }
+}
 
-    // verbose mode flag check
-    let is_verbose = args.contains(&String::from("--verbose")) || args.contains(&String::from("-v"));
+fn td() -> i32 {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let invocation = match cli::parse(&args) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            eprintln!("Run 'td help' for usage.");
+            process::exit(1);
+        }
+    };
 
-    // error display lul
-    if args.len() < 2 || args.contains(&String::from("help")) || args.contains(&String::from("--help")) || args.contains(&String::from("-h")) {
-        help();
-        process::exit(1);
-    }
+    let code = match invocation.command.name {
+        "run" => run_script(&invocation),
+        "trace-view" => view_trace(&invocation),
+        "bench" => run_benchmarks(&invocation),
+        "docs" => show_docs(invocation.value("pg")),
+        "explain" => explain(&invocation.operands[0]),
+        // the syntax as EBNF, built partly from the parser's own tables
+        "grammar" => {
+            print!("{}", grammar::ebnf());
+            0
+        }
+        // the import graph as Graphviz DOT or Mermaid, warning on cycles
+        "graph" => graph::run(&invocation.operands[0], invocation.has("mermaid")),
+        // the script without comments or spacing, with short local names
+        "minify" => minify::run(&invocation.operands[0], invocation.value("out")),
+        // readable Python or JavaScript for the constructs both can express
+        "transpile" => transpile::run(&invocation.operands[0], invocation.value("to"), invocation.value("out")),
+        // Markdown or HTML API docs for a .tdx library
+        "doc" => docgen::run(&invocation.operands[0], invocation.value("out"), invocation.value("format")),
+        // examples in /** doc comments */, checked against their # => lines
+        "test" if invocation.has("doc") => doctest::run_file(&invocation.operands[0]),
+        "test" => {
+            eprintln!("Error: 'td test' only runs doc comment examples so far; use 'td test --doc <file>'");
+            1
+        }
+        // interactive prompt; `td repl --verbose` traces like a script run
+        "repl" => repl::run(invocation.has("verbose"), invocation.has("quiet")),
+        // JSON-RPC kernel for notebook front ends
+        "notebook" => notebook::run(),
+        // self-update from the latest GitHub release; --check only reports
+        "upgrade" => match upgrade::upgrade(invocation.has("check")) {
+            Ok(()) => 0,
+            Err(e) => {
+                eprintln!("Error upgrading td: {}", e);
+                1
+            }
+        },
+        // shell completion scripts, generated from the definitions in cli.rs
+        "completions" => {
+            // parse() only accepts shells that generate() knows
+            print!("{}", completions::generate(&invocation.operands[0]).unwrap());
+            0
+        }
+        "version" => {
+            version();
+            0
+        }
+        // td help <command> shows its options, then its page in src/command_docs
+        "help" if !invocation.operands.is_empty() => {
+            let command = cli::COMMANDS.iter().find(|command| command.name == invocation.operands[0]).unwrap();
+            print!("{}", help::command_page(command));
+            if let Some(text) = docs::command_doc(command.name) {
+                println!();
+                docs::print_explanation(&text);
+            }
+            0
+        }
+        _ => {
+            print!("{}", help::overview());
+            // a bare `td` is a usage error, asking for help isn't
+            if args.is_empty() { 1 } else { 0 }
+        }
+    };
 
-    let filename = &args[1];
-    let is_brain_rot = filename.ends_with(".br");
+    output::flush();
+    code
+}
 
-    if !filename.ends_with(".td") && !is_brain_rot {
-        eprintln!("Error: Input file must have a .td or .br extension");
-        process::exit(1);
-    }
+// the settings td run's flags give the script, and the dialect it is read in
+fn run_settings(invocation: &cli::Invocation) -> Result<(interpreter::Settings, dialect::Choice), String> {
+    let is_strict = invocation.has("strict");
 
-    // file check disk
-    if !Path::new(filename).exists() {
-        eprintln!("Error: File '{}' not found", filename);
-        process::exit(1);
+    // --seed and --fake-time make the random and time libraries repeat the
+    // same values on every run
+    let seed = match invocation.value("seed").map(str::parse::<i64>) {
+        None => None,
+        Some(Ok(seed)) => Some(seed as u64),
+        Some(Err(_)) => return Err("--seed expects an integer".to_string()),
+    };
+    let fake_time = match invocation.value("fake-time").map(str::parse::<f64>) {
+        None => None,
+        Some(Ok(start)) if start.is_finite() => Some(start),
+        Some(_) => return Err("--fake-time expects a unix time in seconds".to_string()),
+    };
+    let loop_guard = match invocation.value("loop-guard").map(str::parse::<usize>) {
+        None => None,
+        Some(Ok(limit)) if limit > 0 => Some(limit),
+        Some(_) => return Err("--loop-guard expects a positive number of iterations".to_string()),
+    };
+    let max_allocation = match invocation.value("max-alloc").map(str::parse::<usize>) {
+        None => interpreter::DEFAULT_MAX_ALLOCATION,
+        Some(Ok(mb)) if mb > 0 => mb.saturating_mul(1024 * 1024),
+        Some(_) => return Err("--max-alloc expects a positive number of megabytes".to_string()),
+    };
+    if let Some(format) = invocation.value("summary") {
+        if !summary::FORMATS.contains(&format) {
+            return Err(format!("--summary expects one of: {}", summary::FORMATS.join(", ")));
+        }
     }
+    let dialect = dialect::choose(invocation.value("dialect"))?;
+    let settings = interpreter::Settings {
+        warnings: is_strict || invocation.has("warn"),
+        strict: is_strict,
+        loop_guard,
+        max_allocation,
+        script: Some(invocation.operands[0].clone()),
+        script_args: invocation.script_args.clone(),
+        seed,
+        fake_time,
+        cancellation: None,
+    };
+    Ok((settings, dialect))
+}
 
-    // read file
-    let contents = fs::read_to_string(filename)
-        .map_err(|e| error::Error::FileNotFound(format!("Failed to read file: {}", e)))
-        .unwrap();
-
-    // Brain Rot Parser
-    let processed_contents = if is_brain_rot {
-        preprocess_skibidi(&contents)
-    } else {
-        contents
+fn run_script(invocation: &cli::Invocation) -> i32 {
+    let (settings, dialect) = match run_settings(invocation) {
+        Ok(chosen) => chosen,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            return 1;
+        }
     };
+    output::set_unbuffered(invocation.has("unbuffered"));
+    // --summary covers the whole run, parsing included, and is written
+    // after everything the script printed
+    if invocation.has("summary") {
+        summary::start();
+    }
+    let code = execute_script(invocation, settings, dialect);
+    if let Some(summary) = summary::finish(code) {
+        output::flush();
+        eprintln!("{}", summary);
+    }
+    code
+}
 
-    // Parser
-    let mut parser = parser::Parser::new(&processed_contents);
+fn execute_script(invocation: &cli::Invocation, settings: interpreter::Settings, dialect: dialect::Choice) -> i32 {
+    let filename = &invocation.operands[0];
 
-    // Parser to AST
-    let ast = match parser.parse() {
+    let trace_out = invocation.value("trace-out");
+    let debug_dump = invocation.value("debug-dump");
+    // traces, --loop-guard reports, debug dumps and the summary's statement
+    // count need each statement's line
+    let with_lines = trace_out.is_some() || invocation.has("loop-guard") || debug_dump.is_some() || invocation.has("summary");
+    let ast = match parse_script(filename, with_lines, dialect) {
         Ok(ast) => ast,
         Err(e) => {
+            summary::failed(e.code());
             print_error(&e);
-            process::exit(1);
+            return 1;
         }
     };
+    if let Some(path) = trace_out {
+        if let Err(e) = trace::start(path, filename) {
+            eprintln!("Error: cannot write trace '{}': {}", path, e);
+            return 1;
+        }
+    }
+    if debug_dump.is_some() {
+        crash_dump::enable();
+    }
 
-    // Interpreter
-    match interpreter::interpret(ast, is_verbose) {
-        Ok(_) => {},
+    match interpreter::run(ast, invocation.has("verbose"), settings) {
+        // `return <int>;` at top level becomes the exit code (the OS keeps
+        // only the low 8 bits on Unix); returning any other value, or
+        // nothing, exits with 0 like reaching the end of the script
+        Ok(result) => match (result.returned, result.value) {
+            (true, Some(parser::Value::Number(code))) => code,
+            _ => 0,
+        },
+        Err(error::Error::Exit(code)) => code,
         Err(e) => {
+            summary::failed(e.code());
             print_error(&e);
-            process::exit(1);
+            if let Some(path) = debug_dump {
+                match crash_dump::write(path, filename, &e) {
+                    Ok(()) => eprintln!("Wrote a debug dump to {}; please attach it to bug reports.", path),
+                    Err(write_error) => eprintln!("Error: cannot write debug dump '{}': {}", path, write_error),
+                }
+            }
+            1
         }
     }
 }
 
-fn help() {
-    println!("");
-    println!("Tidal Programming Language");
-    println!("Made by Pranav Verma - For the Lagoon Project.");
-    println!("");
-    println!("Usage: td <file.td | file.br> [--verbose | -v]");
-    println!("Options:");
-    println!("  --verbose, -v      Enable verbose output");
-    println!("  help, --help, -h   Display this help message");
-    println!("  docs               Display Built-in Docs.");
-    println!("");
-}
-
-//okay, here is where the brainrot starts ☠️☠️
-fn preprocess_skibidi(input: &str) -> String {
-    let replacements: HashMap<&str, &str> = [
-        ("rizzler", "var"),
-        ("sigma", "novar"),
-        ("be", "="),
-        ("no cap", ";"),
-        ("skibidi", "print"),
-        ("fanum tax", "type"),
-        ("bussin", "for"),
-        ("yeet", "return"),
-        ("sussy", "/*"),
-        ("baka", "*/"),
-        ("aura +69420", "break"),
-        ("aura -69420", "continue"),
-        ("drip", "if"),
-        ("mid", "elif"),
-        ("nah", "else"),
-        ("gyatt", "true"),
-        ("diddy", "false"),
-        ("big yikes", "func"),
-        ("spill", "while"),
-        ("goat", "input"),
-        ("boogey", "import"),
-    ].iter().cloned().collect();
-
-    let mut result = String::new();
-    let mut in_word = false;
-    let mut word_buffer = String::new();
-
-    for c in input.chars() {
-        if c.is_whitespace() {
-            if in_word {
-                if let Some(&replacement) = replacements.get(word_buffer.trim()) {
-                    result.push_str(replacement);
-                } else {
-                    result.push_str(&word_buffer);
-                }
-                word_buffer.clear();
-                in_word = false;
-            }
-            result.push(' ');
-        } else {
-            if "=(),[]{}".contains(c) {
-                if in_word {
-                    if let Some(&replacement) = replacements.get(word_buffer.trim()) {
-                        result.push_str(replacement);
-                    } else {
-                        result.push_str(&word_buffer);
-                    }
-                    word_buffer.clear();
-                    in_word = false;
-                }
-                result.push(c);
-                continue;
-            }
-
-            word_buffer.push(c);
-            in_word = true;
+fn view_trace(invocation: &cli::Invocation) -> i32 {
+    let top = match invocation.value("top").map(str::parse::<usize>) {
+        None => 10,
+        Some(Ok(top)) => top,
+        Some(Err(_)) => {
+            eprintln!("Error: --top expects a number");
+            return 1;
+        }
+    };
+    match trace::view(&invocation.operands[0], top, invocation.has("replay")) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
         }
     }
+}
 
-    if in_word {
-        if let Some(&replacement) = replacements.get(word_buffer.trim()) {
-            result.push_str(replacement);
-        } else {
-            result.push_str(&word_buffer);
+fn run_benchmarks(invocation: &cli::Invocation) -> i32 {
+    let iterations = match invocation.value("iterations").map(str::parse::<usize>) {
+        None => libs::bench::DEFAULT_ITERATIONS,
+        Some(Ok(n)) if n > 0 => n,
+        Some(_) => {
+            eprintln!("Error: --iterations expects a positive number");
+            return 1;
+        }
+    };
+    let threshold = match invocation.value("threshold").map(str::parse::<f64>) {
+        None => 10.0,
+        Some(Ok(percent)) if percent >= 0.0 => percent,
+        Some(_) => {
+            eprintln!("Error: --threshold expects a percentage");
+            return 1;
+        }
+    };
+    let filename = &invocation.operands[0];
+    let ast = match parse_script(filename, false, dialect::Choice::ByExtension) {
+        Ok(ast) => ast,
+        Err(e) => {
+            print_error(&e);
+            return 1;
+        }
+    };
+    let options = bench::Options {
+        script: filename,
+        iterations,
+        baseline: invocation.value("baseline"),
+        save: invocation.value("save"),
+        threshold,
+    };
+    match bench::run(ast, &options) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            output::flush();
+            eprintln!("Error: {}", e);
+            1
         }
     }
+}
 
-    for (&pattern, &replacement) in &replacements {
-        if pattern.contains(' ') {
-            result = result.replace(pattern, replacement);
+fn show_docs(page: Option<&str>) -> i32 {
+    let pages = match docs::fetch_docs() {
+        Ok(pages) => pages,
+        Err(e) => {
+            eprintln!("Error fetching documentation: {}", e);
+            return 1;
+        }
+    };
+    match page.map(str::parse::<usize>) {
+        Some(Ok(num)) => docs::display_docs(&pages, num),
+        Some(Err(_)) => {
+            eprintln!("Error: --pg expects a page number");
+            return 1;
         }
+        None => docs::list_pages(&pages),
     }
+    0
+}
 
-    result
+fn version() {
+    println!("Tidal {} ({}, built {})", env!("CARGO_PKG_VERSION"), env!("TIDAL_GIT_COMMIT"), env!("TIDAL_BUILD_DATE"));
+    println!("Libraries: {}", libs::embedded_libraries().join(", "));
+    let features = libs::enabled_features();
+    println!("Features: {}", if features.is_empty() { "none".to_string() } else { features.join(", ") });
 }
 
-fn print_error(error: &error::Error) {
-    let stderr = io::stderr();
-    let mut handle = stderr.lock();
 
-    writeln!(handle, "\x1b[31m{}\x1b[0m", error).unwrap();
+fn explain(code: &str) -> i32 {
+    match docs::explanation(code) {
+        Some(text) => {
+            docs::print_explanation(&text);
+            0
+        }
+        None => {
+            eprintln!("Error: no explanation for {}", code);
+            1
+        }
+    }
 }
@@ -0,0 +1,287 @@
+use crate::error::Error;
+use crate::lexer::keyword;
+use crate::parser::ASTNode;
+use crate::snapshot;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+// td minify: a script written back as small as it still runs. Comments go
+// (the parser drops them), whitespace shrinks to what keeps tokens apart,
+// and the parameters and variables of each function get short names.
+// Globals and function names keep theirs, since other code and the host
+// may refer to them. A local also keeps its name when a global has it too,
+// when a nested function uses it (functions do not close over locals, so
+// there it names something else), and, for a parameter, when a call passes
+// an argument by that name.
+
+// the nodes directly inside a node, statements of its blocks included
+fn children(node: &mut ASTNode) -> Vec<&mut ASTNode> {
+    match node {
+        ASTNode::Located(_, inner) | ASTNode::UnaryOp(_, inner) | ASTNode::Type(inner) | ASTNode::TypeCast(_, inner)
+        | ASTNode::Input(inner) | ASTNode::LenCall(inner) | ASTNode::DelCall(inner) | ASTNode::Assign(_, inner)
+        | ASTNode::LibraryAssign(_, _, inner) | ASTNode::KeywordArg(_, inner) | ASTNode::Spread(inner)
+        | ASTNode::Var(_, Some(inner), _) | ASTNode::Return(Some(inner)) => vec![inner.as_mut()],
+        ASTNode::BinaryOp(left, _, right) | ASTNode::Index(left, right) => vec![left.as_mut(), right.as_mut()],
+        ASTNode::IndexAssign(target, index, value) => vec![target.as_mut(), index.as_mut(), value.as_mut()],
+        ASTNode::Slice(target, start, stop, step) => {
+            let mut nodes = vec![target.as_mut()];
+            nodes.extend([start, stop, step].into_iter().flatten().map(|part| part.as_mut()));
+            nodes
+        }
+        ASTNode::Comparison(nodes, _) | ASTNode::Print(nodes) | ASTNode::Array(nodes) | ASTNode::FunctionCall(_, nodes)
+        | ASTNode::LibraryFunctionCall(_, _, nodes) | ASTNode::FunctionDecl(_, _, nodes) => nodes.iter_mut().collect(),
+        ASTNode::While(condition, body) | ASTNode::ForEach(_, condition, body) => {
+            let mut nodes = vec![condition.as_mut()];
+            nodes.extend(body.iter_mut());
+            nodes
+        }
+        ASTNode::For(init, condition, update, body) => {
+            let mut nodes = vec![init.as_mut(), condition.as_mut(), update.as_mut()];
+            nodes.extend(body.iter_mut());
+            nodes
+        }
+        ASTNode::If(condition, then, elifs, otherwise) => {
+            let mut nodes = vec![condition.as_mut()];
+            nodes.extend(then.iter_mut());
+            for (condition, body) in elifs {
+                nodes.push(condition);
+                nodes.extend(body.iter_mut());
+            }
+            nodes.extend(otherwise.iter_mut().flatten());
+            nodes
+        }
+        _ => Vec::new(),
+    }
+}
+
+// calls f on the node and everything inside it
+fn walk(node: &mut ASTNode, f: &mut impl FnMut(&mut ASTNode)) {
+    f(node);
+    for child in children(node) {
+        walk(child, f);
+    }
+}
+
+// the same, but a nested function is passed to f without going inside it
+fn walk_local(node: &mut ASTNode, f: &mut impl FnMut(&mut ASTNode)) {
+    f(node);
+    if !matches!(node, ASTNode::FunctionDecl(..)) {
+        for child in children(node) {
+            walk_local(child, f);
+        }
+    }
+}
+
+// every name used in the nodes
+fn names(nodes: &mut [ASTNode], found: &mut HashSet<String>) {
+    for node in nodes {
+        walk(node, &mut |node| match node {
+            ASTNode::Identifier(name) | ASTNode::Assign(name, _) | ASTNode::Var(name, _, _) | ASTNode::ForEach(name, _, _)
+            | ASTNode::FunctionCall(name, _) | ASTNode::KeywordArg(name, _) | ASTNode::Import(name, _, _)
+            | ASTNode::LibraryAccess(name, _) | ASTNode::LibraryFunctionCall(name, _, _) | ASTNode::LibraryAssign(name, _, _) => {
+                found.insert(name.clone());
+            }
+            ASTNode::FunctionDecl(name, params, _) => {
+                found.insert(name.clone());
+                found.extend(params.iter().cloned());
+            }
+            ASTNode::Global(globals) => found.extend(globals.iter().cloned()),
+            _ => {}
+        });
+    }
+}
+
+struct Renamer {
+    // every name in the script, which a short name must not be
+    taken: HashSet<String>,
+    // variables declared outside functions, and the functions
+    globals: HashSet<String>,
+    // passed by name in some call, so parameters keep them
+    keyword_args: HashSet<String>,
+}
+
+impl Renamer {
+    fn new(ast: &mut [ASTNode]) -> Self {
+        let mut renamer = Renamer { taken: HashSet::new(), globals: HashSet::new(), keyword_args: HashSet::new() };
+        names(ast, &mut renamer.taken);
+        for node in ast.iter_mut() {
+            walk_local(node, &mut |node| match node {
+                ASTNode::Var(name, _, _) | ASTNode::ForEach(name, _, _) => {
+                    renamer.globals.insert(name.clone());
+                }
+                ASTNode::Global(names) => renamer.globals.extend(names.iter().cloned()),
+                _ => {}
+            });
+            walk(node, &mut |node| match node {
+                ASTNode::FunctionDecl(name, _, _) => {
+                    renamer.globals.insert(name.clone());
+                }
+                ASTNode::KeywordArg(name, _) => {
+                    renamer.keyword_args.insert(name.clone());
+                }
+                _ => {}
+            });
+        }
+        renamer
+    }
+
+    // gives a function's locals short names; nested functions are left to
+    // their own call
+    fn function(&self, node: &mut ASTNode) {
+        let ASTNode::FunctionDecl(_, params, body) = node else { return };
+        let mut kept = self.globals.clone();
+        let mut declared: Vec<String> = Vec::new();
+        for statement in body.iter_mut() {
+            walk_local(statement, &mut |node| match node {
+                ASTNode::Var(name, _, _) | ASTNode::ForEach(name, _, _) if !declared.contains(name) => declared.push(name.clone()),
+                ASTNode::Global(names) => kept.extend(names.iter().cloned()),
+                ASTNode::FunctionDecl(_, _, inner) => names(inner, &mut kept),
+                _ => {}
+            });
+        }
+
+        let mut short_names = (0..).map(short_name).filter(|name| keyword(name).is_none() && !self.taken.contains(name));
+        let mut renames: HashMap<String, String> = HashMap::new();
+        let params_to_rename = params.iter().filter(|param| !self.keyword_args.contains(*param));
+        for name in params_to_rename.chain(&declared) {
+            if !kept.contains(name) && !renames.contains_key(name) {
+                renames.insert(name.clone(), short_names.next().unwrap());
+            }
+        }
+
+        for param in params.iter_mut() {
+            if let Some(short) = renames.get(param) {
+                *param = short.clone();
+            }
+        }
+        for statement in body.iter_mut() {
+            walk_local(statement, &mut |node| {
+                if let ASTNode::Identifier(name) | ASTNode::Assign(name, _) | ASTNode::Var(name, _, _) | ASTNode::ForEach(name, _, _) | ASTNode::FunctionCall(name, _) = node {
+                    if let Some(short) = renames.get(name) {
+                        *name = short.clone();
+                    }
+                }
+            });
+        }
+    }
+}
+
+// a, b, ..., z, aa, ab, ...
+fn short_name(mut n: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'a' + (n % 26) as u8);
+        n /= 26;
+        if n == 0 {
+            break;
+        }
+        n -= 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+// drops whitespace outside strings, keeping one space only where the
+// characters on either side would otherwise run together into another token
+fn squeeze(source: &str) -> String {
+    const OPERATORS: &str = "+-*/%<>=!&|.";
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let chars: Vec<char> = source.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' {
+            // strings have no escapes; a triple-quoted one ends only at """
+            let quotes = if chars[i..].starts_with(&['"'; 3]) { 3 } else { 1 };
+            let end = (i + quotes..chars.len())
+                .find(|&j| chars[j..].starts_with(&['"'; 3][..quotes]))
+                .map_or(chars.len(), |j| j + quotes);
+            out.extend(&chars[i..end]);
+            i = end;
+        } else if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if let (Some(before), Some(&after)) = (out.chars().last(), chars.get(i)) {
+                if (is_word(before) && is_word(after)) || (OPERATORS.contains(before) && OPERATORS.contains(after)) {
+                    out.push(' ');
+                }
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+pub fn minify(mut ast: Vec<ASTNode>) -> Result<String, Error> {
+    let renamer = Renamer::new(&mut ast);
+    for node in ast.iter_mut() {
+        walk(node, &mut |node| renamer.function(node));
+    }
+    Ok(squeeze(&snapshot::program(&ast)?))
+}
+
+// td minify <script> [-o out.td]: writes to the file, or prints
+pub fn run(path: &str, out: Option<&str>) -> i32 {
+    let minified = crate::parse_script(path, false, crate::dialect::Choice::ByExtension).and_then(minify);
+    let result = match (minified, out) {
+        (Ok(source), Some(out)) => fs::write(out, format!("{}\n", source))
+            .map_err(|e| Error::FileNotFound(format!("cannot write '{}': {}", out, e))),
+        (Ok(source), None) => {
+            outln!("{}", source);
+            Ok(())
+        }
+        (Err(e), _) => Err(e),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("\x1b[31m{}\x1b[0m", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{run_source_captured, Settings};
+    use crate::parser::Parser;
+
+    #[test]
+    fn minified_scripts_print_the_same() {
+        let source = r#"
+/* totals */
+var total = 0;
+func add(amount, times) {
+    "Adds amount to the total, times times.";
+    global total;
+    for (var count = 0; count < times; count = count + 1) {
+        total = total + amount;
+    }
+    var label = "total is " + str(total);
+    return label;
+}
+func scale(value, factor) {
+    func inner() { global total; return total; }
+    var total = value * factor;
+    return [total, inner(), value - -1];
+}
+print(add(2, 3));
+print(scale(value = 4, factor = 2), sep = "  ");
+print("""a "quoted"   string""");
+"#;
+        let minified = minify(Parser::new(source).parse().unwrap()).unwrap();
+        assert_eq!(minified, concat!(
+            r#"var total=0;func add(a,b){"Adds amount to the total, times times.";global total;"#,
+            r#"for(var c=0;c<b;c=c+1){total=total+a;}var d="total is "+str(total);return d;}"#,
+            r#"func scale(value,factor){func inner(){global total;return total;}var total=value*factor;return[total,inner(),value- -1];}"#,
+            r#"print(add(2,3));print(scale(value=4,factor=2),sep="  ");print("""a "quoted"   string""");"#,
+        ));
+        let (before, after) = (run_source_captured(source, &[], Settings::default()), run_source_captured(&minified, &[], Settings::default()));
+        assert_eq!((after.stdout, after.value.is_ok()), (before.stdout, true));
+    }
+}
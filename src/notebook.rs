@@ -0,0 +1,154 @@
+use crate::error::Error;
+use crate::input::{self, CannedInput};
+use crate::interpreter::Session;
+use crate::output::CaptureOutput;
+use crate::parser::Value;
+use crate::repl;
+use serde_json::{json, Value as Json};
+
+// td notebook: a kernel for notebook front ends. Each line on stdin is a
+// JSON-RPC 2.0 request and gets one response line on stdout; every cell runs
+// in the same session, so variables, functions and imports carry over.
+//   {"jsonrpc":"2.0","id":1,"method":"execute","params":{"code":"var x = 2; x * 21"}}
+//   {"jsonrpc":"2.0","id":1,"result":{"stdout":"","stderr":"","value":"42"}}
+// Methods are execute, variables, reset and shutdown. A cell that fails
+// answers with an error whose data holds what the cell printed before it.
+// input() gets end of input, since stdin carries the requests.
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+// the cell's own error, not the protocol's
+const CELL_ERROR: i32 = 1;
+
+struct Kernel {
+    session: Session,
+    capture: CaptureOutput,
+}
+
+impl Kernel {
+    fn new() -> Self {
+        let capture = CaptureOutput::new();
+        let session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        Kernel { session, capture }
+    }
+
+    // the response to one request line, and whether the kernel should stop
+    // afterwards with an exit code
+    fn handle(&mut self, line: &str) -> (Json, Option<i32>) {
+        let request: Json = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => return (error(Json::Null, PARSE_ERROR, &format!("invalid JSON: {}", e), None), None),
+        };
+        let id = request["id"].clone();
+        let Some(method) = request["method"].as_str() else {
+            return (error(id, INVALID_REQUEST, "the request has no method", None), None);
+        };
+
+        match method {
+            "execute" => match request["params"]["code"].as_str() {
+                Some(code) => self.execute(id, code),
+                None => (error(id, INVALID_PARAMS, "execute needs params.code, a string", None), None),
+            },
+            "variables" => {
+                let variables: Vec<Json> = self.session.variables().into_iter()
+                    .map(|(name, value, is_mutable)| json!({ "name": name, "value": value.to_string(), "mutable": is_mutable }))
+                    .collect();
+                (success(id, json!(variables)), None)
+            }
+            "reset" => {
+                self.session.clear();
+                (success(id, Json::Null), None)
+            }
+            "shutdown" => (success(id, Json::Null), Some(0)),
+            other => (error(id, METHOD_NOT_FOUND, &format!("unknown method '{}'", other), None), None),
+        }
+    }
+
+    fn execute(&mut self, id: Json, code: &str) -> (Json, Option<i32>) {
+        let result = repl::parse(code.trim()).and_then(|ast| self.session.eval(ast));
+        let (stdout, stderr) = self.capture.take();
+        let mut printed = json!({ "stdout": stdout, "stderr": stderr });
+        match result {
+            Ok(value) => {
+                printed["value"] = match value {
+                    Value::Null => Json::Null,
+                    value => Json::String(value.to_string()),
+                };
+                (success(id, printed), None)
+            }
+            // exit() in a cell ends the kernel, like it ends a script
+            Err(Error::Exit(code)) => {
+                printed["exit"] = json!(code);
+                (success(id, printed), Some(code))
+            }
+            Err(e) => (error(id, CELL_ERROR, &e.to_string(), Some(printed)), None),
+        }
+    }
+}
+
+fn success(id: Json, result: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error(id: Json, code: i32, message: &str, data: Option<Json>) -> Json {
+    let mut error = json!({ "code": code, "message": message });
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+// Serves requests until shutdown, exit() or end of input
+pub fn run() -> i32 {
+    let mut kernel = Kernel::new();
+    while let Some(line) = input::read_line("") {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, stop) = kernel.handle(&line);
+        outln!("{}", response);
+        crate::output::flush();
+        if let Some(code) = stop {
+            return code;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_share_one_session() {
+        let mut kernel = Kernel::new();
+        let (response, _) = kernel.handle(r#"{"jsonrpc":"2.0","id":1,"method":"execute","params":{"code":"var x = 2;\nprint(\"set\");"}}"#);
+        assert_eq!(response["result"], json!({ "stdout": "set\n", "stderr": "", "value": null }));
+        let (response, _) = kernel.handle(r#"{"jsonrpc":"2.0","id":2,"method":"execute","params":{"code":"var y = 21;\nx * y"}}"#);
+        assert_eq!(response["id"], json!(2));
+        assert_eq!(response["result"]["value"], json!("42"));
+
+        let (response, _) = kernel.handle(r#"{"id":3,"method":"execute","params":{"code":"print(1);\nprint(missing);"}}"#);
+        assert_eq!(response["error"]["code"], json!(CELL_ERROR));
+        assert_eq!(response["error"]["data"]["stdout"], json!("1\n"));
+
+        let (response, _) = kernel.handle(r#"{"id":4,"method":"variables"}"#);
+        assert_eq!(response["result"], json!([{ "name": "x", "value": "2", "mutable": true }, { "name": "y", "value": "21", "mutable": true }]));
+        kernel.handle(r#"{"id":5,"method":"reset"}"#);
+        let (response, _) = kernel.handle(r#"{"id":6,"method":"variables"}"#);
+        assert_eq!(response["result"], json!([]));
+    }
+
+    #[test]
+    fn bad_requests_get_protocol_errors() {
+        let mut kernel = Kernel::new();
+        assert_eq!(kernel.handle("{oops").0["error"]["code"], json!(PARSE_ERROR));
+        assert_eq!(kernel.handle(r#"{"id":1}"#).0["error"]["code"], json!(INVALID_REQUEST));
+        assert_eq!(kernel.handle(r#"{"id":1,"method":"run"}"#).0["error"]["code"], json!(METHOD_NOT_FOUND));
+        assert_eq!(kernel.handle(r#"{"id":1,"method":"execute","params":{}}"#).0["error"]["code"], json!(INVALID_PARAMS));
+        let (response, stop) = kernel.handle(r#"{"id":2,"method":"execute","params":{"code":"std.exit(3);"}}"#);
+        assert_eq!((response["result"]["exit"].clone(), stop), (json!(3), Some(3)));
+    }
+}
@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::io::{self, IsTerminal, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+// Where script output, verbose logs and warnings end up. Defaults to the real
+// stdout/stderr; a Session installs its own sink here while it evaluates.
+pub trait Output {
+    fn write_stdout(&mut self, text: &str);
+    fn write_stderr(&mut self, text: &str);
+    fn flush(&mut self) {}
+}
+
+// Script output to a pipe or file collects here and goes out in large
+// writes: when the buffer fills, on flush() (std.flush(), input(), the end of
+// an eval or of the run) and before anything is written to stderr. A terminal
+// still sees each line as it is printed, and --unbuffered writes every print
+// at once for pipes that need to see output as it happens.
+static STDOUT_BUFFER: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static UNBUFFERED: AtomicBool = AtomicBool::new(false);
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// main records --unbuffered before the script runs
+pub fn set_unbuffered(unbuffered: bool) {
+    UNBUFFERED.store(unbuffered, Ordering::Relaxed);
+}
+
+fn stdout_is_terminal() -> bool {
+    static IS_TERMINAL: OnceLock<bool> = OnceLock::new();
+    *IS_TERMINAL.get_or_init(|| io::stdout().is_terminal())
+}
+
+fn drain(buffer: &mut Vec<u8>) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(buffer);
+    let _ = stdout.flush();
+    buffer.clear();
+}
+
+pub struct StdStreams;
+
+impl Output for StdStreams {
+    fn write_stdout(&mut self, text: &str) {
+        let mut buffer = STDOUT_BUFFER.lock().unwrap_or_else(PoisonError::into_inner);
+        buffer.extend_from_slice(text.as_bytes());
+        if buffer.len() >= BUFFER_SIZE || UNBUFFERED.load(Ordering::Relaxed) || (stdout_is_terminal() && text.contains('\n')) {
+            drain(&mut buffer);
+        }
+    }
+
+    fn write_stderr(&mut self, text: &str) {
+        // what the script printed before a warning shows up before it
+        drain(&mut STDOUT_BUFFER.lock().unwrap_or_else(PoisonError::into_inner));
+        let _ = io::stderr().write_all(text.as_bytes());
+    }
+
+    fn flush(&mut self) {
+        drain(&mut STDOUT_BUFFER.lock().unwrap_or_else(PoisonError::into_inner));
+        let _ = io::stderr().flush();
+    }
+}
+
+// Collects everything written; clones share the same buffers.
+#[derive(Clone, Default)]
+pub struct CaptureOutput {
+    stdout: Rc<RefCell<String>>,
+    stderr: Rc<RefCell<String>>,
+}
+
+impl CaptureOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stdout(&self) -> String {
+        self.stdout.borrow().clone()
+    }
+
+    pub fn stderr(&self) -> String {
+        self.stderr.borrow().clone()
+    }
+
+    // (stdout, stderr) so far, leaving both empty
+    pub fn take(&self) -> (String, String) {
+        (self.stdout.take(), self.stderr.take())
+    }
+}
+
+impl Output for CaptureOutput {
+    fn write_stdout(&mut self, text: &str) {
+        self.stdout.borrow_mut().push_str(text);
+    }
+
+    fn write_stderr(&mut self, text: &str) {
+        self.stderr.borrow_mut().push_str(text);
+    }
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Box<dyn Output>> = RefCell::new(Box::new(StdStreams));
+}
+
+// returns the previous sink so callers can restore it
+pub fn set_output(output: Box<dyn Output>) -> Box<dyn Output> {
+    OUTPUT.with(|o| std::mem::replace(&mut *o.borrow_mut(), output))
+}
+
+pub fn write_stdout(text: &str) {
+    OUTPUT.with(|o| o.borrow_mut().write_stdout(text));
+}
+
+pub fn write_stderr(text: &str) {
+    OUTPUT.with(|o| o.borrow_mut().write_stderr(text));
+}
+
+pub fn flush() {
+    OUTPUT.with(|o| o.borrow_mut().flush());
+}
+
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::write_stdout(&format!($($arg)*))
+    };
+}
+
+macro_rules! outln {
+    () => {
+        $crate::output::write_stdout("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write_stdout(&format!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+macro_rules! errln {
+    ($($arg:tt)*) => {
+        $crate::output::write_stderr(&format!("{}\n", format_args!($($arg)*)))
+    };
+}
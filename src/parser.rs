@@ -1,20 +1,24 @@
-use crate::lexer::{Lexer, Token};
+use crate::lexer::{keyword_text, Lexer, Token, KEYWORDS};
 use crate::error::Error;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::mem::{discriminant, Discriminant};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(i32),
-    String(String),
+    String(Arc<str>),
     Boolean(bool),
     Float(f64),
     Null,
-    Type(String),
+    Type(Arc<str>),
     Break,
     Continue,
     Array(Arc<Mutex<Vec<Value>>>), // Change array storage to use Arc<Mutex<>>
-    Function(String, Vec<String>, Vec<ASTNode>),  
+    // shared, so passing a function around or copying a scope's functions
+    // into a call doesn't copy its body, and Value stays small
+    Function(Arc<str>, Arc<Vec<String>>, Arc<Vec<ASTNode>>),
     ReturnValue(Box<Value>),
 }
 
@@ -41,15 +45,26 @@ impl PartialEq for Value {
             (Value::Break, Value::Break) => true,
             (Value::Continue, Value::Continue) => true,
             (Value::Array(a), Value::Array(b)) => {
-                let a_guard = a.lock().unwrap();
-                let b_guard = b.lock().unwrap();
-                *a_guard == *b_guard
+                // comparing an array with itself must not lock it twice
+                Arc::ptr_eq(a, b) || *read_array(a) == *read_array(b)
             },
             _ => false
         }
     }
 }
 
+// A panic while an array is locked (in a library, say) poisons its mutex.
+// lock_array turns that into an error the script sees instead of a second
+// panic; read_array is for places that can't fail, like Display, and reads
+// whatever the array held when the panic happened.
+pub fn lock_array(arr: &Mutex<Vec<Value>>) -> Result<MutexGuard<'_, Vec<Value>>, Error> {
+    arr.lock().map_err(|_| Error::InterpreterError("Array is unusable: a panic interrupted an earlier update to it".to_string()))
+}
+
+pub fn read_array(arr: &Mutex<Vec<Value>>) -> MutexGuard<'_, Vec<Value>> {
+    arr.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 #[derive(Debug, Clone)]
 pub enum ASTNode {
     Number(i32),
@@ -58,7 +73,8 @@ pub enum ASTNode {
     Float(f64),
     Null,
     BinaryOp(Box<ASTNode>, Token, Box<ASTNode>),
-    Print(Box<ASTNode>),
+    Comparison(Vec<ASTNode>, Vec<Token>),  // a chain like a < b <= c: operands, and the operators between them
+    Print(Vec<ASTNode>),  // values, with optional sep = and end = keyword arguments
     Var(String, Option<Box<ASTNode>>, bool),
     Assign(String, Box<ASTNode>),
     UnaryOp(Token, Box<ASTNode>),
@@ -70,6 +86,7 @@ pub enum ASTNode {
     TypeCast(String, Box<ASTNode>),
     If(Box<ASTNode>, Vec<ASTNode>, Vec<(ASTNode, Vec<ASTNode>)>, Option<Vec<ASTNode>>),
     For(Box<ASTNode>, Box<ASTNode>, Box<ASTNode>, Vec<ASTNode>),
+    ForEach(String, Box<ASTNode>, Vec<ASTNode>),  // for (name in values) { body }
     While(Box<ASTNode>, Vec<ASTNode>),
     Array(Vec<ASTNode>),
     Break,
@@ -80,10 +97,62 @@ pub enum ASTNode {
     LenCall(Box<ASTNode>),
     DelCall(Box<ASTNode>),
     Return(Option<Box<ASTNode>>),
-    Import(String, Option<String>),
+    Import(String, Option<String>, Option<(Token, String)>),  // name, mode, version requirement like >= "1.0"
     LibraryAccess(String, String), 
     LibraryFunctionCall(String, String, Vec<ASTNode>),
+    LibraryAssign(String, String, Box<ASTNode>),  // lib.name = value
     Slice(Box<ASTNode>, Option<Box<ASTNode>>, Option<Box<ASTNode>>, Option<Box<ASTNode>>), // expr, start, stop, step
+    KeywordArg(String, Box<ASTNode>),  // name = value inside a call
+    Spread(Box<ASTNode>),  // ...expr inside a call or array literal
+    Global(Vec<String>),
+    Located(usize, Box<ASTNode>),  // a statement and its line, only when the parser records lines
+}
+
+impl ASTNode {
+    // the variant name, for traces
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ASTNode::Number(_) => "Number",
+            ASTNode::String(_) => "String",
+            ASTNode::Boolean(_) => "Boolean",
+            ASTNode::Float(_) => "Float",
+            ASTNode::Null => "Null",
+            ASTNode::BinaryOp(_, _, _) => "BinaryOp",
+            ASTNode::Comparison(_, _) => "Comparison",
+            ASTNode::Print(_) => "Print",
+            ASTNode::Var(_, _, _) => "Var",
+            ASTNode::Assign(_, _) => "Assign",
+            ASTNode::UnaryOp(_, _) => "UnaryOp",
+            ASTNode::Identifier(_) => "Identifier",
+            ASTNode::Index(_, _) => "Index",
+            ASTNode::IndexAssign(_, _, _) => "IndexAssign",
+            ASTNode::Type(_) => "Type",
+            ASTNode::TypeLiteral(_) => "TypeLiteral",
+            ASTNode::TypeCast(_, _) => "TypeCast",
+            ASTNode::If(_, _, _, _) => "If",
+            ASTNode::For(_, _, _, _) => "For",
+            ASTNode::ForEach(_, _, _) => "ForEach",
+            ASTNode::While(_, _) => "While",
+            ASTNode::Array(_) => "Array",
+            ASTNode::Break => "Break",
+            ASTNode::Continue => "Continue",
+            ASTNode::FunctionDecl(_, _, _) => "FunctionDecl",
+            ASTNode::FunctionCall(_, _) => "FunctionCall",
+            ASTNode::Input(_) => "Input",
+            ASTNode::LenCall(_) => "LenCall",
+            ASTNode::DelCall(_) => "DelCall",
+            ASTNode::Return(_) => "Return",
+            ASTNode::Import(_, _, _) => "Import",
+            ASTNode::LibraryAccess(_, _) => "LibraryAccess",
+            ASTNode::LibraryFunctionCall(_, _, _) => "LibraryFunctionCall",
+            ASTNode::LibraryAssign(_, _, _) => "LibraryAssign",
+            ASTNode::Slice(_, _, _, _) => "Slice",
+            ASTNode::KeywordArg(_, _) => "KeywordArg",
+            ASTNode::Spread(_) => "Spread",
+            ASTNode::Global(_) => "Global",
+            ASTNode::Located(_, node) => node.kind(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -93,71 +162,149 @@ struct Scope {
     is_block: bool,
 }
 
+// Unary minus applies to everything up to the next operator looser than **,
+// so -2 ** 2 is -(2 ** 2) = -4 as in maths and Python, while -a * b is
+// (-a) * b and 2 ** -1 still works.
+pub const UNARY_MINUS_PRECEDENCE: u8 = 6;
+
+// How deeply expressions and blocks may nest. Parsing and evaluating both
+// recurse once per level, so without a limit a file of a few thousand '('
+// overflows the stack instead of reporting an error.
+const MAX_NESTING: usize = 128;
+
 pub struct Parser<'a> {
     lexer: Lexer<'a>,
     current_token: Token,
     scopes: Vec<Scope>,
+    record_lines: bool,
+    depth: usize,
+    // for hints: the token before the current one and the lines both are on,
+    // and the lines of the '{' not closed yet
+    previous_kind: Discriminant<Token>,
+    previous_line: usize,
+    current_line: usize,
+    open_braces: Vec<usize>,
+    // where the current token starts, for error messages
+    current_column: usize,
+    // where include_str looks for relative paths; the working directory if None
+    base_dir: Option<PathBuf>,
+    // a lexer error in the very first token, which parse() reports
+    first_error: Option<Error>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         let mut lexer = Lexer::new(input);
-        let current_token = lexer.next_token().unwrap();
+        let (current_token, first_error) = match lexer.next_token() {
+            Ok(token) => (token, None),
+            Err(e) => (Token::EOF, Some(e)),
+        };
         let mut parser = Parser {
             lexer,
             current_token,
             scopes: Vec::new(),
+            record_lines: false,
+            depth: 0,
+            previous_kind: discriminant(&Token::EOF),
+            previous_line: 0,
+            current_line: 0,
+            open_braces: Vec::new(),
+            current_column: 0,
+            base_dir: None,
+            first_error,
         };
+        parser.current_line = parser.lexer.token_line;
+        parser.current_column = parser.lexer.token_column;
         parser.push_scope(false, false);
         parser
     }
 
+    // wraps every statement in ASTNode::Located so its line can be traced
+    pub fn with_lines(mut self) -> Self {
+        self.record_lines = true;
+        self
+    }
+
+    // resolves include_str paths against dir, the directory of the script
+    pub fn with_base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(dir.into());
+        self
+    }
+
+    // include_str("data.txt") is replaced by the file's contents as a string
+    // literal while parsing, so the script needs no io at run time
+    fn parse_include_str(&mut self) -> Result<ASTNode, Error> {
+        self.expect(Token::IncludeStr)?;
+        self.expect(Token::LParen)?;
+        let position = self.position();
+        let Token::String(path) = self.advance()? else {
+            return Err(Error::ParserError(format!("include_str takes a path in a string literal at {}", position)));
+        };
+        self.expect(Token::RParen)?;
+        let full_path = match &self.base_dir {
+            Some(dir) => dir.join(&path),
+            None => PathBuf::from(&path),
+        };
+        let contents = std::fs::read_to_string(&full_path)
+            .map_err(|e| Error::FileNotFound(format!("include_str cannot read '{}' at {}: {}", path, position, e)))?;
+        crate::summary::read(&full_path);
+        Ok(ASTNode::String(contents))
+    }
+
     fn parse_import(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Import)?;
-        self.eat(Token::LParen)?;
+        self.expect(Token::Import)?;
+        self.expect(Token::LParen)?;
         
-        let lib_name = if let Token::Identifier(name) = self.current_token.clone() {
-            self.eat(Token::Identifier(name.clone()))?;
-            name
+        let lib_name = self.expect_identifier("library name")?;
+
+        // import(mylib >= "1.0");
+        let requirement = if Self::is_comparison(&self.current_token) && self.current_token != Token::NotEqual {
+            let op = self.advance()?;
+            if let Token::String(_) = self.current_token {
+                let Token::String(version) = self.advance()? else { unreachable!() };
+                Some((op, version))
+            } else {
+                return Err(Error::ParserError(format!("Expected a version string after {:?} at {}", op, self.position())));
+            }
         } else {
-            return Err(Error::ParserError("Expected library name".to_string()));
+            None
         };
 
         let mode = if self.current_token == Token::Comma {
-            self.eat(Token::Comma)?;
+            self.expect(Token::Comma)?;
             match self.current_token {
                 Token::Embedded => {
-                    self.eat(Token::Embedded)?;
+                    self.expect(Token::Embedded)?;
                     Some("embedded".to_string())
                 }
                 Token::External => {
-                    self.eat(Token::External)?;
+                    self.expect(Token::External)?;
                     Some("external".to_string())
                 }
-                _ => return Err(Error::ParserError("Expected 'embedded' or 'external'".to_string()))
+                _ => return Err(Error::ParserError(format!("Expected 'embedded' or 'external' at {}", self.position())))
             }
         } else {
             None
         };
 
-        self.eat(Token::RParen)?;
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::RParen)?;
+        self.expect(Token::Semicolon)?;
 
-        Ok(ASTNode::Import(lib_name, mode))
+        Ok(ASTNode::Import(lib_name, mode, requirement))
     }
     /*     
     fn parse_library_access(&mut self) -> Result<ASTNode, Error> {
         let lib_name = if let Token::Identifier(name) = self.current_token.clone() {
-            self.eat(Token::Identifier(name.clone()))?;
+            self.expect(Token::Identifier(name.clone()))?;
             name
         } else {
             return Err(Error::ParserError("Expected library name".to_string()));
         };
 
-        self.eat(Token::Dot)?;
+        self.expect(Token::Dot)?;
 
         let item_name = if let Token::Identifier(name) = self.current_token.clone() {
-            self.eat(Token::Identifier(name.clone()))?;
+            self.expect(Token::Identifier(name.clone()))?;
             name
         } else {
             return Err(Error::ParserError("Expected function/constant name".to_string()));
@@ -208,16 +355,115 @@ impl<'a> Parser<'a> {
         false
     }
 
-    fn eat(&mut self, token: Token) -> Result<(), Error> {
-        if self.current_token == token {
-            self.current_token = self.lexer.next_token()?;
-            Ok(())
+    // moves to the next token and hands back the one that was current
+    fn advance(&mut self) -> Result<Token, Error> {
+        match self.current_token {
+            Token::LBrace => self.open_braces.push(self.current_line),
+            Token::RBrace => { self.open_braces.pop(); }
+            _ => {}
+        }
+        let next = self.lexer.next_token()?;
+        let token = std::mem::replace(&mut self.current_token, next);
+        self.previous_kind = discriminant(&token);
+        self.previous_line = self.current_line;
+        self.current_line = self.lexer.token_line;
+        self.current_column = self.lexer.token_column;
+        Ok(token)
+    }
+
+    // Advances past a token of the given kind, comparing only the kind, so
+    // expect(Token::Number(0)) takes any number and returns it
+    fn expect(&mut self, kind: Token) -> Result<Token, Error> {
+        if discriminant(&self.current_token) == discriminant(&kind) {
+            self.advance()
         } else {
-            Err(Error::ParserError(format!("Unexpected token: {:?}, expected: {:?} at line {}", self.current_token, token, self.lexer.line)))
+            Err(self.unexpected(
+                format!("Unexpected token: {:?}, expected: {:?} at {}", self.current_token, kind, self.position()),
+                Some(&kind),
+            ))
         }
     }
 
+    // an identifier's name, or an error saying what the name was for
+    fn expect_identifier(&mut self, what: &str) -> Result<String, Error> {
+        match self.current_token {
+            Token::Identifier(_) => match self.advance()? {
+                Token::Identifier(name) => Ok(name),
+                _ => unreachable!(),
+            },
+            _ => match keyword_text(&self.current_token) {
+                Some(word) => Err(self.reserved_word(word, what)),
+                None => Err(self.unexpected(format!("Expected {} at {}", what, self.position()), None)),
+            },
+        }
+    }
+
+    // the name after `lib.`, where keywords are fine: std.print, std.len
+    fn expect_member_name(&mut self) -> Result<String, Error> {
+        match keyword_text(&self.current_token) {
+            Some(word) => {
+                self.advance()?;
+                Ok(word.to_string())
+            }
+            None => self.expect_identifier("identifier after dot"),
+        }
+    }
+
+    // the token n places after the current one
+    fn peek(&mut self, n: usize) -> Result<&Token, Error> {
+        self.lexer.peek_token(n)
+    }
+
+    // A syntax error, with a hint when the tokens around it look like a
+    // common beginner mistake
+    fn unexpected(&self, message: String, expected: Option<&Token>) -> Error {
+        match self.hint(expected) {
+            Some(hint) => Error::ParserError(format!("{}\nhint: {}", message, hint)),
+            None => Error::ParserError(message),
+        }
+    }
+
+    fn hint(&self, expected: Option<&Token>) -> Option<String> {
+        let after_print = self.previous_kind == discriminant(&Token::Print);
+        match (after_print, &self.current_token, expected) {
+            (_, Token::EOF, _) if !self.open_braces.is_empty() => Some(format!(
+                "the '{{' opened at line {} is never closed", self.open_braces.last().unwrap()
+            )),
+            (true, _, Some(Token::LParen)) => Some("print needs parentheses: print(value);".to_string()),
+            (_, Token::Assign, Some(Token::RParen)) => Some("'=' assigns a value; use '==' to compare".to_string()),
+            (_, Token::Elif | Token::Else, _) => Some(format!(
+                "'{}' has to come right after the closing '}}' of an if block",
+                if self.current_token == Token::Elif { "elif" } else { "else" }
+            )),
+            (_, _, Some(Token::Semicolon)) if self.current_line > self.previous_line => Some(format!(
+                "missing ';' at the end of line {}", self.previous_line
+            )),
+            _ => None,
+        }
+    }
+
+    // where the current token starts, as "line 3, column 7"
+    fn position(&self) -> String {
+        format!("line {}, column {}", self.current_line, self.current_column)
+    }
+
+    // runs one level of nested parsing, failing cleanly past MAX_NESTING
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth >= MAX_NESTING {
+            return Err(Error::ParserError(format!(
+                "Expression or block nested more than {} levels deep at {}", MAX_NESTING, self.position()
+            )));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
     pub fn parse(&mut self) -> Result<Vec<ASTNode>, Error> {
+        if let Some(e) = self.first_error.take() {
+            return Err(e);
+        }
         let mut ast_nodes = Vec::new();
         while self.current_token != Token::EOF {
             ast_nodes.push(self.parse_statement()?);
@@ -225,56 +471,127 @@ impl<'a> Parser<'a> {
         Ok(ast_nodes)
     }
 
+    // a single expression with an optional trailing semicolon, for the REPL
+    pub fn parse_expression(&mut self) -> Result<ASTNode, Error> {
+        if let Some(e) = self.first_error.take() {
+            return Err(e);
+        }
+        let expr = self.parse_expr()?;
+        if self.current_token == Token::Semicolon {
+            self.expect(Token::Semicolon)?;
+        }
+        self.expect(Token::EOF)?;
+        Ok(expr)
+    }
+
 
     fn parse_function_decl(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Func)?;
+        self.expect(Token::Func)?;
         
-        let name = if let Token::Identifier(name) = self.current_token.clone() {
-            self.eat(Token::Identifier(name.clone()))?;
-            name
-        } else {
-            return Err(Error::ParserError("Expected function name".to_string()));
-        };
+        let name = self.expect_identifier("function name")?;
 
         if Self::is_keyword(&name) {
-            return Err(Error::SyntaxError(format!("Cannot use keyword '{}' as function name", name)));
+            return Err(Error::SyntaxError(format!("Cannot use keyword '{}' as function name at {}", name, self.position())));
         }
 
-        self.eat(Token::LParen)?;
+        self.expect(Token::LParen)?;
         
+        let line = self.current_line;
         let mut params = Vec::new();
+        let mut bindings = Vec::new();
         while self.current_token != Token::RParen {
-            if let Token::Identifier(param) = self.current_token.clone() {
-                params.push(param.clone());
-                self.eat(Token::Identifier(param))?;
-                
-                if self.current_token == Token::Comma {
-                    self.eat(Token::Comma)?;
-                }
+            if matches!(self.current_token, Token::LBracket | Token::LBrace) {
+                let param = format!("__arg{}", params.len() + 1);
+                bindings.extend(self.parse_param_pattern(&param)?);
+                params.push(param);
             } else {
-                return Err(Error::ParserError("Expected parameter name".to_string()));
+                params.push(self.expect_identifier("parameter name")?);
+            }
+            if self.current_token == Token::Comma {
+                self.expect(Token::Comma)?;
             }
         }
         
-        self.eat(Token::RParen)?;
-        self.eat(Token::LBrace)?;
+        self.expect(Token::RParen)?;
+        self.expect(Token::LBrace)?;
         
         self.push_scope(true, false);
-        
-        let mut body = Vec::new();
-        while self.current_token != Token::RBrace {
-            body.push(self.parse_statement()?);
+
+        // a pattern's names become `var`s at the top of the body, after a
+        // docstring if there is one
+        let mut prologue = Vec::new();
+        for (binding, value) in bindings {
+            if self.current_scope().variables.contains_key(&binding) {
+                return Err(Error::VariableAlreadyDeclared(format!(
+                    "'{}' is bound twice in the parameters of '{}' at {}", binding, name, self.position()
+                )));
+            }
+            self.current_scope_mut().variables.insert(binding.clone(), true);
+            let var = ASTNode::Var(binding, Some(Box::new(value)), true);
+            prologue.push(if self.record_lines { ASTNode::Located(line, Box::new(var)) } else { var });
         }
         
+        let mut body = self.parse_block()?;
+        let docstring = match body.first() {
+            Some(ASTNode::Located(_, node)) => matches!(node.as_ref(), ASTNode::String(_)),
+            first => matches!(first, Some(ASTNode::String(_))),
+        };
+        let at = usize::from(docstring);
+        body.splice(at..at, prologue);
+
         self.pop_scope();
         
-        self.eat(Token::RBrace)?;
+        self.expect(Token::RBrace)?;
         
         Ok(ASTNode::FunctionDecl(name, params, body))
     }
 
+    // `[first, ...rest]` or `{x, y}` in a parameter list, received by param:
+    // the names it binds and how each is read from param. Array patterns
+    // index and slice, so they take strings too; an element that isn't there
+    // is an error. Key patterns read [key, value] pairs, and a missing key
+    // is null.
+    fn parse_param_pattern(&mut self, param: &str) -> Result<Vec<(String, ASTNode)>, Error> {
+        let value = || Box::new(ASTNode::Identifier(param.to_string()));
+        let mut bindings = Vec::new();
+        if self.current_token == Token::LBrace {
+            self.expect(Token::LBrace)?;
+            while self.current_token != Token::RBrace {
+                let key = self.expect_identifier("key name")?;
+                let read = ASTNode::LibraryFunctionCall(
+                    "std".to_string(), "get_path".to_string(), vec![*value(), ASTNode::String(key.clone())],
+                );
+                bindings.push((key, read));
+                if self.current_token != Token::RBrace {
+                    self.expect(Token::Comma)?;
+                }
+            }
+            self.expect(Token::RBrace)?;
+            return Ok(bindings);
+        }
+        self.expect(Token::LBracket)?;
+        while self.current_token != Token::RBracket {
+            let index = Box::new(ASTNode::Number(bindings.len() as i32));
+            if self.current_token == Token::Ellipsis {
+                self.expect(Token::Ellipsis)?;
+                let rest = self.expect_identifier("parameter name")?;
+                if self.current_token != Token::RBracket {
+                    return Err(Error::SyntaxError(format!("'...{}' has to be the last name in the pattern at {}", rest, self.position())));
+                }
+                bindings.push((rest, ASTNode::Slice(value(), Some(index), None, None)));
+                break;
+            }
+            bindings.push((self.expect_identifier("parameter name")?, ASTNode::Index(value(), index)));
+            if self.current_token != Token::RBracket {
+                self.expect(Token::Comma)?;
+            }
+        }
+        self.expect(Token::RBracket)?;
+        Ok(bindings)
+    }
+
     fn parse_return(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Return)?;
+        self.expect(Token::Return)?;
         
         let expr = if self.current_token != Token::Semicolon {
             Some(Box::new(self.parse_expr()?))
@@ -282,12 +599,27 @@ impl<'a> Parser<'a> {
             None
         };
         
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::Semicolon)?;
         
         Ok(ASTNode::Return(expr))
     }
     
     fn parse_statement(&mut self) -> Result<ASTNode, Error> {
+        let line = self.current_line;
+        let statement = self.parse_bare_statement()?;
+        if self.record_lines {
+            Ok(ASTNode::Located(line, Box::new(statement)))
+        } else {
+            Ok(statement)
+        }
+    }
+
+    fn parse_bare_statement(&mut self) -> Result<ASTNode, Error> {
+        if let Some(word) = keyword_text(&self.current_token) {
+            if *self.peek(0)? == Token::Assign {
+                return Err(self.reserved_word(word, "variable name"));
+            }
+        }
         match &self.current_token {
             Token::Var | Token::NoVar => self.parse_var_decl(),
             Token::Print => self.parse_print(),
@@ -301,125 +633,129 @@ impl<'a> Parser<'a> {
             Token::Return => self.parse_return(),
             Token::Del => {
                 let node = self.parse_del()?;
-                self.eat(Token::Semicolon)?;
+                self.expect(Token::Semicolon)?;
                 Ok(node)
             },
             Token::Import => self.parse_import(),
+            Token::Global => self.parse_global(),
             Token::Number(_) | Token::String(_) | Token::Float(_) | 
             Token::Boolean(_) | Token::LBracket => {
                 let expr = self.parse_expr()?;
                 if self.current_token == Token::Semicolon {
-                    self.eat(Token::Semicolon)?;
+                    self.expect(Token::Semicolon)?;
                 }
                 Ok(expr)
             },
-            Token::Identifier(name) => {
-                let name = name.clone();
-                self.eat(Token::Identifier(name.clone()))?;
+            Token::Identifier(_) => {
+                let name = self.expect_identifier("name")?;
                 
                 match &self.current_token {
                     Token::LParen => {
-                        self.eat(Token::LParen)?;
-                        let mut args = Vec::new();
-                        
-                        if self.current_token != Token::RParen {
-                            loop {
-                                args.push(self.parse_expr()?);
-                                if self.current_token == Token::Comma {
-                                    self.eat(Token::Comma)?;
-                                } else {
-                                    break;
-                                }
-                            }
-                        }
-                        
-                        self.eat(Token::RParen)?;
-                        self.eat(Token::Semicolon)?;
+                        let args = self.parse_call_args()?;
+                        self.expect(Token::Semicolon)?;
                         Ok(ASTNode::FunctionCall(name, args))
                     },
                     Token::Assign => {
-                        self.eat(Token::Assign)?;
+                        self.expect(Token::Assign)?;
                         let value = self.parse_expr()?;
-                        self.eat(Token::Semicolon)?;
+                        self.expect(Token::Semicolon)?;
                         Ok(ASTNode::Assign(name, Box::new(value)))
                     },
                     Token::LBracket => {
-                        self.eat(Token::LBracket)?;
-                        let index = self.parse_expr()?;
-                        self.eat(Token::RBracket)?;
-                        self.eat(Token::Assign)?;
+                        let (target, index) = self.parse_index_target(ASTNode::Identifier(name))?;
+                        self.expect(Token::Assign)?;
                         let value = self.parse_expr()?;
-                        self.eat(Token::Semicolon)?;
-                        Ok(ASTNode::IndexAssign(
-                            Box::new(ASTNode::Identifier(name)),
-                            Box::new(index),
-                            Box::new(value)
-                        ))
+                        self.expect(Token::Semicolon)?;
+                        Ok(ASTNode::IndexAssign(Box::new(target), Box::new(index), Box::new(value)))
                     },
                     Token::Dot => {
-                        self.eat(Token::Dot)?;
-                        let item_name = if let Token::Identifier(name) = self.current_token.clone() {
-                            self.eat(Token::Identifier(name.clone()))?;
-                            name
-                        } else {
-                            return Err(Error::ParserError("Expected identifier after dot".to_string()));
-                        };
+                        self.expect(Token::Dot)?;
+                        let item_name = self.expect_member_name()?;
     
                         if self.current_token == Token::LParen {
-                            self.eat(Token::LParen)?;
-                            let mut args = Vec::new();
-                            
-                            if self.current_token != Token::RParen {
-                                loop {
-                                    args.push(self.parse_expr()?);
-                                    if self.current_token == Token::Comma {
-                                        self.eat(Token::Comma)?;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                            }
-                            
-                            self.eat(Token::RParen)?;
-                            self.eat(Token::Semicolon)?;
+                            let args = self.parse_call_args()?;
+                            self.expect(Token::Semicolon)?;
                             Ok(ASTNode::LibraryFunctionCall(name, item_name, args))
+                        } else if self.current_token == Token::Assign {
+                            self.expect(Token::Assign)?;
+                            let value = self.parse_expr()?;
+                            self.expect(Token::Semicolon)?;
+                            Ok(ASTNode::LibraryAssign(name, item_name, Box::new(value)))
                         } else {
-                            self.eat(Token::Semicolon)?;
+                            self.expect(Token::Semicolon)?;
                             Ok(ASTNode::LibraryAccess(name, item_name))
                         }
                     },
-                    _ => Err(Error::ParserError(format!(
-                        "Unexpected token after identifier: {:?} at line {}", 
+                    _ => Err(self.unexpected(format!(
+                        "Unexpected token after identifier: {:?} at {}", 
                         self.current_token, 
-                        self.lexer.line
-                    ))),
+                        self.position()
+                    ), None)),
                 }
             },
-            _ => Err(Error::ParserError(format!(
-                "Unexpected token in statement: {:?} at line {}", 
+            _ => Err(self.unexpected(format!(
+                "Unexpected token in statement: {:?} at {}", 
                 self.current_token, 
-                self.lexer.line
-            ))),
+                self.position()
+            ), None)),
         }
     }
 
-
     fn is_keyword(name: &str) -> bool {
-        matches!(name, 
-            "var" | "novar" | "print" | "type" | "if" | "elif" | "else" | 
-            "null" | "true" | "false" | "for" | "while" | "break" | "continue" |
-            "int" | "str" | "float" | "bool" | "func" | "return"
-        )
+        KEYWORDS.contains(&name)
+    }
+
+    // `var len = 1;` and the like: says the word is reserved and offers
+    // names that are free
+    fn reserved_word(&self, word: &str, what: &str) -> Error {
+        let alternatives = match word {
+            "len" => vec!["length", "size"],
+            "type" => vec!["kind", "type_name"],
+            "input" => vec!["text", "user_input"],
+            "str" => vec!["text", "s"],
+            "int" => vec!["n", "number"],
+            "float" => vec!["x", "value"],
+            "bool" => vec!["flag", "is_set"],
+            "print" => vec!["show", "output"],
+            "del" => vec!["remove", "delete"],
+            "import" => vec!["load", "module"],
+            "global" => vec!["shared", "total"],
+            _ => vec![],
+        };
+        let mut alternatives: Vec<String> = alternatives.into_iter().map(str::to_string).collect();
+        alternatives.push(format!("{}_", word));
+        let alternatives: Vec<String> = alternatives.iter().map(|name| format!("'{}'", name)).collect();
+        Error::SyntaxError(format!(
+            "'{}' is a reserved word and can't be used as a {} at {}; try {} instead",
+            word, what, self.position(), alternatives.join(", ")
+        ))
+    }
+
+    // global a, b;
+    fn parse_global(&mut self) -> Result<ASTNode, Error> {
+        self.expect(Token::Global)?;
+
+        let mut names = Vec::new();
+        loop {
+            names.push(self.expect_identifier("variable name")?);
+            if self.current_token == Token::Comma {
+                self.expect(Token::Comma)?;
+            } else {
+                break;
+            }
+        }
+        self.expect(Token::Semicolon)?;
+        Ok(ASTNode::Global(names))
     }
 
     fn parse_del(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Del)?;
-        self.eat(Token::LParen)?;
+        self.expect(Token::Del)?;
+        self.expect(Token::LParen)?;
         if let Token::Identifier(name) = self.current_token.clone() {
             self.remove_from_sym_table(&name);
         }
         let expr = self.parse_expr()?;
-        self.eat(Token::RParen)?;
+        self.expect(Token::RParen)?;
         Ok(ASTNode::DelCall(Box::new(expr)))
     }
 
@@ -427,12 +763,12 @@ impl<'a> Parser<'a> {
     fn parse_assign_stmt_with_node(&mut self, left: ASTNode) -> Result<ASTNode, Error> {
         if let ASTNode::Identifier(name) = &left { //check for array first
             if self.current_token == Token::LBracket {
-                self.eat(Token::LBracket)?;
+                self.expect(Token::LBracket)?;
                 let index = self.parse_expr()?;
-                self.eat(Token::RBracket)?;
-                self.eat(Token::Assign)?;
+                self.expect(Token::RBracket)?;
+                self.expect(Token::Assign)?;
                 let value = self.parse_expr()?;
-                self.eat(Token::Semicolon)?;
+                self.expect(Token::Semicolon)?;
                 return Ok(ASTNode::IndexAssign(
                     Box::new(ASTNode::Identifier(name.clone())),
                     Box::new(index),
@@ -442,9 +778,9 @@ impl<'a> Parser<'a> {
         }
         match left { //then function.
             ASTNode::Identifier(name) => {
-                self.eat(Token::Assign)?;
+                self.expect(Token::Assign)?;
                 let value = self.parse_expr()?;
-                self.eat(Token::Semicolon)?;
+                self.expect(Token::Semicolon)?;
                 Ok(ASTNode::Assign(name, Box::new(value)))
             },
             _ => Err(Error::ParserError("Invalid assignment target".to_string()))
@@ -453,25 +789,25 @@ impl<'a> Parser<'a> {
     */
 
     fn parse_type(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Type)?;
-        self.eat(Token::LParen)?;
+        self.expect(Token::Type)?;
+        self.expect(Token::LParen)?;
         let expr = self.parse_expr()?;
-        self.eat(Token::RParen)?;
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::RParen)?;
+        self.expect(Token::Semicolon)?;
         Ok(ASTNode::Type(Box::new(expr)))
     }
 
     fn parse_while_loop(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::While)?;
-        self.eat(Token::LParen)?;
+        self.expect(Token::While)?;
+        self.expect(Token::LParen)?;
         let condition = self.parse_expr()?;
-        self.eat(Token::RParen)?;
+        self.expect(Token::RParen)?;
         
         self.push_scope(false, true);
         
-        self.eat(Token::LBrace)?;
+        self.expect(Token::LBrace)?;
         let body = self.parse_block()?;
-        self.eat(Token::RBrace)?;
+        self.expect(Token::RBrace)?;
         
         self.pop_scope();
 
@@ -479,41 +815,46 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_if_statement(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::If)?;
-        self.eat(Token::LParen)?;
+        self.expect(Token::If)?;
+        self.expect(Token::LParen)?;
         let condition = self.parse_expr()?;
-        self.eat(Token::RParen)?;
-        self.eat(Token::LBrace)?;
+        self.expect(Token::RParen)?;
+        self.expect(Token::LBrace)?;
         let if_block = self.parse_block()?;
-        self.eat(Token::RBrace)?;
+        self.expect(Token::RBrace)?;
 
         let mut elif_blocks = Vec::new();
         let mut else_block = None;
 
         while self.current_token == Token::Elif {
-            self.eat(Token::Elif)?;
-            self.eat(Token::LParen)?;
+            self.expect(Token::Elif)?;
+            self.expect(Token::LParen)?;
             let elif_condition = self.parse_expr()?;
-            self.eat(Token::RParen)?;
-            self.eat(Token::LBrace)?;
+            self.expect(Token::RParen)?;
+            self.expect(Token::LBrace)?;
             let elif_statements = self.parse_block()?;
-            self.eat(Token::RBrace)?;
+            self.expect(Token::RBrace)?;
             elif_blocks.push((elif_condition, elif_statements));
         }
 
         if self.current_token == Token::Else {
-            self.eat(Token::Else)?;
-            self.eat(Token::LBrace)?;
+            self.expect(Token::Else)?;
+            self.expect(Token::LBrace)?;
             else_block = Some(self.parse_block()?);
-            self.eat(Token::RBrace)?;
+            self.expect(Token::RBrace)?;
         }
 
         Ok(ASTNode::If(Box::new(condition), if_block, elif_blocks, else_block))
     }
 
     fn parse_for_loop(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::For)?;
-        self.eat(Token::LParen)?;
+        self.expect(Token::For)?;
+        self.expect(Token::LParen)?;
+
+        // `in` is a word only here, so scripts can still use it as a name
+        if matches!(self.current_token, Token::Identifier(_)) && matches!(self.peek(0)?, Token::Identifier(word) if word == "in") {
+            return self.parse_for_each();
+        }
 
         self.push_scope(false, true);
 
@@ -524,206 +865,176 @@ impl<'a> Parser<'a> {
         };
 
         let condition = self.parse_expr()?;
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::Semicolon)?;
 
         let update = self.parse_assign_stmt()?;
-        self.eat(Token::RParen)?;
+        self.expect(Token::RParen)?;
 
-        self.eat(Token::LBrace)?;
+        self.expect(Token::LBrace)?;
         let body = self.parse_block()?;
-        self.eat(Token::RBrace)?;
+        self.expect(Token::RBrace)?;
 
         self.pop_scope();
 
         Ok(ASTNode::For(Box::new(init), Box::new(condition), Box::new(update), body))
     }
 
+    // for (x in values) { ... }, from the loop variable on
+    fn parse_for_each(&mut self) -> Result<ASTNode, Error> {
+        let name = self.expect_identifier("loop variable")?;
+        self.advance()?;
+        let values = self.parse_expr()?;
+        self.expect(Token::RParen)?;
+
+        self.push_scope(false, true);
+        self.current_scope_mut().variables.insert(name.clone(), true);
+
+        self.expect(Token::LBrace)?;
+        let body = self.parse_block()?;
+        self.expect(Token::RBrace)?;
+
+        self.pop_scope();
+
+        Ok(ASTNode::ForEach(name, Box::new(values), body))
+    }
+
     fn parse_break(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Break)?;
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::Break)?;
+        self.expect(Token::Semicolon)?;
         Ok(ASTNode::Break)
     }
 
     fn parse_continue(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Continue)?;
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::Continue)?;
+        self.expect(Token::Semicolon)?;
         Ok(ASTNode::Continue)
     }
 
     fn parse_block(&mut self) -> Result<Vec<ASTNode>, Error> {
-        let mut statements = Vec::new();
-        while self.current_token != Token::RBrace {
-            statements.push(self.parse_statement()?);
-        }
-        Ok(statements)
+        self.nested(|parser| {
+            let mut statements = Vec::new();
+            while parser.current_token != Token::RBrace {
+                statements.push(parser.parse_statement()?);
+            }
+            Ok(statements)
+        })
     }
 
     fn parse_expr(&mut self) -> Result<ASTNode, Error> {
-        self.parse_logical_or()
-    }
-
-    fn parse_logical_or(&mut self) -> Result<ASTNode, Error> {
-        let mut node = self.parse_logical_and()?;
-
-        while self.current_token == Token::Or {
-            let op = self.current_token.clone();
-            self.eat(Token::Or)?;
-            let right = self.parse_logical_and()?;
-            node = ASTNode::BinaryOp(Box::new(node), op, Box::new(right));
-        }
-
-        Ok(node)
+        self.nested(|parser| parser.parse_binary(0))
     }
 
-    fn parse_logical_and(&mut self) -> Result<ASTNode, Error> {
-        let mut node = self.parse_comparison()?;
-
-        while self.current_token == Token::And {
-            let op = self.current_token.clone();
-            self.eat(Token::And)?;
-            let right = self.parse_comparison()?;
-            node = ASTNode::BinaryOp(Box::new(node), op, Box::new(right));
+    // Binary operators, loosest first. A new operator only needs a row here
+    // (and a token); parse_binary works out the grouping from this table.
+    // (precedence, right associative)
+    pub fn infix_precedence(token: &Token) -> Option<(u8, bool)> {
+        match token {
+            Token::Or => Some((1, false)),
+            Token::And => Some((2, false)),
+            Token::Equal | Token::NotEqual | Token::Greater | Token::Less | Token::GreaterEqual | Token::LessEqual => Some((3, false)),
+            Token::Plus | Token::Minus => Some((4, false)),
+            Token::Multiply | Token::Divide | Token::FloorDivide | Token::Modulus => Some((5, false)),
+            // 2 ** 3 ** 2 is 2 ** 9, as in maths
+            Token::Power => Some((UNARY_MINUS_PRECEDENCE, true)),
+            _ => None,
         }
-
-        Ok(node)
     }
 
-    fn parse_comparison(&mut self) -> Result<ASTNode, Error> {
-        let mut node = self.parse_arithmetic()?;
+    // Pratt parsing: reads an operand, then keeps folding in operators that
+    // bind at least as tightly as min_precedence
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<ASTNode, Error> {
+        let mut node = self.parse_factor()?;
 
-        loop {
-            match &self.current_token {
-                Token::Equal | Token::NotEqual | Token::Greater | Token::Less | Token::GreaterEqual | Token::LessEqual => {
-                    let op = self.current_token.clone();
-                    self.eat(op.clone())?;
-                    let right = self.parse_arithmetic()?;
-                    node = ASTNode::BinaryOp(Box::new(node), op, Box::new(right));
-                }
-                _ => break,
+        while let Some((precedence, right_assoc)) = Self::infix_precedence(&self.current_token) {
+            if precedence < min_precedence {
+                break;
             }
-        }
-
-        Ok(node)
-    }
-
-    fn parse_arithmetic(&mut self) -> Result<ASTNode, Error> {
-        let mut node = self.parse_term()?;
+            let op = self.advance()?;
 
-        loop {
-            match &self.current_token {
-                Token::Plus | Token::Minus => {
-                    let op = self.current_token.clone();
-                    self.eat(op.clone())?;
-                    let right = self.parse_term()?;
-                    node = ASTNode::BinaryOp(Box::new(node), op, Box::new(right));
-                }
-                _ => break,
+            let next_min = if right_assoc { precedence } else { precedence + 1 };
+            let right = self.nested(|parser| parser.parse_binary(next_min))?;
+            if Self::is_comparison(&op) && Self::is_comparison(&self.current_token) {
+                node = self.parse_comparison_chain(node, op, right)?;
+                continue;
             }
+            node = ASTNode::BinaryOp(Box::new(node), op, Box::new(right));
         }
 
         Ok(node)
     }
 
-    fn parse_term(&mut self) -> Result<ASTNode, Error> {
-        let mut node = self.parse_power()?;
-
-        loop {
-            match &self.current_token {
-                Token::Multiply => {
-                    self.eat(Token::Multiply)?;
-                    let right = self.parse_power()?;
-                    node = ASTNode::BinaryOp(Box::new(node), Token::Multiply, Box::new(right));
-                }
-                Token::Divide => {
-                    self.eat(Token::Divide)?;
-                    if self.current_token == Token::Divide {
-                        self.eat(Token::Divide)?;
-                        let right = self.parse_power()?;
-                        node = ASTNode::BinaryOp(Box::new(node), Token::FloorDivide, Box::new(right));
-                    } else {
-                        let right = self.parse_power()?;
-                        node = ASTNode::BinaryOp(Box::new(node), Token::Divide, Box::new(right));
-                    }
-                }
-                Token::Modulus => {
-                    self.eat(Token::Modulus)?;
-                    let right = self.parse_power()?;
-                    node = ASTNode::BinaryOp(Box::new(node), Token::Modulus, Box::new(right));
-                }
-                _ => break,
-            }
-        }
-
-        Ok(node)
+    fn is_comparison(token: &Token) -> bool {
+        matches!(token, Token::Equal | Token::NotEqual | Token::Greater | Token::Less | Token::GreaterEqual | Token::LessEqual)
     }
 
-    fn parse_power(&mut self) -> Result<ASTNode, Error> {
-        let mut node = self.parse_factor()?;
-
-        while self.current_token == Token::Power {
-            let op = self.current_token.clone();
-            self.eat(Token::Power)?;
-            let right = self.parse_factor()?;
-            node = ASTNode::BinaryOp(Box::new(node), op, Box::new(right));
+    // 1 < x < 10 means 1 < x && x < 10 as in Python, with x evaluated once,
+    // rather than comparing the boolean 1 < x with 10
+    fn parse_comparison_chain(&mut self, first: ASTNode, op: Token, second: ASTNode) -> Result<ASTNode, Error> {
+        let mut operands = vec![first, second];
+        let mut operators = vec![op];
+        while Self::is_comparison(&self.current_token) {
+            let op = self.advance()?;
+            let (precedence, _) = Self::infix_precedence(&op).unwrap();
+            operands.push(self.nested(|parser| parser.parse_binary(precedence + 1))?);
+            operators.push(op);
         }
-
-        Ok(node)
+        Ok(ASTNode::Comparison(operands, operators))
     }
 
     fn parse_factor(&mut self) -> Result<ASTNode, Error> {
         match &self.current_token {
             Token::Input => {
-                self.eat(Token::Input)?;
-                self.eat(Token::LParen)?;
+                self.expect(Token::Input)?;
+                self.expect(Token::LParen)?;
                 let prompt = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 Ok(ASTNode::Input(Box::new(prompt)))
             },
             Token::Len => {
-                self.eat(Token::Len)?;
-                self.eat(Token::LParen)?;
+                self.expect(Token::Len)?;
+                self.expect(Token::LParen)?;
                 let expr = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 Ok(ASTNode::LenCall(Box::new(expr)))
             },
             Token::Del => {
-                self.eat(Token::Del)?;
-                self.eat(Token::LParen)?;
+                self.expect(Token::Del)?;
+                self.expect(Token::LParen)?;
                 let expr = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 Ok(ASTNode::DelCall(Box::new(expr)))
             },
             Token::Minus => {
-                self.eat(Token::Minus)?;
-                let factor = self.parse_factor()?;
-                Ok(ASTNode::BinaryOp(Box::new(ASTNode::Number(0)), Token::Minus, Box::new(factor)))
+                self.expect(Token::Minus)?;
+                let operand = self.nested(|parser| parser.parse_binary(UNARY_MINUS_PRECEDENCE))?;
+                Ok(ASTNode::UnaryOp(Token::Minus, Box::new(operand)))
             }
             Token::Number(val) => {
                 let num = *val;
-                self.eat(Token::Number(num))?;
+                self.advance()?;
                 Ok(ASTNode::Number(num))
             }
             Token::Not => {
-                self.eat(Token::Not)?;
-                let factor = self.parse_factor()?;
+                self.expect(Token::Not)?;
+                let factor = self.nested(Self::parse_factor)?;
                 Ok(ASTNode::UnaryOp(Token::Not, Box::new(factor)))
             },
             Token::Float(val) => {
                 let num = *val;
-                self.eat(Token::Float(num))?;
+                self.advance()?;
                 Ok(ASTNode::Float(num))
             },
             Token::LParen => {
-                self.eat(Token::LParen)?;
+                self.expect(Token::LParen)?;
                 let expr = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 Ok(expr)
             },
             Token::LBracket => self.parse_array_literal(),
-            Token::Identifier(_) | Token::String(_) | Token::Boolean(_) | Token::Null | Token::TypeLiteral(_) | Token::TypeCast(_) | Token::Type => {
+            Token::Identifier(_) | Token::String(_) | Token::Boolean(_) | Token::Null | Token::TypeLiteral(_) | Token::TypeCast(_) | Token::Type | Token::IncludeStr => {
                 self.parse_primary()
             },
-            _ => Err(Error::ParserError(format!("Unexpected token in factor: {:?} at line {}", self.current_token, self.lexer.line))),
+            _ => Err(self.unexpected(format!("Unexpected token in factor: {:?} at {}", self.current_token, self.position()), None)),
         }
     }
 
@@ -731,62 +1042,41 @@ impl<'a> Parser<'a> {
         let mut node = match &self.current_token {
             Token::Number(val) => {
                 let num = *val;
-                self.eat(Token::Number(num))?;
+                self.advance()?;
                 ASTNode::Number(num)
             }
             Token::Float(val) => {
                 let num = *val;
-                self.eat(Token::Float(num))?;
+                self.advance()?;
                 ASTNode::Float(num)
             }
-            Token::String(val) => {
-                let s = val.clone();
-                self.eat(Token::String(s.clone()))?;
-                ASTNode::String(s)
-            }
+            Token::String(_) => match self.advance()? {
+                Token::String(s) => ASTNode::String(s),
+                _ => unreachable!(),
+            },
             Token::Boolean(val) => {
                 let b = *val;
-                self.eat(Token::Boolean(b))?;
+                self.advance()?;
                 ASTNode::Boolean(b)
             }
             Token::Del => {
-                self.eat(Token::Del)?;
-                self.eat(Token::LParen)?;
+                self.expect(Token::Del)?;
+                self.expect(Token::LParen)?;
                 let expr = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 ASTNode::DelCall(Box::new(expr))
             }
-            Token::Identifier(var_name) => {
-                let name = var_name.clone();
-                self.eat(Token::Identifier(name.clone()))?;
+            Token::Identifier(_) => {
+                let name = self.expect_identifier("name")?;
                 
                 match self.current_token {
                     Token::Dot => {
-                        self.eat(Token::Dot)?;
-                        let item_name = if let Token::Identifier(name) = self.current_token.clone() {
-                            self.eat(Token::Identifier(name.clone()))?;
-                            name
-                        } else {
-                            return Err(Error::ParserError("Expected identifier after dot".to_string()));
-                        };
+                        self.expect(Token::Dot)?;
+                        let item_name = self.expect_member_name()?;
     
                         // check for lib
                         if self.current_token == Token::LParen {
-                            self.eat(Token::LParen)?;
-                            let mut args = Vec::new();
-                            
-                            if self.current_token != Token::RParen {
-                                loop {
-                                    args.push(self.parse_expr()?);
-                                    if self.current_token == Token::Comma {
-                                        self.eat(Token::Comma)?;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                            }
-                            
-                            self.eat(Token::RParen)?;
+                            let args = self.parse_call_args()?;
                             ASTNode::LibraryFunctionCall(name, item_name, args)
                         } else {
                             ASTNode::LibraryAccess(name, item_name)
@@ -794,52 +1084,36 @@ impl<'a> Parser<'a> {
                     },
                     Token::LParen => {
                         // reg func call
-                        self.eat(Token::LParen)?;
-                        let mut args = Vec::new();
-                        
-                        if self.current_token != Token::RParen {
-                            loop {
-                                args.push(self.parse_expr()?);
-                                if self.current_token == Token::Comma {
-                                    self.eat(Token::Comma)?;
-                                } else {
-                                    break;
-                                }
-                            }
-                        }
-                        
-                        self.eat(Token::RParen)?;
+                        let args = self.parse_call_args()?;
                         ASTNode::FunctionCall(name, args)
                     },
                     _ => ASTNode::Identifier(name)
                 }
             }
-            Token::TypeLiteral(type_name) => {
-                let name = type_name.clone();
-                self.eat(Token::TypeLiteral(name.clone()))?;
-                ASTNode::TypeLiteral(name)
-            }
-            Token::TypeCast(type_name) => {
-                self.parse_type_cast(type_name.clone())?
-            }
+            Token::TypeLiteral(_) => match self.advance()? {
+                Token::TypeLiteral(name) => ASTNode::TypeLiteral(name),
+                _ => unreachable!(),
+            },
+            Token::TypeCast(_) => self.parse_type_cast()?,
+            Token::IncludeStr => self.parse_include_str()?,
             Token::Null => {
-                self.eat(Token::Null)?;
+                self.expect(Token::Null)?;
                 ASTNode::Null
             }
             Token::LParen => {
-                self.eat(Token::LParen)?;
+                self.expect(Token::LParen)?;
                 let expr = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 expr
             }
             Token::Type => {
-                self.eat(Token::Type)?;
-                self.eat(Token::LParen)?;
+                self.expect(Token::Type)?;
+                self.expect(Token::LParen)?;
                 let expr = self.parse_expr()?;
-                self.eat(Token::RParen)?;
+                self.expect(Token::RParen)?;
                 ASTNode::Type(Box::new(expr))
             }
-            _ => return Err(Error::ParserError(format!("Unexpected token in primary: {:?} at line {}", self.current_token, self.lexer.line))),
+            _ => return Err(self.unexpected(format!("Unexpected token in primary: {:?} at {}", self.current_token, self.position()), None)),
         };
         while self.current_token == Token::LBracket {
             node = self.parse_index(node)?;
@@ -848,27 +1122,86 @@ impl<'a> Parser<'a> {
         Ok(node)
     }
 
+    // positional args first, then `name = value` keyword args
+    fn parse_call_args(&mut self) -> Result<Vec<ASTNode>, Error> {
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        let mut keywords: Vec<String> = Vec::new();
+
+        if self.current_token != Token::RParen {
+            loop {
+                if self.current_token == Token::Ellipsis {
+                    if !keywords.is_empty() {
+                        return Err(Error::SyntaxError(format!("Spread argument follows keyword argument at {}", self.position())));
+                    }
+                    self.expect(Token::Ellipsis)?;
+                    let expr = self.parse_expr()?;
+                    args.push(ASTNode::Spread(Box::new(expr)));
+                    if self.current_token == Token::Comma {
+                        self.expect(Token::Comma)?;
+                        continue;
+                    }
+                    break;
+                }
+
+                if matches!(self.current_token, Token::Identifier(_)) && *self.peek(0)? == Token::Assign {
+                    let key = self.expect_identifier("keyword argument name")?;
+                    if keywords.contains(&key) {
+                        return Err(Error::SyntaxError(format!("Keyword argument '{}' repeated at {}", key, self.position())));
+                    }
+                    self.expect(Token::Assign)?;
+                    let value = self.parse_expr()?;
+                    keywords.push(key.clone());
+                    args.push(ASTNode::KeywordArg(key, Box::new(value)));
+                } else {
+                    let arg = self.parse_expr()?;
+                    if self.current_token == Token::Assign {
+                        return Err(Error::SyntaxError(format!("Keyword argument name must be an identifier at {}", self.position())));
+                    }
+                    if !keywords.is_empty() {
+                        return Err(Error::SyntaxError(format!("Positional argument follows keyword argument at {}", self.position())));
+                    }
+                    args.push(arg);
+                }
+
+                if self.current_token == Token::Comma {
+                    self.expect(Token::Comma)?;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        self.expect(Token::RParen)?;
+        Ok(args)
+    }
+
     fn parse_array_literal(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::LBracket)?;
+        self.expect(Token::LBracket)?;
         let mut elements = Vec::new();
 
         if self.current_token != Token::RBracket {
             loop {
-                elements.push(self.parse_expr()?);
+                if self.current_token == Token::Ellipsis {
+                    self.expect(Token::Ellipsis)?;
+                    elements.push(ASTNode::Spread(Box::new(self.parse_expr()?)));
+                } else {
+                    elements.push(self.parse_expr()?);
+                }
                 if self.current_token == Token::Comma {
-                    self.eat(Token::Comma)?;
+                    self.expect(Token::Comma)?;
                 } else {
                     break;
                 }
             }
         }
 
-        self.eat(Token::RBracket)?;
+        self.expect(Token::RBracket)?;
         Ok(ASTNode::Array(elements))
     }
 
     fn parse_index(&mut self, expr: ASTNode) -> Result<ASTNode, Error> {
-        self.eat(Token::LBracket)?;
+        self.expect(Token::LBracket)?;
         
         // Check if it's a slice operation
         let first_expr = if self.current_token == Token::Colon {
@@ -878,7 +1211,7 @@ impl<'a> Parser<'a> {
         };
 
         if self.current_token == Token::Colon {
-            self.eat(Token::Colon)?;
+            self.expect(Token::Colon)?;
             
             // Parse stop
             let second_expr = if self.current_token == Token::Colon || self.current_token == Token::RBracket {
@@ -889,7 +1222,7 @@ impl<'a> Parser<'a> {
 
             // Parse step if present
             let third_expr = if self.current_token == Token::Colon {
-                self.eat(Token::Colon)?;
+                self.expect(Token::Colon)?;
                 if self.current_token == Token::RBracket {
                     None
                 } else {
@@ -899,21 +1232,21 @@ impl<'a> Parser<'a> {
                 None
             };
 
-            self.eat(Token::RBracket)?;
+            self.expect(Token::RBracket)?;
             return Ok(ASTNode::Slice(Box::new(expr), first_expr, second_expr, third_expr));
         }
 
         // Regular index access - fixed the double boxing issue
         let index = first_expr.unwrap();  // Already a Box<ASTNode>
-        self.eat(Token::RBracket)?;
+        self.expect(Token::RBracket)?;
         Ok(ASTNode::Index(Box::new(expr), index))
     }
 
-    fn parse_type_cast(&mut self, type_name: String) -> Result<ASTNode, Error> {
-        self.eat(Token::TypeCast(type_name.clone()))?;
-        self.eat(Token::LParen)?;
+    fn parse_type_cast(&mut self) -> Result<ASTNode, Error> {
+        let Token::TypeCast(type_name) = self.expect(Token::TypeCast(String::new()))? else { unreachable!() };
+        self.expect(Token::LParen)?;
         let expr = self.parse_expr()?;
-        self.eat(Token::RParen)?;
+        self.expect(Token::RParen)?;
         Ok(ASTNode::TypeCast(type_name, Box::new(expr)))
     }
 
@@ -921,70 +1254,266 @@ impl<'a> Parser<'a> {
         let is_mutable = match self.current_token {
             Token::Var => true,
             Token::NoVar => false,
-            _ => return Err(Error::ParserError(format!("Expected var or novar at line {}", self.lexer.line))),
+            _ => return Err(Error::ParserError(format!("Expected var or novar at {}", self.position()))),
         };
-        self.eat(self.current_token.clone())?;
+        self.advance()?;
 
-        let name = if let Token::Identifier(ident) = self.current_token.clone() {
-            self.eat(Token::Identifier(ident.clone()))?;
-            ident
-        } else {
-            return Err(Error::ParserError(format!("Expected identifier in variable declaration at line {}", self.lexer.line)));
-        };
+        let name = self.expect_identifier("variable name")?;
 
         if self.is_variable_declared(&name) {
-            return Err(Error::VariableAlreadyDeclared(format!("Variable '{}' has already been declared at line {}", name, self.lexer.line)));
+            return Err(Error::VariableAlreadyDeclared(format!("Variable '{}' has already been declared at {}", name, self.position())));
         }
 
         self.current_scope_mut().variables.insert(name.clone(), is_mutable);
 
         let initializer = if self.current_token == Token::Assign {
-            self.eat(Token::Assign)?;
+            self.expect(Token::Assign)?;
             Some(Box::new(self.parse_expr()?))
         } else {
             None
         };
 
-        self.eat(Token::Semicolon)?;
+        self.expect(Token::Semicolon)?;
         Ok(ASTNode::Var(name, initializer, is_mutable))
     }
 
     fn parse_assign_stmt(&mut self) -> Result<ASTNode, Error> {
-        let name = if let Token::Identifier(ident) = self.current_token.clone() {
-            self.eat(Token::Identifier(ident.clone()))?;
-            ident
+        let name = self.expect_identifier("variable name")?;
+
+        let target = if self.current_token == Token::LBracket {
+            Some(self.parse_index_target(ASTNode::Identifier(name.clone()))?)
         } else {
-            return Err(Error::ParserError(format!("Expected identifier in assignment at line {}", self.lexer.line)));
+            None
         };
 
-        let mut expr = ASTNode::Identifier(name.clone());
-        if self.current_token == Token::LBracket {
-            self.eat(Token::LBracket)?;
-            let index = self.parse_expr()?;
-            self.eat(Token::RBracket)?;
-            expr = ASTNode::Index(Box::new(expr), Box::new(index));
-        }
-
-        self.eat(Token::Assign)?;
+        self.expect(Token::Assign)?;
         let value = self.parse_expr()?;
 
         if self.current_token == Token::Semicolon {
-            self.eat(Token::Semicolon)?;
+            self.expect(Token::Semicolon)?;
         }
 
-        match expr {
-            ASTNode::Index(array, index) => Ok(ASTNode::IndexAssign(array, index, Box::new(value))),
-            _ => Ok(ASTNode::Assign(name, Box::new(value))),
+        match target {
+            Some((array, index)) => Ok(ASTNode::IndexAssign(Box::new(array), Box::new(index), Box::new(value))),
+            None => Ok(ASTNode::Assign(name, Box::new(value))),
+        }
+    }
+
+    // a[i][j] on the left of `=`: returns the array being written into (a[i])
+    // and the index written at (j)
+    fn parse_index_target(&mut self, mut target: ASTNode) -> Result<(ASTNode, ASTNode), Error> {
+        self.expect(Token::LBracket)?;
+        let mut index = self.parse_expr()?;
+        self.expect(Token::RBracket)?;
+        while self.current_token == Token::LBracket {
+            self.expect(Token::LBracket)?;
+            let next = self.parse_expr()?;
+            self.expect(Token::RBracket)?;
+            target = ASTNode::Index(Box::new(target), Box::new(index));
+            index = next;
         }
+        Ok((target, index))
     }
 
     fn parse_print(&mut self) -> Result<ASTNode, Error> {
-        self.eat(Token::Print)?;
-        self.eat(Token::LParen)?;
-        let expr = self.parse_expr()?;
-        self.eat(Token::RParen)?;
-        self.eat(Token::Semicolon)?;
-        Ok(ASTNode::Print(Box::new(expr)))
+        self.expect(Token::Print)?;
+        let args = self.parse_call_args()?;
+        self.expect(Token::Semicolon)?;
+        Ok(ASTNode::Print(args))
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> String {
+        match Parser::new(source).parse() {
+            Ok(ast) => panic!("expected a syntax error, got {:?}", ast),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn a_bad_first_token_is_an_error() {
+        for source in ["\"abc", "1.2.3;", "@x;"] {
+            assert!(parse_error(source).starts_with("LexerError"), "{}: {}", source, parse_error(source));
+            assert!(Parser::new(source).parse_expression().is_err());
+        }
+        assert!(parse_error("@x;").ends_with("at line 1, column 1"));
+        assert!(parse_error("var x = @;").ends_with("Unexpected character: @ at line 1, column 9"));
+    }
+
+    #[test]
+    fn values_stay_small() {
+        // a function's name, parameters and body don't make every int as big as they are
+        assert!(std::mem::size_of::<Value>() <= 40, "{}", std::mem::size_of::<Value>());
+    }
+
+    #[test]
+    fn common_mistakes_get_a_hint() {
+        assert!(parse_error("var a = 1\nprint(a);").ends_with("hint: missing ';' at the end of line 1"));
+        assert!(parse_error("var x = 1;\nif (x = 1) { print(x); }").ends_with("hint: '=' assigns a value; use '==' to compare"));
+        assert!(parse_error("var x = 1;\nelif (x == 1) { print(x); }").contains("'elif' has to come right after"));
+        assert!(parse_error("print x;").ends_with("hint: print needs parentheses: print(value);"));
+        assert!(parse_error("func f() {\n    if (true) {\n        print(1);\n    }\n").ends_with("hint: the '{' opened at line 1 is never closed"));
+
+        assert!(parse_error("var x = 1;\nif (x == 1) {\n    print(x)\n}").starts_with("ParserError: Unexpected token: RBrace, expected: Semicolon at line 4, column 1"));
+
+        // a missing token on the same line is not a missing semicolon
+        assert!(!parse_error("var a = 1 2;").contains("hint"));
+    }
+
+    #[test]
+    fn reserved_words_used_as_names_say_so() {
+        assert_eq!(
+            parse_error("var len = 3;"),
+            "SyntaxError: 'len' is a reserved word and can't be used as a variable name at line 1, column 5; try 'length', 'size', 'len_' instead"
+        );
+        assert!(parse_error("type = 2;").contains("'type' is a reserved word and can't be used as a variable name"));
+        assert!(parse_error("func input(prompt) { return 1; }").contains("'input' is a reserved word and can't be used as a function name"));
+        assert!(parse_error("func f(global) { return 1; }").contains("'global' is a reserved word and can't be used as a parameter name"));
+        assert!(parse_error("novar embedded = 1;").contains("try 'embedded_' instead"));
+    }
+
+    #[test]
+    fn keyword_args_follow_positional_ones() {
+        let ast = Parser::new("f(1, b = 2);").parse().unwrap();
+        match &ast[0] {
+            ASTNode::FunctionCall(name, args) => {
+                assert_eq!(name, "f");
+                assert!(matches!(args[0], ASTNode::Number(1)));
+                assert!(matches!(&args[1], ASTNode::KeywordArg(key, _) if key == "b"));
+            }
+            other => panic!("expected a call, got {:?}", other),
+        }
+        assert!(parse_error("f(a = 1, 2);").contains("Positional argument follows keyword argument"));
+        assert!(parse_error("f(a = 1, a = 2);").contains("Keyword argument 'a' repeated"));
+        assert!(parse_error("f(1 + 1 = 2);").contains("must be an identifier"));
+    }
+
+    // the expression with every operator's operands in parentheses
+    fn grouping(source: &str) -> String {
+        fn render_op(op: &Token) -> &'static str {
+            match op {
+                Token::Or => "||", Token::And => "&&",
+                Token::Equal => "==", Token::NotEqual => "!=",
+                Token::Less => "<", Token::LessEqual => "<=",
+                Token::Greater => ">", Token::GreaterEqual => ">=",
+                Token::Plus => "+", Token::Minus => "-",
+                Token::Multiply => "*", Token::Divide => "/",
+                Token::FloorDivide => "//", Token::Modulus => "%",
+                Token::Power => "**",
+                other => panic!("unexpected operator {:?}", other),
+            }
+        }
+
+        fn render(node: &ASTNode) -> String {
+            match node {
+                ASTNode::BinaryOp(left, op, right) => format!("({} {} {})", render(left), render_op(op), render(right)),
+                ASTNode::Comparison(operands, operators) => {
+                    let mut text = format!("({}", render(&operands[0]));
+                    for (op, operand) in operators.iter().zip(&operands[1..]) {
+                        text.push_str(&format!(" {} {}", render_op(op), render(operand)));
+                    }
+                    text + ")"
+                }
+                ASTNode::UnaryOp(Token::Not, operand) => format!("(!{})", render(operand)),
+                ASTNode::UnaryOp(Token::Minus, operand) => format!("(-{})", render(operand)),
+                ASTNode::Number(n) => n.to_string(),
+                ASTNode::Identifier(name) => name.clone(),
+                other => panic!("unexpected node {:?}", other),
+            }
+        }
+        render(&Parser::new(source).parse_expression().unwrap())
+    }
+
+    #[test]
+    fn a_poisoned_array_is_an_error_not_a_panic() {
+        let arr = Arc::new(Mutex::new(vec![Value::Number(1)]));
+        let held = Arc::clone(&arr);
+        let _ = std::thread::spawn(move || {
+            let _guard = held.lock().unwrap();
+            panic!("interrupted");
+        }).join();
+
+        assert!(lock_array(&arr).unwrap_err().to_string().contains("Array is unusable"));
+        assert_eq!(Value::Array(Arc::clone(&arr)).to_string(), "[1]");
+        assert_eq!(Value::Array(Arc::clone(&arr)), Value::Array(arr));
+    }
+
+    #[test]
+    fn operators_group_by_precedence_and_associativity() {
+        let cases = [
+            ("a || b && c", "(a || (b && c))"),
+            ("a && b || c", "((a && b) || c)"),
+            ("a == b && c != d", "((a == b) && (c != d))"),
+            ("a + b < c * d", "((a + b) < (c * d))"),
+            ("a < b < c", "(a < b < c)"),
+            ("a <= b + c == d > e", "(a <= (b + c) == d > e)"),
+            ("a < b && c < d < e", "((a < b) && (c < d < e))"),
+            ("a - b - c", "((a - b) - c)"),
+            ("a + b * c", "(a + (b * c))"),
+            ("a * b + c", "((a * b) + c)"),
+            ("a / b // c % d", "(((a / b) // c) % d)"),
+            ("a * b ** c", "(a * (b ** c))"),
+            ("a ** b ** c", "(a ** (b ** c))"),
+            ("(a + b) * c", "((a + b) * c)"),
+            ("!a && b", "((!a) && b)"),
+            ("-a ** b", "(-(a ** b))"),
+            ("-a * b", "((-a) * b)"),
+            ("a ** -b", "(a ** (-b))"),
+            ("a - -b ** c", "(a - (-(b ** c)))"),
+            ("-a ** b ** c", "(-(a ** (b ** c)))"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(grouping(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_not_a_crash() {
+        // test threads have a smaller stack than the one td runs scripts on
+        std::thread::Builder::new()
+            .stack_size(crate::interpreter::STACK_SIZE)
+            .spawn(check_deep_nesting)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn check_deep_nesting() {
+        let deep = format!("var x = {}1{};", "(".repeat(5000), ")".repeat(5000));
+        assert!(parse_error(&deep).contains("nested more than 128 levels deep at line 1"));
+        let deep = format!("var x = {}1{};", "[".repeat(5000), "]".repeat(5000));
+        assert!(parse_error(&deep).contains("nested more than 128 levels deep"));
+        assert!(parse_error(&format!("var x = {}1;", "-".repeat(5000))).contains("nested more than 128 levels deep"));
+        let blocks = format!("{}{}", "if (true) {\n".repeat(5000), "}".repeat(5000));
+        assert!(parse_error(&blocks).contains("nested more than 128 levels deep"));
+
+        let allowed = format!("var x = {}1{};", "[".repeat(127), "]".repeat(127));
+        assert!(Parser::new(&allowed).parse().is_ok());
+    }
+
+    #[test]
+    fn include_str_embeds_a_file_relative_to_the_script() {
+        let dir = std::env::temp_dir().join(format!("tidal_include_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("fixtures")).unwrap();
+        std::fs::write(dir.join("fixtures/greeting.txt"), "Hello, \"{name}\"\n").unwrap();
+
+        let ast = Parser::new("novar data = include_str(\"fixtures/greeting.txt\");").with_base_dir(&dir).parse().unwrap();
+        match &ast[0] {
+            ASTNode::Var(name, Some(value), false) => {
+                assert_eq!(name, "data");
+                assert!(matches!(value.as_ref(), ASTNode::String(text) if text == "Hello, \"{name}\"\n"));
+            }
+            other => panic!("expected a novar declaration, got {:?}", other),
+        }
+
+        let missing = Parser::new("print(include_str(\"nope.txt\"));").with_base_dir(&dir).parse().unwrap_err().to_string();
+        assert!(missing.contains("include_str cannot read 'nope.txt' at line 1, column 19"), "{}", missing);
+        assert!(parse_error("var path = \"a.txt\"; print(include_str(path));").contains("include_str takes a path in a string literal"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
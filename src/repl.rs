@@ -0,0 +1,289 @@
+use crate::docs;
+use crate::error::Error;
+use crate::input;
+use crate::interpreter::Session;
+use crate::parser::{ASTNode, Parser, Value};
+use crate::snapshot::{self, Snapshot};
+use std::fs;
+
+const META_HELP: &str = "\
+Commands:
+  :help [topic]    List documentation pages, or show one by name or number;
+                   a function or library like math.sqrt shows its signature
+  :vars            Show variables, functions and libraries defined so far
+  :type <expr>     Evaluate an expression and show its type
+  :load <file>     Run a script (.td, .br, .fr) or a .tds snapshot in this session
+  :save <file.tds> Save variables, functions and imports to load later
+  :clear           Forget everything defined so far
+  :quit            Leave the REPL (Ctrl-D works too)";
+
+// Runs the interactive prompt until :quit or end of input. Returns the exit
+// code, which is only non-zero when a script called exit(). --quiet drops
+// the banner.
+pub fn run(is_verbose: bool, is_quiet: bool) -> i32 {
+    if !is_quiet {
+        outln!("Tidal {} REPL - type :help for commands, :quit to leave", env!("CARGO_PKG_VERSION"));
+    }
+    let mut session = Session::new(is_verbose);
+
+    while let Some(source) = read_statement() {
+        let source = source.trim();
+        if source.is_empty() {
+            continue;
+        }
+
+        match run_line(&mut session, source) {
+            Ok(None) => break,
+            Ok(Some(Value::Null)) => {}
+            Ok(Some(value)) => outln!("{}", value),
+            Err(Error::Exit(code)) => return code,
+            Err(e) => crate::print_error(&e),
+        }
+    }
+    0
+}
+
+// Reads one line, or several while brackets are still open so blocks can be
+// typed across lines. None at end of input.
+fn read_statement() -> Option<String> {
+    let mut source = input::read_line(">>> ")?;
+    while !source.trim_start().starts_with(':') && open_brackets(&source) > 0 {
+        match input::read_line("... ") {
+            Some(line) => {
+                source.push('\n');
+                source.push_str(&line);
+            }
+            None => break,
+        }
+    }
+    Some(source)
+}
+
+fn open_brackets(source: &str) -> i32 {
+    let mut depth = 0;
+    let mut quote = None;
+    // strings have no escapes, so the next matching quote always closes one
+    for c in source.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '{' | '(' | '[' => depth += 1,
+                '}' | ')' | ']' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth
+}
+
+// one entry at the prompt: a :command, or statements whose value is echoed.
+// None when the REPL should stop
+fn run_line(session: &mut Session, line: &str) -> Result<Option<Value>, Error> {
+    match line.strip_prefix(':') {
+        Some(command) => Ok(meta_command(session, command)?.then_some(Value::Null)),
+        None => session.eval(parse(line)?).map(Some),
+    }
+}
+
+// statements as in a script, optionally ending in a bare expression such as
+// `x + 1` whose value is echoed; a trailing semicolon is optional at the prompt
+pub fn parse(source: &str) -> Result<Vec<ASTNode>, Error> {
+    let statements = if source.ends_with(';') || source.ends_with('}') {
+        source.to_string()
+    } else {
+        format!("{};", source)
+    };
+    Parser::new(&statements).parse().or_else(|e| {
+        // `var x = 2; x * 21`: the statements up to some ';' or '}', then the
+        // expression; a split inside a string just fails to parse
+        let splits = source.char_indices().rev().filter(|(_, c)| *c == ';' || *c == '}');
+        std::iter::once(0).chain(splits.map(|(i, _)| i + 1))
+            .find_map(|at| {
+                let mut ast = Parser::new(&source[..at]).parse().ok()?;
+                ast.push(Parser::new(&source[at..]).parse_expression().ok()?);
+                Some(ast)
+            })
+            .ok_or(e)
+    })
+}
+
+// Ok(false) when the REPL should stop
+fn meta_command(session: &mut Session, command: &str) -> Result<bool, Error> {
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+    };
+
+    match (name, arg) {
+        ("help" | "h", "") => {
+            outln!("{}", META_HELP);
+            if let Ok(pages) = docs::fetch_docs() {
+                docs::list_pages(&pages);
+            }
+        }
+        ("help" | "h", topic) => {
+            if let Some(text) = session.help(topic) {
+                outln!("{}", text);
+                return Ok(true);
+            }
+            let pages = docs::fetch_docs()
+                .map_err(|e| Error::InterpreterError(format!("Documentation unavailable: {}", e)))?;
+            match docs::find_page(&pages, topic) {
+                Some(page) => docs::print_page(page),
+                None if pages.is_empty() => docs::list_pages(&pages),
+                None => return Err(Error::InterpreterError(format!("No documentation page matches '{}'", topic))),
+            }
+        }
+        ("vars", "") => print_vars(session),
+        ("type", "") => return Err(Error::SyntaxError(":type requires an expression".to_string())),
+        ("type", expr) => outln!("{}", session.type_of(parse(expr)?)?),
+        ("load", "") => return Err(Error::SyntaxError(":load requires a file name".to_string())),
+        ("load", path) => load(session, path)?,
+        ("save", "") => return Err(Error::SyntaxError(":save requires a file name".to_string())),
+        ("save", path) => save(session, path)?,
+        ("clear", "") => {
+            session.clear();
+            outln!("Session cleared");
+        }
+        ("quit" | "q" | "exit", "") => return Ok(false),
+        _ => return Err(Error::SyntaxError(format!("Unknown command ':{}', try :help", command))),
+    }
+    Ok(true)
+}
+
+fn print_vars(session: &Session) {
+    let variables = session.variables();
+    let functions = session.functions();
+    let libraries = session.libraries();
+    if variables.is_empty() && functions.is_empty() && libraries.is_empty() {
+        outln!("Nothing defined yet");
+        return;
+    }
+
+    for (name, value, is_mutable) in variables {
+        let keyword = if is_mutable { "var" } else { "novar" };
+        outln!("{} {} = {}", keyword, name, value);
+    }
+    for (name, params, _) in functions {
+        outln!("func {}({})", name, params.join(", "));
+    }
+    if !libraries.is_empty() {
+        outln!("imported: {}", libraries.join(", "));
+    }
+}
+
+fn load(session: &mut Session, path: &str) -> Result<(), Error> {
+    let ast = if path.ends_with(".tds") {
+        let contents = fs::read_to_string(path).map_err(|e| Error::FileNotFound(format!("Failed to read '{}': {}", path, e)))?;
+        let dir = std::path::Path::new(path).parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+        Parser::new(&contents).with_base_dir(dir).parse()?
+    } else {
+        crate::parse_script(path, false, crate::dialect::Choice::ByExtension)?
+    };
+    session.eval(ast)?;
+    outln!("Loaded {}", path);
+    Ok(())
+}
+
+fn save(session: &Session, path: &str) -> Result<(), Error> {
+    if !path.ends_with(".tds") {
+        return Err(Error::InvalidFileExtension(format!("'{}' must have a .tds extension", path)));
+    }
+    let source = snapshot::write(&Snapshot {
+        libraries: &session.libraries(),
+        functions: &session.functions(),
+        variables: &session.variables(),
+    })?;
+    fs::write(path, source).map_err(|e| Error::InterpreterError(format!("Failed to write '{}': {}", path, e)))?;
+    outln!("Saved session to {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::CannedInput;
+    use crate::output::{self, CaptureOutput};
+
+    // what the lines print, with the prompt's output and the session's both captured
+    fn run_lines(lines: &[&str]) -> (String, Result<Option<Value>, Error>) {
+        let capture = CaptureOutput::new();
+        let previous = output::set_output(Box::new(capture.clone()));
+        let mut session = Session::with_io(false, Box::new(capture.clone()), Box::new(CannedInput::default()));
+        let mut result = Ok(Some(Value::Null));
+        for line in lines {
+            result = run_line(&mut session, line);
+        }
+        output::set_output(previous);
+        (capture.stdout(), result)
+    }
+
+    #[test]
+    fn help_lists_the_commands_and_shows_signatures() {
+        let (out, result) = run_lines(&[":help"]);
+        assert!(matches!(result, Ok(Some(Value::Null))));
+        assert!(out.starts_with(META_HELP), "{}", out);
+
+        let (out, _) = run_lines(&["import(math);", ":help math.sqrt"]);
+        assert!(out.contains("sqrt(x)"), "{}", out);
+        let (_, result) = run_lines(&[":help no such page"]);
+        assert!(result.unwrap_err().to_string().contains("No documentation page matches 'no such page'"));
+    }
+
+    #[test]
+    fn vars_lists_what_the_session_defined() {
+        let (out, _) = run_lines(&[":vars"]);
+        assert_eq!(out, "Nothing defined yet\n");
+        let (out, _) = run_lines(&["var count = 2;", "novar name = \"td\";", "func add(a, b) { return a + b; }", "import(math);", ":vars"]);
+        assert_eq!(out, "var count = 2\nnovar name = td\nfunc add(a, b)\nimported: math\n");
+    }
+
+    #[test]
+    fn type_names_the_type_of_an_expression() {
+        let (out, _) = run_lines(&["var xs = [1];", ":type xs", ":type 1.5 * 2"]);
+        assert_eq!(out, "array\nfloat\n");
+        let (_, result) = run_lines(&[":type missing"]);
+        assert!(matches!(result, Err(Error::VariableNotDeclared(_))), "{:?}", result);
+        let (_, result) = run_lines(&[":type"]);
+        assert!(result.unwrap_err().to_string().contains(":type requires an expression"));
+    }
+
+    #[test]
+    fn clear_forgets_everything() {
+        let (out, result) = run_lines(&["var count = 2;", ":clear", ":vars"]);
+        assert!(result.is_ok());
+        assert_eq!(out, "Session cleared\nNothing defined yet\n");
+        let (_, result) = run_lines(&["var total = 2;", ":clear", "total"]);
+        assert!(matches!(result, Err(Error::VariableNotDeclared(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn load_runs_a_script_in_the_session() {
+        let dir = std::env::temp_dir().join(format!("tidal-repl-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("setup.td");
+        fs::write(&script, "var loaded = 40;\nfunc more(x) { return x + 2; }\n").unwrap();
+        let load = format!(":load {}", script.display());
+        let (out, result) = run_lines(&[&load, "more(loaded)"]);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(out, format!("Loaded {}\n", script.display()));
+        assert_eq!(result.unwrap(), Some(Value::Number(42)));
+
+        let missing = dir.join("missing.td");
+        let (out, result) = run_lines(&[&format!(":load {}", missing.display())]);
+        assert_eq!(out, "");
+        assert!(result.unwrap_err().to_string().contains("missing.td"));
+        let (_, result) = run_lines(&[":load"]);
+        assert!(result.unwrap_err().to_string().contains(":load requires a file name"));
+    }
+
+    #[test]
+    fn quit_stops_and_unknown_commands_fail() {
+        assert!(matches!(run_lines(&[":quit"]).1, Ok(None)));
+        assert!(matches!(run_lines(&[":q"]).1, Ok(None)));
+        let (_, result) = run_lines(&[":frobnicate"]);
+        assert!(result.unwrap_err().to_string().contains("Unknown command ':frobnicate', try :help"));
+    }
+}
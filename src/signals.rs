@@ -0,0 +1,131 @@
+use crate::error::Error;
+use crate::parser::Value;
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use signal_hook::flag;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+// Script signal handlers. The OS-level handler only raises flags; the
+// interpreter runs the script function at its next node (see take_pending),
+// so handlers never execute in signal context. A handler runs on the thread
+// that installed it, whose variables it was written for.
+struct Handler {
+    pending: Arc<AtomicBool>,
+    function: Value,
+    // the installing thread's OWNER; gone once that thread has exited
+    owner: Weak<()>,
+    // set when the owner is gone, so the signal gets its default action again
+    orphaned: Arc<AtomicBool>,
+}
+
+lazy_static! {
+    static ref HANDLERS: Mutex<IndexMap<String, Handler>> = Mutex::new(IndexMap::new());
+    static ref ANY_PENDING: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+}
+
+thread_local! {
+    // dropped when the thread exits, which tells its handlers apart from live ones
+    static OWNER: Arc<()> = Arc::new(());
+}
+
+fn current_owner() -> Weak<()> {
+    OWNER.with(Arc::downgrade)
+}
+
+fn signal_number(name: &str) -> Option<c_int> {
+    use signal_hook::consts::*;
+    match name {
+        "INT" => Some(SIGINT),
+        "TERM" => Some(SIGTERM),
+        #[cfg(unix)]
+        "HUP" => Some(SIGHUP),
+        #[cfg(unix)]
+        "QUIT" => Some(SIGQUIT),
+        #[cfg(unix)]
+        "USR1" => Some(SIGUSR1),
+        #[cfg(unix)]
+        "USR2" => Some(SIGUSR2),
+        // the C runtime delivers Ctrl-Break console events as SIGBREAK
+        #[cfg(windows)]
+        "BREAK" => Some(21),
+        _ => None,
+    }
+}
+
+pub fn register(name: &str, function: Value) -> Result<(), Error> {
+    let name = name.trim_start_matches("SIG").to_uppercase();
+    let signal = signal_number(&name).ok_or_else(|| Error::LibraryError(format!("on_signal() unsupported signal '{}'", name)))?;
+
+    let mut handlers = HANDLERS.lock().unwrap();
+    if let Some(existing) = handlers.get_mut(&name) {
+        existing.function = function;
+        existing.owner = current_owner();
+        existing.orphaned.store(false, Ordering::Relaxed);
+        return Ok(());
+    }
+
+    let pending = Arc::new(AtomicBool::new(false));
+    let hook_error = |e: std::io::Error| Error::LibraryError(format!("on_signal() cannot handle '{}': {}", name, e));
+    // a second signal arriving before the script handled the first one kills
+    // the process, so a script stuck in a blocking call can still be stopped
+    flag::register_conditional_shutdown(signal, 128 + signal, Arc::clone(&pending)).map_err(hook_error)?;
+    flag::register(signal, Arc::clone(&pending)).map_err(hook_error)?;
+    flag::register(signal, Arc::clone(&ANY_PENDING)).map_err(hook_error)?;
+    let orphaned = Arc::new(AtomicBool::new(false));
+    flag::register_conditional_default(signal, Arc::clone(&orphaned)).map_err(hook_error)?;
+    handlers.insert(name, Handler { pending, function, owner: current_owner(), orphaned });
+    Ok(())
+}
+
+pub fn any_pending() -> bool {
+    ANY_PENDING.load(Ordering::Relaxed)
+}
+
+// (signal name, handler) for every signal raised since the last call whose
+// handler this thread installed; others stay pending for their own thread.
+// A handler whose thread has exited is cleared: its signal is dropped, and
+// the next one gets the default action, as if it had never been installed.
+pub fn take_pending() -> Vec<(String, Value)> {
+    if !ANY_PENDING.swap(false, Ordering::Relaxed) {
+        return Vec::new();
+    }
+    let current = current_owner();
+    let handlers = HANDLERS.lock().unwrap();
+    let mut taken = Vec::new();
+    for (name, handler) in handlers.iter() {
+        if handler.owner.strong_count() == 0 {
+            handler.orphaned.store(true, Ordering::Relaxed);
+            handler.pending.store(false, Ordering::Relaxed);
+        } else if !handler.owner.ptr_eq(&current) {
+            if handler.pending.load(Ordering::Relaxed) {
+                ANY_PENDING.store(true, Ordering::Relaxed);
+            }
+        } else if handler.pending.swap(false, Ordering::Relaxed) {
+            taken.push((name.clone(), handler.function.clone()));
+        }
+    }
+    taken
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn a_handler_whose_thread_exited_is_cleared() {
+        let function = Value::Function("on_usr1".into(), Arc::default(), Arc::default());
+        thread::spawn(move || register("USR1", function).unwrap()).join().unwrap();
+        signal_hook::low_level::raise(signal_hook::consts::SIGUSR1).unwrap();
+        assert!(any_pending());
+
+        assert!(take_pending().iter().all(|(name, _)| name != "USR1"));
+        let handlers = HANDLERS.lock().unwrap();
+        let handler = &handlers["USR1"];
+        // nothing left to set ANY_PENDING again for a thread that will never take it
+        assert!(!handler.pending.load(Ordering::Relaxed));
+        assert!(handler.orphaned.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::error::Error;
+use crate::lexer::Token;
+use crate::parser::{read_array, ASTNode, Value};
+use std::sync::{Arc, Mutex};
+
+// A .tds session snapshot is plain Tidal source: the imports, functions and
+// variables of a REPL session written back out, so :load can run it like any
+// script. Arrays shared between variables come back as separate copies.
+
+pub struct Snapshot<'a> {
+    pub libraries: &'a [String],
+    pub functions: &'a [(String, Vec<String>, Vec<ASTNode>)],
+    pub variables: &'a [(String, Value, bool)],
+}
+
+pub fn write(snapshot: &Snapshot) -> Result<String, Error> {
+    let mut out = String::from("/* Tidal session snapshot */\n");
+    for name in snapshot.libraries {
+        out.push_str(&format!("import({});\n", name));
+    }
+    // functions before variables, which may hold them
+    for (name, params, body) in snapshot.functions {
+        out.push_str(&statement(&ASTNode::FunctionDecl(name.clone(), params.clone(), body.clone()), 0)?);
+    }
+    for (name, value, is_mutable) in snapshot.variables {
+        let keyword = if *is_mutable { "var" } else { "novar" };
+        out.push_str(&format!("{} {} = {};\n", keyword, name, value_source(value, &mut Vec::new())?));
+    }
+    Ok(out)
+}
+
+// statements written back as a script, one per line
+pub fn program(nodes: &[ASTNode]) -> Result<String, Error> {
+    nodes.iter().map(|node| statement(node, 0)).collect()
+}
+
+fn value_source(value: &Value, parents: &mut Vec<*const Mutex<Vec<Value>>>) -> Result<String, Error> {
+    Ok(match value {
+        Value::Number(i32::MIN) => "(-2147483647 - 1)".to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Float(f) => float_source(*f),
+        Value::String(s) => string_source(s)?,
+        Value::Boolean(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        Value::Type(name) => name.to_string(),
+        Value::Array(arr) => {
+            if parents.contains(&Arc::as_ptr(arr)) {
+                return Err(Error::UnsupportedOperation("Cannot save an array that contains itself".to_string()));
+            }
+            parents.push(Arc::as_ptr(arr));
+            let elements = read_array(arr).iter()
+                .map(|element| value_source(element, parents))
+                .collect::<Result<Vec<_>, _>>()?;
+            parents.pop();
+            format!("[{}]", elements.join(", "))
+        }
+        // a script function by name, or a library function as lib.name
+        Value::Function(name, _, _) => name.to_string(),
+        Value::Break | Value::Continue | Value::ReturnValue(_) => {
+            return Err(Error::UnsupportedOperation("Cannot save a control-flow value".to_string()))
+        }
+    })
+}
+
+fn float_source(f: f64) -> String {
+    if f.is_nan() {
+        return "float(\"NaN\")".to_string();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "float(\"inf\")" } else { "float(\"-inf\")" }.to_string();
+    }
+    // Display never uses an exponent, so whole floats need a ".0" to read back as floats
+    let text = f.to_string();
+    if text.contains('.') { text } else { format!("{}.0", text) }
+}
+
+// strings have no escapes, so a quote inside one needs the triple-quoted form
+fn string_source(s: &str) -> Result<String, Error> {
+    if !s.contains('"') {
+        return Ok(format!("\"{}\"", s));
+    }
+    if !s.contains("\"\"\"") && !s.starts_with('"') && !s.ends_with('"') {
+        return Ok(format!("\"\"\"{}\"\"\"", s));
+    }
+    Err(Error::UnsupportedOperation(format!("Cannot save the string {:?}: it has quotes the source form cannot hold", s)))
+}
+
+pub fn op_source(op: &Token) -> &'static str {
+    match op {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Multiply => "*",
+        Token::Divide => "/",
+        Token::FloorDivide => "//",
+        Token::Modulus => "%",
+        Token::Power => "**",
+        Token::Equal => "==",
+        Token::NotEqual => "!=",
+        Token::Greater => ">",
+        Token::Less => "<",
+        Token::GreaterEqual => ">=",
+        Token::LessEqual => "<=",
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::Not => "!",
+        _ => "?",
+    }
+}
+
+fn block(statements: &[ASTNode], depth: usize) -> Result<String, Error> {
+    let mut out = String::from("{\n");
+    for node in statements {
+        out.push_str(&statement(node, depth + 1)?);
+    }
+    out.push_str(&"    ".repeat(depth));
+    out.push('}');
+    Ok(out)
+}
+
+fn statement(node: &ASTNode, depth: usize) -> Result<String, Error> {
+    let indent = "    ".repeat(depth);
+    let text = match node {
+        ASTNode::Located(_, node) => return statement(node, depth),
+        ASTNode::FunctionDecl(name, params, body) => {
+            format!("func {}({}) {}", name, params.join(", "), block(body, depth)?)
+        }
+        ASTNode::If(condition, then, elifs, otherwise) => {
+            let mut text = format!("if ({}) {}", expr(condition)?, block(then, depth)?);
+            for (condition, body) in elifs {
+                text.push_str(&format!(" elif ({}) {}", expr(condition)?, block(body, depth)?));
+            }
+            if let Some(body) = otherwise {
+                text.push_str(&format!(" else {}", block(body, depth)?));
+            }
+            text
+        }
+        ASTNode::While(condition, body) => format!("while ({}) {}", expr(condition)?, block(body, depth)?),
+        ASTNode::ForEach(name, values, body) => format!("for ({} in {}) {}", name, expr(values)?, block(body, depth)?),
+        ASTNode::For(init, condition, update, body) => format!(
+            "for ({} {}; {}) {}",
+            simple_statement(init)?, expr(condition)?, simple_statement(update)?.trim_end_matches(';'), block(body, depth)?
+        ),
+        _ => simple_statement(node)?,
+    };
+    Ok(format!("{}{}\n", indent, text))
+}
+
+// a statement that fits on one line, with its semicolon
+fn simple_statement(node: &ASTNode) -> Result<String, Error> {
+    Ok(match node {
+        ASTNode::Located(_, node) => return simple_statement(node),
+        ASTNode::Var(name, None, is_mutable) => format!("{} {};", if *is_mutable { "var" } else { "novar" }, name),
+        ASTNode::Var(name, Some(value), is_mutable) => {
+            format!("{} {} = {};", if *is_mutable { "var" } else { "novar" }, name, expr(value)?)
+        }
+        ASTNode::Assign(name, value) => format!("{} = {};", name, expr(value)?),
+        ASTNode::IndexAssign(target, index, value) => format!("{}[{}] = {};", expr(target)?, expr(index)?, expr(value)?),
+        ASTNode::LibraryAssign(lib, name, value) => format!("{}.{} = {};", lib, name, expr(value)?),
+        ASTNode::Print(args) => format!("print({});", list(args)?),
+        ASTNode::Return(None) => "return;".to_string(),
+        ASTNode::Return(Some(value)) => format!("return {};", expr(value)?),
+        ASTNode::Break => "break;".to_string(),
+        ASTNode::Continue => "continue;".to_string(),
+        ASTNode::Global(names) => format!("global {};", names.join(", ")),
+        ASTNode::Import(name, mode, requirement) => {
+            let mut text = format!("import({}", name);
+            if let Some((op, version)) = requirement {
+                text.push_str(&format!(" {} {}", op_source(op), string_source(version)?));
+            }
+            if let Some(mode) = mode {
+                text.push_str(&format!(", {}", mode));
+            }
+            text.push_str(");");
+            text
+        }
+        other => format!("{};", expr(other)?),
+    })
+}
+
+// an expression written back as source
+pub fn expr(node: &ASTNode) -> Result<String, Error> {
+    Ok(match node {
+        ASTNode::Located(_, node) => return expr(node),
+        ASTNode::Number(n) => n.to_string(),
+        ASTNode::Float(f) => float_source(*f),
+        ASTNode::String(s) => string_source(s)?,
+        ASTNode::Boolean(b) => b.to_string(),
+        ASTNode::Null => "null".to_string(),
+        ASTNode::Identifier(name) => name.clone(),
+        ASTNode::TypeLiteral(name) => name.clone(),
+        ASTNode::BinaryOp(left, op, right) => {
+            let level = precedence(op);
+            // a statement may not start with a bracket, so the left side only
+            // gets one when the grouping needs it
+            let left = match &**left {
+                ASTNode::BinaryOp(_, inner, _) if precedence(inner) < level || (precedence(inner) == level && *op == Token::Power) => operand(left)?,
+                // a < b == c would read back as a chain
+                ASTNode::BinaryOp(_, inner, _) if level == 3 && precedence(inner) == 3 => operand(left)?,
+                ASTNode::Comparison(..) => operand(left)?,
+                ASTNode::UnaryOp(..) if *op == Token::Power => operand(left)?,
+                _ => expr(left)?,
+            };
+            let right = match &**right {
+                ASTNode::BinaryOp(_, inner, _) if precedence(inner) > level || (precedence(inner) == level && *op == Token::Power) => expr(right)?,
+                ASTNode::UnaryOp(..) => expr(right)?,
+                _ => operand(right)?,
+            };
+            format!("{} {} {}", left, op_source(op), right)
+        }
+        ASTNode::Comparison(operands, ops) => {
+            let mut text = operand(&operands[0])?;
+            for (op, right) in ops.iter().zip(&operands[1..]) {
+                text.push_str(&format!(" {} {}", op_source(op), operand(right)?));
+            }
+            text
+        }
+        ASTNode::UnaryOp(op, value) => format!("{}{}", op_source(op), operand(value)?),
+        ASTNode::Index(target, index) => format!("{}[{}]", operand(target)?, expr(index)?),
+        ASTNode::Slice(target, start, stop, step) => {
+            let part = |part: &Option<Box<ASTNode>>| part.as_deref().map(expr).transpose().map(Option::unwrap_or_default);
+            let mut text = format!("{}[{}:{}", operand(target)?, part(start)?, part(stop)?);
+            if step.is_some() {
+                text.push_str(&format!(":{}", part(step)?));
+            }
+            text.push(']');
+            text
+        }
+        ASTNode::Array(elements) => format!("[{}]", list(elements)?),
+        ASTNode::Type(value) => format!("type({})", expr(value)?),
+        ASTNode::TypeCast(name, value) => format!("{}({})", name, expr(value)?),
+        ASTNode::Input(prompt) => format!("input({})", expr(prompt)?),
+        ASTNode::LenCall(value) => format!("len({})", expr(value)?),
+        ASTNode::DelCall(value) => format!("del({})", expr(value)?),
+        ASTNode::FunctionCall(name, args) => format!("{}({})", name, list(args)?),
+        ASTNode::LibraryAccess(lib, name) => format!("{}.{}", lib, name),
+        ASTNode::LibraryFunctionCall(lib, name, args) => format!("{}.{}({})", lib, name, list(args)?),
+        ASTNode::KeywordArg(name, value) => format!("{} = {}", name, expr(value)?),
+        ASTNode::Spread(value) => format!("...{}", expr(value)?),
+        other => return Err(Error::UnsupportedOperation(format!("Cannot save a {} as an expression", other.kind()))),
+    })
+}
+
+// as in Parser::infix_precedence
+fn precedence(op: &Token) -> u8 {
+    match op {
+        Token::Or => 1,
+        Token::And => 2,
+        Token::Equal | Token::NotEqual | Token::Greater | Token::Less | Token::GreaterEqual | Token::LessEqual => 3,
+        Token::Plus | Token::Minus => 4,
+        Token::Multiply | Token::Divide | Token::FloorDivide | Token::Modulus => 5,
+        _ => 6,
+    }
+}
+
+// an operation used as an operand is bracketed
+fn operand(node: &ASTNode) -> Result<String, Error> {
+    match node {
+        ASTNode::BinaryOp(..) | ASTNode::Comparison(..) | ASTNode::UnaryOp(..) => Ok(format!("({})", expr(node)?)),
+        _ => expr(node),
+    }
+}
+
+fn list(nodes: &[ASTNode]) -> Result<String, Error> {
+    Ok(nodes.iter().map(expr).collect::<Result<Vec<_>, _>>()?.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Session;
+    use crate::parser::Parser;
+
+    fn run(session: &mut Session, source: &str) -> Value {
+        session.eval(Parser::new(source).parse().unwrap()).unwrap()
+    }
+
+    fn save(session: &Session) -> Result<String, Error> {
+        write(&Snapshot {
+            libraries: &session.libraries(),
+            functions: &session.functions(),
+            variables: &session.variables(),
+        })
+    }
+
+    #[test]
+    fn a_snapshot_restores_the_session() {
+        let mut session = Session::new(false);
+        run(&mut session, "import(math);\n\
+            func grow(xs, n) { for (var i = 0; i < n; i = i + 1) { xs[i] = (i + 1) * 2 ** 2; } return xs[0:n]; }\n\
+            func sign(x) { if (x < 0 && !(x == -1)) { return -1; } elif (x == 0) { return 0; } else { return 1 - 0; } }\n\
+            var data = [1, [2.0, \"two\"], null, true];\n\
+            novar limit = 10 - (4 - 1);\n\
+            var root = math.sqrt;");
+        let source = save(&session).unwrap();
+
+        let mut restored = Session::new(false);
+        run(&mut restored, &source);
+        assert_eq!(restored.variables().iter().map(|(name, value, is_mutable)| (name.clone(), value.to_string(), *is_mutable)).collect::<Vec<_>>(),
+            session.variables().iter().map(|(name, value, is_mutable)| (name.clone(), value.to_string(), *is_mutable)).collect::<Vec<_>>());
+        assert_eq!(restored.libraries(), vec!["math"]);
+        assert_eq!(run(&mut restored, "return grow([0, 0, 0], 3);").to_string(), "[4, 8, 12]");
+        assert_eq!(run(&mut restored, "return [sign(-5), sign(-1), sign(0), sign(3)];").to_string(), "[-1, 1, 0, 1]");
+        assert_eq!(save(&restored).unwrap(), source);
+    }
+
+    #[test]
+    fn values_without_a_source_form_are_errors() {
+        let mut session = Session::new(false);
+        run(&mut session, "var a = [1]; insert(a, a);");
+        assert!(save(&session).unwrap_err().to_string().contains("contains itself"));
+        assert_eq!(float_source(1e300).parse::<f64>().unwrap(), 1e300);
+        assert_eq!(float_source(2.0), "2.0");
+    }
+}
@@ -0,0 +1,319 @@
+use indexmap::IndexMap;
+use serde_json::Value as Json;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, LineWriter, Write};
+use std::time::Instant;
+
+// --trace-out <file>: one JSON record per executed statement, e.g.
+//   {"seq":12,"line":4,"depth":1,"kind":"Assign","start_us":840,"duration_us":3,"type":"int"}
+// seq counts finished statements, so nested statements come before the loop
+// or call around them. Records are written line by line as statements finish,
+// so the trace of a script that hangs and gets killed is complete up to there.
+// The first line names the script: {"script":"loop.td"}.
+struct Trace {
+    writer: LineWriter<File>,
+    started: Instant,
+    seq: u64,
+}
+
+// The trace, like --debug-dump and --summary, is td's own instrumentation
+// and not a setting of a run: it records every statement run on the thread
+// that started it, from any run or Session there, and none run elsewhere.
+thread_local! {
+    static TRACE: RefCell<Option<Trace>> = const { RefCell::new(None) };
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+    // the last records, kept for --debug-dump whether or not a trace is written
+    static RECENT: RefCell<Option<Recent>> = const { RefCell::new(None) };
+}
+
+struct Recent {
+    records: VecDeque<String>,
+    capacity: usize,
+    started: Instant,
+    seq: u64,
+}
+
+// keep the last `count` records in memory from now on
+pub fn keep_recent(count: usize) {
+    RECENT.with(|recent| *recent.borrow_mut() = Some(Recent { records: VecDeque::new(), capacity: count, started: Instant::now(), seq: 0 }));
+}
+
+// the records kept by keep_recent, oldest first
+pub fn recent() -> Vec<String> {
+    RECENT.with(|recent| recent.borrow().as_ref().map(|recent| recent.records.iter().cloned().collect()).unwrap_or_default())
+}
+
+fn format_record(seq: u64, line: usize, depth: usize, kind: &str, start: u128, duration: u128, value_type: &str) -> String {
+    format!(
+        "{{\"seq\":{},\"line\":{},\"depth\":{},\"kind\":\"{}\",\"start_us\":{},\"duration_us\":{},\"type\":\"{}\"}}",
+        seq, line, depth, kind, start, duration, value_type
+    )
+}
+
+pub fn start(path: &str, script: &str) -> io::Result<()> {
+    let mut writer = LineWriter::new(File::create(path)?);
+    writeln!(writer, "{{\"script\":{}}}", Json::from(script))?;
+    TRACE.with(|trace| *trace.borrow_mut() = Some(Trace { writer, started: Instant::now(), seq: 0 }));
+    Ok(())
+}
+
+// called when a statement starts; pass the result to record() when it ends
+pub fn enter() -> Instant {
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    Instant::now()
+}
+
+pub fn record(line: usize, kind: &str, entered: Instant, value_type: &str) {
+    let duration = entered.elapsed().as_micros();
+    let depth = DEPTH.with(|depth| {
+        depth.set(depth.get() - 1);
+        depth.get()
+    });
+    TRACE.with(|trace| {
+        let mut trace = trace.borrow_mut();
+        let Some(trace) = trace.as_mut() else { return };
+        trace.seq += 1;
+        let start = entered.saturating_duration_since(trace.started).as_micros();
+        // a trace that can't be written shouldn't stop the script
+        let _ = writeln!(trace.writer, "{}", format_record(trace.seq, line, depth, kind, start, duration, value_type));
+    });
+    RECENT.with(|recent| {
+        let mut recent = recent.borrow_mut();
+        let Some(recent) = recent.as_mut() else { return };
+        recent.seq += 1;
+        let start = entered.saturating_duration_since(recent.started).as_micros();
+        if recent.records.len() == recent.capacity {
+            recent.records.pop_front();
+        }
+        recent.records.push_back(format_record(recent.seq, line, depth, kind, start, duration, value_type));
+    });
+}
+
+struct Record {
+    seq: u64,
+    line: u64,
+    depth: u64,
+    kind: String,
+    start_us: u64,
+    duration_us: u64,
+    value_type: String,
+}
+
+#[derive(Default)]
+struct LineStats {
+    kind: String,
+    count: u64,
+    total_us: u64,
+    max_us: u64,
+}
+
+fn read_trace(path: &str) -> Result<(Option<String>, Vec<Record>), Box<dyn Error>> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path, e))?;
+    let mut script = None;
+    let mut records = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let json: Json = match serde_json::from_str(line) {
+            Ok(json) => json,
+            // the last line of a trace cut off mid-write
+            Err(_) if number + 1 == contents.lines().count() => break,
+            Err(e) => return Err(format!("{}:{}: not a trace record: {}", path, number + 1, e).into()),
+        };
+        if let Some(name) = json["script"].as_str() {
+            script = Some(name.to_string());
+            continue;
+        }
+        let field = |name: &str| json[name].as_u64().ok_or_else(|| format!("{}:{}: missing \"{}\"", path, number + 1, name));
+        records.push(Record {
+            seq: field("seq")?,
+            line: field("line")?,
+            depth: field("depth")?,
+            kind: json["kind"].as_str().unwrap_or("?").to_string(),
+            start_us: field("start_us")?,
+            duration_us: field("duration_us")?,
+            value_type: json["type"].as_str().unwrap_or("?").to_string(),
+        });
+    }
+    Ok((script, records))
+}
+
+// the script's source lines, if it's still where the trace says
+fn source_lines(script: Option<&str>) -> Vec<String> {
+    script.and_then(|path| fs::read_to_string(path).ok())
+        .map(|source| source.lines().map(|line| line.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+fn snippet(source: &[String], line: u64) -> String {
+    let text = source.get((line as usize).wrapping_sub(1)).map(String::as_str).unwrap_or("");
+    if text.chars().count() > 40 {
+        format!("{}...", text.chars().take(37).collect::<String>())
+    } else {
+        text.to_string()
+    }
+}
+
+fn ms(us: u64) -> String {
+    format!("{:.2}", us as f64 / 1000.0)
+}
+
+// td trace-view <trace> [--top n] [--replay]: summarizes a trace written by
+// --trace-out, or with --replay prints every statement in the order it started
+pub fn view(path: &str, top: usize, replay: bool) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    match write_view(path, top, replay, &mut stdout.lock()) {
+        // td trace-view out.json | head: the reader has all it wanted
+        Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => Ok(()),
+        result => result,
+    }
+}
+
+fn write_view(path: &str, top: usize, replay: bool, out: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let (script, mut records) = read_trace(path)?;
+    let source = source_lines(script.as_deref());
+
+    if replay {
+        // a statement starts before the ones nested in it
+        records.sort_by_key(|r| (r.start_us, r.depth));
+        for r in &records {
+            writeln!(
+                out,
+                "{:>8.3} ms  {}line {:<4} {} -> {} ({} us)  {}",
+                r.start_us as f64 / 1000.0, "  ".repeat(r.depth as usize), r.line, r.kind, r.value_type, r.duration_us, snippet(&source, r.line)
+            )?;
+        }
+        out.flush()?;
+        return Ok(());
+    }
+
+    let total_us: u64 = records.iter().filter(|r| r.depth == 0).map(|r| r.duration_us).sum();
+    writeln!(
+        out,
+        "{}: {} statements from {}, {} ms at the top level",
+        path, records.len(), script.as_deref().unwrap_or("an unknown script"), ms(total_us)
+    )?;
+    if records.is_empty() {
+        out.flush()?;
+        return Ok(());
+    }
+
+    let mut lines: IndexMap<u64, LineStats> = IndexMap::new();
+    for r in &records {
+        let stats = lines.entry(r.line).or_default();
+        stats.kind = r.kind.clone();
+        stats.count += 1;
+        stats.total_us += r.duration_us;
+        stats.max_us = stats.max_us.max(r.duration_us);
+    }
+
+    let print_table = |out: &mut dyn Write, title: &str, order: &mut dyn FnMut(&LineStats, &LineStats) -> std::cmp::Ordering| -> io::Result<()> {
+        let mut sorted: Vec<(&u64, &LineStats)> = lines.iter().collect();
+        sorted.sort_by(|a, b| order(a.1, b.1).then(a.0.cmp(b.0)));
+        writeln!(out, "\n{}", title)?;
+        writeln!(out, "  {:>5} {:>9} {:>10} {:>10} {:>10}  {:<20} source", "line", "count", "total ms", "avg us", "max us", "kind")?;
+        for (line, stats) in sorted.into_iter().take(top) {
+            writeln!(
+                out,
+                "  {:>5} {:>9} {:>10} {:>10} {:>10}  {:<20} {}",
+                line, stats.count, ms(stats.total_us), stats.total_us / stats.count, stats.max_us, stats.kind, snippet(&source, *line)
+            )?;
+        }
+        Ok(())
+    };
+    print_table(out, "Slowest lines (time includes nested statements):", &mut |a, b| b.total_us.cmp(&a.total_us))?;
+    print_table(out, "Most executed lines:", &mut |a, b| b.count.cmp(&a.count))?;
+
+    // where a script that hung or crashed got to
+    writeln!(out, "\nLast statements:")?;
+    records.sort_by_key(|r| r.seq);
+    for r in records.iter().skip(records.len().saturating_sub(top)) {
+        writeln!(out, "  #{:<8} line {:<4} {} -> {} ({} us)  {}", r.seq, r.line, r.kind, r.value_type, r.duration_us, snippet(&source, r.line))?;
+    }
+    // statements only get a record when they finish
+    if let Some(last) = records.last().filter(|r| r.depth > 0) {
+        writeln!(out, "\nThe trace ends inside {} unfinished statement(s); the script stopped or hung there.", last.depth)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a reader that went away, like head after its lines
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_trace_shows_where_the_script_got_to() {
+        let path = std::env::temp_dir().join(format!("tidal-trace-{}.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        start(&path, "gone.td").unwrap();
+        // line 2 runs twice inside the loop on line 1, then line 3 never finishes
+        let outer = enter();
+        for _ in 0..2 {
+            let inner = enter();
+            record(2, "Assign", inner, "int");
+        }
+        record(1, "While", outer, "null");
+        let _unfinished = enter();
+        let _hung = enter();
+        record(4, "Call", enter(), "str");
+        TRACE.with(|trace| trace.borrow_mut().take());
+
+        let mut summary = Vec::new();
+        write_view(&path, 10, false, &mut summary).unwrap();
+        let mut replay = Vec::new();
+        write_view(&path, 10, true, &mut replay).unwrap();
+        let broken = write_view(&path, 10, false, &mut ClosedPipe).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        let summary = String::from_utf8(summary).unwrap();
+        assert!(summary.starts_with(&format!("{}: 4 statements from gone.td", path)), "{}", summary);
+        assert!(summary.contains("\nMost executed lines:\n"), "{}", summary);
+        let most_executed = summary.split("Most executed lines:\n").nth(1).unwrap().lines().nth(1).unwrap();
+        assert!(most_executed.split_whitespace().take(2).eq(["2", "2"]), "{}", most_executed);
+        assert!(summary.ends_with("The trace ends inside 2 unfinished statement(s); the script stopped or hung there.\n"), "{}", summary);
+
+        // the loop starts before the statements in it
+        let kinds: Vec<&str> = std::str::from_utf8(&replay).unwrap().lines()
+            .map(|line| line.split_whitespace().nth(4).unwrap())
+            .collect();
+        assert_eq!(kinds, ["While", "Assign", "Assign", "Call"]);
+        assert_eq!(broken.downcast_ref::<io::Error>().unwrap().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn a_trace_records_its_own_thread_only() {
+        let path = std::env::temp_dir().join(format!("tidal-trace-threads-{}.json", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let run = |source: &str| {
+            let ast = crate::parser::Parser::new(source).with_lines().parse().unwrap();
+            crate::interpreter::run(ast, false, crate::interpreter::Settings::default()).unwrap();
+        };
+        start(&path, "main.td").unwrap();
+        run("var a = 1;");
+        std::thread::spawn(move || run("var b = 2;\nvar c = 3;")).join().unwrap();
+        run("var d = 4;");
+        TRACE.with(|trace| trace.borrow_mut().take());
+
+        let records = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(records.lines().count(), 3, "{}", records);
+    }
+}
@@ -0,0 +1,186 @@
+use super::{common_op, unsupported, Target, Writer};
+use crate::error::Error;
+use crate::lexer::Token;
+use crate::parser::ASTNode;
+
+pub struct JavaScript;
+
+const NAME: &str = "JavaScript";
+
+impl Target for JavaScript {
+    fn declare(&self, name: &str, value: Option<String>, is_mutable: bool) -> String {
+        match (value, is_mutable) {
+            (Some(value), false) => format!("const {} = {};", name, value),
+            (Some(value), true) => format!("let {} = {};", name, value),
+            (None, _) => format!("let {};", name),
+        }
+    }
+
+    // console.log separates with a space and ends with a newline, as print does
+    fn print(&self, args: Vec<String>, sep: Option<String>, end: Option<String>) -> String {
+        if sep.is_none() && end.is_none() {
+            return format!("console.log({});", args.join(", "));
+        }
+        format!(
+            "process.stdout.write([{}].join({}) + {});",
+            args.join(", "), sep.unwrap_or_else(|| "\" \"".to_string()), end.unwrap_or_else(|| "\"\\n\"".to_string()),
+        )
+    }
+
+    fn import(&self, name: &str) -> String {
+        format!("import * as {0} from \"./{0}.js\";", name)
+    }
+
+    // functions see the script's variables without declaring them
+    fn global(&self, _names: &[String]) -> Option<String> {
+        None
+    }
+
+    fn del(&self, _target: String) -> Result<String, Error> {
+        Err(unsupported(NAME, "del()"))
+    }
+
+    fn statement(&self, text: String) -> String {
+        format!("{};", text)
+    }
+
+    fn open(&self, header: String) -> String {
+        format!("{} {{", header)
+    }
+
+    fn close(&self) -> Option<&'static str> {
+        Some("}")
+    }
+
+    fn empty_body(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn if_header(&self, condition: String) -> String {
+        format!("if ({})", condition)
+    }
+
+    fn elif_header(&self, condition: String) -> String {
+        format!("else if ({})", condition)
+    }
+
+    fn else_header(&self) -> String {
+        "else".to_string()
+    }
+
+    fn while_header(&self, condition: String) -> String {
+        format!("while ({})", condition)
+    }
+
+    fn for_each_header(&self, name: &str, values: String) -> String {
+        format!("for (let {} of {})", name, values)
+    }
+
+    fn function_header(&self, name: &str, params: &[String]) -> String {
+        format!("function {}({})", name, params.join(", "))
+    }
+
+    fn for_loop(&self, writer: &mut Writer, [init, condition, update]: [&ASTNode; 3], body: &[ASTNode], depth: usize) -> Result<(), Error> {
+        let init = match init {
+            ASTNode::Located(_, node) => node.as_ref(),
+            node => node,
+        };
+        let init = match init {
+            ASTNode::Var(name, Some(value), _) => format!("let {} = {}", name, writer.expr(value)?),
+            ASTNode::Var(name, None, _) => format!("let {}", name),
+            other => writer.simple(other)?,
+        };
+        let header = format!("for ({}; {}; {})", init, writer.expr(condition)?, writer.simple(update)?);
+        writer.block(header, body, depth)
+    }
+
+    fn null(&self) -> &'static str {
+        "null"
+    }
+
+    fn boolean(&self, value: bool) -> &'static str {
+        if value { "true" } else { "false" }
+    }
+
+    fn float(&self, value: f64) -> String {
+        if value.is_nan() {
+            return "NaN".to_string();
+        }
+        if value.is_infinite() {
+            return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+        }
+        let text = value.to_string();
+        if text.contains('.') { text } else { format!("{}.0", text) }
+    }
+
+    fn binary(&self, left: String, op: &Token, right: String) -> String {
+        let op = match op {
+            Token::FloorDivide => return format!("Math.floor({} / {})", left, right),
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Equal => "===",
+            Token::NotEqual => "!==",
+            op => common_op(op),
+        };
+        format!("{} {} {}", left, op, right)
+    }
+
+    fn unary(&self, op: &Token, operand: String) -> String {
+        match op {
+            Token::Not => format!("!{}", operand),
+            _ => format!("-{}", operand),
+        }
+    }
+
+    // 1 < x < 10 as 1 < x && x < 10; x is written, and evaluated, twice
+    fn chain(&self, operands: Vec<String>, ops: &[Token]) -> String {
+        ops.iter().enumerate()
+            .map(|(i, op)| self.binary(operands[i].clone(), op, operands[i + 1].clone()))
+            .collect::<Vec<_>>()
+            .join(" && ")
+    }
+
+    fn len(&self, _value: String, operand: String) -> String {
+        format!("{}.length", operand)
+    }
+
+    fn type_of(&self, _value: String) -> Result<String, Error> {
+        Err(unsupported(NAME, "type()"))
+    }
+
+    fn type_name(&self, name: &str) -> Result<String, Error> {
+        Err(unsupported(NAME, &format!("the type name {}", name)))
+    }
+
+    fn cast(&self, name: &str, value: String) -> String {
+        match name {
+            "int" => format!("Math.trunc(Number({}))", value),
+            "float" => format!("Number({})", value),
+            "bool" => format!("Boolean({})", value),
+            _ => format!("String({})", value),
+        }
+    }
+
+    fn input(&self, prompt: String) -> String {
+        format!("prompt({})", prompt)
+    }
+
+    fn slice(&self, target: String, start: Option<String>, stop: Option<String>, step: Option<String>) -> Result<String, Error> {
+        if step.is_some() {
+            return Err(unsupported(NAME, "a slice with a step"));
+        }
+        Ok(match (start, stop) {
+            (start, Some(stop)) => format!("{}.slice({}, {})", target, start.unwrap_or_else(|| "0".to_string()), stop),
+            (Some(start), None) => format!("{}.slice({})", target, start),
+            (None, None) => format!("{}.slice()", target),
+        })
+    }
+
+    fn spread(&self, value: String) -> String {
+        format!("...{}", value)
+    }
+
+    fn keyword_arg(&self, _name: &str, _value: String) -> Result<String, Error> {
+        Err(unsupported(NAME, "an argument passed by name"))
+    }
+}
@@ -0,0 +1,420 @@
+use crate::error::Error;
+use crate::lexer::Token;
+use crate::parser::ASTNode;
+use std::fs;
+
+mod javascript;
+mod python;
+
+// td transpile: a script as readable Python or JavaScript, to compare how
+// the languages behave or to move a project over. Writer walks the AST and
+// asks a Target how each construct is spelled in its language. A construct
+// the target has nothing readable for is an error naming it, rather than
+// output that runs differently. Library calls are written as they are, so
+// the result needs a module of the same name to run.
+
+pub trait Target {
+    fn declare(&self, name: &str, value: Option<String>, is_mutable: bool) -> String;
+    fn print(&self, args: Vec<String>, sep: Option<String>, end: Option<String>) -> String;
+    fn import(&self, name: &str) -> String;
+    // None when the target needs no declaration
+    fn global(&self, names: &[String]) -> Option<String>;
+    fn del(&self, target: String) -> Result<String, Error>;
+    // a simple statement, with the target's terminator
+    fn statement(&self, text: String) -> String;
+
+    // a block is opened with its header; Python blocks close by indentation
+    fn open(&self, header: String) -> String;
+    fn close(&self) -> Option<&'static str>;
+    fn empty_body(&self) -> Option<&'static str>;
+    fn if_header(&self, condition: String) -> String;
+    fn elif_header(&self, condition: String) -> String;
+    fn else_header(&self) -> String;
+    fn while_header(&self, condition: String) -> String;
+    fn for_each_header(&self, name: &str, values: String) -> String;
+    fn function_header(&self, name: &str, params: &[String]) -> String;
+    fn for_loop(&self, writer: &mut Writer, parts: [&ASTNode; 3], body: &[ASTNode], depth: usize) -> Result<(), Error>;
+
+    fn null(&self) -> &'static str;
+    fn boolean(&self, value: bool) -> &'static str;
+    fn float(&self, value: f64) -> String;
+    fn binary(&self, left: String, op: &Token, right: String) -> String;
+    fn unary(&self, op: &Token, operand: String) -> String;
+    fn chain(&self, operands: Vec<String>, ops: &[Token]) -> String;
+    // the value, and the value bracketed if it needs to be to take a member
+    fn len(&self, value: String, operand: String) -> String;
+    fn type_of(&self, value: String) -> Result<String, Error>;
+    fn type_name(&self, name: &str) -> Result<String, Error>;
+    fn cast(&self, name: &str, value: String) -> String;
+    fn input(&self, prompt: String) -> String;
+    fn slice(&self, target: String, start: Option<String>, stop: Option<String>, step: Option<String>) -> Result<String, Error>;
+    fn spread(&self, value: String) -> String;
+    fn keyword_arg(&self, name: &str, value: String) -> Result<String, Error>;
+}
+
+pub struct Writer<'t> {
+    target: &'t dyn Target,
+    lines: Vec<String>,
+}
+
+impl<'t> Writer<'t> {
+    pub fn line(&mut self, depth: usize, text: String) {
+        self.lines.push(format!("{}{}", "    ".repeat(depth), text));
+    }
+
+    // a block's statements, or the target's placeholder for none
+    pub fn body(&mut self, body: &[ASTNode], depth: usize) -> Result<(), Error> {
+        if body.is_empty() {
+            if let Some(empty) = self.target.empty_body() {
+                self.line(depth, empty.to_string());
+            }
+        }
+        self.statements(body, depth)
+    }
+
+    pub fn block(&mut self, header: String, body: &[ASTNode], depth: usize) -> Result<(), Error> {
+        self.line(depth, self.target.open(header));
+        self.body(body, depth + 1)?;
+        if let Some(close) = self.target.close() {
+            self.line(depth, close.to_string());
+        }
+        Ok(())
+    }
+
+    // the header that continues an if, after the block before it
+    fn continue_block(&mut self, header: String, body: &[ASTNode], depth: usize) -> Result<(), Error> {
+        let open = self.target.open(header);
+        match self.target.close() {
+            Some(close) => {
+                self.lines.pop();
+                self.line(depth, format!("{} {}", close, open));
+            }
+            None => self.line(depth, open),
+        }
+        self.body(body, depth + 1)?;
+        if let Some(close) = self.target.close() {
+            self.line(depth, close.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn statements(&mut self, nodes: &[ASTNode], depth: usize) -> Result<(), Error> {
+        for node in nodes {
+            self.statement(node, depth)?;
+        }
+        Ok(())
+    }
+
+    pub fn statement(&mut self, node: &ASTNode, depth: usize) -> Result<(), Error> {
+        let target = self.target;
+        let text = match node {
+            ASTNode::Located(_, node) => return self.statement(node, depth),
+            ASTNode::FunctionDecl(name, params, body) => {
+                // top-level functions stand apart, as both languages' style guides ask
+                if depth == 0 && self.lines.last().is_some_and(|line| !line.is_empty()) {
+                    self.lines.push(String::new());
+                }
+                self.block(target.function_header(name, params), body, depth)?;
+                if depth == 0 {
+                    self.lines.push(String::new());
+                }
+                return Ok(());
+            }
+            ASTNode::If(condition, then, elifs, otherwise) => {
+                self.block(target.if_header(self.expr(condition)?), then, depth)?;
+                for (condition, body) in elifs {
+                    self.continue_block(target.elif_header(self.expr(condition)?), body, depth)?;
+                }
+                if let Some(body) = otherwise {
+                    self.continue_block(target.else_header(), body, depth)?;
+                }
+                return Ok(());
+            }
+            ASTNode::While(condition, body) => return self.block(target.while_header(self.expr(condition)?), body, depth),
+            ASTNode::ForEach(name, values, body) => return self.block(target.for_each_header(name, self.expr(values)?), body, depth),
+            ASTNode::For(init, condition, update, body) => return target.for_loop(self, [init, condition, update], body, depth),
+            ASTNode::Var(name, value, is_mutable) => {
+                let value = value.as_deref().map(|value| self.expr(value)).transpose()?;
+                target.declare(name, value, *is_mutable)
+            }
+            ASTNode::Print(args) => {
+                let (mut sep, mut end, mut values) = (None, None, Vec::new());
+                for arg in args {
+                    match arg {
+                        ASTNode::KeywordArg(name, value) if name == "sep" => sep = Some(self.expr(value)?),
+                        ASTNode::KeywordArg(name, value) if name == "end" => end = Some(self.expr(value)?),
+                        arg => values.push(self.expr(arg)?),
+                    }
+                }
+                target.print(values, sep, end)
+            }
+            ASTNode::Import(name, _, _) => target.import(name),
+            ASTNode::Global(names) => match target.global(names) {
+                Some(text) => text,
+                None => return Ok(()),
+            },
+            ASTNode::DelCall(value) => target.del(self.expr(value)?)?,
+            ASTNode::Return(None) => target.statement("return".to_string()),
+            ASTNode::Break => target.statement("break".to_string()),
+            ASTNode::Continue => target.statement("continue".to_string()),
+            other => target.statement(self.simple(other)?),
+        };
+        self.line(depth, text);
+        Ok(())
+    }
+
+    // a statement without its terminator: an assignment, return or expression
+    pub fn simple(&self, node: &ASTNode) -> Result<String, Error> {
+        Ok(match node {
+            ASTNode::Located(_, node) => return self.simple(node),
+            ASTNode::Assign(name, value) => format!("{} = {}", name, self.expr(value)?),
+            ASTNode::IndexAssign(target, index, value) => format!("{}[{}] = {}", self.operand(target)?, self.expr(index)?, self.expr(value)?),
+            ASTNode::LibraryAssign(lib, name, value) => format!("{}.{} = {}", lib, name, self.expr(value)?),
+            ASTNode::Return(Some(value)) => format!("return {}", self.expr(value)?),
+            other => self.expr(other)?,
+        })
+    }
+
+    pub fn expr(&self, node: &ASTNode) -> Result<String, Error> {
+        let target = self.target;
+        Ok(match node {
+            ASTNode::Located(_, node) => return self.expr(node),
+            ASTNode::Number(n) => n.to_string(),
+            ASTNode::Float(f) => target.float(*f),
+            ASTNode::String(s) => string_literal(s),
+            ASTNode::Boolean(b) => target.boolean(*b).to_string(),
+            ASTNode::Null => target.null().to_string(),
+            ASTNode::Identifier(name) => name.clone(),
+            ASTNode::TypeLiteral(name) => target.type_name(name)?,
+            ASTNode::BinaryOp(left, op, right) => {
+                let level = precedence(op);
+                let left = match left.as_ref() {
+                    ASTNode::BinaryOp(_, inner, _) if precedence(inner) < level || (level == 3 && precedence(inner) == 3) || *op == Token::Power => self.operand(left)?,
+                    _ => self.operand_if_compound(left)?,
+                };
+                let right = match right.as_ref() {
+                    ASTNode::BinaryOp(_, inner, _) if precedence(inner) > level && *op != Token::Power => self.expr(right)?,
+                    // a && !b reads the same unbracketed in both languages
+                    ASTNode::UnaryOp(..) if level <= 2 => self.expr(right)?,
+                    _ => self.operand(right)?,
+                };
+                target.binary(left, op, right)
+            }
+            ASTNode::Comparison(operands, ops) => {
+                let operands = operands.iter().map(|node| self.operand(node)).collect::<Result<Vec<_>, _>>()?;
+                target.chain(operands, ops)
+            }
+            ASTNode::UnaryOp(op, value) => target.unary(op, self.operand(value)?),
+            ASTNode::Index(value, index) => format!("{}[{}]", self.operand(value)?, self.expr(index)?),
+            ASTNode::Slice(value, start, stop, step) => {
+                let part = |part: &Option<Box<ASTNode>>| part.as_deref().map(|node| self.expr(node)).transpose();
+                target.slice(self.operand(value)?, part(start)?, part(stop)?, part(step)?)?
+            }
+            ASTNode::Array(elements) => format!("[{}]", self.list(elements)?),
+            ASTNode::Type(value) => target.type_of(self.expr(value)?)?,
+            ASTNode::TypeCast(name, value) => target.cast(name, self.expr(value)?),
+            ASTNode::Input(prompt) => target.input(self.expr(prompt)?),
+            ASTNode::LenCall(value) => target.len(self.expr(value)?, self.operand(value)?),
+            ASTNode::FunctionCall(name, args) => format!("{}({})", name, self.list(args)?),
+            ASTNode::LibraryAccess(lib, name) => format!("{}.{}", lib, name),
+            ASTNode::LibraryFunctionCall(lib, name, args) => format!("{}.{}({})", lib, name, self.list(args)?),
+            ASTNode::KeywordArg(name, value) => target.keyword_arg(name, self.expr(value)?)?,
+            ASTNode::Spread(value) => target.spread(self.operand(value)?),
+            other => return Err(Error::CannotTranspile(format!("Cannot transpile a {} as an expression", other.kind()))),
+        })
+    }
+
+    // an operation used as an operand is bracketed
+    fn operand(&self, node: &ASTNode) -> Result<String, Error> {
+        match node {
+            ASTNode::Located(_, node) => self.operand(node),
+            ASTNode::BinaryOp(..) | ASTNode::Comparison(..) | ASTNode::UnaryOp(..) => Ok(format!("({})", self.expr(node)?)),
+            _ => self.expr(node),
+        }
+    }
+
+    // the left side of a binary operation needs brackets for a chain or a
+    // unary operation, whose precedence differs between the languages
+    fn operand_if_compound(&self, node: &ASTNode) -> Result<String, Error> {
+        match node {
+            ASTNode::Comparison(..) | ASTNode::UnaryOp(..) => self.operand(node),
+            _ => self.expr(node),
+        }
+    }
+
+    fn list(&self, nodes: &[ASTNode]) -> Result<String, Error> {
+        Ok(nodes.iter().map(|node| self.expr(node)).collect::<Result<Vec<_>, _>>()?.join(", "))
+    }
+}
+
+// as in Parser::infix_precedence
+fn precedence(op: &Token) -> u8 {
+    match op {
+        Token::Or => 1,
+        Token::And => 2,
+        Token::Equal | Token::NotEqual | Token::Greater | Token::Less | Token::GreaterEqual | Token::LessEqual => 3,
+        Token::Plus | Token::Minus => 4,
+        Token::Multiply | Token::Divide | Token::FloorDivide | Token::Modulus => 5,
+        _ => 6,
+    }
+}
+
+// the operators both languages spell like Tidal
+fn common_op(op: &Token) -> &'static str {
+    match op {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Multiply => "*",
+        Token::Divide => "/",
+        Token::Modulus => "%",
+        Token::Power => "**",
+        Token::Greater => ">",
+        Token::Less => "<",
+        Token::GreaterEqual => ">=",
+        Token::LessEqual => "<=",
+        _ => "?",
+    }
+}
+
+// a double-quoted string both languages read the same way
+fn string_literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7f' => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn unsupported(target: &str, what: &str) -> Error {
+    Error::CannotTranspile(format!("{} has no readable equivalent in {}", what, target))
+}
+
+pub fn transpile(ast: &[ASTNode], to: &str) -> Result<String, Error> {
+    let target: &dyn Target = match to {
+        "py" | "python" => &python::Python,
+        "js" | "javascript" => &javascript::JavaScript,
+        other => return Err(Error::CannotTranspile(format!("--to expects py or js, not '{}'", other))),
+    };
+    let mut writer = Writer { target, lines: Vec::new() };
+    writer.statements(ast, 0)?;
+    while writer.lines.last().is_some_and(|line| line.is_empty()) {
+        writer.lines.pop();
+    }
+    Ok(writer.lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+// td transpile <script> --to py|js [-o out]: writes to the file, or prints
+pub fn run(path: &str, to: Option<&str>, out: Option<&str>) -> i32 {
+    let source = match to {
+        Some(to) => crate::parse_script(path, false, crate::dialect::Choice::ByExtension).and_then(|ast| transpile(&ast, to)),
+        None => Err(Error::CannotTranspile("'td transpile' needs --to py or --to js".to_string())),
+    };
+    let result = match (source, out) {
+        (Ok(source), Some(out)) => fs::write(out, source)
+            .map_err(|e| Error::FileNotFound(format!("cannot write '{}': {}", out, e))),
+        (Ok(source), None) => {
+            out!("{}", source);
+            Ok(())
+        }
+        (Err(e), _) => Err(e),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            crate::print_error(&e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    const SOURCE: &str = r#"novar LIMIT = 3;
+var total = 0.5;
+func add(values, scale) {
+    global total;
+    for (var i = 0; i < len(values); i = i + 1) {
+        if (values[i] % 2 == 0 && !(values[i] > LIMIT)) {
+            total = total + values[i] * scale;
+        } elif (1 < values[i] <= LIMIT) {
+            continue;
+        } else {
+            break;
+        }
+    }
+    return int(total // 1);
+}
+print(add([1, 2, 4], -2 ** 2), "done", sep = ", ");
+var n = 3;
+while (n > 0) { n = n - 1; }
+"#;
+
+    fn transpiled(to: &str) -> String {
+        transpile(&Parser::new(SOURCE).parse().unwrap(), to).unwrap()
+    }
+
+    #[test]
+    fn scripts_transpile_to_python() {
+        assert_eq!(transpiled("py"), r#"LIMIT = 3
+total = 0.5
+
+def add(values, scale):
+    global total
+    for i in range(len(values)):
+        if values[i] % 2 == 0 and not (values[i] > LIMIT):
+            total = total + values[i] * scale
+        elif 1 < values[i] <= LIMIT:
+            continue
+        else:
+            break
+    return int(total // 1)
+
+print(add([1, 2, 4], -(2 ** 2)), "done", sep=", ")
+n = 3
+while n > 0:
+    n = n - 1
+"#);
+    }
+
+    #[test]
+    fn scripts_transpile_to_javascript() {
+        assert_eq!(transpiled("js"), r#"const LIMIT = 3;
+let total = 0.5;
+
+function add(values, scale) {
+    for (let i = 0; i < values.length; i = i + 1) {
+        if (values[i] % 2 === 0 && !(values[i] > LIMIT)) {
+            total = total + values[i] * scale;
+        } else if (1 < values[i] && values[i] <= LIMIT) {
+            continue;
+        } else {
+            break;
+        }
+    }
+    return Math.trunc(Number(Math.floor(total / 1)));
+}
+
+process.stdout.write([add([1, 2, 4], -(2 ** 2)), "done"].join(", ") + "\n");
+let n = 3;
+while (n > 0) {
+    n = n - 1;
+}
+"#);
+        let ast = Parser::new("print(type(1));").parse().unwrap();
+        assert_eq!(transpile(&ast, "js").unwrap_err().to_string(), "CannotTranspile: type() has no readable equivalent in JavaScript");
+        let unknown = transpile(&ast, "rb").unwrap_err();
+        assert_eq!((unknown.code(), unknown.to_string().as_str()), ("E0030", "CannotTranspile: --to expects py or js, not 'rb'"));
+    }
+}
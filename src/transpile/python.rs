@@ -0,0 +1,248 @@
+use super::{common_op, string_literal, Target, Writer};
+use crate::error::Error;
+use crate::lexer::Token;
+use crate::parser::ASTNode;
+
+pub struct Python;
+
+impl Target for Python {
+    fn declare(&self, name: &str, value: Option<String>, _is_mutable: bool) -> String {
+        format!("{} = {}", name, value.unwrap_or_else(|| "None".to_string()))
+    }
+
+    fn print(&self, args: Vec<String>, sep: Option<String>, end: Option<String>) -> String {
+        let mut args = args;
+        args.extend(sep.map(|sep| format!("sep={}", sep)));
+        args.extend(end.map(|end| format!("end={}", end)));
+        format!("print({})", args.join(", "))
+    }
+
+    fn import(&self, name: &str) -> String {
+        format!("import {}", name)
+    }
+
+    fn global(&self, names: &[String]) -> Option<String> {
+        Some(format!("global {}", names.join(", ")))
+    }
+
+    fn del(&self, target: String) -> Result<String, Error> {
+        Ok(format!("del {}", target))
+    }
+
+    fn statement(&self, text: String) -> String {
+        text
+    }
+
+    fn open(&self, header: String) -> String {
+        format!("{}:", header)
+    }
+
+    fn close(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn empty_body(&self) -> Option<&'static str> {
+        Some("pass")
+    }
+
+    fn if_header(&self, condition: String) -> String {
+        format!("if {}", condition)
+    }
+
+    fn elif_header(&self, condition: String) -> String {
+        format!("elif {}", condition)
+    }
+
+    fn else_header(&self) -> String {
+        "else".to_string()
+    }
+
+    fn while_header(&self, condition: String) -> String {
+        format!("while {}", condition)
+    }
+
+    fn for_each_header(&self, name: &str, values: String) -> String {
+        format!("for {} in {}", name, values)
+    }
+
+    fn function_header(&self, name: &str, params: &[String]) -> String {
+        format!("def {}({})", name, params.join(", "))
+    }
+
+    // A counting loop becomes for ... in range(); any other for loop becomes
+    // a while loop with the update at the end of its body, which a continue
+    // would skip, so a loop like that is refused.
+    fn for_loop(&self, writer: &mut Writer, [init, condition, update]: [&ASTNode; 3], body: &[ASTNode], depth: usize) -> Result<(), Error> {
+        if let Some(header) = range_header(writer, init, condition, update, body)? {
+            return writer.block(header, body, depth);
+        }
+        if has_continue(body) {
+            return Err(Error::CannotTranspile(
+                "continue in a for loop that does not count with a step has no readable equivalent in Python".to_string(),
+            ));
+        }
+        writer.statement(init, depth)?;
+        writer.line(depth, self.open(self.while_header(writer.expr(condition)?)));
+        writer.statements(body, depth + 1)?;
+        writer.line(depth + 1, writer.simple(update)?);
+        Ok(())
+    }
+
+    fn null(&self) -> &'static str {
+        "None"
+    }
+
+    fn boolean(&self, value: bool) -> &'static str {
+        if value { "True" } else { "False" }
+    }
+
+    fn float(&self, value: f64) -> String {
+        if value.is_nan() {
+            return "float(\"nan\")".to_string();
+        }
+        if value.is_infinite() {
+            return if value > 0.0 { "float(\"inf\")" } else { "float(\"-inf\")" }.to_string();
+        }
+        let text = value.to_string();
+        if text.contains('.') { text } else { format!("{}.0", text) }
+    }
+
+    fn binary(&self, left: String, op: &Token, right: String) -> String {
+        let op = match op {
+            Token::FloorDivide => "//",
+            Token::And => "and",
+            Token::Or => "or",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            op => common_op(op),
+        };
+        format!("{} {} {}", left, op, right)
+    }
+
+    fn unary(&self, op: &Token, operand: String) -> String {
+        match op {
+            Token::Not => format!("not {}", operand),
+            _ => format!("-{}", operand),
+        }
+    }
+
+    // Python chains comparisons just as Tidal does
+    fn chain(&self, operands: Vec<String>, ops: &[Token]) -> String {
+        let mut text = operands[0].clone();
+        for (op, operand) in ops.iter().zip(&operands[1..]) {
+            text = self.binary(text, op, operand.clone());
+        }
+        text
+    }
+
+    fn len(&self, value: String, _operand: String) -> String {
+        format!("len({})", value)
+    }
+
+    fn type_of(&self, value: String) -> Result<String, Error> {
+        Ok(format!("type({}).__name__", value))
+    }
+
+    // Tidal's int, float, str and bool are Python's names too
+    fn type_name(&self, name: &str) -> Result<String, Error> {
+        Ok(string_literal(name))
+    }
+
+    fn cast(&self, name: &str, value: String) -> String {
+        format!("{}({})", name, value)
+    }
+
+    fn input(&self, prompt: String) -> String {
+        format!("input({})", prompt)
+    }
+
+    fn slice(&self, target: String, start: Option<String>, stop: Option<String>, step: Option<String>) -> Result<String, Error> {
+        let mut text = format!("{}[{}:{}", target, start.unwrap_or_default(), stop.unwrap_or_default());
+        if let Some(step) = step {
+            text.push_str(&format!(":{}", step));
+        }
+        text.push(']');
+        Ok(text)
+    }
+
+    fn spread(&self, value: String) -> String {
+        format!("*{}", value)
+    }
+
+    fn keyword_arg(&self, name: &str, value: String) -> Result<String, Error> {
+        Ok(format!("{}={}", name, value))
+    }
+}
+
+fn unlocated(node: &ASTNode) -> &ASTNode {
+    match node {
+        ASTNode::Located(_, node) => unlocated(node),
+        node => node,
+    }
+}
+
+// for (var i = a; i < b; i = i + s) as "for i in range(a, b, s)", when the
+// body leaves i alone; <= and >= move the end by one, and a minus update
+// counts down
+fn range_header(writer: &Writer, init: &ASTNode, condition: &ASTNode, update: &ASTNode, body: &[ASTNode]) -> Result<Option<String>, Error> {
+    let (name, start) = match unlocated(init) {
+        ASTNode::Var(name, Some(start), _) | ASTNode::Assign(name, start) => (name, start),
+        _ => return Ok(None),
+    };
+    let ASTNode::BinaryOp(counter, op, end) = unlocated(condition) else { return Ok(None) };
+    let ASTNode::Assign(updated, step) = unlocated(update) else { return Ok(None) };
+    let ASTNode::BinaryOp(counted, direction, step) = unlocated(step) else { return Ok(None) };
+    let ASTNode::Number(step) = unlocated(step) else { return Ok(None) };
+    let is_counter = |node: &ASTNode| matches!(unlocated(node), ASTNode::Identifier(n) if n == name);
+    if !is_counter(counter) || !is_counter(counted) || updated != name || *step <= 0 || assigns(body, name) {
+        return Ok(None);
+    }
+
+    // an inclusive end moves by one, worked out when it is a number
+    let moved = |by: i64| -> Result<String, Error> {
+        Ok(match unlocated(end) {
+            ASTNode::Number(n) => (*n as i64 + by).to_string(),
+            end if by > 0 => format!("{} + {}", writer.expr(end)?, by),
+            end => format!("{} - {}", writer.expr(end)?, -by),
+        })
+    };
+    let (end, step) = match (direction, op) {
+        (Token::Plus, Token::Less) => (writer.expr(end)?, *step),
+        (Token::Plus, Token::LessEqual) => (moved(1)?, *step),
+        (Token::Minus, Token::Greater) => (writer.expr(end)?, -step),
+        (Token::Minus, Token::GreaterEqual) => (moved(-1)?, -step),
+        _ => return Ok(None),
+    };
+    let start = writer.expr(start)?;
+    let range = match (start.as_str(), step) {
+        ("0", 1) => end,
+        (_, 1) => format!("{}, {}", start, end),
+        _ => format!("{}, {}, {}", start, end, step),
+    };
+    Ok(Some(format!("for {} in range({})", name, range)))
+}
+
+// whether a statement in the body, or in a block inside it, assigns name
+fn assigns(body: &[ASTNode], name: &str) -> bool {
+    body.iter().any(|node| match unlocated(node) {
+        ASTNode::Assign(assigned, _) | ASTNode::Var(assigned, _, _) => assigned == name,
+        ASTNode::If(_, then, elifs, otherwise) => {
+            assigns(then, name) || elifs.iter().any(|(_, body)| assigns(body, name)) || otherwise.as_deref().is_some_and(|body| assigns(body, name))
+        }
+        ASTNode::While(_, body) => assigns(body, name),
+        ASTNode::ForEach(variable, _, body) => variable == name || assigns(body, name),
+        ASTNode::For(init, _, update, body) => assigns(&[(**init).clone(), (**update).clone()], name) || assigns(body, name),
+        _ => false,
+    })
+}
+
+// whether a continue in the body belongs to this loop rather than an inner one
+fn has_continue(body: &[ASTNode]) -> bool {
+    body.iter().any(|node| match unlocated(node) {
+        ASTNode::Continue => true,
+        ASTNode::If(_, then, elifs, otherwise) => {
+            has_continue(then) || elifs.iter().any(|(_, body)| has_continue(body)) || otherwise.as_deref().is_some_and(has_continue)
+        }
+        _ => false,
+    })
+}
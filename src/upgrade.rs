@@ -0,0 +1,246 @@
+use reqwest::blocking::Client;
+use serde_json::Value as Json;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/PranavVerma-droid/Tidal/releases/latest";
+
+// files published next to the binaries that list "<sha256>  <file name>"
+const CHECKSUM_LISTS: [&str; 3] = ["SHA256SUMS", "sha256sums.txt", "checksums.txt"];
+
+struct Asset {
+    name: String,
+    url: String,
+}
+
+struct Release {
+    tag: String,
+    assets: Vec<Asset>,
+}
+
+// td upgrade [--check]: replace the running executable with the binary from
+// the latest GitHub release for this platform, after checking its sha256
+pub fn upgrade(check_only: bool) -> Result<(), Box<dyn Error>> {
+    let client = Client::builder()
+        .user_agent(concat!("td/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    // TIDAL_RELEASES_URL points at a mirror of the releases API
+    let url = env::var("TIDAL_RELEASES_URL").unwrap_or_else(|_| RELEASES_URL.to_string());
+    let release = latest_release(&client, &url)?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    if !is_newer(&release.tag, current) {
+        println!("td {} is up to date", current);
+        return Ok(());
+    }
+
+    let binary = platform_binary(&release, env::consts::OS, env::consts::ARCH)
+        .ok_or_else(|| format!("release {} has no binary for {}-{}", release.tag, env::consts::OS, env::consts::ARCH))?;
+    if check_only {
+        println!("td {} is available (installed: {}), run 'td upgrade' to install {}", release.tag, current, binary.name);
+        return Ok(());
+    }
+
+    let expected = expected_checksum(&client, &release, &binary.name)?;
+    println!("Downloading {}...", binary.name);
+    let data = download(&client, &binary.url)?;
+    let actual = to_hex(&Sha256::digest(&data));
+    if actual != expected {
+        return Err(format!("checksum mismatch for {}: expected {}, got {}", binary.name, expected, actual).into());
+    }
+
+    replace_executable(&data)?;
+    println!("Upgraded td {} -> {}", current, release.tag);
+    Ok(())
+}
+
+fn latest_release(client: &Client, url: &str) -> Result<Release, Box<dyn Error>> {
+    let body = client.get(url).send()?.error_for_status()?.text()?;
+    let json: Json = serde_json::from_str(&body)?;
+
+    let tag = json["tag_name"].as_str().ok_or("release has no tag_name")?.to_string();
+    let assets = json["assets"].as_array().map(|assets| {
+        assets.iter().filter_map(|asset| Some(Asset {
+            name: asset["name"].as_str()?.to_string(),
+            url: asset["browser_download_url"].as_str()?.to_string(),
+        })).collect()
+    }).unwrap_or_default();
+    Ok(Release { tag, assets })
+}
+
+fn download(client: &Client, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(client.get(url).send()?.error_for_status()?.bytes()?.to_vec())
+}
+
+// "v1.13.0" > "1.12.0"; missing or non-numeric parts count as 0
+fn is_newer(tag: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.trim_start_matches('v').split('.')
+            .map(|part| part.chars().take_while(char::is_ascii_digit).collect::<String>().parse().unwrap_or(0))
+            .collect()
+    };
+    let (latest, current) = (parse(tag), parse(current));
+    let len = latest.len().max(current.len());
+    let padded = |v: &[u64]| (0..len).map(|i| v.get(i).copied().unwrap_or(0)).collect::<Vec<_>>();
+    padded(&latest) > padded(&current)
+}
+
+// the names release assets give each CPU, by std::env::consts::ARCH
+const ARCH_NAMES: [(&str, &[&str]); 4] = [
+    ("x86_64", &["x86_64", "amd64", "x64"]),
+    ("aarch64", &["aarch64", "arm64"]),
+    ("x86", &["i686", "i386"]),
+    ("arm", &["armv7", "armhf"]),
+];
+
+// The asset naming the OS and the CPU, or the OS and no CPU at all, falling
+// back to a bare td / td.exe for releases that only ship one build
+fn platform_binary<'a>(release: &'a Release, os: &str, arch: &str) -> Option<&'a Asset> {
+    let os_names: &[&str] = match os {
+        "windows" => &["windows", "win"],
+        "macos" => &["macos", "darwin", "mac"],
+        os => &[os],
+    };
+    let own_name = [arch];
+    let arch_names: &[&str] = ARCH_NAMES.iter()
+        .find(|(name, _)| *name == arch)
+        .map_or(&own_name, |(_, names)| names);
+    // whole words between '-', '_' and '.', so "win" isn't found in "darwin"
+    let mentions = |name: &str, words: &[&str]| {
+        let name = name.to_lowercase();
+        let boundary = |c: Option<char>| c.is_none_or(|c| matches!(c, '-' | '_' | '.'));
+        words.iter().any(|word| name.match_indices(word).any(|(at, _)| {
+            boundary(name[..at].chars().next_back()) && boundary(name[at + word.len()..].chars().next())
+        }))
+    };
+
+    let binaries: Vec<&Asset> = release.assets.iter()
+        .filter(|asset| !is_checksum_file(&asset.name))
+        .collect();
+    let for_os: Vec<&Asset> = binaries.iter().copied()
+        .filter(|asset| mentions(&asset.name, os_names))
+        .collect();
+    // a build for another CPU won't run here, so only one naming none will do
+    let names_any_arch = |name: &str| mentions(name, arch_names) || ARCH_NAMES.iter().any(|(_, names)| mentions(name, names));
+    for_os.iter().copied()
+        .find(|asset| mentions(&asset.name, arch_names))
+        .or_else(|| for_os.iter().copied().find(|asset| !names_any_arch(&asset.name)))
+        .or_else(|| {
+            let bare = if os == "windows" { "td.exe" } else { "td" };
+            binaries.into_iter().find(|asset| asset.name == bare)
+        })
+}
+
+fn is_checksum_file(name: &str) -> bool {
+    name.ends_with(".sha256") || CHECKSUM_LISTS.contains(&name)
+}
+
+// from "<binary>.sha256" if published, otherwise from a checksum list
+fn expected_checksum(client: &Client, release: &Release, binary: &str) -> Result<String, Box<dyn Error>> {
+    let sidecar = format!("{}.sha256", binary);
+    let source = release.assets.iter()
+        .find(|asset| asset.name == sidecar)
+        .or_else(|| release.assets.iter().find(|asset| CHECKSUM_LISTS.contains(&asset.name.as_str())))
+        .ok_or_else(|| format!("release {} publishes no sha256 checksum for {}, refusing to install it", release.tag, binary))?;
+
+    let text = String::from_utf8(download(client, &source.url)?)?;
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let hash = fields.next()?;
+            // a sidecar may hold just the hash; list entries may mark binary mode with '*'
+            match fields.next().map(|name| name.trim_start_matches('*')) {
+                None if source.name == sidecar => Some(hash),
+                Some(name) if name == binary => Some(hash),
+                _ => None,
+            }
+        })
+        .map(|hash| hash.to_lowercase())
+        .find(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("{} has no sha256 entry for {}", source.name, binary).into())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// The new binary is written next to the old one and renamed over it, so an
+// interrupted upgrade never leaves a half-written td behind. Windows can't
+// overwrite a running executable but can rename it out of the way.
+fn replace_executable(data: &[u8]) -> Result<(), Box<dyn Error>> {
+    let exe = env::current_exe()?;
+    let file_name = exe.file_name().ok_or("cannot locate the td executable")?.to_string_lossy().into_owned();
+    let staged = exe.with_file_name(format!("{}.new", file_name));
+    let cannot_write = |path: &Path, e: std::io::Error| format!("cannot write {}: {}", path.display(), e);
+
+    fs::write(&staged, data).map_err(|e| cannot_write(&staged, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755)).map_err(|e| cannot_write(&staged, e))?;
+    }
+    #[cfg(windows)]
+    let old = {
+        let old = exe.with_file_name(format!("{}.old", file_name));
+        let _ = fs::remove_file(&old);
+        fs::rename(&exe, &old).map_err(|e| cannot_write(&exe, e))?;
+        old
+    };
+
+    if let Err(e) = fs::rename(&staged, &exe) {
+        let _ = fs::remove_file(&staged);
+        #[cfg(windows)]
+        let _ = fs::rename(&old, &exe);
+        return Err(cannot_write(&exe, e).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(names: &[&str]) -> Release {
+        Release {
+            tag: "v2.0.0".to_string(),
+            assets: names.iter().map(|name| Asset { name: name.to_string(), url: String::new() }).collect(),
+        }
+    }
+
+    #[test]
+    fn newer_compares_versions_by_number() {
+        assert!(is_newer("v1.10.0", "1.9.3"));
+        assert!(is_newer("2.0", "1.99.99"));
+        assert!(is_newer("v1.2.1", "1.2"));
+        assert!(!is_newer("v1.2.0", "1.2"));
+        assert!(!is_newer("v1.2.0", "1.12.0"));
+        assert!(!is_newer("v1.12.0-beta", "1.12.0"));
+    }
+
+    #[test]
+    fn the_binary_is_picked_by_whole_words() {
+        let name = |release: &Release, os: &str, arch: &str| platform_binary(release, os, arch).map(|asset| asset.name.clone());
+        let full = release(&["td-darwin-arm64", "td-linux-x86_64", "td-linux-aarch64", "td-win-x64.exe", "td-win-x64.exe.sha256"]);
+        assert_eq!(name(&full, "windows", "x86_64").as_deref(), Some("td-win-x64.exe"));
+        assert_eq!(name(&full, "macos", "aarch64").as_deref(), Some("td-darwin-arm64"));
+        assert_eq!(name(&full, "linux", "aarch64").as_deref(), Some("td-linux-aarch64"));
+        assert_eq!(name(&full, "linux", "x86_64").as_deref(), Some("td-linux-x86_64"));
+
+        // "darwin" isn't a Windows build, even though it ends in "win"
+        let mac_only = release(&["td-darwin-x86_64", "td", "td.exe"]);
+        assert_eq!(name(&mac_only, "windows", "x86_64").as_deref(), Some("td.exe"));
+        assert_eq!(name(&mac_only, "linux", "x86_64").as_deref(), Some("td"));
+        assert_eq!(name(&release(&["td-darwin-x86_64"]), "windows", "x86_64"), None);
+
+        // an aarch64 host never gets an x86_64 build, but does get one naming no CPU
+        let intel_only = release(&["td-linux-x86_64", "td-macos-amd64"]);
+        assert_eq!(name(&intel_only, "linux", "aarch64"), None);
+        assert_eq!(name(&intel_only, "macos", "aarch64"), None);
+        let with_generic = release(&["td-linux-x86_64", "td-linux", "td"]);
+        assert_eq!(name(&with_generic, "linux", "aarch64").as_deref(), Some("td-linux"));
+        assert_eq!(name(&release(&["td-linux-x86_64", "td"]), "linux", "aarch64").as_deref(), Some("td"));
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// td writes a script's output to a pipe in large chunks. These run td with
+// its stdout on a pipe and check that what a reader is waiting for still
+// arrives: before input() blocks, on std.flush(), with --unbuffered, and when
+// the script exits.
+
+const WAIT: Duration = Duration::from_secs(10);
+
+// td running the script, and the lines it prints as they reach the pipe
+fn spawn(name: &str, flags: &[&str], source: &str) -> (Child, Receiver<String>) {
+    let script = std::env::temp_dir().join(format!("tidal-buffering-{}-{}.td", name, std::process::id()));
+    fs::write(&script, source).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_td"))
+        .args(flags)
+        .arg(&script)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (lines, received) = mpsc::channel();
+    thread::spawn(move || {
+        for line in stdout.lines() {
+            if lines.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+        let _ = fs::remove_file(script);
+    });
+    (child, received)
+}
+
+#[test]
+fn a_prompt_reaches_the_pipe_before_input_waits() {
+    let (mut td, lines) = spawn("input", &[], "print(\"name?\");\nvar name = input(\"\");\nprint(\"hi \" + name);\n");
+    assert_eq!(lines.recv_timeout(WAIT).unwrap(), "name?");
+    writeln!(td.stdin.take().unwrap(), "ada").unwrap();
+    assert_eq!(lines.recv_timeout(WAIT).unwrap(), "hi ada");
+    assert!(td.wait().unwrap().success());
+}
+
+#[test]
+fn output_is_held_until_a_flush() {
+    let forever = "while (true) {}\n";
+    let (mut held, held_lines) = spawn("held", &[], &format!("print(\"held\");\n{}", forever));
+    let (mut flushed, flushed_lines) = spawn("flushed", &[], &format!("print(\"flushed\");\nstd.flush();\n{}", forever));
+    let (mut unbuffered, unbuffered_lines) = spawn("unbuffered", &["--unbuffered"], &format!("print(\"unbuffered\");\n{}", forever));
+
+    assert_eq!(flushed_lines.recv_timeout(WAIT).unwrap(), "flushed");
+    assert_eq!(unbuffered_lines.recv_timeout(WAIT).unwrap(), "unbuffered");
+    assert!(held_lines.recv_timeout(Duration::from_millis(300)).is_err());
+    for td in [&mut held, &mut flushed, &mut unbuffered] {
+        td.kill().unwrap();
+        td.wait().unwrap();
+    }
+}
+
+#[test]
+fn exiting_writes_what_is_left() {
+    let (mut td, lines) = spawn("exit", &[], "print(\"a\");\nprint(\"b\");\nstd.exit(3);\n");
+    assert_eq!(td.wait().unwrap().code(), Some(3));
+    assert_eq!(lines.iter().collect::<Vec<_>>(), ["a", "b"]);
+}
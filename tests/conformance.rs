@@ -0,0 +1,64 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// The conformance corpus: every program in tests/conformance/valid must print
+// exactly its .out file and exit 0, and every program in
+// tests/conformance/invalid must print nothing and fail with the error code
+// and line in its .err file ("E0012 at line 3"). Any implementation of Tidal
+// should pass it; TIDAL_CONFORMANCE_BIN runs it against another executable
+// that takes a script path the way td does.
+
+fn corpus(kind: &str) -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance").join(kind);
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "td"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+    scripts
+}
+
+fn run(script: &Path) -> Output {
+    let bin = env::var("TIDAL_CONFORMANCE_BIN").unwrap_or_else(|_| env!("CARGO_BIN_EXE_td").to_string());
+    Command::new(bin).arg(script).current_dir(script.parent().unwrap()).output().unwrap()
+}
+
+#[test]
+fn valid_programs_print_what_they_should() {
+    let mut failures = Vec::new();
+    for script in corpus("valid") {
+        let expected = fs::read_to_string(script.with_extension("out")).unwrap();
+        let output = run(&script);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() || stdout != expected {
+            failures.push(format!(
+                "{}: exit {:?}\n--- expected\n{}--- got\n{}{}",
+                script.display(), output.status.code(), expected, stdout, String::from_utf8_lossy(&output.stderr),
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn invalid_programs_are_refused_before_they_run() {
+    let mut failures = Vec::new();
+    for script in corpus("invalid") {
+        let expected = fs::read_to_string(script.with_extension("err")).unwrap();
+        let (code, line) = expected.trim().split_once(" at ").unwrap();
+        let output = run(&script);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // "line 2, column 7", so line 2 doesn't also match line 20
+        let reported = stderr.contains(&format!("[{}]", code)) && stderr.contains(&format!("{}, column", line));
+        if output.status.success() || !output.stdout.is_empty() || !reported {
+            failures.push(format!(
+                "{}: expected {}, exit {:?}\n--- stdout\n{}--- stderr\n{}",
+                script.display(), expected.trim(), output.status.code(), String::from_utf8_lossy(&output.stdout), stderr,
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+
+// td --version names the build, then what it can import and which optional
+// features it was built with, the same lists sys.FEATURES holds
+
+#[test]
+fn version_lists_libraries_and_features() {
+    let output = Command::new(env!("CARGO_BIN_EXE_td")).arg("--version").output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+
+    assert!(lines[0].starts_with(&format!("Tidal {} (", env!("CARGO_PKG_VERSION"))), "{}", lines[0]);
+    assert!(lines[1].starts_with("Libraries: std, math, sys, "), "{}", lines[1]);
+    assert_eq!(lines[1].contains(", image"), cfg!(feature = "imaging"), "{}", lines[1]);
+
+    let expected: Vec<&str> = [("unix", cfg!(feature = "unix")), ("imaging", cfg!(feature = "imaging")), ("mail", cfg!(feature = "mail"))]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect();
+    let features = if expected.is_empty() { "none".to_string() } else { expected.join(", ") };
+    assert_eq!(lines[2], format!("Features: {}", features));
+}